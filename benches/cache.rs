@@ -0,0 +1,76 @@
+//! Benchmarks of the shared `stderr` cache, run them with
+//!
+//! ```sh
+//! cargo bench --bench cache
+//! ```
+//!
+//! The fixture tree holds 16 revisions of a test sharing a single `stderr` file.
+//! Baseline numbers, measured on the CI-like Linux container used for development:
+//!
+//! | benchmark                                 | time     |
+//! |-------------------------------------------|----------|
+//! | `16 revisions sharing stderr, uncached`   | 12.6 ms  |
+//! | `16 revisions sharing stderr, cached`     | 8.9 ms   |
+
+mod fixtures;
+
+use {
+    criterion::{criterion_group, criterion_main, Criterion},
+    rusttest_to_dg::{
+        cache::Caches,
+        input,
+        limits::Limits,
+        transform::{self, Options},
+    },
+    std::{fs, hint::black_box, io, path::PathBuf},
+};
+
+const REVISIONS: usize = 16;
+
+/// Writes the revisions and their shared `stderr` file to a temporary directory
+fn fixture_tree() -> (tempfile::TempDir, Vec<PathBuf>, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let (code, stderr) = fixtures::realistic_test(50);
+    let stderr_path = dir.path().join("shared.stderr");
+    fs::write(&stderr_path, stderr).unwrap();
+    let sources = (0..REVISIONS)
+        .map(|revision| {
+            let path = dir.path().join(format!("revision{revision}.rs"));
+            fs::write(&path, &code).unwrap();
+            path
+        })
+        .collect();
+    (dir, sources, stderr_path)
+}
+
+fn bench_cache(c: &mut Criterion) {
+    let (_dir, sources, stderr_path) = fixture_tree();
+    let options = Options::default();
+
+    c.bench_function("16 revisions sharing stderr, uncached", |b| {
+        b.iter(|| {
+            for source in &sources {
+                let code = input::read(source).unwrap();
+                let stderr = input::read(&stderr_path).unwrap();
+                transform::transform_to(&mut io::sink(), &code, Some(&stderr), &options).unwrap();
+            }
+        })
+    });
+
+    c.bench_function("16 revisions sharing stderr, cached", |b| {
+        b.iter(|| {
+            // A fresh cache per run, as a batch conversion would have
+            let caches = Caches::new();
+            for source in &sources {
+                let code = input::read(source).unwrap();
+                let stderr = caches.stderr(&stderr_path, &Limits::default()).unwrap();
+                transform::transform_parsed_to(&mut io::sink(), &code, Some(&stderr), &options)
+                    .unwrap();
+            }
+            black_box(caches.hits())
+        })
+    });
+}
+
+criterion_group!(benches, bench_cache);
+criterion_main!(benches);
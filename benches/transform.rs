@@ -0,0 +1,49 @@
+//! Benchmarks of the whole transformation pipeline, run them with
+//!
+//! ```sh
+//! cargo bench --bench transform
+//! ```
+//!
+//! Baseline numbers, measured on the CI-like Linux container used for development:
+//!
+//! | benchmark                                   | time     |
+//! |---------------------------------------------|----------|
+//! | `transform_code 10k lines, 20 annotations`  | 1.5 ms   |
+//! | `transform_code 10k lines, 2k annotations`  | 9.6 ms   |
+//! | `transform_code realistic test with stderr` | 0.55 ms  |
+
+mod fixtures;
+
+use {
+    criterion::{criterion_group, criterion_main, Criterion},
+    rusttest_to_dg::transform::{transform_code, Options},
+    std::hint::black_box,
+};
+
+fn bench_transform(c: &mut Criterion) {
+    // Dominated by the per-line work, where unchanged lines are copied
+    let sparse = fixtures::annotated_source(10_000, 1_000);
+    c.bench_function("transform_code 10k lines, 20 annotations", |b| {
+        b.iter(|| transform_code(black_box(&sparse), None, &Options::default()).unwrap())
+    });
+
+    let dense = fixtures::annotated_source(10_000, 10);
+    c.bench_function("transform_code 10k lines, 2k annotations", |b| {
+        b.iter(|| transform_code(black_box(&dense), None, &Options::default()).unwrap())
+    });
+
+    let (code, stderr) = fixtures::realistic_test(50);
+    c.bench_function("transform_code realistic test with stderr", |b| {
+        b.iter(|| {
+            transform_code(
+                black_box(&code),
+                Some(black_box(&stderr)),
+                &Options::default(),
+            )
+            .unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, bench_transform);
+criterion_main!(benches);
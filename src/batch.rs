@@ -0,0 +1,507 @@
+//! This module contains the logic for converting many files at once.
+//!
+//! Workers never print anything: each file's diagnostics are buffered in its
+//! [`FileOutcome`], and [`BatchOutcome::write_log`] emits them grouped per file in
+//! sorted path order once every worker is done. The log and its totals are thus
+//! identical whatever the number of jobs.
+
+use {
+    crate::{
+        cache::Caches,
+        cli, header, input,
+        stats::Stats,
+        transform::{self, SkipCategory, TransformReport},
+    },
+    anyhow::{Context, Result},
+    serde::Serialize,
+    std::{
+        fs, io,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Mutex,
+        },
+        thread,
+    },
+};
+
+/// A file to convert, with its optional `stderr` file
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Job {
+    pub source_file: PathBuf,
+    pub stderr_file: Option<PathBuf>,
+}
+
+/// The converted code of each revision of a test, by revision
+pub type Revisions = Vec<(String, String)>;
+
+/// What happened to a single file
+#[derive(Debug)]
+pub struct FileOutcome {
+    pub job: Job,
+    /// The converted code and its report, or the error which stopped the conversion
+    pub result: Result<(String, TransformReport)>,
+    /// The converted code of each revision of a test with a `revisions` header
+    pub revisions: Revisions,
+    /// The output file which already existed and was kept instead of being overwritten
+    pub kept: Option<PathBuf>,
+}
+
+/// A test left out of a batch, in the `--skipped-report`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct SkipRecord<'a> {
+    pub path: &'a Path,
+    pub category: SkipCategory,
+    pub reason: &'a str,
+}
+
+/// What happened to every file of a batch, sorted by path
+#[derive(Debug, Default)]
+pub struct BatchOutcome {
+    pub files: Vec<FileOutcome>,
+}
+
+impl BatchOutcome {
+    /// Number of files converted successfully
+    pub fn converted(&self) -> usize {
+        self.reports()
+            .filter(|report| report.skipped.is_none())
+            .count()
+    }
+
+    /// Number of files which failed to convert
+    pub fn failed(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| file.result.is_err())
+            .count()
+    }
+
+    /// Number of the failed files which look binary rather than like Rust source, see
+    /// [`input::is_binary`]
+    pub fn binary(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| {
+                file.result.as_ref().is_err_and(|err| {
+                    err.chain()
+                        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+                        .any(input::is_binary_error)
+                })
+            })
+            .count()
+    }
+
+    /// Number of converted files whose output already existed and was kept
+    pub fn kept(&self) -> usize {
+        self.files.iter().filter(|file| file.kept.is_some()).count()
+    }
+
+    /// Number of files left out of the conversion, see [`transform::skipped`]
+    pub fn skipped(&self) -> usize {
+        self.reports()
+            .filter(|report| report.skipped.is_some())
+            .count()
+    }
+
+    /// The files left out of the conversion, in path order
+    pub fn skip_records(&self) -> Vec<SkipRecord<'_>> {
+        self.files
+            .iter()
+            .filter_map(|file| {
+                let (_, report) = file.result.as_ref().ok()?;
+                let skip = report.skipped.as_ref()?;
+                Some(SkipRecord {
+                    path: &file.job.source_file,
+                    category: skip.category,
+                    reason: &skip.reason,
+                })
+            })
+            .collect()
+    }
+
+    /// Total number of warnings over every converted file
+    pub fn warnings(&self) -> usize {
+        self.reports()
+            .map(|report| report.warnings.len() + report.lints.len())
+            .sum()
+    }
+
+    /// The counts of every converted file
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        for report in self.reports() {
+            stats.merge(&report.stats);
+        }
+        stats
+    }
+
+    fn reports(&self) -> impl Iterator<Item = &TransformReport> {
+        self.files
+            .iter()
+            .filter_map(|file| file.result.as_ref().ok().map(|(_, report)| report))
+    }
+
+    /// Writes the error of every file which failed to convert, one per line
+    pub fn write_errors(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        for file in &self.files {
+            if let Err(err) = &file.result {
+                writeln!(writer, "error: {err:#}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the diagnostics of every file, grouped under a `=== path ===` line,
+    /// followed by the totals
+    pub fn write_log(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        for file in &self.files {
+            writeln!(writer, "=== {} ===", file.job.source_file.display())?;
+            match &file.result {
+                Ok((_, report)) => cli::write_report(report, writer)?,
+                Err(err) => writeln!(writer, "error: {err:#}")?,
+            }
+            if let Some(kept) = &file.kept {
+                writeln!(writer, "{}", cli::kept_warning(kept))?;
+            }
+        }
+        write!(
+            writer,
+            "{} files: {} converted, {} failed, {} warnings",
+            self.files.len(),
+            self.converted(),
+            self.failed(),
+            self.warnings()
+        )?;
+        for category in [SkipCategory::KnownBug, SkipCategory::ProcMacro] {
+            let skipped = self
+                .reports()
+                .filter_map(|report| report.skipped.as_ref())
+                .filter(|skip| skip.category == category)
+                .count();
+            if skipped > 0 {
+                write!(writer, ", {skipped} {} skipped", category.plural())?;
+            }
+        }
+        match self.binary() {
+            0 => {}
+            1 => write!(writer, ", 1 binary input")?,
+            binary => write!(writer, ", {binary} binary inputs")?,
+        }
+        match self.kept() {
+            0 => writeln!(writer),
+            kept => writeln!(writer, ", {kept} existing kept"),
+        }
+    }
+}
+
+/// Every `.rs` file under `root` in path order, with the `stderr` file next to it if any
+pub fn discover(root: &Path) -> io::Result<Vec<Job>> {
+    Ok(rust_files(root)?
+        .into_iter()
+        .map(|path| Job {
+            stderr_file: cli::discover_stderr(&path),
+            source_file: path,
+        })
+        .collect())
+}
+
+/// The files named by the list file `list`, one per line with its 1-based line number.
+///
+/// Blank lines and `#` comments are ignored, relative paths are relative to the directory
+/// of the list rather than to the working directory.
+pub fn read_file_list(list: &Path) -> io::Result<Vec<(usize, PathBuf)>> {
+    let content = fs::read_to_string(list)?;
+    let dir = list.parent().unwrap_or(Path::new(""));
+    Ok(parse_file_list(&content, dir))
+}
+
+/// [`read_file_list`] over the `content` of a list in `dir`
+pub fn parse_file_list(content: &str, dir: &Path) -> Vec<(usize, PathBuf)> {
+    (1..)
+        .zip(content.lines())
+        .filter_map(|(line_num, line)| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            Some((line_num, dir.join(line)))
+        })
+        .collect()
+}
+
+/// Every `.rs` file under `root`, in path order
+pub fn rust_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// The default number of worker threads: one per CPU
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |jobs| jobs.get())
+}
+
+/// Converts every job using `jobs` worker threads (`0` is the same as `1`). With
+/// `fail_fast`, no job is started once one failed, the ones already started are finished.
+pub fn convert(
+    jobs_list: &[Job],
+    jobs: usize,
+    options: &transform::Options,
+    fail_fast: bool,
+) -> BatchOutcome {
+    let caches = Caches::new();
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let outcomes = Mutex::new(Vec::with_capacity(jobs_list.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, jobs_list.len().max(1)) {
+            scope.spawn(|| loop {
+                if failed.load(Ordering::Relaxed) {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs_list.get(index) else {
+                    break;
+                };
+                let (result, revisions) = match convert_file(job, &caches, options) {
+                    Ok((new_code, report, revisions)) => (Ok((new_code, report)), revisions),
+                    Err(err) => (Err(err), Vec::new()),
+                };
+                if fail_fast && result.is_err() {
+                    failed.store(true, Ordering::Relaxed);
+                }
+                let outcome = FileOutcome {
+                    job: job.clone(),
+                    result,
+                    revisions,
+                    kept: None,
+                };
+                outcomes
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .push(outcome);
+            });
+        }
+    });
+
+    let mut files = outcomes
+        .into_inner()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    files.sort_by(|a, b| a.job.cmp(&b.job));
+    BatchOutcome { files }
+}
+
+/// Converts a single file, sharing the parsed `stderr` files through `caches`, then each of
+/// its revisions on their own
+fn convert_file(
+    job: &Job,
+    caches: &Caches,
+    options: &transform::Options,
+) -> Result<(String, TransformReport, Revisions)> {
+    let source_file = &job.source_file;
+    let size = std::fs::metadata(source_file).map_or(0, |metadata| metadata.len());
+    options.limits.check_file_size(size)?;
+    let code = input::read(source_file)
+        .with_context(|| format!("could not read sourcefile `{}`", source_file.display()))?;
+    let name = format!("`{}`", source_file.display());
+    if let Some(report) = transform::skipped(&code, &name, options) {
+        return Ok((String::new(), report, Vec::new()));
+    }
+    let stderr = match &job.stderr_file {
+        Some(stderr_file) => Some(caches.stderr(stderr_file, &options.limits)?),
+        None => None,
+    };
+    // The expected output of a `check-run-results` test is in the file next to it
+    let stdout_file = source_file.with_extension("run.stdout");
+    let stdout =
+        if header::checks_run_results(&code) && stdout_file.is_file() {
+            Some(input::read(&stdout_file).with_context(|| {
+                format!("could not read stdout file `{}`", stdout_file.display())
+            })?)
+        } else {
+            None
+        };
+    let stdout_name = format!("`{}`", stdout_file.display());
+
+    let options = &transform::Options {
+        source_name: cli::source_name(source_file),
+        ..options.clone()
+    };
+    let transform = |options: &transform::Options| {
+        let mut new_code = Vec::with_capacity(code.len());
+        let mut report = transform::transform_parsed_to(
+            &mut new_code,
+            &code,
+            stderr.as_ref().map(|s| s.as_slice()),
+            options,
+        )
+        .map_err(|err| cli::annotation_context(err, &code))
+        .with_context(|| {
+            format!(
+                "could not transform code from file `{}`",
+                source_file.display()
+            )
+        })?;
+        if let Some(stdout) = &stdout {
+            transform::write_run_output(
+                &mut new_code,
+                &code,
+                stdout,
+                &stdout_name,
+                options,
+                &mut report,
+            )?;
+        }
+        anyhow::Ok((String::from_utf8(new_code)?, report))
+    };
+    let (new_code, mut report) = transform(options)?;
+    if header::checks_run_results(&code) && stdout.is_none() {
+        report.warnings.push(format!(
+            "{name} checks the results of its run but has no {stdout_name} file, its output is not checked"
+        ));
+    }
+    let mut revisions = Vec::new();
+    if options.revision.is_none() {
+        for revision in header::revisions(&code) {
+            let options = transform::Options {
+                revision: Some(revision.clone()),
+                ..options.clone()
+            };
+            let (revision_code, revision_report) = transform(&options)?;
+            report.add_revision(revision_report);
+            revisions.push((revision, revision_code));
+        }
+    }
+    Ok((new_code, report, revisions))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::fs};
+
+    /// A small tree mixing clean files, files with warnings and broken ones
+    fn tree() -> (tempfile::TempDir, Vec<Job>) {
+        let dir = tempfile::tempdir().unwrap();
+        let stderr = dir.path().join("shared.stderr");
+        fs::write(
+            &stderr,
+            "error[E0308]: mismatched types\n  --> $DIR/a.rs:2:18\n",
+        )
+        .unwrap();
+        let mut jobs = Vec::new();
+        for i in 0..20 {
+            let path = dir.path().join(format!("test{i:02}.rs"));
+            let code = match i % 4 {
+                0 => "fn main() {}\n".to_owned(),
+                1 => {
+                    "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n}\n".to_owned()
+                }
+                2 => format!("{} //~ ERROR hidden\n", "x".repeat(70_000)),
+                _ => "//~| NOTE orphan\n".to_owned(),
+            };
+            fs::write(&path, code).unwrap();
+            jobs.push(Job {
+                source_file: path,
+                stderr_file: (i % 2 == 1).then(|| stderr.clone()),
+            });
+        }
+        // The order of the jobs must not matter either
+        jobs.reverse();
+        (dir, jobs)
+    }
+
+    fn transcript(outcome: &BatchOutcome, dir: &std::path::Path) -> String {
+        let mut log = Vec::new();
+        outcome.write_log(&mut log).unwrap();
+        // Temporary directories differ between runs
+        String::from_utf8(log)
+            .unwrap()
+            .replace(&dir.display().to_string(), "$DIR")
+    }
+
+    #[test]
+    fn test_parse_file_list() {
+        let list = "\
+# tests gccrs can take
+typeck/mismatch.rs
+
+  lint/unused.rs  \n\
+\t# indented comment
+/abs/test.rs
+";
+        assert_eq!(
+            parse_file_list(list, Path::new("manifests")),
+            [
+                (2, PathBuf::from("manifests/typeck/mismatch.rs")),
+                (4, PathBuf::from("manifests/lint/unused.rs")),
+                (6, PathBuf::from("/abs/test.rs")),
+            ]
+        );
+        // A list in the working directory keeps the paths as they are
+        assert_eq!(
+            parse_file_list("a.rs\n", Path::new("")),
+            [(1, PathBuf::from("a.rs"))]
+        );
+    }
+
+    #[test]
+    fn test_log_does_not_depend_on_the_number_of_jobs() {
+        let (dir, jobs) = tree();
+        let options = transform::Options::default();
+        let sequential = convert(&jobs, 1, &options, false);
+        let parallel = convert(&jobs, 8, &options, false);
+
+        let log = transcript(&sequential, dir.path());
+        assert_eq!(log, transcript(&parallel, dir.path()));
+        assert_eq!(
+            (parallel.converted(), parallel.failed(), parallel.warnings()),
+            (15, 5, 5)
+        );
+        assert!(log.starts_with("=== $DIR/test00.rs ===\n=== $DIR/test01.rs ===\n"));
+        assert!(log.contains(
+            "=== $DIR/test02.rs ===\nwarning: line 1 is 70017 bytes long, not scanning it for annotations (limit is 65536)\n"
+        ));
+        assert!(log.contains(
+            "=== $DIR/test03.rs ===\nerror: could not transform code from file `$DIR/test03.rs`: in `//~| NOTE orphan`: line 1: `//~|` without a preceding annotation to follow\n"
+        ));
+        assert!(log.ends_with("20 files: 15 converted, 5 failed, 5 warnings\n"));
+    }
+
+    #[test]
+    fn test_outputs_match_single_file_conversion() {
+        let (_dir, jobs) = tree();
+        let options = transform::Options::default();
+        for file in convert(&jobs, 4, &options, false).files {
+            let code = fs::read_to_string(&file.job.source_file).unwrap();
+            let stderr = file
+                .job
+                .stderr_file
+                .as_ref()
+                .map(|path| fs::read_to_string(path).unwrap());
+            // Like the command line, which only matches the entries of the converted file
+            let options = transform::Options {
+                source_name: cli::source_name(&file.job.source_file),
+                ..options.clone()
+            };
+            let expected = transform::transform_code(&code, stderr.as_deref(), &options);
+            match (file.result, expected) {
+                (Ok(actual), Ok(expected)) => assert_eq!(actual, expected),
+                (Err(_), Err(_)) => {}
+                (actual, expected) => panic!("{actual:?} != {expected:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! This module contains the caches shared by the threads of a batch conversion.
+//!
+//! Several files often share the same artifacts, like the revisions of a test sharing
+//! a `stderr` file. [`Caches`] parses each of them once per run and hands out shared
+//! copies. Nothing is ever invalidated, the inputs are not expected to change during a run.
+//! The regex registry needs no entry here, [`crate::regexes`] already compiles each
+//! pattern once for the whole process.
+
+use {
+    crate::{errors, input, limits::Limits},
+    anyhow::{Context, Result},
+    std::{
+        collections::BTreeMap,
+        fs,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    },
+};
+
+/// A parsed `stderr` file, as shared by every file using it
+pub type ParsedStderr = Arc<Vec<errors::StderrDiagnostic>>;
+
+/// Caches shared across worker threads, usually behind an `Arc`
+#[derive(Debug, Default)]
+pub struct Caches {
+    /// Parsed `stderr` files, keyed by canonical path
+    stderr: Mutex<BTreeMap<PathBuf, ParsedStderr>>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl Caches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the parsed `stderr` file at `path`, reading and parsing it on first use
+    pub fn stderr(&self, path: &Path, limits: &Limits) -> Result<ParsedStderr> {
+        let key = fs::canonicalize(path)
+            .with_context(|| format!("could not read stderr file `{}`", path.display()))?;
+        if let Some(parsed) = self.lock().get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(Arc::clone(parsed));
+        }
+
+        // Parsing happens without the lock, two threads may race on the
+        // same file but the first result wins and both are identical
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        limits.check_stderr_size(fs::metadata(&key).map_or(0, |metadata| metadata.len()))?;
+        let content = input::read(&key)
+            .with_context(|| format!("could not read stderr file `{}`", path.display()))?;
+        let parsed = Arc::new(errors::parse_stderr(&content));
+        Ok(Arc::clone(self.lock().entry(key).or_insert(parsed)))
+    }
+
+    /// Number of lookups answered from the cache
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of lookups which had to read and parse the file
+    pub fn misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, ParsedStderr>> {
+        // The map is always left consistent, a panicking thread cannot poison its content
+        self.stderr
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::transform::{self, Options},
+        std::thread,
+    };
+
+    const STDERR: &str = "error[E0308]: mismatched types\n  --> $DIR/shared.rs:2:18\n";
+
+    #[test]
+    fn test_shared_stderr_is_parsed_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let stderr = dir.path().join("shared.stderr");
+        fs::write(&stderr, STDERR).unwrap();
+        let caches = Caches::new();
+
+        let first = caches.stderr(&stderr, &Limits::default()).unwrap();
+        // Another spelling of the same file hits the same entry
+        let other_path = dir.path().join(".").join("shared.stderr");
+        let second = caches.stderr(&other_path, &Limits::default()).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!((caches.hits(), caches.misses()), (1, 1));
+        assert_eq!(first[0].code.as_deref(), Some("E0308"));
+    }
+
+    #[test]
+    fn test_cache_is_shared_across_threads() {
+        let dir = tempfile::tempdir().unwrap();
+        let stderr = dir.path().join("shared.stderr");
+        fs::write(&stderr, STDERR).unwrap();
+        let code = "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n}\n";
+        let caches = Arc::new(Caches::new());
+
+        let outputs: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    let caches = Arc::clone(&caches);
+                    let stderr = &stderr;
+                    scope.spawn(move || {
+                        let parsed = caches.stderr(stderr, &Limits::default()).unwrap();
+                        let mut output = Vec::new();
+                        transform::transform_parsed_to(
+                            &mut output,
+                            code,
+                            Some(&parsed),
+                            &Options::default(),
+                        )
+                        .unwrap();
+                        output
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert!(outputs.windows(2).all(|pair| pair[0] == pair[1]));
+        let expected = transform::transform_code(code, Some(STDERR), &Options::default()).unwrap();
+        assert_eq!(outputs[0], expected.0.as_bytes());
+        assert_eq!(caches.hits() + caches.misses(), 8);
+        assert!(caches.hits() > 0);
+    }
+
+    #[test]
+    fn test_limits_apply_to_cached_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let stderr = dir.path().join("big.stderr");
+        fs::write(&stderr, STDERR).unwrap();
+        let limits = Limits {
+            max_stderr_size: 4,
+            ..Limits::default()
+        };
+        assert!(Caches::new().stderr(&stderr, &limits).is_err());
+        assert!(Caches::new()
+            .stderr(&dir.path().join("missing"), &limits)
+            .is_err());
+    }
+}
@@ -1,56 +1,1971 @@
 //! This module contains the command line interface for the tool
 
 use {
+    crate::{
+        batch::{self, BatchOutcome},
+        corpus, dejagnu, diff,
+        errors::{self, MsgStyle, Progress, MANY_ANNOTATIONS},
+        explain,
+        filter::Filter,
+        header,
+        input::{self, BomMode, Input},
+        layout::Layout,
+        limits::{LimitError, Limits},
+        lint, merge,
+        mirror::{self, Mirror},
+        needs::NeedsPolicy,
+        reverse,
+        stats::Stats,
+        testlist::ListFormat,
+        transform::{self, LineNumbers, SecondaryMode, TransformReport},
+    },
     anyhow::{Context, Result},
-    clap::Parser,
-    std::{fs, path},
+    clap::{CommandFactory, Parser, Subcommand},
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        fmt, fs, io, path,
+        sync::Arc,
+    },
 };
 
-/// Command line arguments for the tool
+/// The command line of the tool: a subcommand, or the arguments of the default conversion
 #[derive(Parser, Debug)]
+#[command(
+    name = "rust test to DejaGnu",
+    long_about = "A tool to convert rust tests into DejaGnu tests format",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Parsed with the defaults when there is a subcommand. clap would never fill an
+    /// `Option`, it leaves the group of a flattened struct empty when it flattens another one.
+    #[command(flatten)]
+    pub convert: Arguments,
+}
+
+/// The subcommands of the tool
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Check the DejaGnu directives of a test, converted by this tool or not
+    Verify {
+        /// The test to check
+        #[arg(value_name = "FILE")]
+        file: path::PathBuf,
+    },
+    /// Convert the whole ui testsuite of a rustc checkout and report what happened
+    Batch {
+        /// The rustc checkout, whose tests are under `tests/ui`
+        #[arg(long = "rust-src", value_name = "DIR")]
+        rust_src: path::PathBuf,
+
+        /// Where to write the converted tests, nothing is written without it
+        #[arg(long = "output-dir", value_name = "DIR")]
+        output_dir: Option<path::PathBuf>,
+
+        /// A gccrs checkout, to compare each converted test with its hand-ported version.
+        /// Nothing is written in this mode.
+        #[arg(long = "gccrs-src", value_name = "DIR", conflicts_with = "output_dir")]
+        gccrs_src: Option<path::PathBuf>,
+
+        /// The directories of the compile-only and executable tests under the output
+        /// directory, ambiguous tests going to the first one
+        #[arg(
+            long = "layout",
+            value_name = "COMPILE,EXECUTE",
+            default_value = "compile,execute",
+            requires = "output_dir"
+        )]
+        layout: Layout,
+
+        /// Write the list of the tests under the output directory to FILE, to include into
+        /// an `.exp` file. The tests which were not written are commented out.
+        #[arg(long = "emit-exp", value_name = "FILE", requires = "output_dir")]
+        test_list: Option<path::PathBuf>,
+
+        /// The format of the test list
+        #[arg(
+            long = "emit-exp-format",
+            value_enum,
+            default_value_t = ListFormat::Tcl,
+            requires = "test_list"
+        )]
+        list_format: ListFormat,
+
+        /// Only convert the tests changed since GIT_REF in the rustc checkout, along with
+        /// the tests whose `stderr` file changed
+        #[arg(long = "since", value_name = "GIT_REF")]
+        since: Option<String>,
+
+        /// Convert N tests at a time [default: the number of CPUs]
+        #[arg(short = 'j', long = "jobs", value_name = "N")]
+        jobs: Option<usize>,
+    },
+    /// Convert the embedded sample tests and compare them with their expected output
+    Selftest,
+    /// Show step by step how a single annotation is converted
+    Explain {
+        /// The annotation, like `//~^ ERROR mismatched types`, possibly after some code
+        #[arg(value_name = "ANNOTATION")]
+        annotation: String,
+
+        /// The 1-based line of the annotation, to show the absolute line it targets
+        #[arg(long = "at-line", value_name = "N")]
+        at_line: Option<usize>,
+
+        #[command(flatten)]
+        render: RenderArguments,
+    },
+    /// Turn the DejaGnu directives of a converted test back into rustc annotations
+    Reverse {
+        /// The converted test
+        #[arg(value_name = "FILE")]
+        file: path::PathBuf,
+    },
+    /// Write the completion script of SHELL to stdout
+    Completions {
+        #[arg(value_name = "SHELL")]
+        shell: clap_complete::Shell,
+    },
+}
+
+/// How the annotations are rendered, by the conversion and by `explain`
+#[derive(clap::Args, Debug, Clone)]
+pub struct RenderArguments {
+    /// How `NOTE` and `HELP` annotations are rendered
+    #[arg(
+        long = "secondary",
+        value_enum,
+        default_value_t = SecondaryMode::Convert,
+        help = "How NOTE and HELP annotations are rendered: converted into directives, kept as plain comments or dropped"
+    )]
+    pub secondary: SecondaryMode,
+
+    #[arg(
+        long = "notes-as-comments",
+        conflicts_with = "secondary",
+        help = "Keep the NOTE and HELP annotations as plain comments, for the suites which do not check them, the same as `--secondary comment`"
+    )]
+    pub notes_as_comments: bool,
+
+    /// How the annotation message is embedded in the directive pattern
+    #[arg(
+        long = "msg-style",
+        value_enum,
+        default_value_t = MsgStyle::None,
+        help = "How the annotation message is embedded in the directive: escaped, truncated at the first regex special character, portable to the messages of gccrs, or left out"
+    )]
+    pub msg_style: MsgStyle,
+
+    #[arg(
+        long = "with-messages",
+        conflicts_with = "msg_style",
+        help = "Match the annotation messages in the directives, and the lints of the warnings, the same as `--msg-style portable`"
+    )]
+    pub with_messages: bool,
+
+    #[arg(
+        long = "max-message-length",
+        value_name = "N",
+        default_value_t = errors::DEFAULT_MAX_MESSAGE_LENGTH,
+        help = "Cut the portable messages longer than N characters at a word boundary (0 keeps them whole)"
+    )]
+    pub max_message_length: usize,
+}
+
+impl RenderArguments {
+    /// The message style of `--msg-style`, or the portable one of `--with-messages`
+    pub fn msg_style(&self) -> MsgStyle {
+        if self.with_messages {
+            MsgStyle::Portable
+        } else {
+            self.msg_style
+        }
+    }
+
+    /// The rendering of `--secondary`, or the comments of `--notes-as-comments`
+    pub fn secondary(&self) -> SecondaryMode {
+        if self.notes_as_comments {
+            SecondaryMode::Comment
+        } else {
+            self.secondary
+        }
+    }
+}
+
+/// What the conversion writes
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Emit {
+    /// The converted file
+    #[default]
+    Text,
+    /// The annotations loaded from the file, with their line, kind, message and error code
+    Json,
+}
+
+/// Command line arguments for the tool
+#[derive(Parser, Debug, Clone)]
 #[command(
     name = "rust test to DejaGnu",
     long_about = "A tool to convert rust tests into DejaGnu tests format"
 )]
 pub struct Arguments {
-    /// The rust source file to convert into `DejaGnu` format
+    /// The rust source files to convert into `DejaGnu` format, required without `--dir`
     #[arg(
         // positional argument
         value_name = "FILE",
-        help = "The rust source file to convert into DejaGnu format"
+        num_args = 1..,
+        required_unless_present_any = ["dir", "file_list"],
+        help = "The rust source files to convert into DejaGnu format, - for standard input"
+    )]
+    pub source_file: Vec<path::PathBuf>,
+
+    /// A file listing the files to convert, along with any FILE
+    #[arg(
+        long = "file-list",
+        value_name = "LIST",
+        conflicts_with = "dir",
+        help = "Convert every file listed in LIST, one path per line relative to the directory of LIST, # for comments"
     )]
-    pub source_file: path::PathBuf,
+    pub file_list: Option<path::PathBuf>,
+
+    /// A directory whose `.rs` files are all converted, each with its sibling `stderr` file
+    #[arg(
+        long = "dir",
+        value_name = "DIR",
+        conflicts_with_all = ["source_file", "stderr_file", "stdout_file", "merge_stderr", "output", "in_place"],
+        requires = "output_dir",
+        help = "Convert every .rs file under DIR, with the .stderr file next to it if any, into --output-dir"
+    )]
+    pub dir: Option<path::PathBuf>,
+
+    /// Where the converted files are written, in the same layout as under `--strip-prefix`
+    #[arg(
+        long = "output-dir",
+        value_name = "OUTPUT_DIR",
+        conflicts_with_all = ["output", "in_place", "check"],
+        help = "Write the converted files under OUTPUT_DIR, at the same place as under --strip-prefix"
+    )]
+    pub output_dir: Option<path::PathBuf>,
+
+    /// How many files of `--dir` are converted at a time, one per CPU by default
+    #[arg(
+        short = 'j',
+        long = "jobs",
+        value_name = "N",
+        requires = "dir",
+        help = "Convert N files of --dir at a time [default: the number of CPUs]"
+    )]
+    pub jobs: Option<usize>,
+
+    #[arg(
+        long = "fail-fast",
+        requires = "dir",
+        help = "Stop converting the files of --dir at the first one which fails, the files in progress are finished"
+    )]
+    pub fail_fast: bool,
+
+    /// Only convert the files of `--dir` matching one of these patterns
+    #[arg(
+        long = "include",
+        value_name = "GLOB",
+        value_parser = globset::Glob::new,
+        requires = "dir",
+        help = "Only convert the files of --dir whose path relative to DIR matches GLOB, can be repeated"
+    )]
+    pub include: Vec<globset::Glob>,
+
+    /// Leave out the files of `--dir` matching one of these patterns, even included ones
+    #[arg(
+        long = "exclude",
+        value_name = "GLOB",
+        value_parser = globset::Glob::new,
+        requires = "dir",
+        help = "Leave out the files of --dir whose path relative to DIR matches GLOB, even if included, can be repeated"
+    )]
+    pub exclude: Vec<globset::Glob>,
+
+    /// The directory the inputs are placed relative to under `--output-dir`
+    #[arg(
+        long = "strip-prefix",
+        value_name = "PREFIX",
+        requires = "output_dir",
+        help = "The directory holding every input, mirrored by --output-dir [default: DIR, or the common parent directory of the FILEs]"
+    )]
+    pub strip_prefix: Option<path::PathBuf>,
 
     /// optional `stderr` file
     #[arg(
         short = 'e',
         long = "stderr",
         value_name = "STDERR_FILE",
-        help = "These file are used to extract rustc error codes, line/column numbers and convert them into DejaGnu format",
+        help = "These file are used to extract rustc error codes, line/column numbers and convert them into DejaGnu format, - for standard input",
         required = false
     )]
     pub stderr_file: Option<path::PathBuf>,
+
+    #[arg(
+        long = "no-stderr",
+        conflicts_with = "stderr_file",
+        help = "Do not use the .stderr file next to FILE when no stderr file is given, like a stale one"
+    )]
+    pub no_stderr: bool,
+
+    /// The expected output of the run of the test, converted into `dg-output` directives
+    #[arg(
+        long = "stdout-file",
+        value_name = "STDOUT_FILE",
+        help = "Expect the run of the test to print the content of STDOUT_FILE, like the .run.stdout file of a check-run-results test"
+    )]
+    pub stdout_file: Option<path::PathBuf>,
+
+    /// Where to write the converted file instead of `stdout`
+    #[arg(
+        short = 'o',
+        long = "output",
+        value_name = "OUTPUT_FILE",
+        help = "Write the converted file to OUTPUT_FILE instead of stdout, creating its directory if needed"
+    )]
+    pub output: Option<path::PathBuf>,
+
+    #[arg(
+        long = "force",
+        help = "Overwrite the files of --output and --output-dir which already exist, they are kept with a warning otherwise"
+    )]
+    pub force: bool,
+
+    /// Print what the conversion changes in FILE instead of the converted file
+    #[arg(
+        long = "diff",
+        conflicts_with_all = ["output", "in_place", "check", "dir", "output_dir"],
+        help = "Print a unified diff from FILE to its conversion instead of the converted file"
+    )]
+    pub diff: bool,
+
+    /// An already converted version of FILE, to check instead of writing anything
+    #[arg(
+        long = "check",
+        value_name = "CONVERTED_FILE",
+        conflicts_with_all = ["output", "in_place", "dir"],
+        help = "Check that CONVERTED_FILE is what FILE converts to, printing a unified diff and failing if not. Nothing is written."
+    )]
+    pub check: Option<path::PathBuf>,
+
+    /// The revision to convert, when FILE has a `revisions` header
+    #[arg(
+        long = "revision",
+        value_name = "REV",
+        conflicts_with_all = ["dir", "merge_stderr"],
+        help = "Only convert the annotations and headers of revision REV of FILE, instead of one section per revision (one file per revision under --output-dir)"
+    )]
+    pub revision: Option<String>,
+
+    #[arg(
+        long = "explicit-dg-do",
+        help = "Start compile-fail tests with `dg-do compile` as well, instead of relying on it being the default"
+    )]
+    pub explicit_dg_do: bool,
+
+    #[arg(
+        long = "explicit-edition",
+        help = "Keep `edition: 2015` headers as `-frust-edition=2015`, instead of dropping the default edition"
+    )]
+    pub explicit_edition: bool,
+
+    #[arg(
+        long = "edition",
+        value_name = "EDITION",
+        value_parser = clap::builder::PossibleValuesParser::new(header::EDITIONS),
+        help = "Convert every test for EDITION, as a `-frust-edition` at the top of the output which wins over the `edition` headers"
+    )]
+    pub edition: Option<String>,
+
+    #[arg(
+        long = "skip-known-bugs",
+        help = "Leave out the tests with a `known-bug` header, which check a rustc bug, with a warning instead of converting them"
+    )]
+    pub skip_known_bugs: bool,
+
+    #[arg(
+        long = "convert-anyway",
+        help = "Convert the proc-macro tests too, which are left out with a warning otherwise, under a warning comment"
+    )]
+    pub convert_anyway: bool,
+
+    /// Where the tests left out of a `--dir` conversion are listed
+    #[arg(
+        long = "skipped-report",
+        value_name = "FILE",
+        requires = "dir",
+        help = "Write the tests left out of the --dir conversion to FILE as JSON, with the category and the reason of each"
+    )]
+    pub skipped_report: Option<path::PathBuf>,
+
+    /// Overrides of the built-in policy of the `needs-*` headers
+    #[arg(
+        long = "needs-policy",
+        value_name = "FILE",
+        value_parser = NeedsPolicy::load,
+        help = "A TOML file of `needs-<capability> = \"drop\"` or `\"skip\"` entries, overriding what is done with those headers"
+    )]
+    pub needs_policy: Option<NeedsPolicy>,
+
+    #[arg(
+        long = "no-rustfix",
+        help = "Do not convert the `.fixed` file of a `run-rustfix` test into a `_fixed.rs` compile test next to it under --output-dir or --dir"
+    )]
+    pub no_rustfix: bool,
+
+    #[arg(
+        long = "in-place",
+        conflicts_with = "output",
+        help = "Rewrite FILE with the converted code, it is left untouched if the conversion fails"
+    )]
+    pub in_place: bool,
+
+    /// The suffix of the copy of the original file made by `--in-place`
+    #[arg(
+        long = "backup-suffix",
+        value_name = "SUFFIX",
+        default_value = ".orig",
+        requires = "in_place",
+        help = "Copy FILE to FILE with SUFFIX appended before rewriting it in place (an empty SUFFIX makes no copy), failing if that copy already exists"
+    )]
+    pub backup_suffix: String,
+
+    /// Merge mode: insert directives into an already-ported file using only the `stderr` file
+    #[arg(
+        long = "merge-stderr",
+        value_name = "STDERR_FILE",
+        help = "Insert directives into an already-ported FILE, using only the diagnostics found in STDERR_FILE",
+        conflicts_with = "stderr_file"
+    )]
+    pub merge_stderr: Option<path::PathBuf>,
+
+    #[command(flatten)]
+    pub render: RenderArguments,
+
+    #[arg(
+        long = "columns",
+        help = "Start the patterns with the column of the error in the stderr file, like `\"18: mismatched types\"`, so that the column is checked too"
+    )]
+    pub columns: bool,
+
+    #[arg(
+        long = "from-stderr",
+        conflicts_with = "merge_stderr",
+        help = "Generate the directives from the stderr file, below the lines it reports, even when FILE has `//~` annotations"
+    )]
+    pub from_stderr: bool,
+
+    /// Warn about generated lines longer than this, `0` disables the check
+    #[arg(
+        long = "max-line-length",
+        value_name = "N",
+        default_value_t = 0,
+        help = "Warn about generated lines longer than N characters (0 disables the check)"
+    )]
+    pub max_line_length: usize,
+
+    /// Expected run outputs longer than this are left for a manual conversion
+    #[arg(
+        long = "max-stdout-lines",
+        value_name = "N",
+        default_value_t = transform::DEFAULT_MAX_STDOUT_LINES,
+        help = "Warn about expected run outputs longer than N lines instead of converting them (0 disables the check)"
+    )]
+    pub max_stdout_lines: usize,
+
+    /// Source lines longer than this are not scanned for annotations, `0` disables the guard
+    #[arg(
+        long = "scan-line-limit",
+        value_name = "BYTES",
+        default_value_t = errors::DEFAULT_SCAN_LINE_LIMIT,
+        help = "Skip scanning source lines longer than BYTES for annotations, with a warning (0 disables the guard)"
+    )]
+    pub scan_line_limit: usize,
+
+    /// Fail on source files larger than this, `0` disables the limit
+    #[arg(
+        long = "max-file-size",
+        value_name = "BYTES",
+        default_value_t = Limits::DEFAULT_MAX_FILE_SIZE,
+        help = "Fail on source files larger than BYTES (0 disables the limit)"
+    )]
+    pub max_file_size: u64,
+
+    /// Fail on `stderr` files larger than this, `0` disables the limit
+    #[arg(
+        long = "max-stderr-size",
+        value_name = "BYTES",
+        default_value_t = Limits::DEFAULT_MAX_STDERR_SIZE,
+        help = "Fail on stderr files larger than BYTES (0 disables the limit)"
+    )]
+    pub max_stderr_size: u64,
+
+    /// Fail on source files with more annotations than this, `0` disables the limit
+    #[arg(
+        long = "max-annotations",
+        visible_alias = "max-errors",
+        value_name = "N",
+        default_value_t = Limits::DEFAULT_MAX_ANNOTATIONS,
+        help = "Fail on source files with more than N annotations (0 disables the limit)"
+    )]
+    pub max_annotations: usize,
+
+    /// Fail on source lines longer than this, `0` disables the limit
+    #[arg(
+        long = "max-input-line-length",
+        value_name = "BYTES",
+        default_value_t = Limits::DEFAULT_MAX_LINE_LENGTH,
+        help = "Fail on source lines longer than BYTES (0 disables the limit)"
+    )]
+    pub max_input_line_length: usize,
+
+    /// Report progress every N annotations, `0` disables the reports
+    #[arg(
+        long = "progress-every",
+        value_name = "N",
+        default_value_t = 0,
+        help = "Report on stderr every time N more annotations are loaded (0 disables the reports)"
+    )]
+    pub progress_every: usize,
+
+    /// What to do with the byte order mark of the source file
+    #[arg(
+        long = "bom",
+        value_enum,
+        default_value_t = BomMode::Keep,
+        help = "Whether a byte order mark at the start of FILE is kept in the output or stripped"
+    )]
+    pub bom: BomMode,
+
+    /// What is written: the converted file, or its annotations as JSON
+    #[arg(
+        long = "emit",
+        value_enum,
+        default_value_t = Emit::Text,
+        help = "What to write: the converted file, or the annotations of FILE as a JSON array"
+    )]
+    pub emit: Emit,
+
+    #[arg(
+        long = "stats",
+        help = "Print on stderr how many files and annotations were converted, by kind"
+    )]
+    pub stats: bool,
+
+    #[arg(
+        long = "stats-json",
+        help = "Print the counts of --stats on stderr as JSON"
+    )]
+    pub stats_json: bool,
+
+    #[arg(
+        short = 'v',
+        long = "verbose",
+        action = clap::ArgAction::Count,
+        help = "List on stderr every annotation found and every line skipped as one, twice to list the errors of the stderr file as well"
+    )]
+    pub verbose: u8,
+
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        conflicts_with = "verbose",
+        help = "Print nothing on stderr but the errors"
+    )]
+    pub quiet: bool,
+
+    #[arg(
+        long = "strict",
+        help = "Fail when the emitted directives do not match the annotations of FILE, instead of warning"
+    )]
+    pub strict: bool,
+
+    #[arg(
+        long = "strict-headers",
+        help = "Fail on the //@ headers compiletest does not know, instead of keeping them as `// UNCONVERTED:` comments, and on an edition header disagreeing with the --edition of the compile flags"
+    )]
+    pub strict_headers: bool,
+
+    #[arg(
+        long = "strict-stderr",
+        help = "Fail on the errors of the stderr file which match no annotation, instead of only warning"
+    )]
+    pub strict_stderr: bool,
+
+    #[arg(
+        long = "match-any-file",
+        help = "Match the annotations against the stderr entries of every file, instead of only the ones located in FILE, like the diagnostics of an auxiliary crate"
+    )]
+    pub match_any_file: bool,
+
+    #[arg(
+        long = "with-notes",
+        help = "Add the notes and help messages of the stderr file no annotation expects below the lines they report, the labels of the secondary spans included, as dg-note and dg-message directives"
+    )]
+    pub with_notes: bool,
+
+    #[arg(
+        long = "no-dedup",
+        help = "Keep the annotations repeating an earlier one of the same line, with the same kind, message and error code, for the diagnostics rustc emits twice"
+    )]
+    pub no_dedup: bool,
+
+    #[arg(
+        long = "line-numbers",
+        value_enum,
+        default_value_t = LineNumbers::Relative,
+        help = "How the directives refer to the line they expect a diagnostic on: by its offset from the directive, like `.-1`, or by its number in the emitted file"
+    )]
+    pub line_numbers: LineNumbers,
+
+    #[arg(
+        long = "keep-ignored-headers",
+        help = "Keep the incremental, pretty-printing and rustdoc headers which do not matter to gccrs, like `//@ incremental`, instead of dropping them"
+    )]
+    pub keep_ignored_headers: bool,
+
+    #[arg(long = "lint", help = "Warn about conversions which are likely wrong")]
+    pub lint: bool,
+
+    #[arg(
+        long = "deny",
+        value_name = "LINT",
+        value_enum,
+        help = "Fail on the findings of LINT instead of warning, implies --lint"
+    )]
+    pub deny: Vec<lint::Lint>,
 }
 
-pub fn parse_arguments_and_read_file(args: &Arguments) -> Result<(String, Option<String>)> {
-    //TODO: maybe to use sanitization to prevent reading files outside the project directory
-    let source_code = fs::read_to_string(&args.source_file)
-        .with_context(|| format!("could not read sourcefile `{}`", args.source_file.display()))?;
+impl Arguments {
+    /// The file to convert, which clap requires unless a directory is converted.
+    ///
+    /// Fails if several were given, [`run_files`] converts them one at a time.
+    pub fn source_file(&self) -> Result<&path::Path> {
+        match self.source_file.as_slice() {
+            [source_file] => Ok(source_file),
+            [] => anyhow::bail!("no FILE to convert was given"),
+            files => anyhow::bail!(
+                "{} FILEs were given where only one can be converted",
+                files.len()
+            ),
+        }
+    }
+
+    /// Where `--output-dir` puts each input, relative to `--strip-prefix`, `--dir` or the
+    /// common parent directory of the FILEs
+    pub fn mirror(&self) -> Result<Option<Mirror>> {
+        let Some(output_dir) = &self.output_dir else {
+            return Ok(None);
+        };
+        let prefix = match (&self.strip_prefix, &self.dir) {
+            (Some(prefix), _) => prefix.clone(),
+            (None, Some(dir)) => dir.clone(),
+            (None, None) => mirror::common_parent(&self.source_file)?,
+        };
+        Ok(Some(Mirror::new(output_dir, &prefix)?))
+    }
+
+    /// Builds the transformation options from the command line arguments
+    pub fn transform_options(&self) -> transform::Options {
+        transform::Options {
+            secondary: self.render.secondary(),
+            msg_style: self.render.msg_style(),
+            max_message_length: self.render.max_message_length,
+            columns: self.columns,
+            from_stderr: self.from_stderr,
+            max_line_length: self.max_line_length,
+            scan_line_limit: self.scan_line_limit,
+            limits: self.limits(),
+            progress_interval: self.progress_every,
+            progress: Some(errors::ProgressCallback(Arc::new({
+                let reporter = self.reporter();
+                move |progress| reporter.progress(progress)
+            }))),
+            strict: self.strict,
+            lint: self.lint,
+            deny: self.deny.clone(),
+            revision: self.revision.clone(),
+            explicit_dg_do: self.explicit_dg_do,
+            skip_known_bugs: self.skip_known_bugs,
+            convert_anyway: self.convert_anyway,
+            needs: self.needs_policy.clone().unwrap_or_default(),
+            explicit_edition: self.explicit_edition,
+            edition: self.edition.clone(),
+            max_stdout_lines: self.max_stdout_lines,
+            keep_ignored_headers: self.keep_ignored_headers,
+            strict_headers: self.strict_headers,
+            strict_stderr: self.strict_stderr,
+            // Set for each file converted, see `source_name`
+            source_name: None,
+            match_any_file: self.match_any_file,
+            with_notes: self.with_notes,
+            dedup: !self.no_dedup,
+            line_numbers: self.line_numbers,
+        }
+    }
 
-    // Read the stderr file if it exists
-    let err_file =
-        match &args.stderr_file {
-            Some(stderr_file) => Some(fs::read_to_string(stderr_file).with_context(|| {
-                format!("could not read stderr file `{}`", stderr_file.display())
-            })?),
-            None => None,
+    /// What is printed on `stderr`, raised by `--verbose` and lowered by `--quiet`
+    pub fn reporter(&self) -> Reporter {
+        let level = match (self.quiet, self.verbose) {
+            (true, _) => Level::Error,
+            (false, 0) => Level::Info,
+            (false, 1) => Level::Debug,
+            (false, _) => Level::Trace,
         };
+        Reporter { level }
+    }
+
+    /// Builds the resource caps from the command line arguments
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_file_size: self.max_file_size,
+            max_stderr_size: self.max_stderr_size,
+            max_annotations: self.max_annotations,
+            max_line_length: self.max_input_line_length,
+        }
+    }
+}
+
+/// Exit status when an input file could not be read
+pub const EXIT_INPUT: u8 = 2;
+/// Exit status when an annotation of the source file is malformed
+pub const EXIT_ANNOTATION: u8 = 3;
+/// Exit status when no error code of the `stderr` file matched an annotation
+pub const EXIT_UNMATCHED: u8 = 4;
+
+/// An input file which could not be read, the message names it
+#[derive(Debug)]
+pub struct InputError(String);
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The `stderr` file has error codes, but none of them matched an annotation of the source
+#[derive(Debug)]
+pub struct UnmatchedErrorCodes {
+    pub codes: usize,
+}
+
+impl fmt::Display for UnmatchedErrorCodes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "none of the {} error codes of the stderr file matched an annotation",
+            self.codes
+        )
+    }
+}
+
+impl std::error::Error for UnmatchedErrorCodes {}
+
+/// The exit status of the tool failing with `err`: [`EXIT_INPUT`], [`EXIT_ANNOTATION`],
+/// [`EXIT_UNMATCHED`], or 1 for anything else
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    if err.downcast_ref::<InputError>().is_some() {
+        EXIT_INPUT
+    } else if err.downcast_ref::<errors::AnnotationError>().is_some() {
+        EXIT_ANNOTATION
+    } else if err.downcast_ref::<UnmatchedErrorCodes>().is_some() {
+        EXIT_UNMATCHED
+    } else {
+        1
+    }
+}
+
+/// The length the lines of an [`errors::AnnotationError`] are cut at in its context
+const REPORTED_LINE_LENGTH: usize = 80;
+
+/// Adds the line of `code` an [`errors::AnnotationError`] is about to `err`, so that the
+/// annotation is seen without opening the file
+pub fn annotation_context(err: anyhow::Error, code: &str) -> anyhow::Error {
+    let Some(line) = err
+        .downcast_ref::<errors::AnnotationError>()
+        .map(errors::AnnotationError::line)
+    else {
+        return err;
+    };
+    let Some(content) = line
+        .checked_sub(1)
+        .and_then(|index| code.lines().nth(index))
+    else {
+        return err;
+    };
+    let content = content.trim();
+    let content = match content.char_indices().nth(REPORTED_LINE_LENGTH) {
+        Some((end, _)) => format!("{}...", &content[..end]),
+        None => content.to_owned(),
+    };
+    err.context(format!("in `{content}`"))
+}
+
+/// Fails with [`UnmatchedErrorCodes`] if the `stderr` file has error codes but `matched`,
+/// the number of annotations given one, is zero
+fn check_error_codes(
+    stderr_code: Option<&str>,
+    matched: usize,
+    options: &transform::Options,
+) -> Result<()> {
+    let codes = stderr_code.map_or(0, |stderr_code| {
+        errors::parse_stderr(stderr_code)
+            .iter()
+            .filter(|result| result.code.is_some() && result.is_in(options.stderr_source_name()))
+            .count()
+    });
+    if codes > 0 && matched == 0 {
+        return Err(UnmatchedErrorCodes { codes }.into());
+    }
+    Ok(())
+}
+
+/// Reads the source file and the `stderr` file named by `args`, either of them may be `-`
+/// for standard input
+pub fn parse_arguments_and_read_file(args: &Arguments) -> Result<(Input, Option<Input>)> {
+    //TODO: maybe to use sanitization to prevent reading files outside the project directory
+    let limits = args.limits();
+    let source_file = args.source_file()?;
+    // In merge mode the `stderr` file is the only source of errors
+    let discovered = match (&args.stderr_file, &args.merge_stderr) {
+        (None, None) if !args.no_stderr => discover_stderr(source_file),
+        _ => None,
+    };
+    if let Some(discovered) = &discovered {
+        args.reporter().info(format_args!(
+            "note: using the stderr file `{}` found next to FILE, --no-stderr to leave it out",
+            discovered.display()
+        ));
+    }
+    let stderr_file = args
+        .stderr_file
+        .as_deref()
+        .or(args.merge_stderr.as_deref())
+        .or(discovered.as_deref());
+    if input::is_stdin(source_file) && stderr_file.is_some_and(input::is_stdin) {
+        anyhow::bail!("FILE and the stderr file cannot both be read from standard input");
+    }
+
+    let source_code =
+        read_input(source_file, |size| limits.check_file_size(size)).with_context(|| {
+            InputError(format!(
+                "could not read sourcefile {}",
+                input::display_name(source_file)
+            ))
+        })?;
+    let err_file = match stderr_file {
+        Some(stderr_file) => Some(
+            read_input(stderr_file, |size| limits.check_stderr_size(size)).with_context(|| {
+                InputError(format!(
+                    "could not read stderr file {}",
+                    input::display_name(stderr_file)
+                ))
+            })?,
+        ),
+        None => None,
+    };
 
     Ok((source_code, err_file))
 }
 
-pub fn print_source_code(source_code: &str) {
-    println!("{source_code}");
+/// The `stderr` file next to `source_file`, with the same stem, if there is one.
+///
+/// The `stderr` files of revisions, like `test.rev1.stderr`, are never picked: there is no
+/// telling which one the conversion should use.
+pub fn discover_stderr(source_file: &path::Path) -> Option<path::PathBuf> {
+    if input::is_stdin(source_file) {
+        return None;
+    }
+    let stderr_file = source_file.with_extension("stderr");
+    stderr_file.is_file().then_some(stderr_file)
+}
+
+/// Reads `path`, or standard input for `-`, failing if `check_size` rejects its size.
+///
+/// Files are checked before reading anything, standard input once it is read.
+fn read_input(
+    path: &path::Path,
+    check_size: impl Fn(u64) -> Result<(), LimitError>,
+) -> Result<Input> {
+    if input::is_stdin(path) {
+        let content = input::read_from(io::stdin().lock())?;
+        check_size(content.len() as u64)?;
+        return Ok(content);
+    }
+    check_size(fs::metadata(path).map_or(0, |metadata| metadata.len()))?;
+    Ok(input::read(path)?)
+}
+
+/// Converts the file named by `args` and writes the result to the `--output` file, over
+/// the file itself with `--in-place`, or to `output` otherwise. With `--check`, the diff
+/// from the converted file to the result is written to `output` instead, if any.
+///
+/// This is the whole command line tool except for argument parsing and printing the report,
+/// malformed inputs end up as an error here rather than a panic.
+pub fn run(args: &Arguments, output: &mut impl io::Write) -> Result<TransformReport> {
+    if let Some(mirror) = args.mirror()? {
+        let source_file = args.source_file()?;
+        if input::is_stdin(source_file) {
+            anyhow::bail!("standard input has no place under --output-dir, use --output");
+        }
+        let args = Arguments {
+            output: Some(mirror.output_path(source_file)?),
+            output_dir: None,
+            strip_prefix: None,
+            ..args.clone()
+        };
+        return run_mirrored(&args, output, &mut BTreeSet::new());
+    }
+    if args.emit == Emit::Json && (args.check.is_some() || args.in_place || args.diff) {
+        anyhow::bail!("--emit json only writes to stdout or --output");
+    }
+    if args.diff {
+        let (code, stderr_code) = parse_arguments_and_read_file(args)?;
+        let mut new_code = Vec::new();
+        let report = convert_code(args, &code, stderr_code, &mut new_code)?;
+        if report.skipped.is_some() {
+            return Ok(report);
+        }
+        let new_code = String::from_utf8(new_code)?;
+        // The byte order mark is not part of the lines of `code` either
+        let new_code = new_code.strip_prefix(input::BOM).unwrap_or(&new_code);
+        let name = args.source_file()?.display().to_string();
+        print_diff(&name, &code, new_code, output)?;
+        return Ok(report);
+    }
+    if let Some(converted_file) = &args.check {
+        let mut new_code = Vec::new();
+        let report = convert(args, &mut new_code)?;
+        if report.skipped.is_none() {
+            check_up_to_date(converted_file, &String::from_utf8(new_code)?, output)?;
+        }
+        return Ok(report);
+    }
+    if args.in_place {
+        let source_file = args.source_file()?;
+        if input::is_stdin(source_file) {
+            anyhow::bail!("standard input cannot be rewritten in place");
+        }
+        let mut new_code = Vec::new();
+        let report = convert(args, &mut new_code)?;
+        if report.skipped.is_some() {
+            return Ok(report);
+        }
+        // A link is followed, the file it names is the one rewritten
+        let target = fs::canonicalize(source_file)
+            .with_context(|| format!("could not resolve `{}`", source_file.display()))?;
+        // A file converted already is left alone, along with its backup
+        if fs::read(&target).is_ok_and(|old_code| old_code == new_code) {
+            return Ok(report);
+        }
+        if !args.backup_suffix.is_empty() {
+            let mut backup = source_file.as_os_str().to_owned();
+            backup.push(&args.backup_suffix);
+            let backup = path::PathBuf::from(backup);
+            // Another run made it, from the original file
+            if backup.exists() {
+                anyhow::bail!(
+                    "the backup `{}` already exists, refusing to overwrite it",
+                    backup.display()
+                );
+            }
+            fs::copy(&target, &backup)
+                .with_context(|| format!("could not back up `{}`", source_file.display()))?;
+        }
+        replace_file(&target, &new_code)?;
+        return Ok(report);
+    }
+    let Some(output_file) = &args.output else {
+        let report = convert(args, output)?;
+        // The output always ended with an empty line, keep it that way for existing scripts
+        if args.emit == Emit::Text && report.skipped.is_none() {
+            writeln!(output)?;
+        }
+        output.flush()?;
+        return Ok(report);
+    };
+    if same_file(output_file, args.source_file()?) {
+        anyhow::bail!(
+            "the output file `{}` is the source file, refusing to overwrite it",
+            output_file.display()
+        );
+    }
+    let mut new_code = Vec::new();
+    let report = convert(args, &mut new_code)?;
+    if report.skipped.is_none() {
+        if keeps_existing(args, output_file) {
+            args.reporter()
+                .warn(format_args!("{}", kept_warning(output_file)));
+        } else {
+            write_source_code(output_file, &String::from_utf8(new_code)?)?;
+        }
+    }
+    Ok(report)
+}
+
+/// [`run`] with `--output` in the `--output-dir`, converting the `aux-build` files of the
+/// test along with it. `written` holds the auxiliary files written so far, the tests of a
+/// directory share them.
+fn run_mirrored(
+    args: &Arguments,
+    output: &mut impl io::Write,
+    written: &mut BTreeSet<path::PathBuf>,
+) -> Result<TransformReport> {
+    let source_file = args.source_file()?;
+    let code = read_source(source_file)?;
+    let auxiliary = auxiliary_sources(source_file, &code)?;
+    let Some(output_file) = &args.output else {
+        return run(args, output);
+    };
+    let revisions = match &args.revision {
+        Some(_) => Vec::new(),
+        None => header::revisions(&code),
+    };
+    let report = if revisions.is_empty() {
+        run(args, output)?
+    } else {
+        // One file per revision, in place of the file of the test
+        let mut report: Option<TransformReport> = None;
+        for revision in revisions {
+            let args = Arguments {
+                output: Some(revision_path(output_file, &revision)),
+                revision: Some(revision),
+                ..args.clone()
+            };
+            let revision_report = run(&args, output)?;
+            match &mut report {
+                Some(report) => report.add_revision(revision_report),
+                None => report = Some(revision_report),
+            }
+        }
+        report.unwrap_or_default()
+    };
+    let mut report = report;
+    if report.skipped.is_none() {
+        write_auxiliary(args, &auxiliary, output_file, written)?;
+        if let Some(warning) = write_fixed(args, source_file, &code, output_file, written)? {
+            report.warnings.push(warning);
+        }
+    }
+    Ok(report)
+}
+
+/// Where the conversion of `revision` of the test converted into `output_file` goes, like
+/// `test.rev.rs` for `test.rs`
+pub fn revision_path(output_file: &path::Path, revision: &str) -> path::PathBuf {
+    output_file.with_extension(format!("{revision}.rs"))
+}
+
+fn read_source(source_file: &path::Path) -> Result<Input> {
+    input::read(source_file).with_context(|| {
+        InputError(format!(
+            "could not read sourcefile `{}`",
+            source_file.display()
+        ))
+    })
+}
+
+/// The auxiliary files of the `aux-build` headers of `code`, the content of `source_file`,
+/// each with its path relative to the `auxiliary` directory. Fails if one of them does not
+/// exist.
+fn auxiliary_sources(source_file: &path::Path, code: &str) -> Result<Vec<(path::PathBuf, String)>> {
+    let dir = source_file.with_file_name(header::AUXILIARY_DIR);
+    header::aux_build_files(code)
+        .into_iter()
+        .map(|name| {
+            let source = dir.join(&name);
+            if !source.is_file() {
+                return Err(anyhow::Error::msg(InputError(format!(
+                    "the auxiliary file `{}` of `aux-build: {name}` in `{}` does not exist",
+                    source.display(),
+                    source_file.display()
+                ))));
+            }
+            Ok((source, name))
+        })
+        .collect()
+}
+
+/// Converts each of `auxiliary` into the `auxiliary` directory next to `output_file`, unless
+/// it is in `written` already. A file without directives is copied as it is.
+fn write_auxiliary(
+    args: &Arguments,
+    auxiliary: &[(path::PathBuf, String)],
+    output_file: &path::Path,
+    written: &mut BTreeSet<path::PathBuf>,
+) -> Result<()> {
+    let dir = output_file.with_file_name(header::AUXILIARY_DIR);
+    for (source, name) in auxiliary {
+        let aux_output = dir.join(name);
+        if !written.insert(aux_output.clone()) {
+            continue;
+        }
+        if keeps_existing(args, &aux_output) {
+            args.reporter()
+                .warn(format_args!("{}", kept_warning(&aux_output)));
+            continue;
+        }
+        let code = input::read(source).with_context(|| {
+            InputError(format!(
+                "could not read auxiliary file `{}`",
+                source.display()
+            ))
+        })?;
+        // An auxiliary file is compiled along with the test, never on its own
+        let options = transform::Options {
+            explicit_dg_do: false,
+            ..args.transform_options()
+        };
+        let (new_code, _) =
+            transform::transform_code(&code, None, &options).with_context(|| {
+                format!("could not transform auxiliary file `{}`", source.display())
+            })?;
+        write_source_code(&aux_output, &new_code)?;
+    }
+    Ok(())
+}
+
+/// Where the conversion of the `.fixed` file of the test converted into `output_file` goes,
+/// like `test_fixed.rs` for `test.rs`
+pub fn fixed_path(output_file: &path::Path) -> path::PathBuf {
+    let stem = output_file
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy();
+    output_file.with_file_name(format!("{stem}_fixed.rs"))
+}
+
+/// Converts the `.fixed` file of `source_file`, a `run-rustfix` test, into a compile test at
+/// the [`fixed_path`] of `output_file`, unless it is in `written` already. A missing `.fixed`
+/// file is not an error, the warning about it is returned instead.
+fn write_fixed(
+    args: &Arguments,
+    source_file: &path::Path,
+    code: &str,
+    output_file: &path::Path,
+    written: &mut BTreeSet<path::PathBuf>,
+) -> Result<Option<String>> {
+    if args.no_rustfix || !header::runs_rustfix(code) {
+        return Ok(None);
+    }
+    let fixed = source_file.with_extension("fixed");
+    if !fixed.is_file() {
+        return Ok(Some(format!(
+            "`{}` runs rustfix but has no `{}` file, only the test is converted",
+            source_file.display(),
+            fixed.display()
+        )));
+    }
+    let fixed_output = fixed_path(output_file);
+    if !written.insert(fixed_output.clone()) {
+        return Ok(None);
+    }
+    if keeps_existing(args, &fixed_output) {
+        args.reporter()
+            .warn(format_args!("{}", kept_warning(&fixed_output)));
+        return Ok(None);
+    }
+    let fixed_code = input::read(&fixed)
+        .with_context(|| InputError(format!("could not read fixed file `{}`", fixed.display())))?;
+    // The fixed code compiles, without the annotations of the errors it fixed
+    let options = transform::Options {
+        explicit_dg_do: true,
+        ..args.transform_options()
+    };
+    let (new_code, _) = transform::transform_code(&fixed_code, None, &options)
+        .with_context(|| format!("could not transform fixed file `{}`", fixed.display()))?;
+    let name = |path: &path::Path| path.file_name().unwrap_or_default().display().to_string();
+    write_source_code(
+        &fixed_output,
+        &format!(
+            "// `{}` once fixed by rustfix, converted from `{}`\n{new_code}",
+            name(source_file),
+            name(&fixed)
+        ),
+    )?;
+    Ok(None)
+}
+
+/// Whether `output_file` already exists and is left alone, without `--force`
+fn keeps_existing(args: &Arguments, output_file: &path::Path) -> bool {
+    !args.force && output_file.exists()
+}
+
+/// The warning about `output_file`, which already exists and was not overwritten
+pub fn kept_warning(output_file: &path::Path) -> String {
+    format!(
+        "warning: `{}` already exists, not overwriting it without --force",
+        output_file.display()
+    )
+}
+
+/// Converts each FILE and each file of `--file-list` in turn like [`run`], writing the result
+/// of each to `output` after a `// ===== path =====` line unless they go under
+/// `--output-dir`, and what happened to each of them to `log`.
+///
+/// Every file gets its own `stderr` file next to it, so there is no single `--stderr`,
+/// `--merge-stderr`, `--output` nor `--check` file to go with them. A file which fails to
+/// convert does not stop the others, the error comes once they are all done.
+pub fn run_files(
+    args: &Arguments,
+    output: &mut impl io::Write,
+    log: &mut impl io::Write,
+) -> Result<()> {
+    if args.stderr_file.is_some()
+        || args.merge_stderr.is_some()
+        || args.stdout_file.is_some()
+        || args.output.is_some()
+        || args.check.is_some()
+    {
+        anyhow::bail!(
+            "--stderr, --merge-stderr, --stdout-file, --output and --check take a single FILE"
+        );
+    }
+    let mut source_files = args.source_file.clone();
+    let mut missing = Vec::new();
+    if let Some(list) = &args.file_list {
+        let entries = batch::read_file_list(list).with_context(|| {
+            InputError(format!("could not read file list `{}`", list.display()))
+        })?;
+        for (line_num, source_file) in entries {
+            if source_file.is_file() {
+                source_files.push(source_file);
+            } else {
+                missing.push(format!(
+                    "{}:{line_num}: `{}` does not exist",
+                    list.display(),
+                    source_file.display()
+                ));
+            }
+        }
+    }
+    let args = &Arguments {
+        source_file: source_files,
+        file_list: None,
+        ..args.clone()
+    };
+    let mirror = args.mirror()?;
+    // Every file is checked before converting any of them
+    let outputs = match &mirror {
+        Some(mirror) => args
+            .source_file
+            .iter()
+            .map(|source_file| mirror.output_path(source_file).map(Some))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![None; args.source_file.len()],
+    };
+    let mut failed = missing.len();
+    for missing in &missing {
+        writeln!(log, "error: {missing}")?;
+    }
+    // Without the notes and warnings, the errors name the file they are about
+    let info = args.reporter().enabled(Level::Info);
+    let mut stats = Stats::default();
+    let mut kept = 0;
+    let mut auxiliary = BTreeSet::new();
+    for (source_file, mut output_file) in args.source_file.iter().zip(outputs) {
+        let written = output_file.is_some() || args.in_place;
+        if info {
+            writeln!(log, "=== {} ===", source_file.display())?;
+        }
+        // The file is still converted, for its errors and counts
+        if let Some(existing) = output_file.take_if(|output_file| keeps_existing(args, output_file))
+        {
+            kept += 1;
+            if args.reporter().enabled(Level::Warn) {
+                writeln!(log, "{}", kept_warning(&existing))?;
+            }
+        }
+        let file_args = Arguments {
+            source_file: vec![source_file.clone()],
+            output: output_file,
+            output_dir: None,
+            strip_prefix: None,
+            ..args.clone()
+        };
+        // Nothing of a file which fails half way through goes to `output`
+        let mut new_code = Vec::new();
+        let result = if mirror.is_some() {
+            run_mirrored(&file_args, &mut new_code, &mut auxiliary)
+        } else {
+            run(&file_args, &mut new_code)
+        };
+        match result {
+            Ok(report) => {
+                if !written && report.skipped.is_none() {
+                    writeln!(output, "// ===== {} =====", source_file.display())?;
+                    output.write_all(&new_code)?;
+                }
+                if info {
+                    write_report(&report, log)?;
+                }
+                stats.merge(&report.stats);
+            }
+            Err(err) => {
+                failed += 1;
+                writeln!(log, "error: {err:#}")?;
+            }
+        }
+    }
+    if kept > 0 && info {
+        writeln!(log, "{kept} existing files kept, --force overwrites them")?;
+    }
+    write_stats(args, &stats, log)?;
+    output.flush()?;
+    log.flush()?;
+    if failed > 0 {
+        anyhow::bail!(
+            "{failed} of {} files failed to convert",
+            args.source_file.len() + missing.len()
+        );
+    }
+    Ok(())
+}
+
+/// Writes the unified diff from `code`, the content of the file `name`, to `new_code`, its
+/// conversion, with [`diff::CONTEXT`] lines around each change. Nothing is written if the
+/// conversion changed nothing.
+pub fn print_diff(
+    name: &str,
+    code: &str,
+    new_code: &str,
+    output: &mut impl io::Write,
+) -> io::Result<()> {
+    let diff = diff::unified(&format!("a/{name}"), &format!("b/{name}"), code, new_code);
+    output.write_all(diff.as_bytes())?;
+    output.flush()
+}
+
+/// Fails if `converted_file` is not `new_code`, after writing their diff to `output`. The
+/// output written on `stdout`, ending with an empty line, is up to date too.
+fn check_up_to_date(
+    converted_file: &path::Path,
+    new_code: &str,
+    output: &mut impl io::Write,
+) -> Result<()> {
+    let converted = input::read(converted_file).with_context(|| {
+        InputError(format!(
+            "could not read converted file `{}`",
+            converted_file.display()
+        ))
+    })?;
+    if *converted == *new_code || converted.strip_suffix('\n') == Some(new_code) {
+        return Ok(());
+    }
+    let name = converted_file.display().to_string();
+    let diff = diff::unified(
+        &name,
+        &format!("{name} (regenerated)"),
+        &converted,
+        new_code,
+    );
+    output.write_all(diff.as_bytes())?;
+    output.flush()?;
+    if diff.is_empty() {
+        anyhow::bail!("`{name}` is out of date, its line endings differ");
+    }
+    anyhow::bail!("`{name}` is out of date, the diff above regenerates it");
+}
+
+/// Writes the converted `code` to `path`, creating its parent directories if needed
+pub fn write_source_code(path: &path::Path, code: &str) -> Result<()> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("could not create directory `{}`", parent.display()))?;
+    }
+    fs::write(path, code).with_context(|| format!("could not write `{}`", path.display()))
+}
+
+/// Replaces the content of `path` at once, through a temporary file next to it, so that it
+/// is never left half written
+fn replace_file(path: &path::Path, content: &[u8]) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".rusttest-to-dg.tmp");
+    let temporary = path::PathBuf::from(temporary);
+    let replaced = fs::write(&temporary, content)
+        .and_then(|()| fs::set_permissions(&temporary, fs::metadata(path)?.permissions()))
+        .and_then(|()| fs::rename(&temporary, path));
+    if replaced.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+    replaced.with_context(|| format!("could not rewrite `{}`", path.display()))
+}
+
+/// Whether `a` and `b` name the same file, through links and relative paths if they exist
+fn same_file(a: &path::Path, b: &path::Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Converts the file named by `args`, writing the result to `output`
+fn convert(args: &Arguments, output: &mut impl io::Write) -> Result<TransformReport> {
+    let (code, stderr_code) = parse_arguments_and_read_file(args)?;
+    convert_code(args, &code, stderr_code, output)
+}
+
+/// The file name the entries of the `stderr` file of `source_file` must be located in, none
+/// for standard input
+pub fn source_name(source_file: &path::Path) -> Option<String> {
+    if input::is_stdin(source_file) {
+        return None;
+    }
+    Some(source_file.file_name()?.to_string_lossy().into_owned())
+}
+
+/// Converts `code`, read from the file named by `args`, writing the result to `output`
+fn convert_code(
+    args: &Arguments,
+    code: &Input,
+    stderr_code: Option<Input>,
+    output: &mut impl io::Write,
+) -> Result<TransformReport> {
+    let source_file = args.source_file()?;
+
+    let options = transform::Options {
+        source_name: source_name(source_file),
+        ..args.transform_options()
+    };
+    let context = || {
+        if input::is_stdin(source_file) {
+            "could not transform code from standard input".to_owned()
+        } else {
+            format!(
+                "could not transform code from file `{}`",
+                source_file.display()
+            )
+        }
+    };
+
+    let name = if input::is_stdin(source_file) {
+        "standard input".to_owned()
+    } else {
+        format!("`{}`", source_file.display())
+    };
+    if let Some(report) = transform::skipped(code, &name, &options) {
+        return Ok(report);
+    }
+
+    if args.emit == Emit::Json {
+        let (errors, warnings, stats) = match (&args.merge_stderr, stderr_code) {
+            (Some(_), Some(stderr_code)) => {
+                let errors = errors::load_stderr_errors(&stderr_code, options.stderr_source_name());
+                let mut stats = Stats {
+                    files: 1,
+                    ..Stats::default()
+                };
+                errors.iter().for_each(|error| stats.add_error(error));
+                (errors, Vec::new(), stats)
+            }
+            (_, stderr_code) => {
+                let stderr = stderr_code.as_deref().map(errors::parse_stderr);
+                let loaded = transform::load_annotations(code, stderr.as_deref(), &options)
+                    .map_err(|err| annotation_context(err, code))
+                    .with_context(context)?;
+                let stats = Stats::of_file(&loaded);
+                check_error_codes(stderr_code.as_deref(), stats.error_codes, &options)?;
+                (loaded.errors, loaded.warnings, stats)
+            }
+        };
+        serde_json::to_writer_pretty(&mut *output, &errors)?;
+        writeln!(output)?;
+        return Ok(TransformReport {
+            warnings,
+            stats,
+            ..TransformReport::default()
+        });
+    }
+
+    // The content never includes the byte order mark, it only goes back into the output
+    if code.has_bom() && args.bom == BomMode::Keep {
+        output.write_all(input::BOM.as_bytes())?;
+    }
+
+    let mut report = match (&args.merge_stderr, stderr_code.as_deref()) {
+        (Some(_), Some(stderr_code)) => {
+            options.limits.check_source(code)?;
+            options.limits.check_stderr_size(stderr_code.len() as u64)?;
+            let (new_code, report) = merge::merge_stderr(code, stderr_code, &options);
+            output.write_all(new_code.as_bytes())?;
+            report
+        }
+        (_, stderr_code) => {
+            let revisions = header::revisions(code);
+            if let Some(revision) = &args.revision {
+                if !revisions.contains(revision) {
+                    anyhow::bail!(
+                        "`{revision}` is not one of the revisions of `{}`: {}",
+                        source_file.display(),
+                        revisions.join(", ")
+                    );
+                }
+            }
+            let report = if revisions.is_empty() || args.revision.is_some() {
+                transform::transform_to(output, code, stderr_code, &options)
+                    .map_err(|err| annotation_context(err, code))
+                    .with_context(context)?
+            } else if args.in_place {
+                anyhow::bail!(
+                    "`{}` has revisions, pick the one to rewrite it with using --revision",
+                    source_file.display()
+                );
+            } else {
+                transform_revisions(output, code, stderr_code, &options, &revisions)
+                    .map_err(|err| annotation_context(err, code))
+                    .with_context(context)?
+            };
+            check_error_codes(stderr_code, report.stats.error_codes, &options)?;
+            report
+        }
+    };
+    if let Some(stdout_file) = &args.stdout_file {
+        let stdout = input::read(stdout_file).with_context(|| {
+            InputError(format!(
+                "could not read stdout file `{}`",
+                stdout_file.display()
+            ))
+        })?;
+        let name = format!("`{}`", stdout_file.display());
+        transform::write_run_output(output, code, &stdout, &name, &options, &mut report)?;
+    }
+    let reporter = args.reporter();
+    if reporter.enabled(Level::Debug) {
+        let merge = args.merge_stderr.is_some();
+        write_annotations(
+            reporter.level,
+            code,
+            stderr_code.as_deref(),
+            merge,
+            &options,
+            &mut io::stderr().lock(),
+        )?;
+    }
+    Ok(report)
+}
+
+/// Converts each of the `revisions` of `code` in turn, after a `// ===== revision REV =====`
+/// line. Nothing goes to `output` unless every revision converts.
+fn transform_revisions(
+    output: &mut impl io::Write,
+    code: &str,
+    stderr_code: Option<&str>,
+    options: &transform::Options,
+    revisions: &[String],
+) -> Result<TransformReport> {
+    use io::Write as _;
+    let mut report: Option<TransformReport> = None;
+    let mut new_code = Vec::new();
+    for revision in revisions {
+        writeln!(new_code, "// ===== revision {revision} =====")?;
+        let options = transform::Options {
+            revision: Some(revision.clone()),
+            ..options.clone()
+        };
+        let revision_report = transform::transform_to(&mut new_code, code, stderr_code, &options)?;
+        match &mut report {
+            Some(report) => report.add_revision(revision_report),
+            None => report = Some(revision_report),
+        }
+    }
+    output.write_all(&new_code)?;
+    Ok(report.unwrap_or_default())
+}
+
+/// Writes one line per annotation loaded from `code`, or from the `stderr` file alone when
+/// merging, and one per line skipped although it looked like an annotation. At the
+/// [`Level::Trace`] `level`, every error found in the `stderr` file follows.
+pub fn write_annotations(
+    level: Level,
+    code: &str,
+    stderr_code: Option<&str>,
+    merge: bool,
+    options: &transform::Options,
+    writer: &mut impl io::Write,
+) -> Result<()> {
+    let stderr = stderr_code.map(errors::parse_stderr);
+    let loaded = match stderr_code {
+        Some(stderr_code) if merge => errors::LoadedErrors {
+            errors: errors::load_stderr_errors(stderr_code, options.stderr_source_name()),
+            ..errors::LoadedErrors::default()
+        },
+        _ => {
+            // The conversion already reported the progress of loading them
+            let options = transform::Options {
+                progress: None,
+                ..options.clone()
+            };
+            transform::load_annotations(code, stderr.as_deref(), &options)?
+        }
+    };
+    for error in &loaded.errors {
+        let code = match &error.error_code {
+            Some(error_code) => format!("code {error_code}"),
+            None => "no code".to_owned(),
+        };
+        writeln!(
+            writer,
+            "line {}: {}, {code}, msg '{}'",
+            error.line_num,
+            errors::dg_directive(error.kind),
+            error.msg
+        )?;
+    }
+    for skipped in &loaded.skipped {
+        writeln!(writer, "line {}: skipped, {}", skipped.line, skipped.reason)?;
+    }
+    for ambiguity in &loaded.ambiguities {
+        writeln!(writer, "{ambiguity}")?;
+    }
+    if loaded.other_files > 0 {
+        writeln!(
+            writer,
+            "{} stderr entries located in another file than the source, left out",
+            loaded.other_files
+        )?;
+    }
+    for (line, header) in header::unconverted_headers(code) {
+        writeln!(writer, "line {line}: unconverted header `//{header}`")?;
+    }
+    if level >= Level::Trace {
+        for result in stderr.iter().flatten() {
+            let name = match &result.code {
+                Some(code) => code.clone(),
+                None => result.level.to_string(),
+            };
+            let lint = match &result.lint {
+                Some(lint) => format!(", lint {lint}"),
+                None => String::new(),
+            };
+            writeln!(
+                writer,
+                "stderr line {}: {name}{lint}, msg '{}'",
+                errors::line_label(result.line_number),
+                result.message
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Converts every `.rs` file under `--dir` into `--output-dir`, at the same place as under
+/// `--strip-prefix` or `--dir`, and writes what happened to each of them to `log`.
+///
+/// A file which fails to convert does not stop the others, the error comes once they are
+/// all done.
+pub fn run_dir(args: &Arguments, log: &mut impl io::Write) -> Result<BatchOutcome> {
+    let (Some(dir), Some(mirror)) = (&args.dir, args.mirror()?) else {
+        anyhow::bail!("converting a directory needs both --dir and --output-dir");
+    };
+    let jobs =
+        batch::discover(dir).with_context(|| format!("could not walk `{}`", dir.display()))?;
+    let filter = Filter::new(&args.include, &args.exclude)?;
+    let (jobs, excluded): (Vec<_>, Vec<_>) = jobs.into_iter().partition(|job| {
+        filter.is_selected(
+            job.source_file
+                .strip_prefix(dir)
+                .unwrap_or(&job.source_file),
+        )
+    });
+    if !excluded.is_empty() && args.reporter().enabled(Level::Debug) {
+        writeln!(log, "{} files excluded:", excluded.len())?;
+        for job in &excluded {
+            writeln!(log, "  {}", job.source_file.display())?;
+        }
+    }
+    // Every file is checked before converting any of them
+    let mut outputs = jobs
+        .iter()
+        .map(|job| {
+            Ok((
+                job.source_file.clone(),
+                mirror.output_path(&job.source_file)?,
+            ))
+        })
+        .collect::<Result<BTreeMap<_, _>, mirror::OutsidePrefix>>()?;
+    let workers = args.jobs.unwrap_or_else(batch::default_jobs);
+    let mut outcome = batch::convert(&jobs, workers, &args.transform_options(), args.fail_fast);
+    // A file which already exists does not stop the others from being written
+    let mut written = BTreeSet::new();
+    for file in &mut outcome.files {
+        let output_file = outputs.remove(&file.job.source_file).unwrap_or_default();
+        let Ok((new_code, report)) = &file.result else {
+            continue;
+        };
+        if report.skipped.is_some() {
+            continue;
+        }
+        let source_file = file.job.source_file.clone();
+        let sources = read_source(&source_file).and_then(|code| {
+            let auxiliary = auxiliary_sources(&source_file, &code)?;
+            Ok((code, auxiliary))
+        });
+        let (code, auxiliary) = match sources {
+            Ok(sources) => sources,
+            Err(err) => {
+                file.result = Err(err);
+                continue;
+            }
+        };
+        // One file per revision, in place of the file of the test
+        let converted = if file.revisions.is_empty() {
+            vec![(output_file.clone(), new_code)]
+        } else {
+            file.revisions
+                .iter()
+                .map(|(revision, new_code)| (revision_path(&output_file, revision), new_code))
+                .collect()
+        };
+        for (path, new_code) in converted {
+            // Auxiliary files are tests of the directory as well, converted only once
+            if written.insert(path.clone()) {
+                if keeps_existing(args, &path) {
+                    file.kept.get_or_insert(path);
+                } else {
+                    write_source_code(&path, new_code)?;
+                }
+            }
+        }
+        let written_too = write_auxiliary(args, &auxiliary, &output_file, &mut written)
+            .and_then(|()| write_fixed(args, &source_file, &code, &output_file, &mut written));
+        match (written_too, &mut file.result) {
+            (Err(err), result) => *result = Err(err),
+            (Ok(Some(warning)), Ok((_, report))) => report.warnings.push(warning),
+            _ => {}
+        }
+    }
+    if args.reporter().enabled(Level::Info) {
+        outcome.write_log(log)?;
+    } else {
+        outcome.write_errors(log)?;
+    }
+    write_stats(args, &outcome.stats(), log)?;
+    log.flush()?;
+    if let Some(report) = &args.skipped_report {
+        let skipped = serde_json::to_string_pretty(&outcome.skip_records())?;
+        fs::write(report, skipped + "\n").with_context(|| {
+            format!("could not write the skipped report `{}`", report.display())
+        })?;
+    }
+    if args.fail_fast {
+        if let Some(index) = outcome.files.iter().position(|file| file.result.is_err()) {
+            let file = outcome.files.remove(index);
+            let converted = outcome.converted();
+            return Err(file.result.unwrap_err().context(format!(
+                "--fail-fast stopped at `{}`, the {converted} files converted so far are kept in `{}`",
+                file.job.source_file.display(),
+                args.output_dir.as_deref().unwrap_or(dir).display()
+            )));
+        }
+    }
+    if outcome.failed() > 0 {
+        anyhow::bail!(
+            "{} of {} files under `{}` failed to convert",
+            outcome.failed(),
+            outcome.files.len(),
+            dir.display()
+        );
+    }
+    Ok(outcome)
+}
+
+/// The name of the binary, which the completion scripts complete
+pub const BIN_NAME: &str = "rusttest-to-dg";
+
+/// Writes the completion script of `shell` for the whole command line to `output`
+pub fn run_completions(shell: clap_complete::Shell, output: &mut impl io::Write) -> Result<()> {
+    clap_complete::generate(shell, &mut Cli::command(), BIN_NAME, output);
+    output.flush()?;
+    Ok(())
+}
+
+/// Checks every directive of `file`, writing one line per problem to `output`.
+///
+/// Fails if any problem was found.
+pub fn run_verify(file: &path::Path, output: &mut impl io::Write) -> Result<()> {
+    let code =
+        input::read(file).with_context(|| format!("could not read file `{}`", file.display()))?;
+    let problems = dejagnu::verify(&code);
+    for (line_num, problem) in &problems {
+        writeln!(output, "{}:{line_num}: {problem}", file.display())?;
+    }
+    output.flush()?;
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "found {} invalid directives in `{}`",
+            problems.len(),
+            file.display()
+        );
+    }
+    Ok(())
+}
+
+/// Writes the explanation of how `annotation` is converted to `output`
+pub fn run_explain(
+    annotation: &str,
+    at_line: Option<usize>,
+    render: &RenderArguments,
+    output: &mut impl io::Write,
+) -> Result<()> {
+    // Rendered like the conversion of a file with no other argument
+    let args = Arguments {
+        render: render.clone(),
+        ..Arguments::parse_from([env!("CARGO_PKG_NAME"), "-"])
+    };
+    let text = explain::explain(annotation, at_line, &args.transform_options())?;
+    output.write_all(text.as_bytes())?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Writes `file` with its directives turned back into annotations to `output`, and a warning
+/// for each directive kept as is to `stderr`
+pub fn run_reverse(file: &path::Path, output: &mut impl io::Write) -> Result<()> {
+    let code =
+        input::read(file).with_context(|| format!("could not read file `{}`", file.display()))?;
+    let reversed = reverse::reverse(&code);
+    output.write_all(reversed.code.as_bytes())?;
+    output.flush()?;
+    for warning in &reversed.warnings {
+        Reporter::default().warn(format_args!("warning: {warning}"));
+    }
+    Ok(())
+}
+
+/// Converts the ui testsuite of `rust_src`, writing the report as JSON to `output` and a
+/// summary to `stderr`
+pub fn run_corpus(
+    rust_src: &path::Path,
+    corpus: &corpus::CorpusOptions,
+    output: &mut impl io::Write,
+) -> Result<corpus::CorpusReport> {
+    let report = corpus::run(rust_src, corpus, &transform::Options::default())?;
+    report.write_json(output)?;
+    output.flush()?;
+    report.write_summary(&mut io::stderr().lock())?;
+    Ok(report)
+}
+
+/// How much the tool tells on `stderr`, from `--quiet` to `-vv`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Only the errors, with `--quiet`
+    Error,
+    /// The problems which did not stop the conversion
+    Warn,
+    /// Notes like the `stderr` file found next to FILE, and the progress
+    #[default]
+    Info,
+    /// Every annotation found, with `-v`
+    Debug,
+    /// The errors of the `stderr` file as well, with `-vv`
+    Trace,
+}
+
+/// Prints the messages of the tool to `stderr`, leaving out the ones above its level
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reporter {
+    pub level: Level,
+}
+
+impl Reporter {
+    /// Whether the messages of `level` are printed
+    pub fn enabled(self, level: Level) -> bool {
+        level <= self.level
+    }
+
+    pub fn log(self, level: Level, message: fmt::Arguments<'_>) {
+        if self.enabled(level) {
+            use io::Write as _;
+            // Nothing sensible can be done if `stderr` itself is broken
+            let _ = writeln!(io::stderr().lock(), "{message}");
+        }
+    }
+
+    pub fn warn(self, message: fmt::Arguments<'_>) {
+        self.log(Level::Warn, message);
+    }
+
+    pub fn info(self, message: fmt::Arguments<'_>) {
+        self.log(Level::Info, message);
+    }
+
+    /// Prints the progress of annotation loading, as it happens
+    fn progress(self, progress: Progress) {
+        match progress {
+            Progress::Loaded { annotations, line } => {
+                self.info(format_args!("loaded {annotations} annotations, up to line {line}"))
+            }
+            Progress::ManyAnnotations { line, lines } => self.info(format_args!(
+                "note: more than {MANY_ANNOTATIONS} annotations found by line {line} of {lines}, this may take a while"
+            )),
+        }
+    }
+}
+
+/// Writes the `--stats` table and the `--stats-json` object of `stats` to `writer`, if asked
+pub fn write_stats(args: &Arguments, stats: &Stats, writer: &mut impl io::Write) -> Result<()> {
+    if args.stats {
+        stats.write_table(writer)?;
+    }
+    if args.stats_json {
+        serde_json::to_writer(&mut *writer, stats)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Prints the summary of the transformation to `stderr`, unless `reporter` only prints errors
+pub fn print_report(reporter: Reporter, report: &TransformReport) {
+    if reporter.enabled(Level::Warn) {
+        // Nothing sensible can be done if `stderr` itself is broken
+        let _ = write_report(report, &mut io::stderr().lock());
+    }
+}
+
+/// Writes the summary of the transformation to `writer`, one line per noteworthy fact
+pub fn write_report(report: &TransformReport, writer: &mut impl io::Write) -> io::Result<()> {
+    let secondary =
+        report.secondary_converted + report.secondary_commented + report.secondary_dropped;
+    if report.messages_truncated > 0 {
+        writeln!(
+            writer,
+            "messages truncated: {} ({} characters cut off)",
+            report.messages_truncated, report.truncated_chars
+        )?;
+    }
+    for warning in &report.warnings {
+        writeln!(writer, "warning: {warning}")?;
+    }
+    for finding in &report.lints {
+        writeln!(writer, "warning{finding}")?;
+    }
+    if report.merged > 0 {
+        writeln!(writer, "merged {} directives", report.merged)?;
+    }
+    if !report.dropped_headers.is_empty() {
+        writeln!(
+            writer,
+            "dropped the headers without a DejaGnu equivalent: {}",
+            report.dropped_headers.join(", ")
+        )?;
+    }
+    if secondary > 0 {
+        writeln!(
+            writer,
+            "note/help annotations: {} converted, {} commented, {} dropped",
+            report.secondary_converted, report.secondary_commented, report.secondary_dropped
+        )?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -60,17 +1975,740 @@ mod tests {
     #[test]
     fn test_required_argument_file() {
         let args = Arguments::parse_from(["test", "test.rs"]);
-        assert_eq!(args.source_file, path::PathBuf::from("test.rs"));
+        assert_eq!(args.source_file, [path::PathBuf::from("test.rs")]);
         assert_eq!(args.stderr_file, None);
     }
 
+    #[test]
+    fn test_lint_arguments() {
+        let args = Arguments::parse_from([
+            "test",
+            "test.rs",
+            "--deny",
+            "empty-pattern",
+            "--deny",
+            "few-error-codes",
+        ]);
+        let options = args.transform_options();
+        assert!(!options.lint);
+        assert_eq!(
+            options.deny,
+            [lint::Lint::EmptyPattern, lint::Lint::FewErrorCodes]
+        );
+        assert!(Arguments::try_parse_from(["test", "test.rs", "--deny", "everything"]).is_err());
+
+        let report = TransformReport {
+            lints: vec![lint::Finding {
+                lint: lint::Lint::DefaultedKind,
+                line: 3,
+                message: "no kind".to_owned(),
+            }],
+            ..TransformReport::default()
+        };
+        let mut output = Vec::new();
+        write_report(&report, &mut output).unwrap();
+        assert_eq!(output, b"warning[defaulted-kind] line 3: no kind\n");
+    }
+
+    #[test]
+    fn test_secondary_argument() {
+        let args = Arguments::parse_from(["test", "test.rs"]);
+        assert_eq!(args.render.secondary, SecondaryMode::Convert);
+        let args = Arguments::parse_from(["test", "test.rs", "--secondary", "comment"]);
+        assert_eq!(args.render.secondary, SecondaryMode::Comment);
+        let args = Arguments::parse_from(["test", "test.rs", "--secondary", "drop"]);
+        assert_eq!(args.transform_options().secondary, SecondaryMode::Drop);
+        let args = Arguments::parse_from(["test", "test.rs", "--notes-as-comments"]);
+        assert_eq!(args.transform_options().secondary, SecondaryMode::Comment);
+        assert!(Arguments::try_parse_from([
+            "test",
+            "test.rs",
+            "--notes-as-comments",
+            "--secondary",
+            "drop"
+        ])
+        .is_err());
+    }
+
     #[test]
     fn test_optional_argument_file() {
         let args = Arguments::parse_from(["test", "test.rs", "-e", "test.stderr"]);
-        assert_eq!(args.source_file, path::PathBuf::from("test.rs"));
+        assert_eq!(args.source_file, [path::PathBuf::from("test.rs")]);
         assert_eq!(args.stderr_file, Some(path::PathBuf::from("test.stderr")));
     }
 
+    #[test]
+    fn test_bom_is_handled_in_source_and_stderr() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("bom.rs");
+        let stderr = dir.path().join("bom.stderr");
+        fs::write(
+            &source,
+            "\u{feff}//@ edition:2021\nfn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n}\n",
+        )
+        .unwrap();
+        fs::write(
+            &stderr,
+            "\u{feff}error[E0308]: mismatched types\n --> $DIR/bom.rs:3:18\n",
+        )
+        .unwrap();
+        let expected = "// { dg-additional-options \"-frust-edition=2021\" }\nfn main() {\n    let x: i32 = \"\"; // { dg-error \".E0308.\" \"\" { target *-*-* } }\n}\n\n";
+
+        let (source, stderr) = (source.to_str().unwrap(), stderr.to_str().unwrap());
+        for (bom, prefix) in [("keep", "\u{feff}"), ("strip", "")] {
+            let args = Arguments::parse_from(["test", source, "-e", stderr, "--bom", bom]);
+            let mut output = Vec::new();
+            run(&args, &mut output).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                format!("{prefix}{expected}")
+            );
+        }
+    }
+
+    #[test]
+    fn test_conversion_invocations_still_parse() {
+        for args in [
+            &["test", "test.rs"][..],
+            &["test", "a.rs", "b.rs", "--output-dir", "out"],
+            &["test", "-", "-e", "test.stderr", "--secondary", "drop"],
+            &[
+                "test",
+                "test.rs",
+                "--merge-stderr",
+                "test.stderr",
+                "-o",
+                "out.rs",
+            ],
+            &["test", "--dir", "ui", "--output-dir", "out", "-j", "4"],
+            &["test", "test.rs", "--check", "test.expected", "-vv"],
+        ] {
+            let cli = Cli::try_parse_from(args).unwrap_or_else(|err| panic!("{args:?}: {err}"));
+            assert!(cli.command.is_none(), "{args:?}");
+        }
+    }
+
+    #[test]
+    fn test_completions() {
+        for shell in [
+            clap_complete::Shell::Bash,
+            clap_complete::Shell::Zsh,
+            clap_complete::Shell::Fish,
+            clap_complete::Shell::PowerShell,
+        ] {
+            let mut output = Vec::new();
+            run_completions(shell, &mut output).unwrap();
+            let script = String::from_utf8(output).unwrap();
+            assert!(script.contains(BIN_NAME), "{shell}");
+            assert!(script.contains("merge-stderr"), "{shell}");
+        }
+    }
+
+    #[test]
+    fn test_subcommands() {
+        // The conversion stays the default
+        let cli = Cli::parse_from(["test", "test.rs", "-e", "test.stderr"]);
+        assert!(cli.command.is_none());
+        assert_eq!(cli.convert.source_file, [path::PathBuf::from("test.rs")]);
+        let cli = Cli::parse_from(["test", "test.rs", "--secondary", "drop"]);
+        assert_eq!(
+            cli.convert.transform_options().secondary,
+            SecondaryMode::Drop
+        );
+
+        let cli = Cli::parse_from(["test", "verify", "test.rs"]);
+        assert!(
+            matches!(cli.command, Some(Command::Verify { file }) if file == path::Path::new("test.rs"))
+        );
+        assert!(Cli::try_parse_from(["test", "verify"]).is_err());
+
+        let cli = Cli::parse_from(["test", "batch", "--rust-src", "rust"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Batch { rust_src, output_dir: None, gccrs_src: None, layout, test_list: None, list_format: ListFormat::Tcl, since: None, jobs: None }) if rust_src == path::Path::new("rust") && layout == Layout::default()
+        ));
+        assert!(Cli::try_parse_from([
+            "test",
+            "batch",
+            "--rust-src",
+            "rust",
+            "--gccrs-src",
+            "gccrs",
+            "--output-dir",
+            "out"
+        ])
+        .is_err());
+        assert!(Cli::try_parse_from(["test", "batch"]).is_err());
+        assert!(matches!(
+            Cli::parse_from(["test", "selftest"]).command,
+            Some(Command::Selftest)
+        ));
+        assert!(Cli::try_parse_from(["test"]).is_err());
+        assert!(matches!(
+            Cli::parse_from(["test", "completions", "zsh"]).command,
+            Some(Command::Completions {
+                shell: clap_complete::Shell::Zsh
+            })
+        ));
+        assert!(Cli::try_parse_from(["test", "completions", "tcsh"]).is_err());
+
+        let cli = Cli::parse_from([
+            "test",
+            "explain",
+            "//~^ ERROR x",
+            "--at-line",
+            "3",
+            "--msg-style",
+            "escaped",
+        ]);
+        let Some(Command::Explain {
+            annotation,
+            at_line,
+            render,
+        }) = cli.command
+        else {
+            panic!("not an explain command");
+        };
+        assert_eq!(annotation, "//~^ ERROR x");
+        assert_eq!(at_line, Some(3));
+        assert_eq!(render.msg_style(), MsgStyle::Escaped);
+
+        assert!(matches!(
+            Cli::parse_from(["test", "reverse", "test.rs"]).command,
+            Some(Command::Reverse { file }) if file == path::Path::new("test.rs")
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixtures = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        for fixture in ["caret", "inline", "edition", "stderr_codes", "unicode"] {
+            let file = fixtures.join(fixture).with_extension("expected");
+            let mut output = Vec::new();
+            run_verify(&file, &mut output).unwrap();
+            assert!(output.is_empty());
+        }
+
+        let file = dir.path().join("edited.rs");
+        fs::write(
+            &file,
+            "fn main() {\n    f(); // { dg-erorr \"\" \"\" { target *-*-* } }\n    g(); // { dg-error \"{\" \"\" { target *-*-* } }\n}\n",
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        let err = run_verify(&file, &mut output).unwrap_err();
+        assert!(err.to_string().starts_with("found 2 invalid directives"));
+        let output = String::from_utf8(output).unwrap();
+        let name = file.display();
+        assert_eq!(
+            output,
+            format!("{name}:2: unknown directive `dg-erorr`\n{name}:3: unbalanced braces\n")
+        );
+    }
+
+    #[test]
+    fn test_output_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("test.rs");
+        fs::write(&source, "fn main() {} //~ ERROR x\n").unwrap();
+        let expected = "fn main() {} // { dg-error \"\" \"\" { target *-*-* } }\n";
+        let convert_to = |output: &path::Path| {
+            let args = Arguments::parse_from([
+                "test",
+                source.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+            ]);
+            let mut stdout = Vec::new();
+            let report = run(&args, &mut stdout);
+            assert!(stdout.is_empty());
+            report
+        };
+
+        let output = dir.path().join("converted.rs");
+        convert_to(&output).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), expected);
+
+        let output = dir.path().join("rust/compile/nested.rs");
+        convert_to(&output).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), expected);
+
+        // The source file is left alone, even named another way
+        let same = dir.path().join(".").join("test.rs");
+        let err = convert_to(&same).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("is the source file, refusing to overwrite it"),
+            "{err}"
+        );
+        assert_eq!(
+            fs::read_to_string(&source).unwrap(),
+            "fn main() {} //~ ERROR x\n"
+        );
+    }
+
+    #[test]
+    fn test_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("test.rs");
+        let original = "fn main() {} //~ ERROR x\n";
+        fs::write(&source, original).unwrap();
+        let args = Arguments::parse_from(["test", source.to_str().unwrap(), "--in-place"]);
+        let mut stdout = Vec::new();
+        run(&args, &mut stdout).unwrap();
+        assert!(stdout.is_empty());
+        assert_eq!(
+            fs::read_to_string(&source).unwrap(),
+            "fn main() {} // { dg-error \"\" \"\" { target *-*-* } }\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("test.rs.orig")).unwrap(),
+            original
+        );
+
+        // A failed conversion leaves the file as it was, without a backup
+        let source = dir.path().join("broken.rs");
+        let broken = b"fn main() {}\n//~| NOTE nothing to follow\n\xff\n";
+        fs::write(&source, broken).unwrap();
+        let args = Arguments::parse_from([
+            "test",
+            source.to_str().unwrap(),
+            "--in-place",
+            "--backup-suffix",
+            ".bak",
+        ]);
+        assert!(run(&args, &mut Vec::new()).is_err());
+        assert_eq!(fs::read(&source).unwrap(), broken);
+        let mut files: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["broken.rs", "test.rs", "test.rs.orig"]);
+
+        // A second run changes nothing and keeps the backup of the original
+        let source = dir.path().join("test.rs");
+        let args = Arguments::parse_from(["test", source.to_str().unwrap(), "--in-place"]);
+        run(&args, &mut Vec::new()).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("test.rs.orig")).unwrap(),
+            original
+        );
+        // Nor is the backup replaced when the file changed since
+        fs::write(&source, "fn f() {} //~ ERROR y\n").unwrap();
+        let err = run(&args, &mut Vec::new()).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("test.rs.orig` already exists, refusing to overwrite it"),
+            "{err}"
+        );
+        assert_eq!(
+            fs::read_to_string(&source).unwrap(),
+            "fn f() {} //~ ERROR y\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("test.rs.orig")).unwrap(),
+            original
+        );
+
+        assert!(
+            Arguments::try_parse_from(["test", "test.rs", "--in-place", "-o", "out.rs"]).is_err()
+        );
+        assert!(Arguments::try_parse_from(["test", "test.rs", "--backup-suffix", "~"]).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_in_place_through_a_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("test.rs");
+        fs::write(&source, "fn main() {} //~ ERROR x\n").unwrap();
+        let link = dir.path().join("link.rs");
+        std::os::unix::fs::symlink(&source, &link).unwrap();
+        let args = Arguments::parse_from(["test", link.to_str().unwrap(), "--in-place"]);
+        run(&args, &mut Vec::new()).unwrap();
+        // The link still names the file, which is the one converted
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(
+            fs::read_to_string(&source).unwrap(),
+            "fn main() {} // { dg-error \"\" \"\" { target *-*-* } }\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("link.rs.orig")).unwrap(),
+            "fn main() {} //~ ERROR x\n"
+        );
+    }
+
+    #[test]
+    fn test_verbose() {
+        let args = Arguments::parse_from(["test", "test.rs", "-vv"]);
+        assert_eq!(args.verbose, 2);
+
+        let fixtures = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let code = fs::read_to_string(fixtures.join("malformed.rs")).unwrap();
+        let mut output = Vec::new();
+        let options = transform::Options::default();
+        write_annotations(Level::Debug, &code, None, false, &options, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+line 2: dg-error, no code, msg 'mismatched types'
+line 2: skipped, unknown annotation kind `FOO`
+line 4: skipped, malformed `//~` sigil
+"
+        );
+
+        let code = fs::read_to_string(fixtures.join("stderr_codes.rs")).unwrap();
+        let stderr_code = fs::read_to_string(fixtures.join("stderr_codes.stderr")).unwrap();
+        let mut output = Vec::new();
+        write_annotations(
+            Level::Trace,
+            &code,
+            Some(&stderr_code),
+            false,
+            &options,
+            &mut output,
+        )
+        .unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.starts_with("line 2: dg-error, code E0308, msg 'mismatched types'\n"),
+            "{output}"
+        );
+        assert!(
+            output.contains("\nstderr line 2: E0308, msg 'mismatched types'\n"),
+            "{output}"
+        );
+
+        // The annotations which could have taken several entries
+        let stderr_code = "error[E0308]: mismatched types\n  --> $DIR/a.rs:1:5\n\nerror[E0308]: mismatched types\n  --> $DIR/a.rs:5:5\n";
+        let code = "\n\nfn main() {} //~ ERROR mismatched\n";
+        let mut output = Vec::new();
+        write_annotations(
+            Level::Debug,
+            code,
+            Some(stderr_code),
+            false,
+            &options,
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+line 3: dg-error, code E0308, msg 'mismatched'
+line 3: ambiguous, `mismatched` is in the stderr entries of lines 1, 5, took E0308 of line 1, the closest
+"
+        );
+    }
+
+    #[test]
+    fn test_multiple_files() {
+        let args = Arguments::parse_from(["test", "a.rs", "b.rs", "c.rs", "--secondary", "drop"]);
+        assert_eq!(
+            args.source_file,
+            ["a.rs", "b.rs", "c.rs"].map(path::PathBuf::from)
+        );
+        assert_eq!(args.render.secondary, SecondaryMode::Drop);
+        assert_eq!(
+            args.source_file().unwrap_err().to_string(),
+            "3 FILEs were given where only one can be converted"
+        );
+
+        // A single `stderr` file cannot go with each of them
+        let args = Arguments::parse_from(["test", "a.rs", "b.rs", "-e", "a.stderr"]);
+        assert!(run_files(&args, &mut Vec::new(), &mut Vec::new()).is_err());
+        assert!(Arguments::try_parse_from(["test", "--dir", "ui", "a.rs", "b.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_stdin_arguments() {
+        let args = Arguments::parse_from(["test", "-"]);
+        assert!(input::is_stdin(args.source_file().unwrap()));
+        let args = Arguments::parse_from(["test", "test.rs", "-e", "-"]);
+        assert!(args.stderr_file.as_deref().is_some_and(input::is_stdin));
+
+        // Only one of them can come from standard input, nothing is read to find out
+        for extra in [["-e", "-"], ["--merge-stderr", "-"]] {
+            let args = Arguments::parse_from(["test", "-"].into_iter().chain(extra));
+            let err = parse_arguments_and_read_file(&args).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "FILE and the stderr file cannot both be read from standard input"
+            );
+        }
+        let args = Arguments::parse_from(["test", "-", "--in-place"]);
+        assert!(run(&args, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_stderr_discovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("mismatch.rs");
+        fs::write(
+            &source,
+            "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n}\n",
+        )
+        .unwrap();
+        // Revisions are never picked
+        fs::write(
+            dir.path().join("mismatch.rev1.stderr"),
+            "error[E0001]: mismatched types\n  --> $DIR/mismatch.rs:2:18\n",
+        )
+        .unwrap();
+        let convert = |extra: &[&str]| {
+            let args = Arguments::parse_from(
+                ["test", source.to_str().unwrap()]
+                    .into_iter()
+                    .chain(extra.iter().copied()),
+            );
+            let mut output = Vec::new();
+            run(&args, &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let without_code = "    let x: i32 = \"\"; // { dg-error \"\" \"\" { target *-*-* } }\n";
+        let with_code =
+            "    let x: i32 = \"\"; // { dg-error \".E0308.\" \"\" { target *-*-* } }\n";
+
+        assert_eq!(discover_stderr(&source), None);
+        assert!(convert(&[]).contains(without_code));
+
+        let stderr = dir.path().join("mismatch.stderr");
+        fs::write(
+            &stderr,
+            "error[E0308]: mismatched types\n  --> $DIR/mismatch.rs:2:18\n",
+        )
+        .unwrap();
+        assert_eq!(discover_stderr(&source), Some(stderr));
+        assert!(convert(&[]).contains(with_code));
+        assert!(convert(&["--no-stderr"]).contains(without_code));
+
+        assert_eq!(discover_stderr(path::Path::new("-")), None);
+        assert!(
+            Arguments::try_parse_from(["test", "test.rs", "-e", "a.stderr", "--no-stderr"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("test.rs");
+        fs::write(&source, "fn main() {\n    f(); //~ ERROR x\n    g();\n}\n").unwrap();
+        let converted = dir.path().join("converted.rs");
+        let check = |converted: &path::Path| {
+            let args = Arguments::parse_from([
+                "test",
+                source.to_str().unwrap(),
+                "--check",
+                converted.to_str().unwrap(),
+            ]);
+            let mut output = Vec::new();
+            let result = run(&args, &mut output);
+            (result, String::from_utf8(output).unwrap())
+        };
+
+        let up_to_date =
+            "fn main() {\n    f(); // { dg-error \"\" \"\" { target *-*-* } }\n    g();\n}\n";
+        fs::write(&converted, up_to_date).unwrap();
+        let (result, output) = check(&converted);
+        assert!(result.is_ok());
+        assert_eq!(output, "");
+
+        fs::write(&converted, up_to_date.replace("g();", "h();")).unwrap();
+        let (result, output) = check(&converted);
+        let name = converted.display();
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            format!("`{name}` is out of date, the diff above regenerates it")
+        );
+        assert_eq!(
+            output,
+            format!(
+                "--- {name}\n+++ {name} (regenerated)\n@@ -1,4 +1,4 @@\n fn main() {{\n     f(); // {{ dg-error \"\" \"\" {{ target *-*-* }} }}\n-    h();\n+    g();\n }}\n"
+            )
+        );
+        // Nothing is written, not even to the converted file
+        assert!(fs::read_to_string(&converted).unwrap().contains("h();"));
+
+        let (result, output) = check(&dir.path().join("missing.rs"));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("could not read converted file"));
+        assert_eq!(output, "");
+        let files = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 2);
+    }
+
+    #[test]
+    fn test_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("test.rs");
+        let mut code =
+            String::from("fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n");
+        for i in 0..10 {
+            code.push_str(&format!("    let y{i} = {i};\n"));
+        }
+        code.push_str("    undefined;\n    //~^ ERROR cannot find value\n}\n");
+        fs::write(&source, &code).unwrap();
+
+        let args = Arguments::parse_from(["test", source.to_str().unwrap(), "--diff"]);
+        let mut output = Vec::new();
+        run(&args, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let name = source.display();
+        assert!(
+            output.starts_with(&format!("--- a/{name}\n+++ b/{name}\n@@ -1,5 +1,5 @@\n")),
+            "{output}"
+        );
+        assert_eq!(output.matches("\n@@ ").count(), 2, "{output}");
+        assert!(output.contains(
+            "\n-    //~^ ERROR cannot find value\n+// { dg-error \"\" \"\" { target *-*-* } .-1 }\n"
+        ));
+
+        // Nothing changed, nothing to show
+        fs::write(&source, "fn main() {}\n").unwrap();
+        let mut output = Vec::new();
+        run(&args, &mut output).unwrap();
+        assert!(output.is_empty());
+
+        assert!(Arguments::try_parse_from(["test", "test.rs", "--diff", "--in-place"]).is_err());
+    }
+
+    #[test]
+    fn test_stats() {
+        let fixtures = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let source = fixtures.join("stats.rs");
+        let args = Arguments::parse_from([
+            "test",
+            source.to_str().unwrap(),
+            source.to_str().unwrap(),
+            "--stats-json",
+        ]);
+        let mut log = Vec::new();
+        run_files(&args, &mut Vec::new(), &mut log).unwrap();
+        let log = String::from_utf8(log).unwrap();
+        let json: serde_json::Value = serde_json::from_str(log.lines().last().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "files": 2,
+                "annotations": 10,
+                "kinds": {"error": 4, "note": 2, "warning": 4},
+                "error_codes": 2,
+                "skipped": 2,
+                "unconverted_headers": 0,
+            })
+        );
+
+        let args = Arguments::parse_from(["test", source.to_str().unwrap(), "--stats"]);
+        let report = run(&args, &mut Vec::new()).unwrap();
+        let mut table = Vec::new();
+        write_stats(&args, &report.stats, &mut table).unwrap();
+        assert!(String::from_utf8(table)
+            .unwrap()
+            .starts_with("files:                  1\nannotations:            5\n"));
+    }
+
+    #[test]
+    fn test_emit_json() {
+        let fixtures = path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let source = fixtures.join("stderr_codes.rs");
+        let args = Arguments::parse_from(["test", source.to_str().unwrap(), "--emit", "json"]);
+        let mut output = Vec::new();
+        run(&args, &mut output).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&output).unwrap();
+
+        // The `stderr` file next to the fixture gives the codes
+        let expected = [
+            (2, -1, "mismatched types", "E0308"),
+            (
+                4,
+                -1,
+                "cannot find value `undefined` in this scope",
+                "E0425",
+            ),
+        ];
+        let annotations = json.as_array().unwrap();
+        assert_eq!(annotations.len(), expected.len());
+        for (annotation, (line_num, relative_line_num, msg, code)) in
+            annotations.iter().zip(expected)
+        {
+            assert_eq!(annotation["line_num"], line_num);
+            assert_eq!(annotation["relative_line_num"], relative_line_num);
+            assert_eq!(annotation["kind"], "error");
+            assert_eq!(annotation["msg"], msg);
+            assert_eq!(annotation["error_code"], code);
+        }
+        assert!(output.ends_with(b"]\n"));
+
+        let args = Arguments::parse_from([
+            "test",
+            source.to_str().unwrap(),
+            "--emit",
+            "json",
+            "--in-place",
+        ]);
+        assert!(run(&args, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_max_errors_alias() {
+        let args = Arguments::parse_from(["test", "test.rs", "--max-errors", "7"]);
+        assert_eq!(args.limits().max_annotations, 7);
+    }
+
+    #[test]
+    fn test_merge_stderr_argument() {
+        let args = Arguments::parse_from(["test", "test.rs", "--merge-stderr", "test.stderr"]);
+        assert_eq!(args.merge_stderr, Some(path::PathBuf::from("test.stderr")));
+        let args = Arguments::try_parse_from([
+            "test",
+            "test.rs",
+            "-e",
+            "a.stderr",
+            "--merge-stderr",
+            "b.stderr",
+        ]);
+        assert!(args.is_err());
+    }
+
+    #[test]
+    fn test_explain_renders_like_the_conversion() {
+        let annotation = "fn main() {} //~ ERROR a long message";
+        let flags = ["--with-messages", "--max-message-length", "5"];
+        let Some(Command::Explain { render, .. }) =
+            Cli::parse_from(["test", "explain", annotation].into_iter().chain(flags)).command
+        else {
+            panic!("not an explain command");
+        };
+        let mut explained = Vec::new();
+        run_explain(annotation, None, &render, &mut explained).unwrap();
+        let args = Arguments::parse_from(["test", "test.rs"].into_iter().chain(flags));
+        let (new_code, _) =
+            transform::transform_code(annotation, None, &args.transform_options()).unwrap();
+        assert_eq!(
+            String::from_utf8(explained).unwrap().lines().last(),
+            Some(format!("output: {}", new_code.trim_end()).as_str())
+        );
+    }
+
+    #[test]
+    fn test_help_has_no_rustdoc_links() {
+        use clap::CommandFactory;
+        let mut command = Cli::command();
+        let mut helps = vec![command.render_long_help().to_string()];
+        for subcommand in command.get_subcommands_mut() {
+            helps.push(subcommand.render_long_help().to_string());
+        }
+        for help in helps {
+            // The doc comments of the values are their help too
+            assert!(!help.contains("[`"), "{help}");
+        }
+    }
+
     // clap reports most development errors as `debug_assert!`s
     // See this for more details, [here](https://docs.rs/clap/4.5.15/clap/_derive/_tutorial/chapter_4/index.html)
     #[test]
@@ -0,0 +1,1132 @@
+//! This module contains the corpus runner measuring how much of the rustc ui testsuite
+//! can be converted.
+//!
+//! [`run`] walks `<rust-src>/tests/ui`, converts every test with the batch converter and
+//! aggregates what happened into a [`CorpusReport`]: how many files were converted, why
+//! the others failed and which headers and annotation forms were encountered, along with
+//! what the tool does with them. Nothing is written unless an output directory is given,
+//! where the converted tests are sorted into the compile and execute directories of the
+//! gccrs testsuite along with the auxiliary and module files they use. A list of the written tests
+//! can be generated along with them, to include into the harness.
+//!
+//! Given a git reference, only the tests changed since then are converted, as listed by
+//! `git diff`, along with the untracked ones. If git cannot list them, every test is converted.
+//!
+//! Given a gccrs checkout, each converted test is also compared with the hand-ported
+//! test of the same name, if any, and the comparison is added to the report.
+
+use {
+    crate::{
+        batch::{self, rust_files, Job},
+        cli,
+        coverage::Coverage,
+        dejagnu, diff,
+        errors::AnnotationError,
+        input,
+        layout::{self, Detection, Layout, TestKind},
+        limits::LimitError,
+        modules,
+        testlist::{ListFormat, TestList},
+        transform::Options,
+    },
+    anyhow::{Context, Result},
+    serde::Serialize,
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        ffi::OsString,
+        fs, io,
+        path::{Path, PathBuf},
+        process,
+    },
+};
+
+/// Number of failure reasons listed in the human summary
+pub const TOP_FAILURE_REASONS: usize = 10;
+
+/// Aggregated results of a corpus run. Every map is sorted by key so that the JSON
+/// form only changes when the results do.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CorpusReport {
+    /// Tests found, skipped ones included
+    pub files: usize,
+    pub converted: usize,
+    pub failed: usize,
+    /// Files which are not tests, like the helpers in `auxiliary` directories
+    pub skipped: usize,
+    /// Number of failed files per reason
+    pub failure_reasons: BTreeMap<String, usize>,
+    /// The header directives and annotation forms of the converted tests
+    pub coverage: Coverage,
+    /// The comparison with the hand-ported tests, when a gccrs checkout is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gccrs: Option<GccrsReport>,
+    /// Where the converted tests were written, when an output directory is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<LayoutReport>,
+    /// The tests left out as unchanged, when a git reference is given
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub incremental: Option<IncrementalReport>,
+}
+
+/// What an incremental run left out
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct IncrementalReport {
+    /// The git reference the changes are listed from
+    pub since: String,
+    /// Tests not converted because neither they nor their `stderr` file changed, not
+    /// counted in `files`
+    pub unchanged: usize,
+    /// Why every test was converted instead, if the changes could not be listed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+}
+
+/// How the converted tests were sorted into the layout of the gccrs testsuite
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct LayoutReport {
+    pub compile: usize,
+    pub execute: usize,
+    /// Auxiliary files copied next to the tests using them
+    pub auxiliary: usize,
+    /// Module files copied next to the tests declaring them
+    pub module_files: usize,
+    /// The module files of each test, by path under `tests/ui`
+    pub modules: BTreeMap<PathBuf, Vec<PathBuf>>,
+    /// The module declarations of each test which have no file to copy, with the reason
+    pub unresolved_modules: BTreeMap<PathBuf, Vec<String>>,
+    /// The tests which could be either kind, written in the compile directory, with the
+    /// reason, by path under `tests/ui`
+    pub ambiguous: BTreeMap<PathBuf, String>,
+}
+
+impl LayoutReport {
+    fn add(&mut self, test: PathBuf, detection: &Detection) {
+        match detection.kind {
+            TestKind::Compile => self.compile += 1,
+            TestKind::Execute => self.execute += 1,
+        }
+        if let Some(ambiguity) = detection.ambiguity {
+            self.ambiguous.insert(test, ambiguity.to_owned());
+        }
+    }
+}
+
+/// Where a corpus run reads and writes its files
+#[derive(Clone, Debug, Default)]
+pub struct CorpusOptions {
+    /// Where to write the converted tests, nothing is written without it
+    pub output_dir: Option<PathBuf>,
+    /// A gccrs checkout to compare the converted tests with
+    pub gccrs_src: Option<PathBuf>,
+    /// The directories of each kind of test under `output_dir`
+    pub layout: Layout,
+    /// Where to write the list of the tests under `output_dir`, if anywhere
+    pub test_list: Option<PathBuf>,
+    pub list_format: ListFormat,
+    /// Only convert the tests changed since this git reference
+    pub since: Option<String>,
+    /// Worker threads converting the tests, `0` is the same as `1`
+    pub jobs: usize,
+}
+
+/// How a converted test differs from its hand-ported version
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Classification {
+    Identical,
+    /// Only the `DejaGnu` directives differ, the code is the same
+    DirectiveOnly,
+    CodeDiffers,
+}
+
+/// The comparison of a converted test with its hand-ported version
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Comparison {
+    /// The hand-ported test
+    pub gccrs_file: PathBuf,
+    pub classification: Classification,
+    /// Unified diff from the hand-ported test to the converted one, empty if identical
+    pub diff: String,
+}
+
+/// The comparisons of a corpus run with a gccrs checkout
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct GccrsReport {
+    pub identical: usize,
+    pub directive_only: usize,
+    pub code_differs: usize,
+    /// Converted tests without a hand-ported version
+    pub unmatched: usize,
+    /// The comparisons, by path of the test under `tests/ui`
+    pub files: BTreeMap<PathBuf, Comparison>,
+}
+
+impl GccrsReport {
+    fn add(&mut self, test: PathBuf, comparison: Comparison) {
+        match comparison.classification {
+            Classification::Identical => self.identical += 1,
+            Classification::DirectiveOnly => self.directive_only += 1,
+            Classification::CodeDiffers => self.code_differs += 1,
+        }
+        self.files.insert(test, comparison);
+    }
+}
+
+impl CorpusReport {
+    /// The most frequent failure reasons, most frequent first, ties in name order
+    pub fn top_failure_reasons(&self) -> Vec<(&str, usize)> {
+        let mut reasons: Vec<_> = self
+            .failure_reasons
+            .iter()
+            .map(|(reason, &count)| (reason.as_str(), count))
+            .collect();
+        reasons.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        reasons.truncate(TOP_FAILURE_REASONS);
+        reasons
+    }
+
+    /// Writes the report as pretty-printed JSON
+    pub fn write_json(&self, writer: &mut impl io::Write) -> Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+
+    /// Writes a short human-readable summary
+    pub fn write_summary(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "{} files: {} converted, {} failed, {} skipped",
+            self.files, self.converted, self.failed, self.skipped
+        )?;
+        let top = self.top_failure_reasons();
+        if !top.is_empty() {
+            writeln!(writer, "top failure reasons:")?;
+            for (reason, count) in top {
+                writeln!(writer, "  {count:>6}  {reason}")?;
+            }
+        }
+        self.coverage.write_tables(writer)?;
+        if let Some(gccrs) = &self.gccrs {
+            writeln!(
+                writer,
+                "compared with gccrs: {} identical, {} directive-only differences, {} code differences, {} not found",
+                gccrs.identical, gccrs.directive_only, gccrs.code_differs, gccrs.unmatched
+            )?;
+        }
+        if let Some(incremental) = &self.incremental {
+            match &incremental.fallback {
+                Some(reason) => writeln!(
+                    writer,
+                    "warning: {reason}, converted every test instead of the changes since `{}`",
+                    incremental.since
+                )?,
+                None => writeln!(
+                    writer,
+                    "{} unchanged tests since `{}` not converted",
+                    incremental.unchanged, incremental.since
+                )?,
+            }
+        }
+        if let Some(layout) = &self.layout {
+            writeln!(
+                writer,
+                "layout: {} compile, {} execute, {} auxiliary files, {} module files, {} ambiguous tests in compile",
+                layout.compile,
+                layout.execute,
+                layout.auxiliary,
+                layout.module_files,
+                layout.ambiguous.len()
+            )?;
+            if !layout.unresolved_modules.is_empty() {
+                writeln!(writer, "unresolved modules:")?;
+                for (test, reasons) in &layout.unresolved_modules {
+                    for reason in reasons {
+                        writeln!(writer, "  {}: {reason}", test.display())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Converts every test of `<rust_src>/tests/ui`, writing or comparing the results as configured
+pub fn run(rust_src: &Path, corpus: &CorpusOptions, options: &Options) -> Result<CorpusReport> {
+    run_with(rust_src, corpus, options, git_changes)
+}
+
+/// [`run`], listing the files changed since a git reference with `changed_files`, like
+/// [`git_changes`]
+fn run_with(
+    rust_src: &Path,
+    corpus: &CorpusOptions,
+    options: &Options,
+    changed_files: impl Fn(&Path, &str) -> Result<Vec<PathBuf>, String>,
+) -> Result<CorpusReport> {
+    let ui = rust_src.join("tests/ui");
+    let (mut jobs, skipped) =
+        discover(&ui).with_context(|| format!("could not walk `{}`", ui.display()))?;
+    let incremental = corpus.since.as_ref().map(|since| {
+        let mut incremental = IncrementalReport {
+            since: since.clone(),
+            ..IncrementalReport::default()
+        };
+        match changed_files(rust_src, since) {
+            Ok(changed) => {
+                let all = jobs.len();
+                jobs = changed_jobs(std::mem::take(&mut jobs), rust_src, &changed);
+                incremental.unchanged = all - jobs.len();
+            }
+            Err(reason) => incremental.fallback = Some(reason),
+        }
+        incremental
+    });
+    let relative = |path: &Path| path.strip_prefix(&ui).unwrap_or(path).to_path_buf();
+    let ported = match &corpus.gccrs_src {
+        Some(gccrs_src) => {
+            let testsuite = gccrs_testsuite(gccrs_src);
+            let index = index_by_name(&testsuite)
+                .with_context(|| format!("could not walk `{}`", testsuite.display()))?;
+            Some(index)
+        }
+        None => None,
+    };
+    let outcome = batch::convert(&jobs, corpus.jobs, options, false);
+
+    let mut report = CorpusReport {
+        files: jobs.len() + skipped.len(),
+        converted: outcome.converted(),
+        failed: outcome.failed(),
+        skipped: skipped.len(),
+        gccrs: ported.as_ref().map(|_| GccrsReport::default()),
+        layout: corpus.output_dir.as_ref().map(|_| LayoutReport::default()),
+        incremental,
+        ..CorpusReport::default()
+    };
+    // Shared auxiliary and module files are copied once per directory
+    let mut copied = BTreeSet::new();
+    let mut test_list = TestList::default();
+    for file in &outcome.files {
+        match &file.result {
+            Ok((new_code, file_report)) => {
+                report.coverage.merge(&file_report.coverage);
+                if let (Some(output_dir), Some(layout)) = (&corpus.output_dir, &mut report.layout) {
+                    let test = &file.job.source_file;
+                    let code = input::read(test)
+                        .with_context(|| format!("could not read file `{}`", test.display()))?;
+                    let detection = layout::detect(&code);
+                    let dir = output_dir.join(corpus.layout.dir(detection.kind));
+                    cli::write_source_code(&output_path(&ui, &dir, test), new_code)?;
+                    layout.add(relative(test), &detection);
+                    test_list.add(corpus.layout.dir(detection.kind).join(relative(test)));
+                    for name in layout::auxiliary_files(&code) {
+                        let helper = test.with_file_name("auxiliary").join(name);
+                        if helper.is_file() && copied.insert((helper.clone(), detection.kind)) {
+                            copy_output(&ui, &dir, &helper)?;
+                            layout.auxiliary += 1;
+                        }
+                    }
+                    let found = modules::module_files(test);
+                    let mut unresolved = found.unresolved;
+                    let mut files = Vec::new();
+                    for module in found.files {
+                        // The module paths are normalized, `tests/ui` may not be
+                        let Ok(under_ui) = module.strip_prefix(modules::normalize(&ui)) else {
+                            unresolved.push(format!(
+                                "`{}` is outside of `tests/ui`, not copied",
+                                module.display()
+                            ));
+                            continue;
+                        };
+                        let module = ui.join(under_ui);
+                        if copied.insert((module.clone(), detection.kind)) {
+                            copy_output(&ui, &dir, &module)?;
+                            layout.module_files += 1;
+                        }
+                        files.push(relative(&module));
+                    }
+                    if !files.is_empty() {
+                        layout.modules.insert(relative(test), files);
+                    }
+                    if !unresolved.is_empty() {
+                        layout.unresolved_modules.insert(relative(test), unresolved);
+                    }
+                }
+                if let (Some(index), Some(gccrs)) = (&ported, &mut report.gccrs) {
+                    let test = &file.job.source_file;
+                    match test.file_name().and_then(|name| index.get(name)) {
+                        Some(gccrs_file) => {
+                            let relative = test.strip_prefix(&ui).unwrap_or(test);
+                            gccrs.add(relative.to_path_buf(), compare(gccrs_file, new_code)?);
+                        }
+                        None => gccrs.unmatched += 1,
+                    }
+                }
+            }
+            Err(err) => {
+                let reason = failure_reason(err);
+                if corpus.output_dir.is_some() {
+                    let test = &file.job.source_file;
+                    let kind = input::read(test)
+                        .map_or(TestKind::Compile, |code| layout::detect(&code).kind);
+                    test_list.add_skipped(
+                        corpus.layout.dir(kind).join(relative(test)),
+                        format!("not converted: {reason}"),
+                    );
+                }
+                *report.failure_reasons.entry(reason).or_default() += 1
+            }
+        }
+    }
+
+    if let (Some(path), Some(_)) = (&corpus.test_list, &corpus.output_dir) {
+        for helper in &skipped {
+            let kinds: Vec<_> = copied
+                .iter()
+                .filter(|(copied, _)| copied == helper)
+                .map(|&(_, kind)| kind)
+                .collect();
+            let (kinds, reason) = if kinds.is_empty() {
+                (
+                    vec![TestKind::Compile],
+                    "unused auxiliary file, not written",
+                )
+            } else {
+                (kinds, "auxiliary file of other tests")
+            };
+            for kind in kinds {
+                test_list.add_skipped(
+                    corpus.layout.dir(kind).join(relative(helper)),
+                    reason.to_owned(),
+                );
+            }
+        }
+        write_test_list(path, &test_list, corpus.list_format)?;
+    }
+    Ok(report)
+}
+
+/// Finds the tests under `root` in path order, with their `stderr` file if there is one.
+/// Also returns the skipped files, in path order.
+pub fn discover(root: &Path) -> io::Result<(Vec<Job>, Vec<PathBuf>)> {
+    // Helpers of other tests, they have no annotations of their own
+    let (skipped, jobs): (Vec<Job>, _) = batch::discover(root)?.into_iter().partition(|job| {
+        job.source_file
+            .components()
+            .any(|c| c.as_os_str() == "auxiliary")
+    });
+    Ok((
+        jobs,
+        skipped.into_iter().map(|job| job.source_file).collect(),
+    ))
+}
+
+/// Lists the files changed since `since` with `git -C <rust_src> diff --name-only`, and the
+/// untracked files `git diff` leaves out, like a new test not committed yet
+pub fn git_changes(rust_src: &Path, since: &str) -> Result<Vec<PathBuf>, String> {
+    let mut changed = git_paths(
+        rust_src,
+        &["diff", "--name-only", "-z", "--end-of-options", since, "--"],
+    )
+    .map_err(|err| format!("`git diff {since}` failed: {err}"))?;
+    changed.extend(
+        git_paths(
+            rust_src,
+            &["ls-files", "--others", "--exclude-standard", "-z", "--"],
+        )
+        .map_err(|err| format!("`git ls-files --others` failed: {err}"))?,
+    );
+    Ok(changed)
+}
+
+/// The NUL-separated paths printed by `git -C <rust_src> <args>`
+fn git_paths(rust_src: &Path, args: &[&str]) -> Result<Vec<PathBuf>, String> {
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(rust_src)
+        .args(args)
+        .output()
+        .map_err(|err| format!("could not run git: {err}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// The jobs whose test or one of its `stderr` files is in `changed`, relative to `rust_src`
+fn changed_jobs(jobs: Vec<Job>, rust_src: &Path, changed: &[PathBuf]) -> Vec<Job> {
+    let mut tests = BTreeSet::new();
+    for path in changed {
+        let path = rust_src.join(path);
+        match path.extension() {
+            Some(ext) if ext == "rs" => {
+                tests.insert(path);
+            }
+            Some(ext) if ext == "stderr" => {
+                // `test.stderr`, or `test.revision.stderr` for a revision
+                tests.insert(path.with_extension("rs"));
+                if let Some(stem) = path
+                    .file_stem()
+                    .and_then(|stem| Path::new(stem).file_stem())
+                {
+                    tests.insert(path.with_file_name(stem).with_extension("rs"));
+                }
+            }
+            _ => {}
+        }
+    }
+    jobs.into_iter()
+        .filter(|job| tests.contains(&job.source_file))
+        .collect()
+}
+
+/// The directory of the rust tests in a gccrs checkout, or `gccrs_src` itself if it has none
+fn gccrs_testsuite(gccrs_src: &Path) -> PathBuf {
+    let testsuite = gccrs_src.join("gcc/testsuite/rust");
+    if testsuite.is_dir() {
+        testsuite
+    } else {
+        gccrs_src.to_path_buf()
+    }
+}
+
+/// The tests under `root` by file name, the first in path order winning
+fn index_by_name(root: &Path) -> io::Result<BTreeMap<OsString, PathBuf>> {
+    let mut index = BTreeMap::new();
+    for path in rust_files(root)? {
+        if let Some(name) = path.file_name() {
+            index.entry(name.to_owned()).or_insert(path);
+        }
+    }
+    Ok(index)
+}
+
+/// Compares a converted test with the hand-ported `gccrs_file`
+fn compare(gccrs_file: &Path, new_code: &str) -> Result<Comparison> {
+    let ported = input::read(gccrs_file)
+        .with_context(|| format!("could not read file `{}`", gccrs_file.display()))?;
+    let diff = diff::unified(
+        &gccrs_file.display().to_string(),
+        "converted",
+        &ported,
+        new_code,
+    );
+    let classification = if diff.is_empty() {
+        Classification::Identical
+    } else if code_lines(&ported).eq(code_lines(new_code)) {
+        Classification::DirectiveOnly
+    } else {
+        Classification::CodeDiffers
+    };
+    Ok(Comparison {
+        gccrs_file: gccrs_file.to_path_buf(),
+        classification,
+        diff,
+    })
+}
+
+/// The lines of `code` without their directive comments, directive-only lines left out
+fn code_lines(code: &str) -> impl Iterator<Item = &str> {
+    code.lines()
+        .filter_map(|line| match dejagnu::code_before_directive(line) {
+            Some("") => None,
+            Some(code) => Some(code),
+            None => Some(line),
+        })
+}
+
+/// Where `file` goes under `output_dir`, at the same place as under `root`
+fn output_path(root: &Path, output_dir: &Path, file: &Path) -> PathBuf {
+    output_dir.join(file.strip_prefix(root).unwrap_or(file))
+}
+
+/// Copies `file` at the same place under `output_dir` as under `root`
+fn copy_output(root: &Path, output_dir: &Path, file: &Path) -> Result<()> {
+    let code =
+        input::read(file).with_context(|| format!("could not read file `{}`", file.display()))?;
+    cli::write_source_code(&output_path(root, output_dir, file), &code)
+}
+
+/// Writes `test_list` to `path` in `format`
+fn write_test_list(path: &Path, test_list: &TestList, format: ListFormat) -> Result<()> {
+    let file =
+        fs::File::create(path).with_context(|| format!("could not create `{}`", path.display()))?;
+    let mut writer = io::BufWriter::new(file);
+    test_list
+        .write(format, &mut writer)
+        .and_then(|()| io::Write::flush(&mut writer))
+        .with_context(|| format!("could not write `{}`", path.display()))
+}
+
+/// A stable name for the reason a file failed, without file names or line numbers
+fn failure_reason(err: &anyhow::Error) -> String {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<AnnotationError>() {
+            return match err {
+                AnnotationError::EmptyMessage { .. } => "empty annotation",
+                AnnotationError::OrphanFollow { .. } => "`//~|` without a preceding annotation",
+                AnnotationError::AboveFirstLine { .. } => "annotation pointing above the file",
+                AnnotationError::BelowLastLine { .. } => "annotation pointing below the file",
+                AnnotationError::MissingAnnotation { .. } => "annotation not found again",
+            }
+            .to_owned();
+        }
+        if let Some(err) = cause.downcast_ref::<LimitError>() {
+            return match err {
+                LimitError::FileSize { .. } => "limit exceeded: --max-file-size",
+                LimitError::StderrSize { .. } => "limit exceeded: --max-stderr-size",
+                LimitError::Annotations { .. } => "limit exceeded: --max-annotations",
+                LimitError::LineLength { .. } => "limit exceeded: --max-input-line-length",
+            }
+            .to_owned();
+        }
+        if let Some(err) = cause.downcast_ref::<io::Error>() {
+            if input::is_binary_error(err) {
+                return "binary input".to_owned();
+            }
+            if err.kind() == io::ErrorKind::InvalidData {
+                return "invalid UTF-8".to_owned();
+            }
+            return format!("i/o error: {:?}", err.kind());
+        }
+    }
+    err.root_cause().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::coverage::{AnnotationEntry, HeaderEntry, Support},
+    };
+
+    /// A tiny rustc checkout with a few typical tests
+    const MINI_CORPUS: &[(&str, &str)] = &[
+        (
+            "tests/ui/typeck/mismatch.rs",
+            "//@ edition:2021\nfn main() {\n    let x: i32 = \"\";\n    //~^ ERROR mismatched types\n}\n",
+        ),
+        (
+            "tests/ui/typeck/mismatch.stderr",
+            "error[E0308]: mismatched types\n  --> $DIR/mismatch.rs:3:18\n",
+        ),
+        (
+            "tests/ui/lint/unused.rs",
+            "//@ check-pass\n//@ compile-flags: -W unused\nfn main() {\n    let x = 1; //~ WARN unused variable\n    //~| NOTE on by default\n}\n",
+        ),
+        (
+            "tests/ui/revisions.rs",
+            "//@ revisions: a b\n//@[a] edition:2018\n//@ gccrs-only\nfn main() {} //[a,b]~ ERROR x\n//~^ expected\n",
+        ),
+        ("tests/ui/broken/orphan.rs", "//~| NOTE nothing to follow\n"),
+        ("tests/ui/broken/orphan2.rs", "fn f() {}\n//~| NOTE nothing\n"),
+        ("tests/ui/broken/binary.rs", "\0\0\0\0"),
+        ("tests/ui/auxiliary/helper.rs", "pub fn helper() {}\n"),
+        ("tests/ui/notes.txt", "not a test\n"),
+    ];
+
+    fn mini_corpus() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in MINI_CORPUS {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    fn counts(pairs: &[(&str, usize)]) -> BTreeMap<String, usize> {
+        pairs.iter().map(|&(k, v)| (k.to_owned(), v)).collect()
+    }
+
+    #[test]
+    fn test_aggregation_over_mini_corpus() {
+        let dir = mini_corpus();
+        let report = run(dir.path(), &CorpusOptions::default(), &Options::default()).unwrap();
+        assert_eq!(
+            report,
+            CorpusReport {
+                files: 7,
+                converted: 3,
+                failed: 3,
+                skipped: 1,
+                failure_reasons: counts(&[
+                    ("`//~|` without a preceding annotation", 2),
+                    ("binary input", 1),
+                ]),
+                coverage: Coverage {
+                    headers: [
+                        ("check-pass", Support::Handled, 1),
+                        ("compile-flags", Support::Handled, 1),
+                        ("edition", Support::Handled, 2),
+                        ("gccrs-only", Support::Unknown, 1),
+                        ("revisions", Support::Handled, 1),
+                    ]
+                    .into_iter()
+                    .map(|(name, support, count)| {
+                        (name.to_owned(), HeaderEntry { support, count })
+                    })
+                    .collect(),
+                    annotations: [
+                        ("//~", Some("ERROR"), false, true, Support::Handled),
+                        ("//~", Some("WARN"), false, false, Support::Handled),
+                        ("//~^", None, false, false, Support::Unknown),
+                        ("//~^", Some("ERROR"), true, false, Support::Handled),
+                        ("//~|", Some("NOTE"), false, false, Support::Handled),
+                    ]
+                    .into_iter()
+                    .map(|(sigil, kind, has_code, has_revision, support)| {
+                        let entry = AnnotationEntry {
+                            sigil: sigil.to_owned(),
+                            kind,
+                            has_code,
+                            has_revision,
+                            support,
+                            count: 1,
+                        };
+                        let label = format!(
+                            "{sigil} {}{}{}",
+                            kind.unwrap_or("(no kind)"),
+                            if has_code { " +code" } else { "" },
+                            if has_revision { " +revision" } else { "" }
+                        );
+                        (label, entry)
+                    })
+                    .collect(),
+                },
+                gccrs: None,
+                layout: None,
+                incremental: None,
+            }
+        );
+        assert_eq!(
+            report.top_failure_reasons(),
+            [
+                ("`//~|` without a preceding annotation", 2),
+                ("binary input", 1)
+            ]
+        );
+        // Analysis only by default
+        assert!(!dir.path().join("out").exists());
+    }
+
+    #[test]
+    fn test_json_and_summary_are_stable() {
+        let dir = mini_corpus();
+        let report = run(dir.path(), &CorpusOptions::default(), &Options::default()).unwrap();
+        let mut json = Vec::new();
+        report.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with("{\n  \"files\": 7,\n  \"converted\": 3,\n"));
+        assert!(json.contains("\"failure_reasons\": {\n    \"`//~|` without a preceding annotation\": 2,\n    \"binary input\": 1\n  },"));
+
+        let mut again = Vec::new();
+        run(dir.path(), &CorpusOptions::default(), &Options::default())
+            .unwrap()
+            .write_json(&mut again)
+            .unwrap();
+        assert_eq!(json.as_bytes(), again);
+
+        let mut summary = Vec::new();
+        report.write_summary(&mut summary).unwrap();
+        let summary = String::from_utf8(summary).unwrap();
+        assert!(summary.starts_with("7 files: 3 converted, 3 failed, 1 skipped\n"));
+        assert!(summary
+            .contains("top failure reasons:\n       2  `//~|` without a preceding annotation\n"));
+        assert!(
+            summary.contains(
+                "\
+annotation coverage:
+  annotation           support   count
+  //~ ERROR +revision  handled       1
+  //~ WARN             handled       1
+  //~^ (no kind)       unknown       1
+  //~^ ERROR +code     handled       1
+  //~| NOTE            handled       1
+"
+            ),
+            "{summary}"
+        );
+    }
+
+    #[test]
+    fn test_changed_jobs() {
+        let dir = mini_corpus();
+        let (jobs, _) = discover(&dir.path().join("tests/ui")).unwrap();
+        let changed = [
+            "tests/ui/typeck/mismatch.stderr",
+            "tests/ui/broken/orphan.rs",
+            "tests/ui/lint/unused.a.stderr",
+            "tests/ui/auxiliary/helper.rs",
+            "tests/ui/deleted.rs",
+            "README.md",
+        ]
+        .map(PathBuf::from);
+        let kept: Vec<_> = changed_jobs(jobs, dir.path(), &changed)
+            .into_iter()
+            .map(|job| {
+                job.source_file
+                    .strip_prefix(dir.path())
+                    .unwrap()
+                    .to_path_buf()
+            })
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                "tests/ui/broken/orphan.rs",
+                "tests/ui/lint/unused.rs",
+                "tests/ui/typeck/mismatch.rs"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_incremental_run() {
+        let dir = mini_corpus();
+        let corpus = CorpusOptions {
+            since: Some("HEAD~1".to_owned()),
+            ..CorpusOptions::default()
+        };
+        let changed = |rust_src: &Path, since: &str| {
+            assert_eq!((rust_src, since), (dir.path(), "HEAD~1"));
+            Ok(vec![PathBuf::from("tests/ui/typeck/mismatch.rs")])
+        };
+        let report = run_with(dir.path(), &corpus, &Options::default(), changed).unwrap();
+        assert_eq!(
+            (
+                report.files,
+                report.converted,
+                report.failed,
+                report.skipped
+            ),
+            (2, 1, 0, 1)
+        );
+        assert_eq!(
+            report.incremental,
+            Some(IncrementalReport {
+                since: "HEAD~1".to_owned(),
+                unchanged: 5,
+                fallback: None,
+            })
+        );
+        let mut summary = Vec::new();
+        report.write_summary(&mut summary).unwrap();
+        assert!(String::from_utf8(summary)
+            .unwrap()
+            .contains("\n5 unchanged tests since `HEAD~1` not converted\n"));
+
+        // Without the changes, every test is converted
+        let failing =
+            |_: &Path, _: &str| Err("`git diff HEAD~1` failed: not a git repository".to_owned());
+        let report = run_with(dir.path(), &corpus, &Options::default(), failing).unwrap();
+        assert_eq!((report.files, report.converted), (7, 3));
+        let mut summary = Vec::new();
+        report.write_summary(&mut summary).unwrap();
+        assert!(String::from_utf8(summary).unwrap().contains(
+            "warning: `git diff HEAD~1` failed: not a git repository, converted every test instead of the changes since `HEAD~1`\n"
+        ));
+    }
+
+    #[test]
+    fn test_git_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        // Not a git checkout, or no git at all
+        assert!(git_changes(dir.path(), "HEAD").is_err());
+
+        let git = |args: &[&str]| {
+            process::Command::new("git")
+                .arg("-C")
+                .arg(dir.path())
+                .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .output()
+                .is_ok_and(|output| output.status.success())
+        };
+        if !git(&["init", "-q"]) {
+            // No git to list the changes with
+            return;
+        }
+        fs::write(dir.path().join("committed.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join("changed.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join(".gitignore"), "ignored.rs\n").unwrap();
+        assert!(git(&["add", "."]));
+        assert!(git(&["commit", "-q", "-m", "tests"]));
+        fs::write(dir.path().join("changed.rs"), "fn main() { f(); }\n").unwrap();
+        fs::write(dir.path().join("new.rs"), "fn main() {}\n").unwrap();
+        fs::write(dir.path().join("ignored.rs"), "fn main() {}\n").unwrap();
+        assert_eq!(
+            git_changes(dir.path(), "HEAD").unwrap(),
+            ["changed.rs", "new.rs"].map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_output_dir_follows_the_gccrs_layout() {
+        let dir = mini_corpus();
+        let out = dir.path().join("out");
+        let corpus = CorpusOptions {
+            output_dir: Some(out.clone()),
+            ..CorpusOptions::default()
+        };
+        run(dir.path(), &corpus, &Options::default()).unwrap();
+        let converted = fs::read_to_string(out.join("compile/typeck/mismatch.rs")).unwrap();
+        assert!(converted.contains("// { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }"));
+        assert!(out.join("compile/lint/unused.rs").is_file());
+        assert!(!out.join("compile/broken/orphan.rs").exists());
+        // Unused auxiliary files are left out
+        assert!(!out.join("compile/auxiliary/helper.rs").exists());
+    }
+
+    #[test]
+    fn test_mixed_tree_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in [
+            (
+                "tests/ui/run/add.rs",
+                "//@ run-pass
+//@ aux-build:math.rs
+fn main() {}
+",
+            ),
+            (
+                "tests/ui/run/auxiliary/math.rs",
+                "pub fn add() {}
+",
+            ),
+            (
+                "tests/ui/run/unused.rs",
+                "//@ run-pass
+fn main() {
+    let x = 1; //~ WARN unused
+}
+",
+            ),
+            (
+                "tests/ui/typeck/error.rs",
+                "//@ aux-build:math.rs
+fn main() {
+    x; //~ ERROR cannot find value
+}
+",
+            ),
+            (
+                "tests/ui/typeck/auxiliary/math.rs",
+                "pub fn add() {}
+",
+            ),
+            (
+                "tests/ui/lint/allowed.rs",
+                "//@ check-pass
+fn main() {}
+",
+            ),
+            (
+                "tests/ui/plain.rs",
+                "fn main() {}
+",
+            ),
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let out = dir.path().join("out");
+        let corpus = CorpusOptions {
+            output_dir: Some(out.clone()),
+            layout: "rust/compile,rust/execute".parse().unwrap(),
+            ..CorpusOptions::default()
+        };
+        let report = run(dir.path(), &corpus, &Options::default()).unwrap();
+
+        let mut written: Vec<String> = rust_files(&out)
+            .unwrap()
+            .iter()
+            .map(|path| path.strip_prefix(&out).unwrap().display().to_string())
+            .collect();
+        written.sort();
+        assert_eq!(
+            written,
+            [
+                "rust/compile/lint/allowed.rs",
+                "rust/compile/plain.rs",
+                "rust/compile/typeck/auxiliary/math.rs",
+                "rust/compile/typeck/error.rs",
+                "rust/execute/run/add.rs",
+                "rust/execute/run/auxiliary/math.rs",
+                "rust/execute/run/unused.rs",
+            ]
+        );
+        let layout = report.layout.unwrap();
+        assert_eq!(
+            (layout.compile, layout.execute, layout.auxiliary),
+            (3, 2, 2)
+        );
+        assert_eq!(
+            layout.ambiguous,
+            [(
+                PathBuf::from("plain.rs"),
+                "no pass header nor error annotation, it may be meant to run".to_owned()
+            )]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn test_module_files_are_copied() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/modules");
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out");
+        let corpus = CorpusOptions {
+            output_dir: Some(out.clone()),
+            ..CorpusOptions::default()
+        };
+        let report = run(&fixtures, &corpus, &Options::default()).unwrap();
+
+        let layout = report.layout.unwrap();
+        assert_eq!(
+            layout.modules,
+            [(
+                PathBuf::from("modules/main.rs"),
+                vec![
+                    PathBuf::from("modules/helper.rs"),
+                    PathBuf::from("modules/support/data.rs")
+                ]
+            )]
+            .into_iter()
+            .collect()
+        );
+        assert_eq!(layout.module_files, 2);
+        assert_eq!(
+            layout.unresolved_modules,
+            [(
+                PathBuf::from("modules/missing.rs"),
+                vec![
+                    "missing.rs:1: `mod gone;` has no file at `gone.rs` or `gone/mod.rs`"
+                        .to_owned()
+                ]
+            )]
+            .into_iter()
+            .collect()
+        );
+        // Copied as they are, next to the executable test
+        for module in ["helper.rs", "support/data.rs"] {
+            assert_eq!(
+                fs::read(out.join("execute/modules").join(module)).unwrap(),
+                fs::read(fixtures.join("tests/ui/modules").join(module)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_emitted_test_list() {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in [
+            (
+                "tests/ui/run/add.rs",
+                "//@ run-pass\n//@ aux-build:math.rs\nfn main() {}\n",
+            ),
+            ("tests/ui/run/auxiliary/math.rs", "pub fn add() {}\n"),
+            ("tests/ui/run/auxiliary/unused.rs", "pub fn unused() {}\n"),
+            (
+                "tests/ui/typeck/orphan.rs",
+                "//~| NOTE nothing to follow\nfn main() {}\n",
+            ),
+            (
+                "tests/ui/typeck/with space.rs",
+                "//@ check-pass\nfn main() {}\n",
+            ),
+        ] {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let out = dir.path().join("out");
+        let list = dir.path().join("batch.exp");
+        let corpus = CorpusOptions {
+            output_dir: Some(out),
+            test_list: Some(list.clone()),
+            ..CorpusOptions::default()
+        };
+        run(dir.path(), &corpus, &Options::default()).unwrap();
+        let expected = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/batch.exp");
+        assert_eq!(
+            fs::read_to_string(list).unwrap(),
+            fs::read_to_string(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_comparison_with_gccrs() {
+        let dir = mini_corpus();
+        let converted = |path: &str| {
+            let code = fs::read_to_string(dir.path().join(path)).unwrap();
+            let stderr = fs::read_to_string(dir.path().join(path).with_extension("stderr"));
+            crate::transform::transform_code(&code, stderr.ok().as_deref(), &Options::default())
+                .unwrap()
+                .0
+        };
+        let gccrs = dir.path().join("gccrs");
+        let testsuite = gccrs.join("gcc/testsuite/rust");
+        for (path, content) in [
+            ("compile/unused.rs", converted("tests/ui/lint/unused.rs")),
+            // Hand-ported with a different pattern and an extra comment
+            (
+                "compile/mismatch.rs",
+                converted("tests/ui/typeck/mismatch.rs")
+                    .replace("\".E0308.\"", "\"mismatched types\"")
+                    + "// ported by hand\n",
+            ),
+            // Same name, later in path order
+            ("execute/mismatch.rs", String::new()),
+        ] {
+            let path = testsuite.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        let corpus = CorpusOptions {
+            gccrs_src: Some(gccrs),
+            ..CorpusOptions::default()
+        };
+        let report = run(dir.path(), &corpus, &Options::default()).unwrap();
+        let gccrs = report.gccrs.unwrap();
+        assert_eq!(
+            (gccrs.identical, gccrs.directive_only, gccrs.code_differs),
+            (1, 0, 1)
+        );
+        assert_eq!(gccrs.unmatched, 1);
+
+        let unused = &gccrs.files[Path::new("lint/unused.rs")];
+        assert_eq!(unused.classification, Classification::Identical);
+        assert_eq!(unused.diff, "");
+        let mismatch = &gccrs.files[Path::new("typeck/mismatch.rs")];
+        assert_eq!(mismatch.gccrs_file, testsuite.join("compile/mismatch.rs"));
+        assert_eq!(mismatch.classification, Classification::CodeDiffers);
+        assert!(mismatch.diff.contains(
+            "-// { dg-error \"mismatched types\" \"\" { target *-*-* } .-1 }\n+// { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }\n"
+        ), "{}", mismatch.diff);
+
+        // Without the comment, only the directive differs
+        let ported = testsuite.join("compile/mismatch.rs");
+        let content = fs::read_to_string(&ported).unwrap();
+        fs::write(&ported, content.replace("// ported by hand\n", "")).unwrap();
+        let report = run(dir.path(), &corpus, &Options::default()).unwrap();
+        let mismatch = &report.gccrs.unwrap().files[Path::new("typeck/mismatch.rs")];
+        assert_eq!(mismatch.classification, Classification::DirectiveOnly);
+
+        // Nothing was written next to the rust tests nor into the gccrs tree
+        assert_eq!(rust_files(&testsuite).unwrap().len(), 3);
+        assert_eq!(
+            fs::read_to_string(testsuite.join("execute/mismatch.rs")).unwrap(),
+            ""
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! This module contains the coverage of the compiletest constructs by the tool.
+//!
+//! Every transformation counts the header directives and the annotation forms it met,
+//! each classified by what the tool did with it. The corpus runner sums them over a whole
+//! testsuite to show which constructs are common and which ones are not handled yet.
+
+use {
+    crate::errors::AnnotationForm,
+    serde::Serialize,
+    std::{collections::BTreeMap, fmt, io},
+};
+
+/// What the tool does with a construct
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Support {
+    /// Converted into its `DejaGnu` equivalent
+    Handled,
+    /// Understood, but left out of the conversion or kept as a plain comment
+    Dropped,
+    /// Not understood by the tool
+    Unknown,
+}
+
+impl fmt::Display for Support {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Support::Handled => "handled",
+            Support::Dropped => "dropped",
+            Support::Unknown => "unknown",
+        })
+    }
+}
+
+/// How many times a header directive was met
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct HeaderEntry {
+    pub support: Support,
+    pub count: usize,
+}
+
+/// How many times an annotation form was met
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AnnotationEntry {
+    /// The sigil without revisions, like `//~^`
+    pub sigil: String,
+    /// The kind keyword, `null` without a known kind
+    pub kind: Option<&'static str>,
+    pub has_code: bool,
+    pub has_revision: bool,
+    pub support: Support,
+    pub count: usize,
+}
+
+/// The constructs met by one or many transformations
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Coverage {
+    /// By header name, like `edition`
+    pub headers: BTreeMap<String, HeaderEntry>,
+    /// By [`label`] of the form
+    pub annotations: BTreeMap<String, AnnotationEntry>,
+}
+
+/// A short name of an annotation form, like `//~^ ERROR +code`
+pub fn label(form: &AnnotationForm) -> String {
+    let mut label = format!(
+        "{} {}",
+        form.sigil,
+        form.kind.map_or("(no kind)", |kind| kind.keyword())
+    );
+    if form.has_code {
+        label.push_str(" +code");
+    }
+    if form.has_revision {
+        label.push_str(" +revision");
+    }
+    label
+}
+
+impl Coverage {
+    pub fn add_header(&mut self, name: &str, support: Support) {
+        self.headers
+            .entry(name.to_owned())
+            .or_insert(HeaderEntry { support, count: 0 })
+            .count += 1;
+    }
+
+    pub fn add_annotation(&mut self, form: &AnnotationForm, support: Support) {
+        self.annotations
+            .entry(label(form))
+            .or_insert_with(|| AnnotationEntry {
+                sigil: form.sigil.to_string(),
+                kind: form.kind.map(|kind| kind.keyword()),
+                has_code: form.has_code,
+                has_revision: form.has_revision,
+                support,
+                count: 0,
+            })
+            .count += 1;
+    }
+
+    /// Adds the counts of `other`. A construct keeps the support it was first seen with,
+    /// which only differs between files converted with different options.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (name, entry) in &other.headers {
+            self.headers
+                .entry(name.clone())
+                .or_insert(HeaderEntry {
+                    support: entry.support,
+                    count: 0,
+                })
+                .count += entry.count;
+        }
+        for (label, entry) in &other.annotations {
+            self.annotations
+                .entry(label.clone())
+                .or_insert_with(|| AnnotationEntry {
+                    count: 0,
+                    ..entry.clone()
+                })
+                .count += entry.count;
+        }
+    }
+
+    /// Writes the coverage as two aligned tables
+    pub fn write_tables(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        if !self.headers.is_empty() {
+            let width = column_width("header", self.headers.keys());
+            writeln!(writer, "header coverage:")?;
+            writeln!(
+                writer,
+                "  {:width$}  {:7}  {:>6}",
+                "header", "support", "count"
+            )?;
+            for (name, entry) in &self.headers {
+                writeln!(
+                    writer,
+                    "  {name:width$}  {:7}  {:>6}",
+                    entry.support.to_string(),
+                    entry.count
+                )?;
+            }
+        }
+        if !self.annotations.is_empty() {
+            let width = column_width("annotation", self.annotations.keys());
+            writeln!(writer, "annotation coverage:")?;
+            writeln!(
+                writer,
+                "  {:width$}  {:7}  {:>6}",
+                "annotation", "support", "count"
+            )?;
+            for (label, entry) in &self.annotations {
+                writeln!(
+                    writer,
+                    "  {label:width$}  {:7}  {:>6}",
+                    entry.support.to_string(),
+                    entry.count
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The width of a column holding `title` and `names`, in characters
+fn column_width<'a>(title: &str, names: impl Iterator<Item = &'a String>) -> usize {
+    names
+        .map(|name| name.chars().count())
+        .chain([title.len()])
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::errors::{RustcErrorKind, Sigil},
+    };
+
+    #[test]
+    fn test_labels() {
+        let form = AnnotationForm {
+            sigil: Sigil::Caret(2),
+            kind: Some(RustcErrorKind::Warning),
+            has_code: true,
+            has_revision: true,
+        };
+        assert_eq!(label(&form), "//~^^ WARN +code +revision");
+        let form = AnnotationForm {
+            sigil: Sigil::Follow,
+            kind: None,
+            has_code: false,
+            has_revision: false,
+        };
+        assert_eq!(label(&form), "//~| (no kind)");
+    }
+
+    #[test]
+    fn test_merge_and_tables() {
+        let form = AnnotationForm {
+            sigil: Sigil::ThisLine,
+            kind: Some(RustcErrorKind::Error),
+            has_code: false,
+            has_revision: false,
+        };
+        let mut first = Coverage::default();
+        first.add_header("edition", Support::Handled);
+        first.add_annotation(&form, Support::Handled);
+        let mut second = Coverage::default();
+        second.add_header("check-pass", Support::Dropped);
+        second.add_header("edition", Support::Handled);
+        second.add_annotation(&form, Support::Handled);
+        first.merge(&second);
+
+        assert_eq!(first.headers["edition"].count, 2);
+        assert_eq!(first.annotations["//~ ERROR"].count, 2);
+        let mut tables = Vec::new();
+        first.write_tables(&mut tables).unwrap();
+        assert_eq!(
+            String::from_utf8(tables).unwrap(),
+            "\
+header coverage:
+  header      support   count
+  check-pass  dropped       1
+  edition     handled       2
+annotation coverage:
+  annotation  support   count
+  //~ ERROR   handled       2
+"
+        );
+    }
+}
@@ -0,0 +1,758 @@
+//! This module contains the logic for checking `DejaGnu` directive comments like
+//! `// { dg-error "pattern" "comment" { target *-*-* } .-1 }`, and for reading them back
+//! as [`ParsedDirective`]s.
+//!
+//! The directives are Tcl lists, so the validator checks what would make Tcl or the
+//! `DejaGnu` harness choke on them: unbalanced braces, unterminated strings, command or
+//! variable substitutions, unknown directive names, malformed selectors and line specifiers.
+//! See [`DejaGnu` documentation](https://gcc.gnu.org/onlinedocs/gccint/testsuites/directives-used-within-dejagnu-tests/syntax-and-descriptions-of-test-directives.html)
+
+use std::fmt;
+
+/// Directive names accepted by the validator
+pub const KNOWN_DIRECTIVES: &[&str] = &[
+    "dg-additional-options",
+    "dg-additional-sources",
+    "dg-bogus",
+    "dg-do",
+    "dg-error",
+    "dg-excess-errors",
+    "dg-final",
+    "dg-ice",
+    "dg-message",
+    "dg-note",
+    "dg-options",
+    "dg-output",
+    "dg-prune-output",
+    "dg-require-effective-target",
+    "dg-set-compiler-env-var",
+    "dg-shouldfail",
+    "dg-skip-if",
+    "dg-warning",
+    "dg-xfail-if",
+    "dg-xfail-run-if",
+];
+
+/// Directives checking a diagnostic: `name "pattern" ["comment" [{ selector } [line]]]`
+const DIAGNOSTIC_DIRECTIVES: &[&str] = &[
+    "dg-bogus",
+    "dg-error",
+    "dg-message",
+    "dg-note",
+    "dg-warning",
+];
+
+/// A word of a directive, kept as written (escapes are not interpreted)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Word {
+    /// `"..."`
+    Quoted(String),
+    /// `{...}`
+    Braced(String),
+    /// Anything else, up to the next whitespace
+    Bare(String),
+}
+
+impl Word {
+    /// The content of the word, without its quotes or braces
+    pub fn text(&self) -> &str {
+        match self {
+            Word::Quoted(text) | Word::Braced(text) | Word::Bare(text) => text,
+        }
+    }
+}
+
+/// Which line a diagnostic directive refers to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineSpec {
+    /// `.`, `.-N` or `.+N`, relative to the line of the directive
+    Relative(i32),
+    /// `N`, where `0` means anywhere in the file
+    Absolute(usize),
+}
+
+/// A directive comment which passed validation
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedDirective {
+    /// The directive name, e.g. `dg-error`
+    pub name: String,
+    /// The words following the name
+    pub args: Vec<Word>,
+    /// For diagnostic directives, the line specifier if any
+    pub line: Option<LineSpec>,
+}
+
+impl ParsedDirective {
+    /// For diagnostic directives, the pattern matching the message
+    pub fn pattern(&self) -> Option<&str> {
+        is_diagnostic(&self.name).then(|| self.args[0].text())
+    }
+
+    /// For diagnostic directives, the comment naming the test, if any
+    pub fn comment(&self) -> Option<&str> {
+        self.args
+            .get(1)
+            .filter(|_| is_diagnostic(&self.name))
+            .map(Word::text)
+    }
+
+    /// For diagnostic directives, the selector without its braces, like ` target *-*-* `
+    pub fn selector(&self) -> Option<&str> {
+        self.args
+            .get(2)
+            .filter(|_| is_diagnostic(&self.name))
+            .map(Word::text)
+    }
+
+    /// The 1-based line checked by a directive written on the 1-based line `line_num`,
+    /// `0` for anywhere in the file
+    pub fn target_line(&self, line_num: usize) -> Option<usize> {
+        match self.line {
+            None => Some(line_num),
+            Some(LineSpec::Relative(offset)) => line_num.checked_add_signed(offset as isize),
+            Some(LineSpec::Absolute(line)) => Some(line),
+        }
+    }
+}
+
+/// Why a directive comment is invalid
+#[derive(Clone, Debug, PartialEq)]
+pub enum DirectiveError {
+    /// There is no `// { dg-... }` comment on the line
+    NotADirective,
+    /// The directive name is not in [`KNOWN_DIRECTIVES`]
+    UnknownDirective(String),
+    /// A `{` is never closed or a `}` closes nothing
+    UnbalancedBraces,
+    /// A `"` is never closed
+    UnterminatedString,
+    /// An unescaped `[` would be run as a Tcl command
+    CommandSubstitution,
+    /// An unescaped `$name` would be replaced by a Tcl variable
+    VariableSubstitution,
+    /// Something follows the closing brace of the directive
+    TrailingContent(String),
+    /// A diagnostic directive without its pattern
+    MissingPattern,
+    /// The selector is not `{ target ... }` or `{ xfail ... }`
+    BadSelector(String),
+    /// The line specifier is not `.`, `.-N`, `.+N` or `N`
+    BadLineSpec(String),
+    /// More words than the directive accepts
+    TooManyArguments,
+}
+
+impl fmt::Display for DirectiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DirectiveError::NotADirective => write!(f, "no `// {{ dg-... }}` directive found"),
+            DirectiveError::UnknownDirective(name) => write!(f, "unknown directive `{name}`"),
+            DirectiveError::UnbalancedBraces => write!(f, "unbalanced braces"),
+            DirectiveError::UnterminatedString => write!(f, "unterminated string"),
+            DirectiveError::CommandSubstitution => {
+                write!(f, "unescaped `[` starts a Tcl command substitution")
+            }
+            DirectiveError::VariableSubstitution => {
+                write!(f, "unescaped `$` starts a Tcl variable substitution")
+            }
+            DirectiveError::TrailingContent(rest) => {
+                write!(f, "unexpected `{rest}` after the directive")
+            }
+            DirectiveError::MissingPattern => write!(f, "missing pattern"),
+            DirectiveError::BadSelector(selector) => {
+                write!(f, "invalid selector `{{{selector}}}`, expected `{{ target ... }}` or `{{ xfail ... }}`")
+            }
+            DirectiveError::BadLineSpec(spec) => write!(f, "invalid line specifier `{spec}`"),
+            DirectiveError::TooManyArguments => write!(f, "too many arguments"),
+        }
+    }
+}
+
+impl std::error::Error for DirectiveError {}
+
+/// Finds the start of the `{ dg-... }` part of a `// { dg-... }` comment
+fn find_directive(line: &str) -> Option<usize> {
+    let mut search_from = 0;
+    while let Some(pos) = line[search_from..].find("//") {
+        let comment = search_from + pos;
+        let after_slashes = &line[comment + 2..];
+        let trimmed = after_slashes.trim_start();
+        if let Some(body) = trimmed.strip_prefix('{') {
+            if looks_like_directive(body.trim_start(), line) {
+                return Some(line.len() - trimmed.len());
+            }
+        }
+        search_from = comment + 2;
+    }
+    None
+}
+
+/// Whether the `body` of a `// {` comment starts like a directive: a `dg-` name,
+/// or any lowercase word on a line closed by a `}`, to catch misspelled names
+fn looks_like_directive(body: &str, line: &str) -> bool {
+    if body.starts_with("dg-") {
+        return true;
+    }
+    let name_len = body
+        .find(|c: char| !(c.is_ascii_lowercase() || c == '-'))
+        .unwrap_or(body.len());
+    name_len > 0
+        && body[name_len..].starts_with(char::is_whitespace)
+        && line.trim_end().ends_with('}')
+}
+
+/// Whether `name` is a directive checking a diagnostic, like `dg-error`
+pub fn is_diagnostic(name: &str) -> bool {
+    DIAGNOSTIC_DIRECTIVES.contains(&name)
+}
+
+/// Whether the line contains something looking like a `DejaGnu` directive comment
+pub fn has_directive(line: &str) -> bool {
+    find_directive(line).is_some()
+}
+
+/// The code of `line` before its directive comment, if it has one
+pub fn code_before_directive(line: &str) -> Option<&str> {
+    let start = find_directive(line)?;
+    let comment = line[..start].rfind("//")?;
+    Some(line[..comment].trim_end())
+}
+
+/// Parses the directive comment found on `line`, `None` if there is none or it is invalid
+pub fn parse_directive(line: &str) -> Option<ParsedDirective> {
+    validate(line).ok()
+}
+
+/// Parses every valid directive comment of `source`, with its 1-based line
+pub fn scan(source: &str) -> Vec<(usize, ParsedDirective)> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| has_directive(line))
+        .filter_map(|(line_num, line)| Some((line_num + 1, parse_directive(line)?)))
+        .collect()
+}
+
+/// Validates every directive comment of `code`, returning the problems with their 1-based line
+pub fn verify(code: &str) -> Vec<(usize, DirectiveError)> {
+    code.lines()
+        .enumerate()
+        .filter(|(_, line)| has_directive(line))
+        .filter_map(|(line_num, line)| validate(line).err().map(|err| (line_num + 1, err)))
+        .collect()
+}
+
+/// Validates the `// { dg-... }` directive comment found on `line`
+pub fn validate(line: &str) -> Result<ParsedDirective, DirectiveError> {
+    let start = find_directive(line).ok_or(DirectiveError::NotADirective)?;
+    // `start` points at the opening brace of the directive
+    let (body, rest) = split_braced(&line[start + 1..])?;
+    let rest = rest.trim();
+    if !rest.is_empty() {
+        return Err(DirectiveError::TrailingContent(rest.to_owned()));
+    }
+
+    let mut words = split_words(body)?.into_iter();
+    let name = match words.next() {
+        Some(Word::Bare(name)) => name,
+        _ => return Err(DirectiveError::NotADirective),
+    };
+    if !KNOWN_DIRECTIVES.contains(&name.as_str()) {
+        return Err(DirectiveError::UnknownDirective(name));
+    }
+    let args: Vec<Word> = words.collect();
+    let line = if is_diagnostic(&name) {
+        validate_diagnostic(&args)?
+    } else {
+        None
+    };
+
+    Ok(ParsedDirective { name, args, line })
+}
+
+/// Checks the arguments of a diagnostic directive, returning its line specifier
+fn validate_diagnostic(args: &[Word]) -> Result<Option<LineSpec>, DirectiveError> {
+    let mut args = args.iter();
+    match args.next() {
+        Some(Word::Quoted(_) | Word::Braced(_)) => {}
+        _ => return Err(DirectiveError::MissingPattern),
+    }
+    // The comment is optional, as well as everything after it
+    if args.next().is_none() {
+        return Ok(None);
+    }
+    match args.next() {
+        None => return Ok(None),
+        Some(Word::Braced(selector)) => {
+            let keyword = selector.split_whitespace().next();
+            if !matches!(keyword, Some("target" | "xfail")) || split_words(selector)?.len() < 2 {
+                return Err(DirectiveError::BadSelector(selector.clone()));
+            }
+        }
+        Some(word) => return Err(DirectiveError::BadSelector(word.text().to_owned())),
+    }
+    let line = match args.next() {
+        None => return Ok(None),
+        Some(Word::Bare(spec)) => parse_line_spec(spec)?,
+        Some(word) => return Err(DirectiveError::BadLineSpec(word.text().to_owned())),
+    };
+    if args.next().is_some() {
+        return Err(DirectiveError::TooManyArguments);
+    }
+    Ok(Some(line))
+}
+
+fn parse_line_spec(spec: &str) -> Result<LineSpec, DirectiveError> {
+    let bad = || DirectiveError::BadLineSpec(spec.to_owned());
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if spec == "." {
+        return Ok(LineSpec::Relative(0));
+    }
+    if let Some(offset) = spec.strip_prefix('.') {
+        let (sign, digits) = offset.split_at(offset.len().min(1));
+        if !matches!(sign, "+" | "-") || !is_number(digits) {
+            return Err(bad());
+        }
+        let offset: i32 = digits.parse().map_err(|_| bad())?;
+        return Ok(LineSpec::Relative(if sign == "-" {
+            -offset
+        } else {
+            offset
+        }));
+    }
+    if is_number(spec) {
+        return spec.parse().map(LineSpec::Absolute).map_err(|_| bad());
+    }
+    Err(bad())
+}
+
+/// Splits `text`, which starts right after an opening brace, into the braced content
+/// and what follows the matching closing brace
+fn split_braced(text: &str) -> Result<(&str, &str), DirectiveError> {
+    let mut depth = 0;
+    let mut chars = text.char_indices();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '{' => depth += 1,
+            '}' if depth == 0 => return Ok((&text[..pos], &text[pos + 1..])),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    Err(DirectiveError::UnbalancedBraces)
+}
+
+/// Splits a Tcl list into its words
+fn split_words(text: &str) -> Result<Vec<Word>, DirectiveError> {
+    let mut words = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let (word, after) = if let Some(quoted) = rest.strip_prefix('"') {
+            let (content, after) = split_quoted(quoted)?;
+            (Word::Quoted(content.to_owned()), after)
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            let (content, after) = split_braced(braced)?;
+            (Word::Braced(content.to_owned()), after)
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let bare = &rest[..end];
+            if bare.contains(['{', '}']) {
+                return Err(DirectiveError::UnbalancedBraces);
+            }
+            check_substitutions(bare)?;
+            (Word::Bare(bare.to_owned()), &rest[end..])
+        };
+        // Words must be separated by whitespace
+        if !after.is_empty() && !after.starts_with(char::is_whitespace) {
+            return Err(DirectiveError::TrailingContent(after.trim().to_owned()));
+        }
+        words.push(word);
+        rest = after.trim_start();
+    }
+    Ok(words)
+}
+
+/// Splits `text`, which starts right after an opening quote, into the quoted content
+/// and what follows the closing quote
+fn split_quoted(text: &str) -> Result<(&str, &str), DirectiveError> {
+    let mut depth = 0;
+    let mut chars = text.char_indices();
+    while let Some((pos, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '"' if depth != 0 => return Err(DirectiveError::UnbalancedBraces),
+            '"' => {
+                let content = &text[..pos];
+                check_substitutions(content)?;
+                return Ok((content, &text[pos + 1..]));
+            }
+            // Braces in strings must be balanced too, the harness reads the
+            // whole directive as a braced Tcl list
+            '{' => depth += 1,
+            '}' if depth == 0 => return Err(DirectiveError::UnbalancedBraces),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    Err(DirectiveError::UnterminatedString)
+}
+
+/// Rejects the unescaped `[` and `$name` substitutions Tcl performs in strings
+fn check_substitutions(text: &str) -> Result<(), DirectiveError> {
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' => return Err(DirectiveError::CommandSubstitution),
+            '$' if chars
+                .peek()
+                .is_some_and(|next| next.is_alphanumeric() || matches!(next, '_' | '{' | ':')) =>
+            {
+                return Err(DirectiveError::VariableSubstitution);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            errors::{AnnotationError, MsgStyle},
+            transform,
+        },
+    };
+
+    #[test]
+    fn test_valid_directives() {
+        let directive =
+            validate("    foo(); // { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }").unwrap();
+        assert_eq!(directive.name, "dg-error");
+        assert_eq!(directive.args[0], Word::Quoted(".E0308.".to_owned()));
+        assert_eq!(directive.line, Some(LineSpec::Relative(-1)));
+
+        let directive = validate("// { dg-warning \"unused\" \"\" { target *-*-* } }").unwrap();
+        assert_eq!(directive.line, None);
+        let directive = validate("// { dg-note \"\" \"\" { xfail *-*-* } 7 }").unwrap();
+        assert_eq!(directive.line, Some(LineSpec::Absolute(7)));
+        let directive = validate("// { dg-error \"x\" \"\" { target *-*-* } .+3 }").unwrap();
+        assert_eq!(directive.line, Some(LineSpec::Relative(3)));
+        assert!(validate("// { dg-error \"x\" }").is_ok());
+        assert!(validate("// { dg-additional-options \"-frust-edition=2021\" }").is_ok());
+        assert!(validate("// { dg-do compile }").is_ok());
+    }
+
+    #[test]
+    fn test_tricky_messages() {
+        // Escaped braces, brackets, quotes and dollars are fine
+        assert!(validate(
+            r#"// { dg-error "found \\\{x\\\} in \\\[\\\$y\\\]" "" { target *-*-* } }"#
+        )
+        .is_ok());
+        assert!(validate(r#"// { dg-error "a \"quoted\" {balanced} word" }"#).is_ok());
+        assert_eq!(
+            validate(r#"// { dg-error "found {x" }"#),
+            Err(DirectiveError::UnbalancedBraces)
+        );
+        // The closing brace ends the directive early, as it would for `DejaGnu`
+        assert!(validate(r#"// { dg-error "found }x{" }"#).is_err());
+        assert_eq!(
+            validate(r#"// { dg-error "expected [E0308]" }"#),
+            Err(DirectiveError::CommandSubstitution)
+        );
+        assert_eq!(
+            validate(r#"// { dg-error "cannot find $DIR" }"#),
+            Err(DirectiveError::VariableSubstitution)
+        );
+        assert_eq!(
+            validate(r#"// { dg-error "unterminated }"#),
+            Err(DirectiveError::UnterminatedString)
+        );
+    }
+
+    #[test]
+    fn test_invalid_directives() {
+        assert_eq!(validate("fn main() {}"), Err(DirectiveError::NotADirective));
+        assert_eq!(
+            validate("// { dg-erorr \"\" }"),
+            Err(DirectiveError::UnknownDirective("dg-erorr".to_owned()))
+        );
+        assert_eq!(
+            validate("// { dg-error \"\" \"\" { target *-*-* } .-1"),
+            Err(DirectiveError::UnbalancedBraces)
+        );
+        assert_eq!(
+            validate("// { dg-error \"\" \"\" { target *-*-* } .-1 } }"),
+            Err(DirectiveError::TrailingContent("}".to_owned()))
+        );
+        assert_eq!(
+            validate("// { dg-error }"),
+            Err(DirectiveError::MissingPattern)
+        );
+        assert_eq!(
+            validate("// { dg-error \"\" \"\" { *-*-* } }"),
+            Err(DirectiveError::BadSelector(" *-*-* ".to_owned()))
+        );
+        assert_eq!(
+            validate("// { dg-error \"\" \"\" { target *-*-* } .1 }"),
+            Err(DirectiveError::BadLineSpec(".1".to_owned()))
+        );
+        assert_eq!(
+            validate("// { dg-error \"\" \"\" { target *-*-* } -1 }"),
+            Err(DirectiveError::BadLineSpec("-1".to_owned()))
+        );
+        assert_eq!(
+            validate("// { dg-error \"\" \"\" { target *-*-* } .-1 extra }"),
+            Err(DirectiveError::TooManyArguments)
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let code = "\
+// { dg-do compile }
+fn main() { // { not a directive
+    let x = 1; // { dg-erorr \"\" \"\" { target *-*-* } }
+    let y = 2; // { dg-error \"\" \"\" { target *-*-* } .-1
+    let z = 3; // { dg-warning \"\" \"\" { target *-*-* } }
+    // { help \"\" \"\" { target *-*-* } .-1 }
+}
+";
+        assert_eq!(
+            verify(code),
+            [
+                (3, DirectiveError::UnknownDirective("dg-erorr".to_owned())),
+                (4, DirectiveError::UnbalancedBraces),
+                (6, DirectiveError::UnknownDirective("help".to_owned())),
+            ]
+        );
+        assert!(verify("fn f() { x } // { dg-error \"\" }\n").is_empty());
+    }
+
+    #[test]
+    fn test_code_before_directive() {
+        assert_eq!(
+            code_before_directive("    f(); // a // { dg-error \"\" \"\" { target *-*-* } }"),
+            Some("    f(); // a")
+        );
+        assert_eq!(
+            code_before_directive("// { dg-note \"\" \"\" { target *-*-* } .-1 }"),
+            Some("")
+        );
+        assert_eq!(code_before_directive("    f(); // plain comment"), None);
+    }
+
+    #[test]
+    fn test_parse_directive_fields() {
+        let directive =
+            parse_directive("    foo(); // { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }")
+                .unwrap();
+        assert_eq!(directive.pattern(), Some(".E0308."));
+        assert_eq!(directive.comment(), Some(""));
+        assert_eq!(directive.selector(), Some(" target *-*-* "));
+        assert_eq!(directive.target_line(3), Some(2));
+
+        // Nested braces in the selector and no comment at all
+        let directive =
+            parse_directive("// { dg-warning \"x\" \"\" { target { ! x86_64-*-* } } 4 }").unwrap();
+        assert_eq!(directive.selector(), Some(" target { ! x86_64-*-* } "));
+        assert_eq!(directive.target_line(9), Some(4));
+        let directive = parse_directive("// { dg-note {braced pattern} }").unwrap();
+        assert_eq!(directive.pattern(), Some("braced pattern"));
+        assert_eq!(directive.comment(), None);
+        assert_eq!(directive.target_line(9), Some(9));
+
+        let directive =
+            parse_directive("// { dg-additional-options \"-frust-edition=2018\" }").unwrap();
+        assert_eq!(directive.pattern(), None);
+        assert_eq!(directive.args[0].text(), "-frust-edition=2018");
+        assert_eq!(directive.target_line(1), Some(1));
+    }
+
+    #[test]
+    fn test_malformed_directives_are_none() {
+        for line in [
+            "",
+            "//",
+            "// {",
+            "// { }",
+            "// { dg-",
+            "// { dg-error",
+            "// { dg-error \"",
+            "// { dg-error \"\\",
+            "// { dg-error \"x\" \"\" {",
+            "// { dg-error \"x\" \"\" { target",
+            "// { dg-error \"x\" \"\" { target *-*-* } .- }",
+            "// { dg-error \"x\" \"\" { target *-*-* } .-99999999999 }",
+            "// { dg-error \"x\" \"\" { target *-*-* } 99999999999999999999999 }",
+            "// { dg-error \"x\"\"\" }",
+            "// { dg-error \"x\" } // { dg-error \"y\" }",
+            "// { dg-error \"é\\",
+            "// { 漢字 }",
+            "// { dg-error {} {} {} {} {} }",
+        ] {
+            assert_eq!(parse_directive(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn test_scan_fixtures() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let mut scanned = 0;
+        for entry in std::fs::read_dir(fixtures).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|ext| ext != "expected") {
+                continue;
+            }
+            let code = std::fs::read_to_string(&path).unwrap();
+            let directives = scan(&code);
+            let lines: Vec<usize> = code
+                .lines()
+                .enumerate()
+                .filter(|(_, line)| line.contains("{ dg-"))
+                .map(|(line_num, _)| line_num + 1)
+                .collect();
+            assert_eq!(
+                directives.iter().map(|(line, _)| *line).collect::<Vec<_>>(),
+                lines,
+                "{}",
+                path.display()
+            );
+            scanned += directives.len();
+        }
+        assert!(scanned > 0);
+        assert_eq!(
+            scan("fn main() {}\n// { dg-error \"\" \"\" { target *-*-* } .-1 }\n// { bad }\n"),
+            [(
+                2,
+                ParsedDirective {
+                    name: "dg-error".to_owned(),
+                    args: vec![
+                        Word::Quoted(String::new()),
+                        Word::Quoted(String::new()),
+                        Word::Braced(" target *-*-* ".to_owned()),
+                        Word::Bare(".-1".to_owned()),
+                    ],
+                    line: Some(LineSpec::Relative(-1)),
+                }
+            )]
+        );
+    }
+
+    /// A small xorshift generator, so that the property test is deterministic
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+            choices[(self.next() % choices.len() as u64) as usize]
+        }
+    }
+
+    const MESSAGES: &[&str] = &[
+        "mismatched types",
+        "expected one of `:`, `@`, or `|`, found `)`",
+        "expected `{`, found `}`",
+        "unclosed delimiter {",
+        "cannot find \"value\" in [this] scope",
+        r"unknown escape \x \\ \{",
+        "$DIR/foo.rs and ${var} and $",
+        "`#[warn(unused_variables)]` on by default",
+        "a̐ é ö 漢字 🦀",
+        "",
+    ];
+
+    const ANNOTATIONS: &[&str] = &[
+        "//~ ERROR",
+        "//~^ ERROR",
+        "//~^^ WARN",
+        "//~| NOTE",
+        "//~| HELP",
+        "//~ SUGGESTION",
+        "//~^ ERROR E0308",
+        "//~",
+        "//[rev]~^ ERROR",
+    ];
+
+    /// Generates a source file with random code lines and annotations
+    fn generate_source(rng: &mut Rng) -> String {
+        let mut code = String::new();
+        if rng.next().is_multiple_of(2) {
+            code.push_str(rng.pick(&["//@ edition:2021\n", "//@ edition: 2018\n"]));
+        }
+        for _ in 0..rng.next() % 20 {
+            let code_part = rng.pick(&["", "    let x = 1; ", "    foo(\"{\"); ", "}"]);
+            if rng.next().is_multiple_of(3) {
+                code.push_str(code_part);
+            } else {
+                let annotation = rng.pick(ANNOTATIONS);
+                let message = rng.pick(MESSAGES);
+                code.push_str(&format!("{code_part}{annotation} {message}"));
+            }
+            code.push('\n');
+        }
+        code
+    }
+
+    #[test]
+    fn test_every_generated_directive_is_valid() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let mut rejected = 0;
+        for _ in 0..500 {
+            let code = generate_source(&mut rng);
+            for msg_style in [
+                MsgStyle::None,
+                MsgStyle::Prefix,
+                MsgStyle::Escaped,
+                MsgStyle::Portable,
+            ] {
+                let options = transform::Options {
+                    msg_style,
+                    ..transform::Options::default()
+                };
+                // Orphan follows and carets pointing to line 0 or above are rejected, not
+                // converted, which a caret on the first line of a source is
+                let new_code = match transform::transform_code(&code, None, &options) {
+                    Ok((new_code, _)) => new_code,
+                    Err(err) => {
+                        assert!(err.is::<AnnotationError>(), "{err}");
+                        rejected += 1;
+                        continue;
+                    }
+                };
+                for line in new_code.lines().filter(|line| line.contains("{ dg-")) {
+                    if let Err(err) = validate(line) {
+                        panic!("invalid directive `{line}` ({err}) generated from:\n{code}");
+                    }
+                }
+                // `HELP` and `SUGGESTION` annotations are rendered under their own names,
+                // which are no `DejaGnu` directives
+                let directives = new_code
+                    .lines()
+                    .filter(|line| line.contains("{ dg-"))
+                    .count();
+                assert_eq!(scan(&new_code).len(), directives, "generated from:\n{code}");
+            }
+        }
+        assert!(
+            rejected < 1000,
+            "most generated sources must be converted, {rejected} were not"
+        );
+    }
+}
@@ -0,0 +1,206 @@
+//! This module contains a small line diff, used to compare converted tests with other
+//! versions of them.
+//!
+//! The diff is the longest common subsequence of the lines, after trimming the common
+//! prefix and suffix. Tests are small, but a pair of files too large for the table is
+//! shown as a whole removal followed by a whole addition rather than taking forever.
+
+use std::fmt::Write;
+
+/// Number of unchanged lines shown around each change
+pub const CONTEXT: usize = 3;
+
+/// Largest number of cells of the longest common subsequence table
+const MAX_TABLE: usize = 4 * 1024 * 1024;
+
+/// A line of a diff
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// The lines turning `old` into `new`
+pub fn lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut diff: Vec<Line> = old[..prefix].iter().map(|line| Line::Same(line)).collect();
+    let (mut i, mut j) = (0, 0);
+    if a.len() * b.len() <= MAX_TABLE {
+        // `table[i * width + j]` is the length of the common subsequence of `a[i..]` and `b[j..]`
+        let width = b.len() + 1;
+        let mut table = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                table[i * width + j] = if a[i] == b[j] {
+                    table[(i + 1) * width + j + 1] + 1
+                } else {
+                    table[(i + 1) * width + j].max(table[i * width + j + 1])
+                };
+            }
+        }
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                diff.push(Line::Same(a[i]));
+                i += 1;
+                j += 1;
+            } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+                diff.push(Line::Removed(a[i]));
+                i += 1;
+            } else {
+                diff.push(Line::Added(b[j]));
+                j += 1;
+            }
+        }
+    }
+    diff.extend(a[i..].iter().map(|line| Line::Removed(line)));
+    diff.extend(b[j..].iter().map(|line| Line::Added(line)));
+    diff.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Line::Same(line)),
+    );
+    diff
+}
+
+/// The diff of `old` and `new` in the unified format, empty if their lines are the same
+pub fn unified(old_name: &str, new_name: &str, old: &str, new: &str) -> String {
+    let diff = lines(old, new);
+    let changes: Vec<usize> = diff
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Same(_)))
+        .map(|(index, _)| index)
+        .collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Number of old and new lines before each line of the diff
+    let mut positions = Vec::with_capacity(diff.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for line in &diff {
+        positions.push((old_pos, new_pos));
+        match line {
+            Line::Same(_) => {
+                old_pos += 1;
+                new_pos += 1;
+            }
+            Line::Removed(_) => old_pos += 1,
+            Line::Added(_) => new_pos += 1,
+        }
+    }
+    positions.push((old_pos, new_pos));
+
+    let mut output = format!("--- {old_name}\n+++ {new_name}\n");
+    let mut change = 0;
+    while change < changes.len() {
+        let start = changes[change].saturating_sub(CONTEXT);
+        let mut last = changes[change];
+        // Changes separated by less than twice the context share a hunk
+        while change + 1 < changes.len() && changes[change + 1] <= last + 2 * CONTEXT + 1 {
+            change += 1;
+            last = changes[change];
+        }
+        change += 1;
+        let end = (last + CONTEXT + 1).min(diff.len());
+
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        let _ = writeln!(
+            output,
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_end - old_start),
+            hunk_range(new_start, new_end - new_start)
+        );
+        for line in &diff[start..end] {
+            let _ = match line {
+                Line::Same(line) => writeln!(output, " {line}"),
+                Line::Removed(line) => writeln!(output, "-{line}"),
+                Line::Added(line) => writeln!(output, "+{line}"),
+            };
+        }
+    }
+    output
+}
+
+/// The range of a hunk, given the number of lines before it and its length
+fn hunk_range(before: usize, count: usize) -> String {
+    match count {
+        0 => format!("{before},0"),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{count}", before + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical_files_have_no_diff() {
+        assert_eq!(unified("a", "b", "x\ny\n", "x\ny"), "");
+        assert_eq!(lines("x\n", "x\n"), [Line::Same("x")]);
+    }
+
+    #[test]
+    fn test_unified_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\n";
+        let new = "1\n2\n3\nfour\n5\n6\n7\n8\n9\n10\n11\n12\n13\n14\n15\n16\n17\n";
+        assert_eq!(
+            unified("old.rs", "new.rs", old, new),
+            "\
+--- old.rs
++++ new.rs
+@@ -1,7 +1,7 @@
+ 1
+ 2
+ 3
+-4
++four
+ 5
+ 6
+ 7
+@@ -14,3 +14,4 @@
+ 14
+ 15
+ 16
++17
+"
+        );
+        // Close changes are merged into one hunk
+        let new = "1\n2\n3\nfour\n5\n6\n7\n8\n9\nten\n11\n12\n13\n14\n15\n16\n";
+        assert_eq!(unified("a", "b", old, new).matches("@@ -").count(), 1);
+    }
+
+    #[test]
+    fn test_insertions_and_removals() {
+        assert_eq!(
+            lines("a\nb\nc\n", "a\nx\nc\nd\n"),
+            [
+                Line::Same("a"),
+                Line::Removed("b"),
+                Line::Added("x"),
+                Line::Same("c"),
+                Line::Added("d"),
+            ]
+        );
+        assert_eq!(
+            unified("a", "b", "", "x\n"),
+            "--- a\n+++ b\n@@ -0,0 +1 @@\n+x\n"
+        );
+    }
+}
@@ -2,33 +2,20 @@
 
 use {
     self::WhichLine::*,
-    std::{fmt, str::FromStr},
+    crate::regexes,
+    serde::Serialize,
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        fmt,
+        ops::Range,
+        str::FromStr,
+    },
 };
 
-/// Macro used to lazily create a new regex the first time it is invoked.
-///
-/// # Arguments
-///
-/// * `re` - The regex literal string used to build the automaton
-///
-/// # Example
-///
-/// ```rust
-/// assert!(regex!(r"\w").is_match(" "));
-/// ```
-///
-/// Taken from here https://docs.rs/once_cell/1.20.2/once_cell/index.html#lazily-compiled-regex
-#[macro_export]
-macro_rules! regex {
-    ($re:literal $(,)?) => {{
-        static RE: once_cell::sync::OnceCell<regex::Regex> = once_cell::sync::OnceCell::new();
-        RE.get_or_init(|| regex::Regex::new($re).unwrap())
-    }};
-}
-
 /// Represents the different kinds of Rustc compiler messages.
 /// See [rustc dev guide](https://rustc-dev-guide.rust-lang.org/tests/ui.html#error-levels)
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum RustcErrorKind {
     Help,
     Error,
@@ -40,23 +27,55 @@ pub enum RustcErrorKind {
 impl FromStr for RustcErrorKind {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.to_uppercase();
+        RustcErrorKind::from_keyword(s).ok_or(())
+    }
+}
+
+impl RustcErrorKind {
+    /// The keywords of each kind, in uppercase
+    const KEYWORDS: [(&'static str, RustcErrorKind); 6] = [
+        ("HELP", RustcErrorKind::Help),
+        ("ERROR", RustcErrorKind::Error),
+        ("NOTE", RustcErrorKind::Note),
+        ("SUGGESTION", RustcErrorKind::Suggestion),
+        ("WARN", RustcErrorKind::Warning),
+        ("WARNING", RustcErrorKind::Warning),
+    ];
+
+    /// Parses the first word of an annotation, ignoring case, without allocating
+    fn from_keyword(word: &str) -> Option<Self> {
         // Some RustcErrorKinds has this colon, so we need to split it
         // See this for example:
         // https://github.com/rust-lang/rust/blob/master/tests/ui/async-await/in-trait/fn-not-async-err.rs#L9
-        let part0: &str = s
-            .split(':')
-            .next()
-            .expect("split always returns at least one element");
-        match part0 {
-            "HELP" => Ok(RustcErrorKind::Help),
-            "ERROR" => Ok(RustcErrorKind::Error),
-            "NOTE" => Ok(RustcErrorKind::Note),
-            "SUGGESTION" => Ok(RustcErrorKind::Suggestion),
-            "WARN" | "WARNING" => Ok(RustcErrorKind::Warning),
-            _ => Err(()),
+        let part0 = word.split(':').next().unwrap_or(word);
+        if !part0.is_ascii() {
+            // Unicode uppercasing turns a few non-ASCII letters (like `ı` or `ſ`)
+            // into ASCII ones, keep accepting them like before
+            return Self::from_uppercase(&part0.to_uppercase());
+        }
+        Self::KEYWORDS
+            .iter()
+            .find(|(keyword, _)| keyword.eq_ignore_ascii_case(part0))
+            .map(|&(_, kind)| kind)
+    }
+
+    /// The keyword annotations of this kind are usually written with, like `WARN`
+    pub fn keyword(self) -> &'static str {
+        match self {
+            RustcErrorKind::Help => "HELP",
+            RustcErrorKind::Error => "ERROR",
+            RustcErrorKind::Note => "NOTE",
+            RustcErrorKind::Suggestion => "SUGGESTION",
+            RustcErrorKind::Warning => "WARN",
         }
     }
+
+    fn from_uppercase(word: &str) -> Option<Self> {
+        Self::KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == word)
+            .map(|&(_, kind)| kind)
+    }
 }
 
 impl fmt::Display for RustcErrorKind {
@@ -71,14 +90,23 @@ impl fmt::Display for RustcErrorKind {
     }
 }
 
+impl RustcErrorKind {
+    /// Secondary diagnostics (`NOTE` and `HELP`) only add context to a primary error.
+    /// gccrs rarely emits them the same way rustc does.
+    pub fn is_secondary(self) -> bool {
+        matches!(self, RustcErrorKind::Note | RustcErrorKind::Help)
+    }
+}
+
 /// To store information from rustc source file
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Error {
     pub line_num: usize,
-    /// We also need to take into account the relative line number.
-    /// - `1` if the error is on the previous line
+    /// The line of the error relative to the line of the annotation, which is where the
+    /// directive goes.
+    /// - `-N` if the error is `N` lines above, like for `//~^`
     /// - `0` if the error is on the same line
-    /// - `-1` if the error is on the next line
+    /// - `N` if the error is `N` lines below, like for `//~v`
     pub relative_line_num: i32,
 
     /// What kind of message we expect (e.g., warning, error, suggestion).
@@ -87,43 +115,318 @@ pub struct Error {
     /// Note: if we are loading this from rustc source file, this might be incomplete
     pub msg: String,
     pub error_code: Option<String>,
+    /// The column (1-based) of the line rustc reported the error at, from the `stderr` file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    /// The revisions of a `//[rev1,rev2]~` annotation, empty when it applies to all of them
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<String>,
+    /// Whether the error has no span, from a `//~?` annotation
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub whole_file: bool,
+    /// The lint of a warning, like `unused_variables`, from the `[unused_variables]` ending
+    /// its annotation or from its `stderr` entry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lint: Option<String>,
+    /// The byte range of the annotation in its line, from the start of its sigil to the end
+    /// of the line, which the code before it is kept without. `None` without annotation,
+    /// for the errors of the `stderr` file.
+    #[serde(skip)]
+    pub annotation_span: Option<(usize, usize)>,
+    /// The line of the emitted file the directive targets, written instead of the offset
+    /// from the directive, see `--line-numbers absolute`. `None` writes the offset.
+    #[serde(skip)]
+    pub target_line: Option<usize>,
 }
 
 impl fmt::Display for Error {
     /// Formats the `Error` for display according to `DejaGnu` format
     /// See [`DejaGnu` documentation](https://gcc.gnu.org/onlinedocs/gccint/testsuites/directives-used-within-dejagnu-tests/syntax-and-descriptions-of-test-directives.html)
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        use RustcErrorKind::*;
+        self.write_directive(f, "", Selector::ANY)
+    }
+}
 
-        let error_code = self.error_code.as_ref().map_or("", |code| &code[..]);
+/// The targets of the directives of a test running on every target
+pub const ANY_TARGET: &str = "*-*-*";
 
-        let error_type = match &self.kind {
-            Some(Help) => "help",
-            Some(Note) => "dg-note",
-            Some(Suggestion) => "suggestion",
-            Some(Warning) => "dg-warning",
-            Some(Error) | None => "dg-error",
-        };
+/// The selector of a directive, `{ target ... }` unless it is a `dg-error` expected to fail
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Selector<'t> {
+    /// The target triplets, like [`ANY_TARGET`]
+    pub targets: &'t str,
+    /// The `dg-error` directives are `{ xfail ... }`, for the tests of a rustc bug
+    pub xfail: bool,
+}
 
-        let error_code = if error_code.is_empty() {
-            error_code.to_owned()
-        } else {
-            format!(".{}.", error_code)
-        };
+impl Selector<'static> {
+    /// Every target, no failure expected
+    pub const ANY: Selector<'static> = Selector {
+        targets: ANY_TARGET,
+        xfail: false,
+    };
+}
 
-        let rel_line_number = if self.relative_line_num == 0 {
-            "".to_owned()
+impl Selector<'_> {
+    /// The selector without its braces, for a directive named `directive`
+    pub fn for_directive(&self, directive: &str) -> String {
+        let keyword = if self.xfail && directive == "dg-error" {
+            "xfail"
         } else {
-            format!(".{} ", self.relative_line_num)
+            "target"
         };
+        format!("{keyword} {}", self.targets)
+    }
+}
 
-        write!(
-            f,
-            "// {{ {error_type} \"{error_code}\" \"\" {{ target *-*-* }} {rel_line_number}}}"
-        )
+impl Error {
+    /// Whether the annotation applies to `revision`, every annotation applies to `None`
+    pub fn applies_to(&self, revision: Option<&str>) -> bool {
+        revision.is_none_or(|revision| {
+            self.revisions.is_empty() || self.revisions.iter().any(|rev| rev == revision)
+        })
+    }
+
+    /// Whether the error has no line, from a `//~?` annotation. Its directive is on line 0,
+    /// which matches anywhere in the file.
+    pub fn is_whole_file(&self) -> bool {
+        self.whole_file
+    }
+
+    /// Formats the `Error` like its `Display` implementation, but with `message`
+    /// (usually built by [`message_pattern`]) embedded in the pattern.
+    pub fn to_directive(&self, message: &str) -> String {
+        self.to_directive_for(message, Selector::ANY)
+    }
+
+    /// [`Error::to_directive`], with `selector` rather than every target
+    pub fn to_directive_for(&self, message: &str, selector: Selector) -> String {
+        let mut directive = String::new();
+        self.write_directive(&mut directive, message, selector)
+            .expect("writing to a String never fails");
+        directive
+    }
+
+    fn write_directive(
+        &self,
+        f: &mut impl fmt::Write,
+        message: &str,
+        selector: Selector,
+    ) -> fmt::Result {
+        let error_type = dg_directive(self.kind);
+
+        write!(f, "// {{ {error_type} \"")?;
+        // The message comes first, as rustc and gccrs print the error code at the end
+        f.write_str(message)?;
+        match self.error_code.as_deref() {
+            Some(error_code) if !error_code.is_empty() => {
+                if !message.is_empty() {
+                    f.write_str(".*")?;
+                }
+                // Only a malformed code needs escaping, a valid one has no special character
+                write!(f, ".{}.", escape_dejagnu(error_code))?;
+            }
+            _ => {}
+        }
+        write!(f, "\" \"\" {{ {} }} ", selector.for_directive(error_type))?;
+
+        if self.is_whole_file() {
+            f.write_str("0 ")?;
+        } else if let Some(line) = self.target_line {
+            write!(f, "{line} ")?;
+        } else if self.relative_line_num != 0 {
+            // `.-1` above the directive, `.+1` below it
+            write!(f, ".{:+} ", self.relative_line_num)?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// The name of the directive an annotation of `kind` is rendered as. `DejaGnu` has no
+/// directive for the help messages and suggestions, `dg-message` matches any diagnostic.
+pub fn dg_directive(kind: Option<RustcErrorKind>) -> &'static str {
+    use RustcErrorKind::*;
+
+    match kind {
+        Some(Help | Suggestion) => "dg-message",
+        Some(Note) => "dg-note",
+        Some(Warning) => "dg-warning",
+        Some(Error) | None => "dg-error",
+    }
+}
+
+/// The start of the patterns of `kind`, which the `dg-message` directives match along with
+/// the message since they do not say which diagnostic they are for
+pub fn message_prefix(kind: Option<RustcErrorKind>) -> &'static str {
+    match dg_directive(kind) {
+        "dg-message" => "help: ",
+        _ => "",
+    }
+}
+
+/// The `pattern` of a message of `kind` in its directive, after the [`message_prefix`].
+/// An empty pattern stays empty, matching any message.
+pub fn prefixed_pattern(kind: Option<RustcErrorKind>, pattern: &str) -> String {
+    match pattern {
+        "" => String::new(),
+        pattern => format!("{}{pattern}", message_prefix(kind)),
+    }
+}
+
+/// The kind of the annotations rendered as the directive `name`, the inverse of [`dg_directive`]
+pub fn directive_kind(name: &str) -> Option<RustcErrorKind> {
+    use RustcErrorKind::*;
+
+    [Error, Warning, Note, Help, Suggestion]
+        .into_iter()
+        .find(|&kind| dg_directive(Some(kind)) == name)
+}
+
+/// How the annotation message is embedded in the directive pattern
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MsgStyle {
+    /// The whole message, with regex and Tcl special characters escaped
+    Escaped,
+    /// The message up to (not including) the first regex special character
+    Prefix,
+    /// The message escaped like `escaped`, where any character matches those gccrs prints
+    /// differently or a pattern cannot hold: backticks, control and non-ASCII characters.
+    /// Long messages are cut at a word boundary, see `--max-message-length`. The lint of a
+    /// warning follows it, like gcc prints the option of its warnings.
+    Portable,
+    /// No message at all, only the error code if any
+    #[default]
+    None,
+}
+
+/// Characters with a special meaning in a `DejaGnu` pattern, either for the regex
+/// engine or for the Tcl string holding it
+const PATTERN_SPECIAL_CHARS: &[char] = &[
+    '\\', '.', '^', '$', '|', '?', '*', '+', '(', ')', '[', ']', '{', '}', '"',
+];
+
+/// The default length of the messages of [`MsgStyle::Portable`], in characters
+pub const DEFAULT_MAX_MESSAGE_LENGTH: usize = 80;
+
+/// A message turned into the message part of a directive pattern
+#[derive(Debug, PartialEq)]
+pub struct MessagePattern {
+    pub pattern: String,
+    /// Number of characters cut off from the message by [`MsgStyle::Prefix`] or
+    /// [`MsgStyle::Portable`]
+    pub truncated: usize,
+}
+
+/// Builds the message part of a directive pattern according to `style`
+pub fn message_pattern(msg: &str, style: MsgStyle) -> MessagePattern {
+    message_pattern_within(msg, style, 0)
+}
+
+/// [`message_pattern`], where a [`MsgStyle::Portable`] message longer than `max_length`
+/// characters is cut at the last word boundary before it. `0` keeps the whole message.
+pub fn message_pattern_within(msg: &str, style: MsgStyle, max_length: usize) -> MessagePattern {
+    match style {
+        MsgStyle::None => MessagePattern {
+            pattern: String::new(),
+            truncated: 0,
+        },
+        MsgStyle::Prefix => {
+            let prefix = msg
+                .find(PATTERN_SPECIAL_CHARS)
+                .map_or(msg, |end| &msg[..end]);
+            MessagePattern {
+                pattern: prefix.to_owned(),
+                truncated: msg[prefix.len()..].chars().count(),
+            }
+        }
+        MsgStyle::Escaped => MessagePattern {
+            pattern: escape_dejagnu(msg),
+            truncated: 0,
+        },
+        MsgStyle::Portable => {
+            let kept = truncate_at_word(msg, max_length);
+            let mut pattern = String::with_capacity(kept.len());
+            for c in kept.chars() {
+                if c == '`' || c.is_control() || !c.is_ascii() {
+                    pattern.push('.');
+                } else {
+                    push_escaped(&mut pattern, c);
+                }
+            }
+            MessagePattern {
+                pattern,
+                truncated: msg.chars().count() - kept.chars().count(),
+            }
+        }
+    }
+}
+
+/// The start of `msg` up to the last word boundary within `max_length` characters, the
+/// whole of it when it is short enough or `max_length` is `0`
+fn truncate_at_word(msg: &str, max_length: usize) -> &str {
+    let Some((end, _)) = msg
+        .char_indices()
+        .nth(max_length)
+        .filter(|_| max_length > 0)
+    else {
+        return msg;
+    };
+    let cut = &msg[..end];
+    if msg[end..].starts_with(char::is_whitespace) {
+        return cut.trim_end();
+    }
+    match cut.rfind(char::is_whitespace) {
+        // A single word longer than the limit is cut in the middle
+        Some(space) if !cut[..space].trim_end().is_empty() => cut[..space].trim_end(),
+        _ => cut,
     }
 }
 
+/// Escapes `msg` so that it matches itself literally once Tcl and the regex engine are done
+/// with it, the way the gcc testsuite writes its patterns: the regex special characters get
+/// a backslash, doubled for Tcl, and those special to Tcl too get one more. A double quote
+/// would end the string, any character matches it instead.
+pub fn escape_dejagnu(msg: &str) -> String {
+    let mut pattern = String::with_capacity(msg.len());
+    for c in msg.chars() {
+        push_escaped(&mut pattern, c);
+    }
+    pattern
+}
+
+/// Pushes `c` to `pattern`, escaped to match itself literally
+fn push_escaped(pattern: &mut String, c: char) {
+    match c {
+        // A double quote would end the Tcl string, any character will do instead
+        '"' => pattern.push('.'),
+        // Tcl turns `\\\\` into `\\`, which the regex engine reads as a literal backslash
+        '\\' => pattern.push_str("\\\\\\\\"),
+        // These are also special for Tcl, so the escaping backslash needs its own one
+        '[' | ']' | '{' | '}' | '$' => {
+            pattern.push_str("\\\\\\");
+            pattern.push(c);
+        }
+        c if PATTERN_SPECIAL_CHARS.contains(&c) => {
+            pattern.push_str("\\\\");
+            pattern.push(c);
+        }
+        c => pattern.push(c),
+    }
+}
+
+/// Escapes `text` for a Tcl string, so that the harness reads it as is rather than as a
+/// pattern: quotes, backslashes and the substitutions get a backslash of their own
+pub fn tcl_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '"' | '\\' | '[' | ']' | '{' | '}' | '$') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 /// Represents the line in the rustc source code where an error occurred.
 /// Luckily, rust compile test only stores error messages on and after the line where the error occurred.
 /// But `DejaGnu` can process error messages on the previous line, the current line, or the next line.
@@ -132,203 +435,1145 @@ enum WhichLine {
     ThisLine,
     FollowPrevious(usize),
     AdjustBackward(usize),
+    AdjustForward(usize),
+    WholeFile,
+}
+
+/// The sigil of an annotation, without its revisions
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Sigil {
+    /// `//~`
+    ThisLine,
+    /// `//~|`
+    Follow,
+    /// `//~^`, with the number of carets
+    Caret(usize),
+    /// `//~v`, with the number of `v`s
+    Down(usize),
+    /// `//~?`, for a diagnostic without a span
+    WholeFile,
+}
+
+impl fmt::Display for Sigil {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Sigil::ThisLine => f.write_str("//~"),
+            Sigil::Follow => f.write_str("//~|"),
+            Sigil::Caret(carets) => write!(f, "//~{}", "^".repeat(carets)),
+            Sigil::Down(downs) => write!(f, "//~{}", "v".repeat(downs)),
+            Sigil::WholeFile => f.write_str("//~?"),
+        }
+    }
+}
+
+/// The shape of an annotation, as counted by the coverage report
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AnnotationForm {
+    pub sigil: Sigil,
+    pub kind: Option<RustcErrorKind>,
+    /// Whether the `stderr` file gave the annotation an error code
+    pub has_code: bool,
+    /// Whether the annotation only applies to some revisions, like `//[rev]~`
+    pub has_revision: bool,
+}
+
+/// Lines longer than this many bytes are not scanned for annotations by default
+pub const DEFAULT_SCAN_LINE_LIMIT: usize = 64 * 1024;
+
+/// Files with more annotations than this get a [`Progress::ManyAnnotations`] event
+pub const MANY_ANNOTATIONS: usize = 1_000;
+
+/// Events reported while loading the annotations of a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// Another [`LoadOptions::progress_interval`] annotations were loaded, up to `line`
+    Loaded { annotations: usize, line: usize },
+    /// The file just crossed [`MANY_ANNOTATIONS`] annotations, at `line` out of `lines`
+    ManyAnnotations { line: usize, lines: usize },
+}
+
+/// A function receiving the [`Progress`] events
+#[derive(Clone)]
+pub struct ProgressCallback(pub std::sync::Arc<dyn Fn(Progress) + Send + Sync>);
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Options controlling how annotations are loaded
+#[derive(Clone, Debug)]
+pub struct LoadOptions {
+    /// Lines longer than this many bytes are skipped with a warning, `0` disables the guard
+    pub scan_line_limit: usize,
+    /// Loading stops as soon as there are more annotations than this, `0` disables the cap
+    pub max_annotations: usize,
+    /// Report progress every this many annotations, `0` disables the reports
+    pub progress_interval: usize,
+    /// Receives the progress events, if any
+    pub progress: Option<ProgressCallback>,
+    /// The file name of the source, like `mismatch.rs`. The `stderr` entries located in
+    /// another file, like an auxiliary crate, are left out. `None` keeps them all.
+    pub source_name: Option<String>,
+    /// Report the annotations repeating an earlier one, see [`LoadedErrors::duplicates`]
+    pub dedup: bool,
+    /// The revision converted, only the annotations applying to it can repeat each other
+    pub revision: Option<String>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            scan_line_limit: DEFAULT_SCAN_LINE_LIMIT,
+            max_annotations: 0,
+            progress_interval: 0,
+            progress: None,
+            source_name: None,
+            dedup: true,
+            revision: None,
+        }
+    }
+}
+
+/// The errors found by [`load_errors`], along with what could not be scanned
+#[derive(Debug, Default)]
+pub struct LoadedErrors {
+    pub errors: Vec<Error>,
+    pub warnings: Vec<String>,
+    /// The form of the annotation of each error, in the same order
+    pub forms: Vec<AnnotationForm>,
+    /// The lines which looked like annotations but were not loaded as any
+    pub skipped: Vec<SkippedLine>,
+    /// The annotations whose message is in the `stderr` entries of several other lines, they
+    /// took the closest one
+    pub ambiguities: Vec<String>,
+    /// The indices of the `stderr` entries no annotation took
+    pub unmatched: Vec<usize>,
+    /// The number of `stderr` entries left out because they are located in another file than
+    /// [`LoadOptions::source_name`]
+    pub other_files: usize,
+    /// The indices of the errors which repeat an earlier one, with the same line, kind,
+    /// message and error code. Their annotations only expect the diagnostic once.
+    pub duplicates: Vec<usize>,
+}
+
+/// A line which looks like an annotation but is not one, left as it is by the conversion
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkippedLine {
+    /// 1-based line number
+    pub line: usize,
+    pub reason: String,
 }
 
 /// The main function for loading errors from source file and from optional stderr file.
-pub fn load_error(text_file: &str, stderr_file: Option<&str>) -> Vec<Error> {
+/// The errors repeating an earlier one are left out.
+pub fn load_error(
+    text_file: &str,
+    stderr_file: Option<&str>,
+) -> Result<Vec<Error>, AnnotationError> {
+    let loaded = load_errors(text_file, stderr_file, &LoadOptions::default())?;
+    Ok(loaded
+        .errors
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !loaded.duplicates.contains(index))
+        .map(|(_, error)| error)
+        .collect())
+}
+
+/// The indices of the `errors` which repeat an earlier one applying to `revision`: the same
+/// line, kind, message and error code, so the same directive
+fn duplicate_errors(errors: &[Error], revision: Option<&str>) -> Vec<usize> {
+    let mut seen = BTreeSet::new();
+    errors
+        .iter()
+        .enumerate()
+        .filter(|(_, error)| error.applies_to(revision))
+        .filter(|(_, error)| {
+            !seen.insert((
+                error.line_num,
+                error.whole_file,
+                error.kind,
+                error.msg.as_str(),
+                error.error_code.as_deref(),
+            ))
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Same as [`load_error`], reporting the lines skipped because of `options`
+pub fn load_errors(
+    text_file: &str,
+    stderr_file: Option<&str>,
+    options: &LoadOptions,
+) -> Result<LoadedErrors, AnnotationError> {
+    let stderr = stderr_file.map(parse_stderr);
+    load_errors_parsed(text_file, stderr.as_deref(), options)
+}
+
+/// Same as [`load_errors`], with a `stderr` file already parsed by [`parse_stderr`]
+pub fn load_errors_parsed(
+    text_file: &str,
+    error_code_stderr: Option<&[StderrDiagnostic]>,
+    options: &LoadOptions,
+) -> Result<LoadedErrors, AnnotationError> {
+    // The 1-based target line of the last annotation a `//~|` can follow
     let mut last_unfollow_error = None;
     // For storing the errors
     let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    let mut forms = Vec::new();
+    let mut skipped = Vec::new();
+    let line_count = text_file.lines().count();
 
     for (line_num, line) in text_file.lines().enumerate() {
-        if let Some((which, error)) = parse_expected(last_unfollow_error, line_num + 1, line) {
-            match which {
-                FollowPrevious(_) => {}
-                _ => last_unfollow_error = Some(line_num),
+        // Minified or generated lines can be huge, scanning them is slow and never useful
+        if options.scan_line_limit != 0 && line.len() > options.scan_line_limit {
+            warnings.push(format!(
+                "line {} is {} bytes long, not scanning it for annotations (limit is {})",
+                line_num + 1,
+                line.len(),
+                options.scan_line_limit
+            ));
+            continue;
+        }
+        let annotation = match parse_expected(last_unfollow_error, line_num + 1, line)? {
+            Parsed::Nothing => continue,
+            Parsed::Skipped(reason) => {
+                skipped.push(SkippedLine {
+                    line: line_num + 1,
+                    reason,
+                });
+                continue;
+            }
+            Parsed::Annotation(annotation) => annotation,
+        };
+        // Only the whole file tells whether the lines a `//~v` points down to exist
+        if let AdjustForward(downs) = annotation.which {
+            if annotation.line_num > line_count {
+                return Err(AnnotationError::BelowLastLine {
+                    line: line_num + 1,
+                    downs,
+                });
+            }
+        }
+        match annotation.which {
+            // Nothing can follow a `//~?`, its error has no line
+            FollowPrevious(_) | WholeFile => {}
+            _ => last_unfollow_error = Some(annotation.line_num),
+        }
+        forms.push(annotation.form());
+        errors.push(annotation.into_error());
+
+        // The caller rejects the file anyway, don't waste time on the rest of it
+        if options.max_annotations != 0 && errors.len() > options.max_annotations {
+            break;
+        }
+        if let Some(ProgressCallback(progress)) = &options.progress {
+            let count = errors.len();
+            if options.progress_interval != 0 && count % options.progress_interval == 0 {
+                progress(Progress::Loaded {
+                    annotations: count,
+                    line: line_num + 1,
+                });
+            }
+            if count == MANY_ANNOTATIONS + 1 {
+                progress(Progress::ManyAnnotations {
+                    line: line_num + 1,
+                    lines: line_count,
+                });
             }
-            errors.push(error);
         }
     }
 
+    let duplicates = |errors: &[Error]| match options.dedup {
+        true => duplicate_errors(errors, options.revision.as_deref()),
+        false => Vec::new(),
+    };
     // If stderr file is not provided, return the errors
-    if stderr_file.is_none() {
-        return errors;
-    }
-    // TODO: improve this code incrementally
-    // parsing error related information from `.stderr` file
-    let error_code_stderr = parse_error_code(stderr_file.expect("stderr file is not found"));
+    let Some(error_code_stderr) = error_code_stderr else {
+        return Ok(LoadedErrors {
+            duplicates: duplicates(&errors),
+            errors,
+            warnings,
+            forms,
+            skipped,
+            ..LoadedErrors::default()
+        });
+    };
 
     // TODO: We need to load error messages from `.stderr` instead of source file become sometimes source file contains incomplete error messages
-    // finding the error code w.r.t line number and error message
-    // TODO: sometimes, the error message might not be same but this doesn't matter as we are not comparing the row number for the message
-    for error in errors.iter_mut() {
-        for error_code in error_code_stderr.iter() {
-            if error.line_num == error_code.line_number
-                || error.msg == error_code.error_message_detail
-            {
-                error.error_code = Some(error_code.error_code.clone());
-            }
-        }
+    let source_name = options.source_name.as_deref();
+    let (ambiguities, unmatched) =
+        assign_stderr_entries(&mut errors, error_code_stderr, source_name);
+    let other_files = error_code_stderr
+        .iter()
+        .filter(|result| !result.is_in(source_name))
+        .count();
+    for (form, error) in forms.iter_mut().zip(&errors) {
+        form.has_code = error.error_code.is_some();
     }
+    // The error codes of the `stderr` file tell more errors apart
+    let duplicates = duplicates(&errors);
     // return error detail with error codes
-    errors
-}
-
-/// To represent information from `stderr` file
-#[derive(Debug)]
-struct StderrResult {
-    error_code: String,
-    error_message_detail: String,
-    line_number: usize,
-}
-
-fn is_error_code(s: &str) -> bool {
-    regex!(r"^E\d{4}$").is_match(s)
+    Ok(LoadedErrors {
+        duplicates,
+        errors,
+        warnings,
+        forms,
+        skipped,
+        ambiguities,
+        unmatched,
+        other_files,
+    })
 }
 
-/// Parses error codes from the `stderr` file
-fn parse_error_code(stderr_content: &str) -> Vec<StderrResult> {
-    // Modified regex pattern with named capture groups
-    let error_pattern = regex!(
-        r"error\[(?P<error_code>E\d{4})\]: (?P<error_message_detail>.+?)\n\s+-->.+:(?P<line_number>\d+):"
-    );
+/// Gives the annotations the error code of their `stderr` entry if any, and its column. The
+/// annotations without a message take the one of their entry.
+///
+/// An entry matches an annotation of its kind when the message of the annotation is a part
+/// of the message of the entry, since annotations usually quote the start of it. Each entry
+/// goes to a single annotation, in three rounds:
+/// 1. the annotations take the entry of their line with their message
+/// 2. the annotations left take the entries of their kind left on their line in the order of
+///    their columns, so that several errors of a line each get their own
+/// 3. the annotations still left take the closest entry left with their message on another
+///    line, and what else could have matched is returned
+///
+/// The entries no annotation took are returned as well. The entries located in another file
+/// than `source_name` are left out of both.
+fn assign_stderr_entries(
+    errors: &mut [Error],
+    stderr: &[StderrDiagnostic],
+    source_name: Option<&str>,
+) -> (Vec<String>, Vec<usize>) {
+    let mut on_line: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (index, result) in stderr.iter().enumerate() {
+        if let Some(line) = result.line_number {
+            on_line.entry(line).or_default().push(index);
+        }
+    }
+    for indices in on_line.values_mut() {
+        indices.sort_by_key(|&index| stderr[index].column_number);
+    }
+    let mut taken: Vec<bool> = stderr
+        .iter()
+        .map(|result| !result.is_in(source_name))
+        .collect();
+    let mut assigned = vec![false; errors.len()];
+    let take = |error: &mut Error, index: usize, on_its_line: bool| {
+        let result = &stderr[index];
+        error.error_code.clone_from(&result.code);
+        if on_its_line {
+            error.column = (result.column_number != 0).then_some(result.column_number);
+        }
+        if error.msg.is_empty() {
+            error.msg.clone_from(&result.message);
+        }
+        // The lint of the annotation itself wins over the one of its entry
+        if error.kind == Some(RustcErrorKind::Warning) && error.lint.is_none() {
+            error.lint.clone_from(&result.lint);
+        }
+    };
+    let of_kind = |error: &Error, index: usize| stderr[index].is_of_kind(error.kind);
+    // An empty message is a part of every message, it tells nothing about the entry
+    let has_message = |error: &Error, index: usize| {
+        of_kind(error, index) && !error.msg.is_empty() && stderr[index].message.contains(&error.msg)
+    };
 
-    let mut results = Vec::new();
+    for (error, assigned) in errors.iter_mut().zip(&mut assigned) {
+        let line = on_line.get(&error.line_num).map_or(&[][..], Vec::as_slice);
+        if let Some(&index) = line
+            .iter()
+            .find(|&&index| !taken[index] && has_message(error, index))
+        {
+            take(error, index, true);
+            taken[index] = true;
+            *assigned = true;
+        }
+    }
+    for (error, assigned) in errors.iter_mut().zip(&mut assigned) {
+        if *assigned {
+            continue;
+        }
+        let line = on_line.get(&error.line_num).map_or(&[][..], Vec::as_slice);
+        if let Some(&index) = line
+            .iter()
+            .find(|&&index| !taken[index] && of_kind(error, index))
+        {
+            take(error, index, true);
+            taken[index] = true;
+            *assigned = true;
+        }
+    }
 
-    for caps in error_pattern.captures_iter(stderr_content) {
-        let error_code = caps.name("error_code").map_or_else(
-            || "Error code not found".to_string(),
-            |m| m.as_str().to_string(),
-        );
-        let error_message_detail = caps.name("error_message_detail").map_or_else(
-            || "Error message detail not found".to_string(),
-            |m| m.as_str().to_string(),
-        );
-        let line_number = caps.name("line_number").map_or_else(
-            || "Line number not found".to_string(),
-            |m| m.as_str().to_string(),
-        );
-        if !is_error_code(&error_code) {
+    let mut ambiguities = Vec::new();
+    for (error, assigned) in errors.iter_mut().zip(&assigned) {
+        if *assigned {
             continue;
         }
-        results.push(StderrResult {
-            error_code,
-            error_message_detail,
-            line_number: line_number
-                .parse::<usize>()
-                .expect("expected line number to be a valid number"),
+        let mut candidates: Vec<usize> = (0..stderr.len())
+            .filter(|&index| !taken[index] && has_message(error, index))
+            .collect();
+        candidates.sort_by_key(|&index| {
+            let result = &stderr[index];
+            // The entries without a line only come after the ones of a known line
+            (
+                result
+                    .line_number
+                    .map_or(usize::MAX, |line| line.abs_diff(error.line_num)),
+                result.line_number,
+                result.column_number,
+            )
         });
+        let Some(&index) = candidates.first() else {
+            continue;
+        };
+        let mut lines: Vec<Option<usize>> = candidates
+            .iter()
+            .map(|&index| stderr[index].line_number)
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+        if lines.len() > 1 {
+            let lines: Vec<_> = lines.iter().map(|&line| line_label(line)).collect();
+            ambiguities.push(format!(
+                "line {}: ambiguous, `{}` is in the stderr entries of lines {}, took {} of line {}, the closest",
+                error.line_num,
+                error.msg,
+                lines.join(", "),
+                stderr[index].name(),
+                line_label(stderr[index].line_number)
+            ));
+        }
+        take(error, index, false);
+        taken[index] = true;
     }
+    let unmatched = (0..stderr.len()).filter(|&index| !taken[index]).collect();
+    (ambiguities, unmatched)
+}
 
-    results
+/// Builds the coded errors of the `stderr` file alone, for the merge mode. Each error
+/// targets the line reported by rustc and expects its directive on the next line. The errors
+/// located in another file than `source_name` are left out.
+pub fn load_stderr_errors(stderr_file: &str, source_name: Option<&str>) -> Vec<Error> {
+    stderr_errors(&parse_stderr(stderr_file), source_name)
+        .into_iter()
+        .filter(|error| error.kind == Some(RustcErrorKind::Error) && error.error_code.is_some())
+        .collect()
 }
 
-/// Parses error details from a source line.
-fn parse_expected(
-    last_nonfollow_error: Option<usize>,
-    line_num: usize,
-    line: &str,
-) -> Option<(WhichLine, Error)> {
-    // Matches comments like:
-    //     //~
-    //     //~|
-    //     //~^
-    //     //~^^^^^
+/// Builds the errors and warnings of the `stderr` file, for sources without `//~`
+/// annotations. Each of them targets the line reported by rustc and expects its directive
+/// on the next line, the notes and help messages attached to them are left out, and so are the
+/// entries without a line or located in another file than `source_name`.
+pub fn stderr_errors(stderr: &[StderrDiagnostic], source_name: Option<&str>) -> Vec<Error> {
+    stderr
+        .iter()
+        .filter(|result| result.is_in(source_name))
+        .filter(|result| {
+            matches!(
+                result.level,
+                RustcErrorKind::Error | RustcErrorKind::Warning
+            )
+        })
+        .filter_map(StderrDiagnostic::to_error)
+        .collect()
+}
 
-    let captures = regex!(r"//(?:\[(?P<revs>[\w\-,]+)])?~(?P<adjust>\||\^*)").captures(line)?;
+/// Builds the notes and help messages among the `entries` of the `stderr` file, the labels
+/// of the secondary spans included, for `--with-notes`. Like [`stderr_errors`], each of them
+/// targets the line reported by rustc, the entries without a line are left out.
+pub fn stderr_notes<'a>(entries: impl IntoIterator<Item = &'a StderrDiagnostic>) -> Vec<Error> {
+    entries
+        .into_iter()
+        .filter(|result| matches!(result.level, RustcErrorKind::Note | RustcErrorKind::Help))
+        .filter_map(StderrDiagnostic::to_error)
+        .collect()
+}
 
-    let (follow, adjusts) = match &captures["adjust"] {
-        "|" => (true, 0),
-        circumflexes => (false, circumflexes.len()),
-    };
+/// A diagnostic of the `stderr` file, at the location of its primary span
+#[derive(Debug)]
+pub struct StderrDiagnostic {
+    pub level: RustcErrorKind,
+    /// The error code, like `E0308`, `None` for the diagnostics without one
+    pub code: Option<String>,
+    pub message: String,
+    /// The path of the `-->` location, like `$DIR/mismatch.rs`
+    pub path: String,
+    /// The line of the `-->` location, `None` for the `LL` of a normalized file, which is
+    /// only matched to an annotation by its message
+    pub line_number: Option<usize>,
+    /// The column of the `-->` location, `0` when it has none
+    pub column_number: usize,
+    /// The lint which emitted the diagnostic, like `unused_variables`, when the `stderr`
+    /// file names it
+    pub lint: Option<String>,
+    /// A note for the label of a secondary span of the diagnostic before it, like
+    /// `expected due to this`, rather than a diagnostic of its own
+    pub label: bool,
+}
 
-    // Get the part of the comment after the sigil (e.g. `~^^` or ~|).
-    let whole_match = captures
-        .get(0)
-        .expect("Failed to parse comments like \"//~\" \"//~^\" \"//~^^^^^\" ");
-    let (_, mut msg) = line.split_at(whole_match.end());
+/// The length a message of the `stderr` file is cut at in the reports
+const REPORTED_MESSAGE_LENGTH: usize = 60;
 
-    let first_word = msg
-        .split_whitespace()
-        .next()
-        .expect("Encountered unexpected empty comment");
+/// The line of a `stderr` entry in the reports, `LL` when the file does not give it
+pub fn line_label(line: Option<usize>) -> String {
+    line.map_or_else(|| "LL".to_owned(), |line| line.to_string())
+}
 
-    // If we find `//~ ERROR foo` or something like that, skip the first word.
-    let kind = first_word.parse::<RustcErrorKind>().ok();
-    if kind.is_some() {
-        msg = msg.trim_start().split_at(first_word.len()).1;
+impl fmt::Display for StderrDiagnostic {
+    /// Formats the diagnostic like the header rustc prints for it, with its line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.level {
+            RustcErrorKind::Help => write!(f, "line {}: help", line_label(self.line_number))?,
+            level => write!(f, "line {}: {level}", line_label(self.line_number))?,
+        }
+        if let Some(code) = &self.code {
+            write!(f, "[{code}]")?;
+        }
+        match self.message.char_indices().nth(REPORTED_MESSAGE_LENGTH) {
+            Some((end, _)) => write!(f, ": {}...", &self.message[..end]),
+            None => write!(f, ": {}", self.message),
+        }
     }
+}
 
-    let msg = msg.trim().to_owned();
-
-    // If we find `//~|` or `//~^`, we need to adjust the line number.
-    let mut relative_line_num = line_num as i32;
-    let (which, line_num) = if follow {
-        assert_eq!(adjusts, 0, "use either //~| or //~^, not both.");
-        let line_num = last_nonfollow_error.expect(
-            "encountered //~| without \
-             preceding //~^ line.",
-        );
-        relative_line_num = (line_num as i32) - relative_line_num;
-        (FollowPrevious(line_num), line_num)
-    } else {
-        let which = if adjusts > 0 {
-            AdjustBackward(adjusts)
-        } else {
-            ThisLine
-        };
-        let line_num = line_num - adjusts;
-        relative_line_num = -(adjusts as i32);
-        (which, line_num)
-    };
+/// The errors of the `stderr` file which match no annotation, returned by
+/// [`transform::Options::strict_stderr`]
+///
+/// [`transform::Options::strict_stderr`]: crate::transform::Options::strict_stderr
+#[derive(Debug)]
+pub struct UnmatchedStderr(pub Vec<String>);
 
-    Some((
-        which,
-        Error {
-            line_num,
-            kind,
-            msg,
-            error_code: None,
-            relative_line_num,
-        },
-    ))
+impl fmt::Display for UnmatchedStderr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.len() {
+            1 => write!(f, "1 error of the stderr file matches no annotation")?,
+            errors => write!(f, "{errors} errors of the stderr file match no annotation")?,
+        }
+        for diagnostic in &self.0 {
+            write!(f, "\n  {diagnostic}")?;
+        }
+        Ok(())
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl std::error::Error for UnmatchedStderr {}
 
-    #[test]
-    fn from_str_help_returns_help() {
-        assert_eq!(
-            RustcErrorKind::from_str("help").unwrap(),
-            RustcErrorKind::Help
-        );
-        assert_eq!(
-            RustcErrorKind::from_str("help:").unwrap(),
-            RustcErrorKind::Help
-        );
+impl StderrDiagnostic {
+    /// The error expecting the diagnostic on the line below its own, `None` without a line
+    fn to_error(&self) -> Option<Error> {
+        Some(Error {
+            line_num: self.line_number?,
+            relative_line_num: -1,
+            kind: Some(self.level),
+            msg: self.message.clone(),
+            error_code: self.code.clone(),
+            column: (self.column_number != 0).then_some(self.column_number),
+            revisions: Vec::new(),
+            whole_file: false,
+            lint: self
+                .lint
+                .clone()
+                .filter(|_| self.level == RustcErrorKind::Warning),
+            annotation_span: None,
+            target_line: None,
+        })
     }
 
-    #[test]
-    fn from_str_error_returns_error() {
-        assert_eq!(
-            RustcErrorKind::from_str("error").unwrap(),
-            RustcErrorKind::Error
-        );
+    /// Whether the diagnostic is located in the file named `source_name`, which any file is
+    /// without a name. The path may come from Windows, whatever the host.
+    pub fn is_in(&self, source_name: Option<&str>) -> bool {
+        source_name
+            .is_none_or(|source_name| self.path.rsplit(['/', '\\']).next() == Some(source_name))
     }
 
-    #[test]
-    fn from_str_note_returns_note() {
-        assert_eq!(
-            RustcErrorKind::from_str("note").unwrap(),
-            RustcErrorKind::Note
-        );
+    /// Whether an annotation of `kind` can be for the diagnostic, any of them when it has no
+    /// kind. The suggestions are help messages.
+    fn is_of_kind(&self, kind: Option<RustcErrorKind>) -> bool {
+        match kind {
+            None => true,
+            Some(RustcErrorKind::Suggestion) => self.level == RustcErrorKind::Help,
+            Some(kind) => self.level == kind,
+        }
     }
 
-    #[test]
-    fn from_str_suggestion_returns_suggestion() {
-        assert_eq!(
+    /// The error code of the diagnostic, or its level when it has none
+    fn name(&self) -> String {
+        self.code
+            .clone()
+            .unwrap_or_else(|| format!("the {}", self.level))
+    }
+}
+
+fn is_error_code(s: &str) -> bool {
+    regexes::error_code().is_match(s)
+}
+
+/// Parses the diagnostics of the `stderr` file, with their error code if any
+///
+/// The file is cut into blocks, each starting at the header of a diagnostic, and the location
+/// of a diagnostic is the first `-->` line of its block. The diagnostics without one, like
+/// most notes, are left out.
+pub fn parse_stderr(stderr_content: &str) -> Vec<StderrDiagnostic> {
+    // Regex patterns with named capture groups, see `regexes::STDERR_HEADER`
+    let headers: Vec<_> = regexes::stderr_header()
+        .captures_iter(stderr_content)
+        .collect();
+
+    let mut results = Vec::new();
+    // The errors and warnings, with where their text starts and ends: up to the next
+    // one, since their notes follow them
+    let mut primaries: Vec<(usize, Range<usize>)> = Vec::new();
+
+    for (index, caps) in headers.iter().enumerate() {
+        let header = caps.get(0).unwrap().range();
+        let block_end = headers
+            .get(index + 1)
+            .map_or(stderr_content.len(), |next| next.get(0).unwrap().start());
+        let level = match &caps["level"] {
+            "error" => RustcErrorKind::Error,
+            "warning" => RustcErrorKind::Warning,
+            "note" => RustcErrorKind::Note,
+            _ => RustcErrorKind::Help,
+        };
+        if matches!(level, RustcErrorKind::Error | RustcErrorKind::Warning) {
+            if let Some((_, text)) = primaries.last_mut() {
+                text.end = text.end.min(header.start);
+            }
+        }
+        let code = caps.name("code").map(|m| m.as_str().to_owned());
+        if code.as_deref().is_some_and(|code| !is_error_code(code)) {
+            continue;
+        }
+        let block = &stderr_content[header.end..block_end];
+        let Some(location) = regexes::stderr_location().captures(block) else {
+            continue;
+        };
+        // The spans in the standard library or the compiler are not in the test
+        let path = location["path"].trim();
+        if path.starts_with("$SRC_DIR") || path.starts_with("$RUSTC") {
+            continue;
+        }
+        // The regex only accepts digits besides `LL`, but they may still overflow
+        let line_number = match &location["line_number"] {
+            "LL" => None,
+            line => match line.parse::<usize>() {
+                Ok(line) => Some(line),
+                Err(_) => continue,
+            },
+        };
+        let column_number = match location.name("column_number").map(|m| m.as_str()) {
+            Some("CC" | "COL") | None => 0,
+            Some(column) => match column.parse() {
+                Ok(column) => column,
+                Err(_) => continue,
+            },
+        };
+        if matches!(level, RustcErrorKind::Error | RustcErrorKind::Warning) {
+            primaries.push((results.len(), header.end..stderr_content.len()));
+        }
+        let (message, lint) = match level {
+            RustcErrorKind::Warning => split_lint(&caps["message"]),
+            _ => (&caps["message"], None),
+        };
+        results.push(StderrDiagnostic {
+            level,
+            code,
+            message: message.to_owned(),
+            path: path.to_owned(),
+            line_number,
+            column_number,
+            lint: lint.map(str::to_owned),
+            label: false,
+        });
+        let snippet = &block[location.get(0).unwrap().end()..];
+        for (line_number, column_number, message) in
+            secondary_labels(snippet, line_number, column_number)
+        {
+            results.push(StderrDiagnostic {
+                level: RustcErrorKind::Note,
+                code: None,
+                message,
+                path: path.to_owned(),
+                line_number,
+                column_number,
+                lint: None,
+                label: true,
+            });
+        }
+    }
+    for (index, text) in primaries {
+        // The lint named in the header wins over the notes
+        if results[index].lint.is_none() {
+            results[index].lint = stderr_lint(&stderr_content[text]);
+        }
+    }
+
+    results
+}
+
+/// A line of the source quoted in the snippet of a diagnostic, with the underlines below it
+#[derive(Default)]
+struct SnippetLine {
+    /// The line number in the gutter, `None` for the `LL` of a normalized file
+    number: Option<usize>,
+    /// The number of `...` gaps above the line in the snippet
+    gaps: usize,
+    /// The underlines, by the column they start at, with whether they are primary (`^`)
+    /// and their label
+    underlines: Vec<(usize, bool, Option<String>)>,
+}
+
+impl SnippetLine {
+    /// Reads the underlines and labels of `marks`, a line of the snippet below this one
+    fn read_marks(&mut self, marks: &str) {
+        let marks: Vec<char> = marks.chars().collect();
+        let mut column = 0;
+        let mut underlined = false;
+        while column < marks.len() {
+            match marks[column] {
+                mark @ ('-' | '^') => {
+                    let start = column;
+                    while marks.get(column) == Some(&mark) {
+                        column += 1;
+                    }
+                    underlined = true;
+                    // The label of an underline follows it on the same line
+                    let text = marks[column..].iter().take_while(|&&c| c == ' ').count();
+                    let label = match marks.get(column + text) {
+                        Some('-' | '^' | '|') | None => None,
+                        Some(_) if text == 0 => None,
+                        Some(_) => Some(marks[column + text..].iter().collect::<String>()),
+                    };
+                    let done = label.is_some();
+                    self.underlines.push((start, mark == '^', label));
+                    if done {
+                        break;
+                    }
+                }
+                ' ' | '|' | '_' | '/' | '\\' => column += 1,
+                _ => {
+                    // Or it is below it, from the column the underline starts at
+                    if !underlined {
+                        let label: String = marks[column..].iter().collect();
+                        if let Some(underline) = self
+                            .underlines
+                            .iter_mut()
+                            .find(|(start, _, label)| *start == column && label.is_none())
+                        {
+                            underline.2 = Some(label);
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// The labels of the secondary spans, underlined with `-` in the `snippet` of a diagnostic
+/// located at `line` and `column`, with their line and column.
+///
+/// The `LL` lines of a normalized file are counted from the one underlined with `^` at
+/// `column`, they have no line across a `...` gap. The snippets of another file, after a
+/// `:::` line, are left out.
+fn secondary_labels(
+    snippet: &str,
+    line: Option<usize>,
+    column: usize,
+) -> Vec<(Option<usize>, usize, String)> {
+    let mut lines: Vec<SnippetLine> = Vec::new();
+    let mut gaps = 0;
+    for text in snippet.lines() {
+        let trimmed = text.trim();
+        if trimmed.starts_with(":::") {
+            break;
+        }
+        if trimmed == "..." {
+            gaps += 1;
+            continue;
+        }
+        let Some((gutter, content)) = text.split_once('|') else {
+            continue;
+        };
+        match gutter.trim() {
+            "" => {
+                if let Some(line) = lines.last_mut() {
+                    line.read_marks(content);
+                }
+            }
+            "LL" => lines.push(SnippetLine {
+                gaps,
+                ..SnippetLine::default()
+            }),
+            number => match number.parse() {
+                Ok(number) => lines.push(SnippetLine {
+                    number: Some(number),
+                    gaps,
+                    ..SnippetLine::default()
+                }),
+                Err(_) => continue,
+            },
+        }
+    }
+
+    let primary = lines.iter().position(|snippet_line| {
+        snippet_line
+            .underlines
+            .iter()
+            .any(|&(start, primary, _)| primary && start == column)
+    });
+    let mut labels = Vec::new();
+    for (index, snippet_line) in lines.iter().enumerate() {
+        let number = snippet_line.number.or_else(|| {
+            let (line, primary) = (line?, primary?);
+            // Only counted within the part of the snippet of the primary line
+            if lines[primary].gaps != snippet_line.gaps {
+                return None;
+            }
+            (line + index).checked_sub(primary)
+        });
+        for (start, primary, label) in &snippet_line.underlines {
+            if let (false, Some(label)) = (primary, label) {
+                labels.push((number, *start, label.trim_end().to_owned()));
+            }
+        }
+    }
+    labels
+}
+
+/// The lint named in the text of a diagnostic, preferring the one of a
+/// ``#[warn(lint)]` on by default`` note to the attribute setting the lint level
+fn stderr_lint(text: &str) -> Option<String> {
+    let mut attribute = None;
+    for caps in regexes::stderr_lint().captures_iter(text) {
+        if let Some(lint) = caps.name("lint") {
+            return Some(lint.as_str().to_owned());
+        }
+        attribute = attribute.or_else(|| caps.name("attribute").map(|m| m.as_str().to_owned()));
+    }
+    attribute
+}
+
+/// The message `msg` without the `[lint_name]` some warnings end with, and the lint
+pub fn split_lint(msg: &str) -> (&str, Option<&str>) {
+    match regexes::lint_suffix().captures(msg) {
+        Some(caps) => (
+            &msg[..caps.get(0).unwrap().start()],
+            caps.name("lint").map(|m| m.as_str()),
+        ),
+        None => (msg, None),
+    }
+}
+
+/// An annotation borrowing its message from the source line, until it becomes an [`Error`]
+#[derive(Debug, PartialEq)]
+struct Annotation<'line> {
+    which: WhichLine,
+    line_num: usize,
+    relative_line_num: i32,
+    kind: Option<RustcErrorKind>,
+    msg: &'line str,
+    /// The revisions of `//[rev1,rev2]~`, if any
+    revisions: Option<&'line str>,
+    /// See [`Error::annotation_span`]
+    span: (usize, usize),
+}
+
+impl Annotation<'_> {
+    /// The form of the annotation, before any error code is known
+    fn form(&self) -> AnnotationForm {
+        AnnotationForm {
+            sigil: match self.which {
+                ThisLine => Sigil::ThisLine,
+                FollowPrevious(_) => Sigil::Follow,
+                AdjustBackward(carets) => Sigil::Caret(carets),
+                AdjustForward(downs) => Sigil::Down(downs),
+                WholeFile => Sigil::WholeFile,
+            },
+            kind: self.kind,
+            has_code: false,
+            has_revision: self.revisions.is_some(),
+        }
+    }
+
+    fn into_error(self) -> Error {
+        let (msg, lint) = match self.kind {
+            Some(RustcErrorKind::Warning) => split_lint(self.msg),
+            _ => (self.msg, None),
+        };
+        Error {
+            line_num: self.line_num,
+            relative_line_num: self.relative_line_num,
+            kind: self.kind,
+            msg: msg.to_owned(),
+            lint: lint.map(str::to_owned),
+            annotation_span: Some(self.span),
+            target_line: None,
+            error_code: None,
+            column: None,
+            whole_file: self.which == WholeFile,
+            revisions: self.revisions.map_or_else(Vec::new, |revisions| {
+                revisions
+                    .split(',')
+                    .map(|revision| revision.trim().to_owned())
+                    .filter(|revision| !revision.is_empty())
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// Parses the annotation of the 1-based line `line_num`, `None` if it has none.
+///
+/// A `//~|` targets the same line as the last other annotation, which targets the 1-based
+/// line `previous_target`.
+pub fn parse_annotation(
+    previous_target: Option<usize>,
+    line_num: usize,
+    line: &str,
+) -> Result<Option<(Error, AnnotationForm)>, AnnotationError> {
+    match parse_expected(previous_target, line_num, line)? {
+        Parsed::Annotation(annotation) => {
+            let form = annotation.form();
+            Ok(Some((annotation.into_error(), form)))
+        }
+        Parsed::Nothing | Parsed::Skipped(_) => Ok(None),
+    }
+}
+
+/// What [`parse_expected`] found on a line
+#[derive(Debug, PartialEq)]
+enum Parsed<'line> {
+    /// No annotation at all
+    Nothing,
+    Annotation(Annotation<'line>),
+    /// Something which looks like an annotation but cannot be read as one, and why
+    Skipped(String),
+}
+
+/// Parses error details from a source line. A `//~|` targets `last_nonfollow_error`, the
+/// 1-based target line of the last other annotation.
+fn parse_expected(
+    last_nonfollow_error: Option<usize>,
+    line_num: usize,
+    line: &str,
+) -> Result<Parsed<'_>, AnnotationError> {
+    // Matches comments like:
+    //     //~
+    //     //~|
+    //     //~^
+    //     //~^^^^^
+    //     //~vvv
+
+    let Some(captures) = regexes::sigil().captures(line) else {
+        if regexes::near_sigil().is_match(line) {
+            return Ok(Parsed::Skipped("malformed `//~` sigil".to_owned()));
+        }
+        return Ok(Parsed::Nothing);
+    };
+
+    // The regex only accepts a single `|` or `?`, a run of `v`s or a run of carets
+    let adjust = &captures["adjust"];
+    let whole_file = adjust == "?";
+    let (follow, downward, adjusts) = match adjust {
+        "|" => (true, false, 0),
+        "?" => (false, false, 0),
+        _ if adjust.starts_with('v') => (false, true, adjust.len()),
+        circumflexes => (false, false, circumflexes.len()),
+    };
+
+    // Get the part of the comment after the sigil (e.g. `~^^` or ~|).
+    // The whole match always exists when the regex matched.
+    let (sigil_start, sigil_end) = captures
+        .get(0)
+        .map_or((line.len(), line.len()), |m| (m.start(), m.end()));
+    let mut msg = &line[sigil_end..];
+
+    // An empty comment like `//~` alone is not a valid annotation
+    let first_word = msg
+        .split_whitespace()
+        .next()
+        .ok_or(AnnotationError::EmptyMessage { line: line_num })?;
+
+    // If we find `//~ ERROR foo` or something like that, skip the first word.
+    let kind = first_word.parse::<RustcErrorKind>().ok();
+    // An unknown keyword is most likely a misspelt kind rather than the start of the message
+    let keyword = first_word.split(':').next().unwrap_or(first_word);
+    if kind.is_none() && keyword.len() > 1 && keyword.bytes().all(|b| b.is_ascii_uppercase()) {
+        return Ok(Parsed::Skipped(format!(
+            "unknown annotation kind `{keyword}`"
+        )));
+    }
+    if kind.is_some() {
+        msg = msg.trim_start().split_at(first_word.len()).1;
+    }
+
+    let msg = msg.trim();
+
+    // If we find `//~|`, `//~^`, `//~v` or `//~?`, we need to adjust the line number.
+    // The error of `//~?` has no line, it is line 0 of the file.
+    let mut relative_line_num = line_num as i32;
+    let (which, target_line) = if follow {
+        // A `//~|` without preceding annotation has nothing to follow
+        let target_line =
+            last_nonfollow_error.ok_or(AnnotationError::OrphanFollow { line: line_num })?;
+        relative_line_num = (target_line as i32) - relative_line_num;
+        (FollowPrevious(target_line), target_line)
+    } else if whole_file {
+        relative_line_num = -relative_line_num;
+        (WholeFile, 0)
+    } else if downward {
+        relative_line_num = adjusts as i32;
+        (AdjustForward(adjusts), line_num + adjusts)
+    } else {
+        let which = if adjusts > 0 {
+            AdjustBackward(adjusts)
+        } else {
+            ThisLine
+        };
+        // Too many carets would point above the start of the file, to line 0 or before it
+        if adjusts >= line_num {
+            return Err(AnnotationError::AboveFirstLine {
+                line: line_num,
+                carets: adjusts,
+            });
+        }
+        relative_line_num = -(adjusts as i32);
+        (which, line_num - adjusts)
+    };
+
+    Ok(Parsed::Annotation(Annotation {
+        which,
+        line_num: target_line,
+        relative_line_num,
+        kind,
+        msg,
+        revisions: captures.name("revs").map(|revs| revs.as_str()),
+        span: (sigil_start, line.len()),
+    }))
+}
+
+/// A malformed annotation in the source file, which `compiletest` would reject as well
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnnotationError {
+    /// A sigil with nothing after it, like a lone `//~`
+    EmptyMessage { line: usize },
+    /// A `//~|` with no earlier annotation to follow
+    OrphanFollow { line: usize },
+    /// A `//~^` with more carets than there are lines above it
+    AboveFirstLine { line: usize, carets: usize },
+    /// A `//~v` with more `v`s than there are lines below it
+    BelowLastLine { line: usize, downs: usize },
+    /// The transformer found no annotation on a line the parser reported one for.
+    /// Only happens if the source changed between parsing and transforming.
+    MissingAnnotation { line: usize },
+}
+
+impl fmt::Display for AnnotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnnotationError::EmptyMessage { line } => {
+                write!(f, "line {line}: empty annotation after `//~`")
+            }
+            AnnotationError::OrphanFollow { line } => {
+                write!(
+                    f,
+                    "line {line}: `//~|` without a preceding annotation to follow"
+                )
+            }
+            AnnotationError::AboveFirstLine { line, carets } => write!(
+                f,
+                "line {line}: `//~{}` points above the first line of the file",
+                "^".repeat(*carets)
+            ),
+            AnnotationError::BelowLastLine { line, downs } => write!(
+                f,
+                "line {line}: `//~{}` points below the last line of the file",
+                "v".repeat(*downs)
+            ),
+            AnnotationError::MissingAnnotation { line } => {
+                write!(f, "line {line}: expected an annotation on this line")
+            }
+        }
+    }
+}
+
+impl AnnotationError {
+    /// The 1-based line of the annotation
+    pub fn line(&self) -> usize {
+        match *self {
+            AnnotationError::EmptyMessage { line }
+            | AnnotationError::OrphanFollow { line }
+            | AnnotationError::AboveFirstLine { line, .. }
+            | AnnotationError::BelowLastLine { line, .. }
+            | AnnotationError::MissingAnnotation { line } => line,
+        }
+    }
+}
+
+impl std::error::Error for AnnotationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_errors_serialize_with_their_kind() {
+        let errors = load_error("f(); //~ WARN unused\n//~^ odd\n", None).unwrap();
+        assert_eq!(
+            serde_json::to_string(&errors).unwrap(),
+            r#"[{"line_num":1,"relative_line_num":0,"kind":"warning","msg":"unused","error_code":null},{"line_num":1,"relative_line_num":-1,"kind":null,"msg":"odd","error_code":null}]"#
+        );
+    }
+
+    #[test]
+    fn test_unmatched_stderr_counts_its_errors() {
+        let unmatched = |count| UnmatchedStderr(vec!["line 1: error: oops".to_owned(); count]);
+        assert_eq!(
+            unmatched(1).to_string(),
+            "1 error of the stderr file matches no annotation\n  line 1: error: oops"
+        );
+        assert!(unmatched(2)
+            .to_string()
+            .starts_with("2 errors of the stderr file match no annotation\n"));
+    }
+
+    #[test]
+    fn from_str_help_returns_help() {
+        assert_eq!(
+            RustcErrorKind::from_str("help").unwrap(),
+            RustcErrorKind::Help
+        );
+        assert_eq!(
+            RustcErrorKind::from_str("help:").unwrap(),
+            RustcErrorKind::Help
+        );
+    }
+
+    #[test]
+    fn from_str_error_returns_error() {
+        assert_eq!(
+            RustcErrorKind::from_str("error").unwrap(),
+            RustcErrorKind::Error
+        );
+    }
+
+    #[test]
+    fn from_str_note_returns_note() {
+        assert_eq!(
+            RustcErrorKind::from_str("note").unwrap(),
+            RustcErrorKind::Note
+        );
+    }
+
+    #[test]
+    fn from_str_suggestion_returns_suggestion() {
+        assert_eq!(
             RustcErrorKind::from_str("suggestion").unwrap(),
             RustcErrorKind::Suggestion
         );
@@ -361,6 +1606,1010 @@ mod tests {
         assert!(RustcErrorKind::from_str("").is_err());
     }
 
+    #[test]
+    fn from_str_matches_the_uppercasing_rules() {
+        for word in ["eRrOr", "Warn:", "note:extra", "HELP:", "Warning"] {
+            assert!(RustcErrorKind::from_str(word).is_ok(), "{word}");
+        }
+        for word in [":ERROR", "ERRORS", "WARNINGS", "E RROR", "ÉRROR"] {
+            assert!(RustcErrorKind::from_str(word).is_err(), "{word}");
+        }
+        // Non-ASCII letters uppercasing to ASCII ones were always accepted
+        assert_eq!(
+            RustcErrorKind::from_str("suggestıon"),
+            Ok(RustcErrorKind::Suggestion)
+        );
+        assert_eq!(
+            RustcErrorKind::from_str("ſuggestion:"),
+            Ok(RustcErrorKind::Suggestion)
+        );
+    }
+
+    #[test]
+    fn annotation_borrows_its_message() {
+        let line = "    f(); //~^ Error:   mismatched types  ";
+        let Ok(Parsed::Annotation(annotation)) = parse_expected(None, 3, line) else {
+            panic!("no annotation in `{line}`");
+        };
+        assert_eq!(annotation.msg, "mismatched types");
+        assert!(line
+            .as_bytes()
+            .as_ptr_range()
+            .contains(&annotation.msg.as_ptr()));
+        let error = annotation.into_error();
+        assert_eq!((error.line_num, error.relative_line_num), (2, -1));
+        assert_eq!(error.kind, Some(RustcErrorKind::Error));
+    }
+
+    #[test]
+    fn downward_annotations_target_the_lines_below() {
+        let code = "fn main() {\n    //~v ERROR mismatched types\n    let x: i32 = \"\";\n    //~vvv WARN unused\n\n\n    let y = 1;\n}\n";
+        let loaded = load_errors(code, None, &LoadOptions::default()).unwrap();
+        let lines: Vec<_> = loaded
+            .errors
+            .iter()
+            .map(|error| (error.line_num, error.relative_line_num))
+            .collect();
+        assert_eq!(lines, [(3, 1), (7, 3)]);
+        let sigils: Vec<_> = loaded.forms.iter().map(|form| form.sigil).collect();
+        assert_eq!(sigils, [Sigil::Down(1), Sigil::Down(3)]);
+        assert_eq!(Sigil::Down(3).to_string(), "//~vvv");
+        assert_eq!(
+            loaded.errors[0].to_string(),
+            "// { dg-error \"\" \"\" { target *-*-* } .+1 }"
+        );
+        assert_eq!(
+            loaded.errors[1].to_string(),
+            "// { dg-warning \"\" \"\" { target *-*-* } .+3 }"
+        );
+
+        // A `v` starting the message is not a sigil
+        let errors = load_error("fn main() {} //~ vexing\n", None).unwrap();
+        assert_eq!(
+            (errors[0].relative_line_num, errors[0].msg.as_str()),
+            (0, "vexing")
+        );
+    }
+
+    #[test]
+    fn whole_file_annotations_target_line_zero() {
+        let code =
+            "fn main() {}\n//~^ ERROR unused\n//~? ERROR invalid crate name\n//~| NOTE follows\n";
+        let loaded = load_errors(code, None, &LoadOptions::default()).unwrap();
+        let lines: Vec<_> = loaded
+            .errors
+            .iter()
+            .map(|error| (error.line_num, error.is_whole_file()))
+            .collect();
+        // `//~|` still follows the annotation before the `//~?`
+        assert_eq!(lines, [(1, false), (0, true), (1, false)]);
+        assert_eq!(loaded.forms[1].sigil, Sigil::WholeFile);
+        assert_eq!(Sigil::WholeFile.to_string(), "//~?");
+        assert_eq!(
+            loaded.errors[1].to_string(),
+            "// { dg-error \"\" \"\" { target *-*-* } 0 }"
+        );
+    }
+
+    #[test]
+    fn malformed_annotations_are_rejected() {
+        assert_eq!(
+            parse_expected(None, 1, "//~"),
+            Err(AnnotationError::EmptyMessage { line: 1 })
+        );
+        assert_eq!(
+            parse_expected(None, 1, "//~|   "),
+            Err(AnnotationError::EmptyMessage { line: 1 })
+        );
+        assert_eq!(
+            parse_expected(None, 2, "//~| NOTE orphan"),
+            Err(AnnotationError::OrphanFollow { line: 2 })
+        );
+        assert_eq!(
+            parse_expected(None, 2, "//~^^^ ERROR too far up"),
+            Err(AnnotationError::AboveFirstLine { line: 2, carets: 3 })
+        );
+        assert_eq!(
+            load_error("fn main() {}\n//~| NOTE orphan\n", None),
+            Err(AnnotationError::OrphanFollow { line: 2 })
+        );
+        assert_eq!(
+            AnnotationError::AboveFirstLine { line: 2, carets: 3 }.to_string(),
+            "line 2: `//~^^^` points above the first line of the file"
+        );
+    }
+
+    #[test]
+    fn repeated_annotations_are_duplicates() {
+        let code = "\
+fn main() {
+    let x: i32 = \"\"; //~ ERROR mismatched types
+    //~^ ERROR mismatched types
+    let y: i32 = \"\"; //~ ERROR E0308
+    //[a]~^ ERROR E0277
+    //[b]~^^ ERROR E0308
+}
+//~? ERROR the whole file
+//~? ERROR the whole file
+";
+        let loaded = load_errors(code, None, &LoadOptions::default()).unwrap();
+        // Another error code is another diagnostic. Without a revision, every annotation is
+        // converted.
+        assert_eq!(loaded.duplicates, [1, 4, 6]);
+        let errors = load_error(code, None).unwrap();
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[1].msg, "E0308");
+
+        // The annotations of another revision repeat none of those converted
+        let options = LoadOptions {
+            revision: Some("a".to_owned()),
+            ..LoadOptions::default()
+        };
+        assert_eq!(
+            load_errors(code, None, &options).unwrap().duplicates,
+            [1, 6]
+        );
+
+        let options = LoadOptions {
+            dedup: false,
+            ..LoadOptions::default()
+        };
+        assert!(load_errors(code, None, &options)
+            .unwrap()
+            .duplicates
+            .is_empty());
+    }
+
+    #[test]
+    fn annotations_record_where_they_start() {
+        let line = "    let url = \"https://example.com\"; //~ ERROR expected `;`, found `//`";
+        let (error, _) = parse_annotation(None, 1, line).unwrap().unwrap();
+        let (start, end) = error.annotation_span.unwrap();
+        assert_eq!(&line[start..end], "//~ ERROR expected `;`, found `//`");
+        assert_eq!(error.msg, "expected `;`, found `//`");
+
+        let line = "x //[a,b]~^ WARN a // b";
+        let (error, _) = parse_annotation(None, 2, line).unwrap().unwrap();
+        assert_eq!(error.annotation_span, Some((2, line.len())));
+        assert_eq!(error.revisions, ["a", "b"]);
+    }
+
+    #[test]
+    fn annotations_point_within_the_file() {
+        // As many carets as lines up to the annotation would point to line 0
+        for (line, carets) in [(1, 1), (1, 3), (2, 2), (2, 5)] {
+            assert_eq!(
+                parse_expected(None, line, &format!("//~{} ERROR", "^".repeat(carets))),
+                Err(AnnotationError::AboveFirstLine { line, carets })
+            );
+        }
+        assert!(matches!(
+            parse_expected(None, 2, "//~^ ERROR"),
+            Ok(Parsed::Annotation(Annotation { line_num: 1, .. }))
+        ));
+
+        let code = "fn main() {\n    //~vvvv ERROR too far down\n    let x = 1;\n}\n";
+        assert_eq!(
+            load_error(code, None),
+            Err(AnnotationError::BelowLastLine { line: 2, downs: 4 })
+        );
+        assert_eq!(
+            AnnotationError::BelowLastLine { line: 2, downs: 4 }.to_string(),
+            "line 2: `//~vvvv` points below the last line of the file"
+        );
+        // The last line is still in the file
+        let errors = load_error(&code.replace("vvvv", "vv"), None).unwrap();
+        assert_eq!(errors[0].line_num, 4);
+    }
+
+    #[test]
+    fn skipped_lines_are_reported() {
+        let code = include_str!("../tests/fixtures/malformed.rs");
+        let loaded = load_errors(code, None, &LoadOptions::default()).unwrap();
+        assert_eq!(loaded.errors.len(), 1);
+        assert_eq!(loaded.errors[0].msg, "mismatched types");
+        assert_eq!(
+            loaded.skipped,
+            [
+                SkippedLine {
+                    line: 2,
+                    reason: "unknown annotation kind `FOO`".to_owned()
+                },
+                SkippedLine {
+                    line: 4,
+                    reason: "malformed `//~` sigil".to_owned()
+                },
+            ]
+        );
+
+        // Neither of these look like a kind nor a sigil
+        for line in ["f(); //~ E0308 x", "f(); //~ I am", "let x = 1; // 5~6"] {
+            assert!(
+                matches!(parse_expected(None, 1, line), Ok(Parsed::Annotation(_)))
+                    == line.contains("//~"),
+                "{line}"
+            );
+        }
+    }
+
+    #[test]
+    fn long_lines_are_not_scanned() {
+        let long_line = format!("let x = \"{}\"; //~ ERROR hidden", "a".repeat(100));
+        let code = format!("fn f() {{}} //~ ERROR kept\n{long_line}\n//~| NOTE follows kept\n");
+        let options = LoadOptions {
+            scan_line_limit: 64,
+            ..LoadOptions::default()
+        };
+        let loaded = load_errors(&code, None, &options).unwrap();
+        let messages: Vec<_> = loaded.errors.iter().map(|e| e.msg.as_str()).collect();
+        assert_eq!(messages, ["kept", "follows kept"]);
+        assert_eq!(
+            loaded.warnings,
+            ["line 2 is 128 bytes long, not scanning it for annotations (limit is 64)"]
+        );
+
+        // The default limit and a disabled guard both keep normal lines
+        assert_eq!(load_error(&code, None).unwrap().len(), 3);
+        let options = LoadOptions {
+            scan_line_limit: 0,
+            ..LoadOptions::default()
+        };
+        assert!(load_errors(&code, None, &options)
+            .unwrap()
+            .warnings
+            .is_empty());
+    }
+
+    #[test]
+    fn progress_is_reported_at_intervals() {
+        use std::sync::{Arc, Mutex};
+
+        let code = "let x = 1; //~ ERROR a\nfn f() {}\n".repeat(1_100);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Arc::clone(&events);
+        let options = LoadOptions {
+            progress_interval: 250,
+            progress: Some(ProgressCallback(Arc::new(move |event| {
+                recorder.lock().unwrap().push(event)
+            }))),
+            ..LoadOptions::default()
+        };
+        let loaded = load_errors(&code, None, &options).unwrap();
+        assert_eq!(loaded.errors.len(), 1_100);
+
+        let loaded_at = |annotations: usize| Progress::Loaded {
+            annotations,
+            line: 2 * annotations - 1,
+        };
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                loaded_at(250),
+                loaded_at(500),
+                loaded_at(750),
+                loaded_at(1_000),
+                Progress::ManyAnnotations {
+                    line: 2_001,
+                    lines: 2_200
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn loading_stops_past_the_annotation_cap() {
+        use std::sync::{atomic::AtomicUsize, atomic::Ordering, Arc};
+
+        let code = "//~ ERROR a\n".repeat(5_000);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let options = LoadOptions {
+            max_annotations: 100,
+            progress_interval: 10,
+            progress: Some(ProgressCallback(Arc::new(move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }))),
+            ..LoadOptions::default()
+        };
+        let loaded = load_errors(&code, None, &options).unwrap();
+        // One past the cap, so that the caller knows it was exceeded
+        assert_eq!(loaded.errors.len(), 101);
+        assert_eq!(calls.load(Ordering::Relaxed), 10);
+    }
+
+    /// A parse error without code, a lint warning, a coded error and a denied lint
+    const LEVELS_STDERR: &str = "\
+error: expected one of `:`, `@`, or `|`, found `b`
+  --> $DIR/levels.rs:4:12
+   |
+LL |     let (a b) = 1;
+   |            ^ expected one of `:`, `@`, or `|`
+
+warning: unused variable: `x`
+  --> $DIR/levels.rs:5:9
+   |
+LL |     let x = 1;
+   |         ^ help: if this is intentional, prefix it with an underscore: `_x`
+   |
+   = note: `#[warn(unused_variables)]` on by default
+
+error[E0308]: mismatched types
+  --> $DIR/levels.rs:6:18
+   |
+LL |     let y: i32 = \"\";
+   |            ---   ^^ expected `i32`, found `&str`
+   |            |
+   |            expected due to this
+
+error: function `F` should have a snake case name
+  --> $DIR/levels.rs:8:4
+   |
+LL | fn F() {}
+   |    ^ help: convert the identifier to snake case: `f`
+   |
+note: the lint level is defined here
+  --> $DIR/levels.rs:1:9
+   |
+LL | #![deny(non_snake_case)]
+   |         ^^^^^^^^^^^^^^
+
+error: aborting due to 3 previous errors; 1 warning emitted
+
+For more information about this error, try `rustc --explain E0308`.
+";
+
+    #[test]
+    fn stderr_diagnostics_without_code_are_parsed() {
+        let diagnostics: Vec<_> = parse_stderr(LEVELS_STDERR)
+            .into_iter()
+            .map(|result| {
+                (
+                    result.level,
+                    result.code,
+                    result.line_number.unwrap(),
+                    result.column_number,
+                    result.lint,
+                )
+            })
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [
+                (RustcErrorKind::Error, None, 4, 12, None),
+                (
+                    RustcErrorKind::Warning,
+                    None,
+                    5,
+                    9,
+                    Some("unused_variables".to_owned())
+                ),
+                (RustcErrorKind::Error, Some("E0308".to_owned()), 6, 18, None),
+                // The label of the secondary span, `expected due to this`
+                (RustcErrorKind::Note, None, 6, 12, None),
+                (
+                    RustcErrorKind::Error,
+                    None,
+                    8,
+                    4,
+                    Some("non_snake_case".to_owned())
+                ),
+                (RustcErrorKind::Note, None, 1, 9, None),
+            ]
+        );
+        assert_eq!(
+            parse_stderr(LEVELS_STDERR)[1].message,
+            "unused variable: `x`"
+        );
+    }
+
+    #[test]
+    fn warnings_name_their_lint() {
+        let stderr = "warning: unused variable: `x` [unused_variables]\n --> $DIR/lint.rs:2:9\n\nerror: expected a slice [u8]\n --> $DIR/lint.rs:3:5\n";
+        let entries = parse_stderr(stderr);
+        assert_eq!(entries[0].message, "unused variable: `x`");
+        assert_eq!(entries[0].lint.as_deref(), Some("unused_variables"));
+        // Only the warnings end with their lint
+        assert_eq!(entries[1].message, "expected a slice [u8]");
+        assert_eq!(entries[1].lint, None);
+
+        let code = "fn main() {\n    let x = 1; //~ WARN\n    let y = 1; //~ WARN unused variable [clippy::unused]\n}\n";
+        let errors = load_error(code, Some(stderr)).unwrap();
+        let lints: Vec<_> = errors
+            .iter()
+            .map(|error| (error.msg.as_str(), error.lint.as_deref()))
+            .collect();
+        assert_eq!(
+            lints,
+            [
+                ("unused variable: `x`", Some("unused_variables")),
+                ("unused variable", Some("clippy::unused")),
+            ]
+        );
+    }
+
+    #[test]
+    fn stderr_diagnostics_without_code_give_columns_and_messages() {
+        let code = "#![deny(non_snake_case)]\n\nfn main() {\n    let (a b) = 1; //~ ERROR expected one of\n    let x = 1; //~ WARN\n    let y: i32 = \"\"; //~ ERROR mismatched types\n}\nfn F() {} //~ ERROR\n";
+        let errors = load_error(code, Some(LEVELS_STDERR)).unwrap();
+        let entries: Vec<_> = errors
+            .iter()
+            .map(|error| {
+                (
+                    error.error_code.as_deref(),
+                    error.column,
+                    error.msg.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (None, Some(12), "expected one of"),
+                // The annotations without a message take the one of the `stderr` file
+                (None, Some(9), "unused variable: `x`"),
+                (Some("E0308"), Some(18), "mismatched types"),
+                (None, Some(4), "function `F` should have a snake case name"),
+            ]
+        );
+
+        // A warning is not the entry of an error annotation
+        let errors = load_error(
+            "fn main() {\n\n\n\n    let x = 1; //~ ERROR\n}\n",
+            Some(LEVELS_STDERR),
+        )
+        .unwrap();
+        assert_eq!((errors[0].column, errors[0].msg.as_str()), (None, ""));
+    }
+
+    #[test]
+    fn overflowing_stderr_line_numbers_are_ignored() {
+        let stderr = "error[E0308]: mismatched types\n  --> $DIR/a.rs:99999999999999999999999:5\n";
+        assert!(parse_stderr(stderr).is_empty());
+    }
+
+    #[test]
+    fn message_pattern_none_is_empty() {
+        let message = message_pattern("mismatched types", MsgStyle::None);
+        assert_eq!(message.pattern, "");
+        assert_eq!(message.truncated, 0);
+    }
+
+    #[test]
+    fn message_pattern_prefix_keeps_plain_messages() {
+        let message = message_pattern("mismatched types", MsgStyle::Prefix);
+        assert_eq!(message.pattern, "mismatched types");
+        assert_eq!(message.truncated, 0);
+    }
+
+    #[test]
+    fn message_pattern_prefix_stops_at_first_special_char() {
+        let message = message_pattern(
+            "expected one of `:`, `@`, or `|`, found `)`",
+            MsgStyle::Prefix,
+        );
+        assert_eq!(message.pattern, "expected one of `:`, `@`, or `");
+        assert_eq!(message.truncated, "|`, found `)`".len());
+
+        let message = message_pattern("[E0308] mismatched types", MsgStyle::Prefix);
+        assert_eq!(message.pattern, "");
+        assert_eq!(message.truncated, "[E0308] mismatched types".len());
+    }
+
+    #[test]
+    fn message_pattern_escaped() {
+        let message = message_pattern("expected `(`, found \"{x}\"", MsgStyle::Escaped);
+        assert_eq!(message.pattern, r"expected `\\(`, found .\\\{x\\\}.");
+        assert_eq!(message.truncated, 0);
+    }
+
+    /// What Tcl makes of the contents of a double-quoted string: one level of backslashes less
+    fn tcl_unquote(text: &str) -> String {
+        let mut unquoted = String::with_capacity(text.len());
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => unquoted.extend(chars.next()),
+                c => unquoted.push(c),
+            }
+        }
+        unquoted
+    }
+
+    #[test]
+    fn escape_dejagnu_matches_the_message_literally() {
+        for (msg, escaped) in [
+            ("mismatched types", "mismatched types"),
+            // A message which is itself a regex
+            ("expected `(`, found `)`", r"expected `\\(`, found `\\)`"),
+            (".*", r"\\.\\*"),
+            ("^a+b?$", r"\\^a\\+b\\?\\\$"),
+            ("a|b", r"a\\|b"),
+            // Special to Tcl as well: braces, brackets and dollars
+            ("unclosed `{`", r"unclosed `\\\{`"),
+            ("found `}`", r"found `\\\}`"),
+            ("expected `[i32; 3]`", r"expected `\\\[i32; 3\\\]`"),
+            ("`$crate` in paths", r"`\\\$crate` in paths"),
+            ("#[derive(Debug)]", r"#\\\[derive\\(Debug\\)\\\]"),
+            // A backslash needs two for the regex, each doubled for Tcl
+            (
+                r"unknown character escape: `\q`",
+                r"unknown character escape: `\\\\q`",
+            ),
+            (r"\\", r"\\\\\\\\"),
+            // A double quote would end the string
+            (r#"expected "{integer}""#, r"expected .\\\{integer\\\}."),
+            (r#"""#, "."),
+            ("", ""),
+            ("unicode `é` is kept", "unicode `é` is kept"),
+        ] {
+            let pattern = escape_dejagnu(msg);
+            assert_eq!(pattern, escaped, "{msg:?}");
+
+            // The directive holding it is well formed
+            let directive = format!("// {{ dg-error \"{pattern}\" \"\" {{ target *-*-* }} }}");
+            crate::dejagnu::validate(&directive).unwrap_or_else(|err| panic!("{directive}: {err}"));
+
+            // Once Tcl is done with it, the regex matches the whole message and nothing else
+            let regex = regex::Regex::new(&format!("^{}$", tcl_unquote(&pattern))).unwrap();
+            let literal = msg.replace('"', "x");
+            assert!(regex.is_match(&literal), "{msg:?} {regex}");
+            if msg.contains(['.', '*', '+', '?']) {
+                assert!(
+                    !regex.is_match(&msg.replace(['.', '*', '+', '?'], "x")),
+                    "{regex}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_error_codes_are_escaped() {
+        let mut errors = load_error("fn main() {} //~ ERROR x\n", None).unwrap();
+        let mut error = errors.remove(0);
+        error.error_code = Some("E0308\"} [exec rm]".to_owned());
+        let directive = error.to_string();
+        assert_eq!(
+            directive,
+            r#"// { dg-error ".E0308.\\\} \\\[exec rm\\\]." "" { target *-*-* } }"#
+        );
+        crate::dejagnu::validate(&directive).unwrap();
+    }
+
+    #[test]
+    fn message_pattern_portable_escapes_tcl_and_regex_characters() {
+        for (msg, pattern) in [
+            ("mismatched types", "mismatched types"),
+            // A double quote would end the Tcl string
+            (r#"expected "{integer}""#, r"expected .\\\{integer\\\}."),
+            ("unclosed delimiter `{`", r"unclosed delimiter .\\\{."),
+            ("found `}` and `]`", r"found .\\\}. and .\\\]."),
+            (
+                "cannot find macro `m` in `$crate`",
+                r"cannot find macro .m. in .\\\$crate.",
+            ),
+            ("expected `[u8; 4]`", r"expected .\\\[u8; 4\\\]."),
+            (r"unknown escape `\q`", r"unknown escape .\\\\q."),
+            (
+                "expected one of `.`, `?` or `*`",
+                r"expected one of .\\.., .\\?. or .\\*.",
+            ),
+            ("a | b ^ c + d", r"a \\| b \\^ c \\+ d"),
+            ("call `f()`", r"call .f\\(\\)."),
+            // `gccrs` quotes with other characters, and the harness reads the pattern as bytes
+            ("unused variable: `x`", "unused variable: .x."),
+            ("expected `’`, found `é`", "expected ..., found ..."),
+            ("line\tbreak\u{7}", "line.break."),
+        ] {
+            let message = message_pattern(msg, MsgStyle::Portable);
+            assert_eq!(message.pattern, pattern, "{msg:?}");
+            assert_eq!(message.truncated, 0, "{msg:?}");
+        }
+    }
+
+    #[test]
+    fn message_pattern_portable_is_cut_at_a_word_boundary() {
+        let msg = "mismatched types: expected `u32`, found `i64`";
+        let message = message_pattern_within(msg, MsgStyle::Portable, 24);
+        assert_eq!(message.pattern, "mismatched types:");
+        assert_eq!(message.truncated, msg.len() - "mismatched types:".len());
+
+        // On a boundary already, or short enough
+        let message = message_pattern_within(msg, MsgStyle::Portable, 17);
+        assert_eq!(message.pattern, "mismatched types:");
+        let message = message_pattern_within(msg, MsgStyle::Portable, 16);
+        assert_eq!(message.pattern, "mismatched");
+        let message = message_pattern_within(msg, MsgStyle::Portable, msg.len());
+        assert_eq!((message.truncated, message.pattern.len()), (0, msg.len()));
+        let message = message_pattern_within(msg, MsgStyle::Portable, 0);
+        assert_eq!(message.truncated, 0);
+
+        // A single long word is cut where it must, the limit counts characters
+        let message = message_pattern_within("ééééé and more", MsgStyle::Portable, 3);
+        assert_eq!((message.pattern.as_str(), message.truncated), ("...", 11));
+        // The cut is made before escaping, on the message characters
+        let message = message_pattern_within("`{` is `}`", MsgStyle::Portable, 6);
+        assert_eq!(message.pattern, r".\\\{. is");
+
+        // The other styles are never cut
+        let message = message_pattern_within(msg, MsgStyle::Escaped, 10);
+        assert_eq!(message.truncated, 0);
+    }
+
+    #[test]
+    fn portable_message_and_error_code_are_joined() {
+        let mut errors = load_error("fn main() {} //~ ERROR mismatched types\n", None).unwrap();
+        let mut error = errors.remove(0);
+        error.error_code = Some("E0308".to_owned());
+        let message = message_pattern(&error.msg, MsgStyle::Portable);
+        assert_eq!(
+            error.to_directive(&message.pattern),
+            "// { dg-error \"mismatched types.*.E0308.\" \"\" { target *-*-* } }"
+        );
+    }
+
+    #[test]
+    fn revisioned_annotations_apply_to_their_revisions() {
+        let code = "fn main() {} //[a,b]~ ERROR x\n//~^ ERROR y\n//[c]~^^ WARN z\n";
+        let errors = load_error(code, None).unwrap();
+        let revisions: Vec<_> = errors.iter().map(|error| &error.revisions).collect();
+        assert_eq!(revisions, [&vec!["a", "b"], &vec![], &vec!["c"]]);
+        let applying = |revision| {
+            errors
+                .iter()
+                .filter(|error| error.applies_to(revision))
+                .map(|error| error.msg.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(applying(None), ["x", "y", "z"]);
+        assert_eq!(applying(Some("b")), ["x", "y"]);
+        assert_eq!(applying(Some("c")), ["y", "z"]);
+    }
+
+    #[test]
+    fn stderr_columns() {
+        let stderr = "error[E0425]: cannot find value `y` in this scope\n  --> $DIR/a.rs:3:30\n\nerror[E0308]: mismatched types\n  --> $DIR/a.rs:3:18\n\nerror[E0599]: no method\n  --> $DIR/a.rs:7:\n";
+        let columns: Vec<_> = parse_stderr(stderr)
+            .iter()
+            .map(|result| (result.line_number, result.column_number))
+            .collect();
+        assert_eq!(columns, [(Some(3), 30), (Some(3), 18), (Some(7), 0)]);
+    }
+
+    /// A `stderr` file blessed with `-Z ui-testing`, whose lines and columns are normalized
+    const NORMALIZED_STDERR: &str = "\
+error[E0277]: `T` doesn't implement `std::fmt::Display`
+  --> $DIR/normalized.rs:LL:COL
+   |
+LL |     println!(\"{}\", t);
+   |               ^^ `T` cannot be formatted with the default formatter
+   |
+note: required by a bound in `std::fmt::Display`
+  --> $SRC_DIR/core/src/fmt/mod.rs:LL:COL
+   = note: this error originates in the macro `$crate::format_args_nl` (in Nightly builds, run with -Z macro-backtrace for more info)
+
+error[E0308]: mismatched types
+  --> $DIR/normalized.rs:7:18
+   |
+LL |     let x: i32 = \"\";
+   |            ---   ^^ expected `i32`, found `&str`
+
+note: the compiler panicked here
+  --> $RUSTC/compiler/rustc_middle/src/ty/mod.rs:12:5
+
+error: aborting due to 2 previous errors
+";
+
+    #[test]
+    fn each_stderr_block_has_its_own_location() {
+        let stderr = "\
+error[E0308]: mismatched types
+  --> C:\\work\\tests\\foo.rs:12:5
+   |
+LL |     let x: i32 = \"\";
+   |            ---   ^^ expected `i32`, found `&str`
+
+note: no span for this one, `a.rs:3:1` stays in its message
+
+error[E0277]: `Foo` is not an iterator
+              because it does not implement `Iterator`
+  --> $DIR/foo.rs:20:14
+   |
+LL |     for x in Foo {}
+   |              ^^^ `Foo` is not an iterator
+
+error: aborting due to 2 previous errors
+";
+        let diagnostics: Vec<_> = parse_stderr(stderr)
+            .into_iter()
+            .map(|result| {
+                (
+                    result.code.unwrap(),
+                    result.message,
+                    result.path,
+                    result.line_number,
+                    result.column_number,
+                )
+            })
+            .collect();
+        assert_eq!(
+            diagnostics,
+            [
+                (
+                    "E0308".to_owned(),
+                    "mismatched types".to_owned(),
+                    "C:\\work\\tests\\foo.rs".to_owned(),
+                    Some(12),
+                    5
+                ),
+                // Only the first line of the message is kept
+                (
+                    "E0277".to_owned(),
+                    "`Foo` is not an iterator".to_owned(),
+                    "$DIR/foo.rs".to_owned(),
+                    Some(20),
+                    14
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_paths_are_in_their_file() {
+        let stderr = "\
+error[E0308]: mismatched types
+  --> C:\\work\\tests\\s.rs:2:18
+   |
+LL |     let x: i32 = \"\";
+   |                  ^^ expected `i32`, found `&str`
+
+error: aborting due to 1 previous error
+";
+        let diagnostics = parse_stderr(stderr);
+        assert!(diagnostics[0].is_in(Some("s.rs")));
+        assert!(!diagnostics[0].is_in(Some("work")));
+        let errors = load_stderr_errors(stderr, Some("s.rs"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line_num, 2);
+    }
+
+    #[test]
+    fn secondary_labels_are_notes_of_their_line() {
+        let stderr = "\
+error[E0308]: mismatched types
+  --> $DIR/a.rs:5:5
+   |
+LL | fn f() -> i32 {
+   |           --- expected `i32` because of return type
+LL |     let s = \"\";
+   |             -- this is a `&str`
+...
+LL |     s
+   |     ^ expected `i32`, found `&str`
+
+error[E0308]: mismatched types
+  --> $DIR/a.rs:9:18
+   |
+9  |     let x: i32 = \"\";
+   |            ---   ^^ expected `i32`, found `&str`
+   |            |
+   |            expected due to this
+";
+        let labels: Vec<_> = parse_stderr(stderr)
+            .into_iter()
+            .filter(|result| result.label)
+            .map(|result| (result.line_number, result.column_number, result.message))
+            .collect();
+        // The lines before the `...` gap cannot be counted from the primary one
+        assert_eq!(
+            labels,
+            [
+                (None, 11, "expected `i32` because of return type".to_owned()),
+                (None, 13, "this is a `&str`".to_owned()),
+                (Some(9), 12, "expected due to this".to_owned()),
+            ]
+        );
+
+        let stderr = "error[E0308]: mismatched types\n  --> $DIR/a.rs:3:5\n   |\nLL | fn f() -> i32 {\n   |           --- expected `i32` because of return type\nLL |     \"\"\n   |     ^^ expected `i32`, found `&str`\n";
+        let label = &parse_stderr(stderr)[1];
+        assert_eq!(
+            (label.level, label.line_number),
+            (RustcErrorKind::Note, Some(2))
+        );
+    }
+
+    #[test]
+    fn stderr_entries_of_other_files_are_left_out() {
+        let code = "fn main() {\n    let x: i32 = \"\"; //~ ERROR\n}\n";
+        let stderr = parse_stderr("error[E0425]: cannot find value `y` in this scope\n  --> $DIR/auxiliary/helper.rs:2:5\n\nerror[E0308]: mismatched types\n  --> $DIR/test.rs:2:18\n");
+        assert_eq!(stderr[0].path, "$DIR/auxiliary/helper.rs");
+        let options = LoadOptions {
+            source_name: Some("test.rs".to_owned()),
+            ..LoadOptions::default()
+        };
+        let loaded = load_errors_parsed(code, Some(&stderr), &options).unwrap();
+        assert_eq!(loaded.errors[0].error_code.as_deref(), Some("E0308"));
+        assert_eq!((loaded.other_files, loaded.unmatched.len()), (1, 0));
+        assert_eq!(stderr_errors(&stderr, Some("test.rs")).len(), 1);
+
+        // Without a name, the first entry of the line is taken whatever its file
+        let loaded = load_errors_parsed(code, Some(&stderr), &LoadOptions::default()).unwrap();
+        assert_eq!(loaded.errors[0].error_code.as_deref(), Some("E0425"));
+        assert_eq!((loaded.other_files, loaded.unmatched), (0, vec![1]));
+    }
+
+    #[test]
+    fn normalized_stderr_lines_match_by_message() {
+        let diagnostics: Vec<_> = parse_stderr(NORMALIZED_STDERR)
+            .into_iter()
+            .map(|result| (result.code, result.line_number, result.column_number))
+            .collect();
+        // The spans of the standard library and the compiler are left out
+        assert_eq!(
+            diagnostics,
+            [
+                (Some("E0277".to_owned()), None, 0),
+                (Some("E0308".to_owned()), Some(7), 18),
+            ]
+        );
+
+        let code = "fn show<T>(t: T) {\n    println!(\"{}\", t);\n    //~^ ERROR doesn't implement\n}\n\nfn main() {\n    let x: i32 = \"\";\n    //~^ ERROR mismatched types\n}\n";
+        let stderr = parse_stderr(NORMALIZED_STDERR);
+        let options = LoadOptions::default();
+        let loaded = load_errors_parsed(code, Some(&stderr), &options).unwrap();
+        let codes: Vec<_> = loaded
+            .errors
+            .iter()
+            .map(|error| (error.line_num, error.error_code.as_deref(), error.column))
+            .collect();
+        // The `LL` entry gives its code but no column, it has no line to compare
+        assert_eq!(
+            codes,
+            [(2, Some("E0277"), None), (7, Some("E0308"), Some(18))]
+        );
+        assert!(loaded.unmatched.is_empty(), "{:?}", loaded.unmatched);
+        assert_eq!(
+            stderr[0].to_string(),
+            "line LL: error[E0277]: `T` doesn't implement `std::fmt::Display`"
+        );
+    }
+
+    #[test]
+    fn errors_of_a_line_take_its_entries_by_column() {
+        let code = "fn main() {\n    let x: i32 = \"\"; let z = y;\n    //~^ ERROR mismatched types\n    //~| ERROR cannot find value `y` in this scope\n    //~| NOTE not found\n    let w = 1; //~ ERROR no method\n}\n";
+        let stderr = "error[E0425]: cannot find value `y` in this scope\n  --> $DIR/a.rs:2:30\n\nerror[E0308]: mismatched types\n  --> $DIR/a.rs:2:18\n\nerror[E0599]: no method\n  --> $DIR/a.rs:9:5\n";
+        let errors = load_error(code, Some(stderr)).unwrap();
+        let codes: Vec<_> = errors
+            .iter()
+            .map(|error| (error.error_code.as_deref(), error.column))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                (Some("E0308"), Some(18)),
+                (Some("E0425"), Some(30)),
+                // A note needs an entry of its line with its message
+                (None, None),
+                // Nothing on its line, the message matches an entry elsewhere
+                (Some("E0599"), None),
+            ]
+        );
+
+        // An entry left on the line of the annotation wins over one with its message elsewhere
+        let stderr = "error[E0277]: other\n  --> $DIR/a.rs:2:5\n\nerror[E0308]: mismatched types\n  --> $DIR/a.rs:5:18\n";
+        let errors = load_error(
+            "fn main() {\n    let x = 1; //~ ERROR mismatched types\n}\n",
+            Some(stderr),
+        )
+        .unwrap();
+        assert_eq!(
+            (errors[0].error_code.as_deref(), errors[0].column),
+            (Some("E0277"), Some(5))
+        );
+    }
+
+    #[test]
+    fn each_entry_goes_to_a_single_annotation() {
+        let code = include_str!("../tests/fixtures/same_line_codes.rs");
+        let stderr = include_str!("../tests/fixtures/same_line_codes.stderr");
+        let codes = |errors: Vec<Error>| -> Vec<_> {
+            errors
+                .into_iter()
+                .map(|error| (error.error_code, error.column))
+                .collect()
+        };
+        // In the order of the annotations, not of the columns
+        assert_eq!(
+            codes(load_error(code, Some(stderr)).unwrap()),
+            [
+                (Some("E0308".to_owned()), Some(57)),
+                (Some("E0106".to_owned()), Some(50)),
+            ]
+        );
+
+        // Without messages, in the order of the columns, and a code only repeats if the
+        // `stderr` file has it twice
+        let code = "fn main() {} //~ ERROR\n//~^ ERROR\n//~^^ ERROR\n";
+        let stderr = "error[E0308]: a\n  --> $DIR/a.rs:1:13\n\nerror[E0308]: b\n  --> $DIR/a.rs:1:9\n\nerror[E0425]: c\n  --> $DIR/a.rs:1:1\n";
+        assert_eq!(
+            codes(load_error(code, Some(stderr)).unwrap()),
+            [
+                (Some("E0425".to_owned()), Some(1)),
+                (Some("E0308".to_owned()), Some(9)),
+                (Some("E0308".to_owned()), Some(13)),
+            ]
+        );
+        let code = "fn main() {} //~ ERROR\n//~^ ERROR\n//~^^ ERROR\n//~^^^ ERROR\n";
+        let errors = load_error(code, Some(stderr)).unwrap();
+        assert_eq!(errors[3].error_code, None);
+    }
+
+    #[test]
+    fn annotations_match_a_part_of_the_stderr_message() {
+        let stderr =
+            "error[E0308]: mismatched types: expected `i32`, found `&str`\n  --> $DIR/a.rs:2:18\n";
+        let errors = load_error(
+            "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n    //~^ ERROR expected `i64`\n}\n",
+            Some(stderr),
+        )
+        .unwrap();
+        let codes: Vec<_> = errors
+            .iter()
+            .map(|error| error.error_code.as_deref())
+            .collect();
+        assert_eq!(codes, [Some("E0308"), None]);
+    }
+
+    #[test]
+    fn the_closest_stderr_entry_wins_with_an_ambiguity() {
+        let stderr = "\
+error[E0425]: cannot find value `x` in this scope\n  --> $DIR/a.rs:2:5\n
+error[E0425]: cannot find value `x` in this scope\n  --> $DIR/a.rs:9:5\n
+error[E0599]: cannot find value `x` in this scope\n  --> $DIR/a.rs:6:5\n";
+        let code =
+            "fn main() {\n    x;\n\n\n\n\n    //~vv ERROR cannot find value `x`\n\n    x;\n}\n";
+        // The entry of its line, there is nothing to choose from
+        let loaded = load_errors(code, Some(stderr), &LoadOptions::default()).unwrap();
+        assert_eq!(loaded.errors[0].column, Some(5));
+        assert!(loaded.ambiguities.is_empty());
+
+        // Line 9 is closer to line 8 than line 6
+        let code = code.replace("//~vv", "//~v");
+        let loaded = load_errors(&code, Some(stderr), &LoadOptions::default()).unwrap();
+        assert_eq!(
+            (
+                loaded.errors[0].line_num,
+                loaded.errors[0].error_code.as_deref()
+            ),
+            (8, Some("E0425"))
+        );
+        assert_eq!(
+            loaded.ambiguities,
+            ["line 8: ambiguous, `cannot find value `x`` is in the stderr entries of lines 2, 6, 9, took E0425 of line 9, the closest"]
+        );
+    }
+
+    #[test]
+    fn directive_joins_message_and_error_code() {
+        let error = Error {
+            line_num: 1,
+            relative_line_num: -1,
+            kind: Some(RustcErrorKind::Error),
+            msg: "mismatched types".to_owned(),
+            error_code: Some("E0308".to_owned()),
+            column: None,
+            revisions: Vec::new(),
+            whole_file: false,
+            lint: None,
+            annotation_span: None,
+            target_line: None,
+        };
+        assert_eq!(
+            error.to_directive("mismatched types"),
+            "// { dg-error \"mismatched types.*.E0308.\" \"\" { target *-*-* } .-1 }"
+        );
+        assert_eq!(error.to_directive(""), error.to_string());
+    }
+
     #[test]
     fn display_help_outputs_correct_string() {
         assert_eq!(format!("{}", RustcErrorKind::Help), "help message");
@@ -0,0 +1,309 @@
+//! This module contains the explanation of how a single annotation is converted.
+//!
+//! It goes through the same parsing and rendering as the conversion of a whole file, and
+//! writes down each step: the sigil and the line it targets, the kind and the directive it
+//! maps to, the pattern built from the message and the line which ends up in the output.
+
+use {
+    crate::{
+        dejagnu,
+        errors::{self, AnnotationError, Sigil},
+        header::AnnotationPolicy,
+        transform::{self, Options, SecondaryMode, TransformReport},
+    },
+    clap::ValueEnum,
+    std::fmt::Write,
+};
+
+/// Explains the conversion of `annotation`, a source line holding a `//~` annotation.
+///
+/// With `at_line`, the annotation is on that 1-based line and the absolute target line is
+/// shown as well. A `//~|` is assumed to follow a `//~^` annotation on the line above it.
+pub fn explain(
+    annotation: &str,
+    at_line: Option<usize>,
+    options: &Options,
+) -> Result<String, AnnotationError> {
+    // Without a line, any line far enough from the start of the file will do: the carets
+    // of the annotation cannot outnumber its characters
+    let line_num = at_line.unwrap_or(annotation.len() + 2);
+    // The `//~^` of the line above targets the line above it
+    let previous = line_num.checked_sub(2).filter(|&line| line > 0);
+    let Some((error, form)) = errors::parse_annotation(previous, line_num, annotation)? else {
+        return Err(AnnotationError::MissingAnnotation {
+            line: at_line.unwrap_or(1),
+        });
+    };
+
+    let mut text = String::new();
+    let mut line = |line: String| {
+        text.push_str(&line);
+        text.push('\n');
+    };
+    line(format!("annotation: `{}`", annotation.trim()));
+
+    let offset = -error.relative_line_num;
+    let above = match offset {
+        0 => "the line of the annotation".to_owned(),
+        1 => "1 line above the annotation".to_owned(),
+        -1 => "1 line below the annotation".to_owned(),
+        _ if offset < 0 => format!("{} lines below the annotation", -offset),
+        _ => format!("{offset} lines above the annotation"),
+    };
+    line(match form.sigil {
+        Sigil::ThisLine => format!("sigil: `{}` targets {above}", form.sigil),
+        Sigil::Caret(carets) => format!(
+            "sigil: `{}` has {carets} caret{} and targets {above}",
+            form.sigil,
+            if carets == 1 { "" } else { "s" }
+        ),
+        Sigil::Down(downs) => format!(
+            "sigil: `{}` has {downs} `v`{} and targets {above}",
+            form.sigil,
+            if downs == 1 { "" } else { "s" }
+        ),
+        Sigil::WholeFile => format!(
+            "sigil: `{}` is for a diagnostic without a span, it targets the whole file",
+            form.sigil
+        ),
+        Sigil::Follow => format!(
+            "sigil: `{}` targets the same line as the annotation it follows, assumed to be a `//~^` on the line above: {above}",
+            form.sigil
+        ),
+    });
+    if form.has_revision {
+        line("revisions: the annotation only applies to some revisions, which gccrs tests do not have".to_owned());
+    }
+    if let Some(at_line) = at_line {
+        line(if error.is_whole_file() {
+            "target: line 0, anywhere in the file".to_owned()
+        } else if offset == 0 {
+            format!("target: line {at_line}")
+        } else if offset < 0 {
+            format!(
+                "target: line {at_line} + {} = line {}",
+                -offset, error.line_num
+            )
+        } else {
+            format!(
+                "target: line {at_line} - {offset} = line {}",
+                error.line_num
+            )
+        });
+    }
+
+    let directive = errors::dg_directive(error.kind);
+    line(match error.kind {
+        Some(kind) => format!("kind: {}, rendered as `{directive}`", kind.keyword()),
+        None => format!("kind: none, rendered like an ERROR as `{directive}`"),
+    });
+    line(format!("message: `{}`", error.msg));
+
+    let rendered = transform::render_error(
+        &error,
+        options,
+        &AnnotationPolicy::default(),
+        errors::Selector::ANY,
+        &mut TransformReport::default(),
+    );
+    let secondary = error.kind.is_some_and(|kind| kind.is_secondary());
+    match options.secondary {
+        SecondaryMode::Comment if secondary => {
+            line("secondary: kept as a plain comment (`--secondary comment`)".to_owned())
+        }
+        SecondaryMode::Drop if secondary => line(
+            "secondary: dropped, the annotation line is left empty (`--secondary drop`)".to_owned(),
+        ),
+        _ => {
+            let message = errors::message_pattern_within(
+                &error.msg,
+                options.msg_style,
+                options.max_message_length,
+            );
+            let style = options
+                .msg_style
+                .to_possible_value()
+                .expect("no message style is skipped");
+            let mut pattern = format!(
+                "pattern: `{}` (`--msg-style {}`",
+                errors::prefixed_pattern(error.kind, &message.pattern),
+                style.get_name()
+            );
+            if message.truncated > 0 {
+                let _ = write!(pattern, ", {} characters cut off", message.truncated);
+            }
+            pattern.push(')');
+            line(pattern);
+            if !dejagnu::is_diagnostic(directive) {
+                line(format!(
+                    "note: `{directive}` is not a DejaGnu directive, the harness will not check it"
+                ));
+            }
+        }
+    }
+
+    // The same line building as the conversion of a whole file
+    let start = error
+        .annotation_span
+        .map_or(annotation.len(), |(start, _)| start);
+    let code = &annotation[..start];
+    let output = if error.relative_line_num != 0 && code.trim().is_empty() {
+        line("code: the annotation line only holds the directive".to_owned());
+        rendered
+    } else {
+        line(format!(
+            "code: `{}` is kept before the directive",
+            code.trim()
+        ));
+        if rendered.is_empty() {
+            code.trim_end().to_owned()
+        } else {
+            format!("{code}{rendered}")
+        }
+    };
+    line(format!("output: {output}"));
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::errors::MsgStyle};
+
+    #[test]
+    fn test_explain_caret() {
+        let text = explain(
+            "//~^ ERROR mismatched types [E0308]",
+            Some(10),
+            &Options::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            text,
+            "\
+annotation: `//~^ ERROR mismatched types [E0308]`
+sigil: `//~^` has 1 caret and targets 1 line above the annotation
+target: line 10 - 1 = line 9
+kind: ERROR, rendered as `dg-error`
+message: `mismatched types [E0308]`
+pattern: `` (`--msg-style none`)
+code: the annotation line only holds the directive
+output: // { dg-error \"\" \"\" { target *-*-* } .-1 }
+"
+        );
+    }
+
+    #[test]
+    fn test_explain_downward() {
+        let text = explain("//~vv ERROR mismatched types", Some(4), &Options::default()).unwrap();
+        assert!(
+            text.contains("sigil: `//~vv` has 2 `v`s and targets 2 lines below the annotation\ntarget: line 4 + 2 = line 6\n"),
+            "{text}"
+        );
+        assert!(
+            text.ends_with("output: // { dg-error \"\" \"\" { target *-*-* } .+2 }\n"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_explain_code_before_a_caret() {
+        let text = explain(
+            "    drop(y); //~^ ERROR mismatched types",
+            None,
+            &Options::default(),
+        )
+        .unwrap();
+        assert!(
+            text.ends_with("code: `drop(y);` is kept before the directive\noutput:     drop(y); // { dg-error \"\" \"\" { target *-*-* } .-1 }\n"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_explain_whole_file() {
+        let text = explain(
+            "//~? ERROR invalid crate name",
+            Some(9),
+            &Options::default(),
+        )
+        .unwrap();
+        assert!(
+            text.contains("target: line 0, anywhere in the file\n"),
+            "{text}"
+        );
+        assert!(
+            text.ends_with("output: // { dg-error \"\" \"\" { target *-*-* } 0 }\n"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_explain_same_line() {
+        let options = Options {
+            msg_style: MsgStyle::Prefix,
+            ..Options::default()
+        };
+        let text = explain(
+            "    let x = y; //~ WARN unused variable: `x`",
+            None,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            text,
+            "\
+annotation: `let x = y; //~ WARN unused variable: `x``
+sigil: `//~` targets the line of the annotation
+kind: WARN, rendered as `dg-warning`
+message: `unused variable: `x``
+pattern: `unused variable: `x`` (`--msg-style prefix`)
+code: `let x = y;` is kept before the directive
+output:     let x = y; // { dg-warning \"unused variable: `x`\" \"\" { target *-*-* } }
+"
+        );
+    }
+
+    #[test]
+    fn test_explain_secondary() {
+        let text = explain(
+            "//~| HELP consider borrowing here",
+            Some(5),
+            &Options::default(),
+        )
+        .unwrap();
+        assert!(
+            text.contains("kind: HELP, rendered as `dg-message`\n"),
+            "{text}"
+        );
+        assert!(!text.contains("is not a DejaGnu directive"), "{text}");
+        assert!(text.contains("sigil: `//~|` targets the same line as the annotation it follows"));
+
+        let options = Options {
+            secondary: SecondaryMode::Comment,
+            ..Options::default()
+        };
+        let text = explain("//~^^ NOTE required by this bound", None, &options).unwrap();
+        assert!(text.ends_with(
+            "secondary: kept as a plain comment (`--secondary comment`)
+code: the annotation line only holds the directive
+output: // rustc-note: required by this bound
+"
+        ));
+    }
+
+    #[test]
+    fn test_explain_errors() {
+        assert_eq!(
+            explain("//~^^ ERROR x", Some(1), &Options::default()),
+            Err(AnnotationError::AboveFirstLine { line: 1, carets: 2 })
+        );
+        assert_eq!(
+            explain("//~| NOTE x", Some(1), &Options::default()),
+            Err(AnnotationError::OrphanFollow { line: 1 })
+        );
+        assert_eq!(
+            explain("fn main() {}", None, &Options::default()),
+            Err(AnnotationError::MissingAnnotation { line: 1 })
+        );
+    }
+}
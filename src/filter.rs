@@ -0,0 +1,77 @@
+//! This module contains the selection of the files of a directory conversion by glob
+//! patterns, matched against their path relative to the directory.
+//!
+//! A file is converted when it matches an include pattern, or when there are none, and no
+//! exclude pattern: excludes win over includes.
+
+use {
+    globset::{Glob, GlobSet, GlobSetBuilder},
+    std::path::Path,
+};
+
+/// Which files of a directory are converted
+#[derive(Clone, Debug)]
+pub struct Filter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Filter {
+    /// Selects the files matching one of `include` if any, but none of `exclude`
+    pub fn new(include: &[Glob], exclude: &[Glob]) -> Result<Filter, globset::Error> {
+        let include = if include.is_empty() {
+            None
+        } else {
+            Some(glob_set(include)?)
+        };
+        Ok(Filter {
+            include,
+            exclude: glob_set(exclude)?,
+        })
+    }
+
+    /// Whether the file at `relative`, relative to the directory, is converted
+    pub fn is_selected(&self, relative: &Path) -> bool {
+        self.include
+            .as_ref()
+            .is_none_or(|include| include.is_match(relative))
+            && !self.exclude.is_match(relative)
+    }
+}
+
+fn glob_set(globs: &[Glob]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(glob.clone());
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn globs(patterns: &[&str]) -> Vec<Glob> {
+        patterns
+            .iter()
+            .map(|pattern| Glob::new(pattern).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_is_selected() {
+        let everything = Filter::new(&[], &[]).unwrap();
+        assert!(everything.is_selected(Path::new("typeck/mismatch.rs")));
+
+        let filter = Filter::new(&[], &globs(&["**/proc-macro/**", "*-2015.rs"])).unwrap();
+        assert!(filter.is_selected(Path::new("typeck/mismatch.rs")));
+        assert!(!filter.is_selected(Path::new("proc-macro/derive.rs")));
+        assert!(!filter.is_selected(Path::new("lint/proc-macro/nested/attr.rs")));
+        assert!(!filter.is_selected(Path::new("editions/async-2015.rs")));
+
+        let filter = Filter::new(&globs(&["typeck/**"]), &globs(&["**/*-2015.rs"])).unwrap();
+        assert!(filter.is_selected(Path::new("typeck/mismatch.rs")));
+        assert!(!filter.is_selected(Path::new("lint/unused.rs")));
+        assert!(!filter.is_selected(Path::new("typeck/dyn-2015.rs")));
+    }
+}
@@ -0,0 +1,84 @@
+//! Entry points of the `cargo fuzz` targets living in `fuzz/`.
+//!
+//! None of them may panic, whatever the input. The unit tests below run them over a
+//! handful of nasty inputs, so that regressions show up in a normal `cargo test`.
+
+use crate::{errors, transform};
+
+/// Splits the fuzzer input at the first NUL byte into a source and an optional `stderr` file.
+/// Returns `None` for inputs which are not valid UTF-8, as the CLI would refuse to read them.
+fn split_input(data: &[u8]) -> Option<(&str, Option<&str>)> {
+    let input = std::str::from_utf8(data).ok()?;
+    Some(match input.split_once('\0') {
+        Some((code, stderr)) => (code, Some(stderr)),
+        None => (input, None),
+    })
+}
+
+/// Target `load_error`: the whole input is the source file
+pub fn load_error(data: &[u8]) {
+    if let Ok(code) = std::str::from_utf8(data) {
+        let _ = errors::load_error(code, None);
+    }
+}
+
+/// Target `load_error_with_stderr`: the input is `<source>\0<stderr>`
+pub fn load_error_with_stderr(data: &[u8]) {
+    if let Some((code, stderr)) = split_input(data) {
+        let _ = errors::load_error(code, Some(stderr.unwrap_or_default()));
+    }
+}
+
+/// Target `transform_code`: the input is `<source>` or `<source>\0<stderr>`,
+/// transformed with every message style
+pub fn transform_code(data: &[u8]) {
+    let Some((code, stderr)) = split_input(data) else {
+        return;
+    };
+    for msg_style in [
+        errors::MsgStyle::None,
+        errors::MsgStyle::Prefix,
+        errors::MsgStyle::Escaped,
+        errors::MsgStyle::Portable,
+    ] {
+        let options = transform::Options {
+            msg_style,
+            ..transform::Options::default()
+        };
+        // Errors are fine, panics are not
+        let _ = transform::transform_code(code, stderr, &options);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NASTY_INPUTS: &[&str] = &[
+        "",
+        "\0",
+        "//~",
+        "//~|",
+        "//~| NOTE orphan follow",
+        "//~^^^^^^^^ ERROR above the start of the file",
+        "//~^",
+        "fn main() {} //~ ERROR\n//~|\n//~^^",
+        "//[rev1,rev2]~^ ERROR revisioned\n//[]~ ERROR",
+        "//~ ERROR é ü 漢字 //~ ERROR twice\r\n//~^ ERROR crlf",
+        "//@ edition:\n//@\n//@ edition\n// edition:2018",
+        "//~ ERROR x\0error[E0308]: x\n  --> a.rs:99999999999999999999999:1:",
+        "//~ ERROR {\"[$\\\0error[E0308]: \n  --> :1:",
+        "\u{feff}//~ ERROR bom",
+    ];
+
+    #[test]
+    fn test_targets_do_not_panic_on_nasty_inputs() {
+        for input in NASTY_INPUTS {
+            load_error(input.as_bytes());
+            load_error_with_stderr(input.as_bytes());
+            transform_code(input.as_bytes());
+        }
+        // Invalid UTF-8 is ignored
+        transform_code(&[0xff, 0xfe, b'/', b'/', b'~']);
+    }
+}
@@ -1,55 +1,1258 @@
 //! This module contains the logic for parsing rust test headers
 //! See [rustc dev guide](https://rustc-dev-guide.rust-lang.org/tests/headers.html#test-headers)
 
+use {
+    crate::{
+        coverage::Support,
+        errors::{self, RustcErrorKind, Selector, ANY_TARGET},
+        needs::{self, Needs, NeedsPolicy},
+    },
+    std::collections::BTreeMap,
+};
+
 #[derive(Debug)]
-pub struct HeaderLine<'ln> {
+pub struct HeaderLine {
     pub line_number: usize,
-    /// The main part of the header directive, after removing the comment prefix
-    /// and the optional revision specifier.
-    pub _directive: &'ln str,
+    /// The directive of the line, without its comment prefix and revision specifier
+    pub directive: Directive,
     /// DejaGnu formatted header line
     pub dejagnu_header: String,
 }
 
-pub fn parse_additional_options(code: &str) -> Vec<HeaderLine> {
+/// A header directive which is converted into a DejaGnu directive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Directive {
+    /// `edition: 2021`
+    Edition(String),
+    /// `compile-flags: -O --edition 2021`, split on whitespace
+    CompileFlags(Vec<String>),
+    /// `aux-build: helper.rs`, the files of the `auxiliary` directory next to the test
+    AuxBuild(Vec<String>),
+    /// The `revisions` header, in the conversion of one of them
+    Revision(String),
+    /// `ignore-windows`, for a target of [`TARGET_TRIPLETS`]
+    Ignore(String),
+    /// `only-x86_64`, for a target of [`TARGET_TRIPLETS`]. The line is kept as a comment,
+    /// the target restricts the selectors of the directives instead, see [`target_selector`].
+    Only(String),
+    /// `check-pass`, `run-pass` and the like. The line is left empty, the `dg-do` of the mode
+    /// is the first line of the output instead, see [`test_mode`].
+    Mode(Mode),
+    /// `error-pattern: cannot find value`, a message expected anywhere in the output
+    ErrorPattern(String),
+    /// `known-bug: #12345`, a test of a rustc bug. The line is left empty, a comment with the
+    /// issue is among the first lines of the output instead.
+    KnownBug(String),
+    /// `needs-asm-support`, removed or skipping the test as [`NeedsPolicy`] says
+    Needs(String),
+    /// `rustc-env: RUST_BACKTRACE=0`, a variable of the environment of the compiler
+    RustcEnv(String, String),
+    /// `exec-env: RUST_MIN_STACK=16384`, a variable of the environment of the run, which
+    /// the harness has none of per test. It is rewritten as a `// UNCONVERTED: ...` comment
+    /// like an [`Directive::Unconverted`] one.
+    ExecEnv(String),
+    /// `dont-require-annotations: NOTE`, the annotations of the kind are optional, see
+    /// [`AnnotationPolicy`]. The line is left empty.
+    DontRequireAnnotations(RustcErrorKind),
+    /// One of [`NO_EQUIVALENT`], like `normalize-stderr-test: "..." -> "..."`. The line
+    /// is left empty.
+    NoEquivalent(String),
+    /// One of [`IGNORED`], like `incremental`. The line is left empty.
+    Ignored(&'static str),
+    /// A `//@` header compiletest does not know, without its `//`. It is rewritten as a
+    /// `// UNCONVERTED: @...` comment which stands out in review.
+    Unconverted(String),
+}
+
+/// What compiletest does with a test, when a header says so rather than the default of
+/// expecting the compilation to fail
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    CheckPass,
+    BuildPass,
+    RunPass,
+    RunFail,
+}
+
+/// The first line of a compile-fail test with `--explicit-dg-do`, DejaGnu compiles by default
+pub const DG_DO_COMPILE: &str = "// { dg-do compile }";
+
+impl Mode {
+    const MODES: [Mode; 4] = [
+        Mode::CheckPass,
+        Mode::BuildPass,
+        Mode::RunPass,
+        Mode::RunFail,
+    ];
+
+    /// The mode of the header `name`
+    pub fn from_name(name: &str) -> Option<Mode> {
+        Mode::MODES.into_iter().find(|mode| mode.name() == name)
+    }
+
+    /// The name of the header
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::CheckPass => "check-pass",
+            Mode::BuildPass => "build-pass",
+            Mode::RunPass => "run-pass",
+            Mode::RunFail => "run-fail",
+        }
+    }
+
+    /// The DejaGnu lines starting the converted test
+    pub fn dejagnu_lines(self) -> &'static [&'static str] {
+        match self {
+            Mode::CheckPass | Mode::BuildPass => &[DG_DO_COMPILE],
+            Mode::RunPass => &["// { dg-do run }"],
+            Mode::RunFail => &["// { dg-do run }", "// { dg-shouldfail \"run-fail\" }"],
+        }
+    }
+}
+
+/// Two mode headers of a test disagree, like `check-pass` and `run-pass`
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConflictingModes {
+    pub first: (usize, Mode),
+    pub second: (usize, Mode),
+}
+
+impl std::fmt::Display for ConflictingModes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ((first_line, first), (second_line, second)) = (self.first, self.second);
+        write!(
+            f,
+            "line {second_line}: `{}` conflicts with the `{}` header of line {first_line}",
+            second.name(),
+            first.name()
+        )
+    }
+}
+
+impl std::error::Error for ConflictingModes {}
+
+/// The mode of `code` for `revision` given by its headers, `None` for a compile-fail test.
+/// Repeating a mode is fine, two different ones are not.
+pub fn test_mode(code: &str, revision: Option<&str>) -> Result<Option<Mode>, ConflictingModes> {
+    let mut found: Option<(usize, Mode)> = None;
+    for (line_number, line) in code.lines().enumerate() {
+        let Some((line_revisions, Directive::Mode(mode))) = revisioned_header_line(line) else {
+            continue;
+        };
+        if !applies_to_revision(line_revisions, revision) {
+            continue;
+        }
+        let line_number = line_number + 1;
+        match found {
+            Some((_, first)) if first == mode => {}
+            Some(first) => {
+                return Err(ConflictingModes {
+                    first,
+                    second: (line_number, mode),
+                })
+            }
+            None => found = Some((line_number, mode)),
+        }
+    }
+    Ok(found.map(|(_, mode)| mode))
+}
+
+/// The GNU triplet globs of the compiletest target names, as in `ignore-<target>` and
+/// `only-<target>`
+pub const TARGET_TRIPLETS: &[(&str, &[&str])] = &[
+    ("windows", &["*-*-mingw*"]),
+    ("linux", &["*-*-linux*"]),
+    ("macos", &["*-*-darwin*"]),
+    ("apple", &["*-*-darwin*"]),
+    ("android", &["*-*-android*"]),
+    ("freebsd", &["*-*-freebsd*"]),
+    ("netbsd", &["*-*-netbsd*"]),
+    ("openbsd", &["*-*-openbsd*"]),
+    ("solaris", &["*-*-solaris*"]),
+    ("emscripten", &["*-*-emscripten*"]),
+    ("musl", &["*-*-*musl*"]),
+    ("wasm32", &["wasm32-*-*"]),
+    ("x86_64", &["x86_64-*-*"]),
+    ("x86", &["i?86-*-*"]),
+    ("aarch64", &["aarch64*-*-*"]),
+    ("arm", &["arm*-*-*"]),
+    ("riscv64", &["riscv64*-*-*"]),
+    ("powerpc", &["powerpc-*-*"]),
+    ("powerpc64", &["powerpc64*-*-*"]),
+    ("s390x", &["s390x-*-*"]),
+    ("sparc64", &["sparc64-*-*"]),
+];
+
+/// The triplet globs of the compiletest `target` name, if it has any
+pub fn target_triplets(target: &str) -> Option<&'static [&'static str]> {
+    TARGET_TRIPLETS
+        .iter()
+        .find_map(|&(name, triplets)| (name == target).then_some(triplets))
+}
+
+/// The editions of Rust, the first one is the default of rustc and gccrs
+pub const EDITIONS: &[&str] = &["2015", "2018", "2021", "2024"];
+
+/// The headers changing how compiletest compares the output of rustc with the annotations
+/// or the `stderr` file, which have no DejaGnu equivalent
+pub const NO_EQUIVALENT: &[&str] = &[
+    "dont-check-compiler-stderr",
+    "dont-check-compiler-stdout",
+    "dont-check-failure-status",
+    "normalize-stderr",
+    "normalize-stderr-32bit",
+    "normalize-stderr-64bit",
+    "normalize-stderr-test",
+    "normalize-stdout",
+    "normalize-stdout-test",
+];
+
+/// The headers of the incremental, pretty-printing and rustdoc test suites, which do not
+/// matter to gccrs. They are counted by `--stats`.
+pub const IGNORED: &[&str] = &[
+    "build-aux-docs",
+    "check-test-line-numbers-match",
+    "doc-flags",
+    "incremental",
+    "pp-exact",
+    "pretty-compare-only",
+    "pretty-expanded",
+    "pretty-mode",
+    "unique-doc-out-dir",
+];
+
+/// How the headers of a test change the conversion of its annotations
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnnotationPolicy {
+    /// The kinds of the `dont-require-annotations` headers, whose annotations are kept as
+    /// comments rather than directives
+    pub optional: Vec<RustcErrorKind>,
+}
+
+impl AnnotationPolicy {
+    /// Whether the annotations of `kind` are optional
+    pub fn is_optional(&self, kind: Option<RustcErrorKind>) -> bool {
+        kind.is_some_and(|kind| self.optional.contains(&kind))
+    }
+}
+
+/// The [`AnnotationPolicy`] of the headers of `code` for `revision`
+pub fn annotation_policy(code: &str, revision: Option<&str>) -> AnnotationPolicy {
+    let optional = code
+        .lines()
+        .filter_map(revisioned_header_line)
+        .filter(|(line_revisions, _)| applies_to_revision(*line_revisions, revision))
+        .filter_map(|(_, directive)| match directive {
+            Directive::DontRequireAnnotations(kind) => Some(kind),
+            _ => None,
+        })
+        .collect();
+    AnnotationPolicy { optional }
+}
+
+/// An `edition` header whose value is not one of [`EDITIONS`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidEdition {
+    pub line: usize,
+    pub edition: String,
+}
+
+impl std::fmt::Display for InvalidEdition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}: `{}` is not an edition, expected one of {}",
+            self.line,
+            self.edition,
+            EDITIONS.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for InvalidEdition {}
+
+/// A [`Directive::Unconverted`] header, an error with `--strict-headers`
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnknownHeader {
+    pub line: usize,
+    pub header: String,
+}
+
+impl std::fmt::Display for UnknownHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}: `//{}` is not a known directive, rejected by --strict-headers",
+            self.line, self.header
+        )
+    }
+}
+
+impl std::error::Error for UnknownHeader {}
+
+/// An `edition` header disagreeing with the `--edition` of the compile flags, which gccrs
+/// would get both of. An error with `--strict-headers`, a warning otherwise.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConflictingEdition {
+    /// The line of the `edition` header
+    pub line: usize,
+    pub header: String,
+    /// The line of the compile flags
+    pub flags_line: usize,
+    pub flag: String,
+}
+
+impl std::fmt::Display for ConflictingEdition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}: `edition: {}` disagrees with `--edition {}` in the compile flags of line {}",
+            self.line, self.header, self.flag, self.flags_line
+        )
+    }
+}
+
+impl std::error::Error for ConflictingEdition {}
+
+/// A header for a revision which is not in the `revisions` header of the test
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnknownRevision {
+    pub line: usize,
+    pub revision: String,
+    /// The revisions of the test
+    pub revisions: Vec<String>,
+}
+
+impl std::fmt::Display for UnknownRevision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.revisions.is_empty() {
+            write!(
+                f,
+                "line {}: the header is for the revision `{}`, but the test has no `revisions` header",
+                self.line, self.revision
+            )
+        } else {
+            write!(
+                f,
+                "line {}: `{}` is not one of the revisions of the test: {}",
+                self.line,
+                self.revision,
+                self.revisions.join(", ")
+            )
+        }
+    }
+}
+
+impl std::error::Error for UnknownRevision {}
+
+/// Fails on the first header of `code` for a revision its `revisions` header does not list,
+/// like `//[e2018]@ edition: 2018`
+pub fn check_revisions(code: &str) -> Result<(), UnknownRevision> {
+    let revisions = revisions(code);
+    for (line_number, line) in code.lines().enumerate() {
+        let Some((Some(line_revisions), _)) = header_directive(line) else {
+            continue;
+        };
+        let unknown = line_revisions
+            .split(',')
+            .map(str::trim)
+            .find(|revision| !revisions.iter().any(|known| known == revision));
+        if let Some(revision) = unknown {
+            return Err(UnknownRevision {
+                line: line_number + 1,
+                revision: revision.to_owned(),
+                revisions,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The directory next to a test holding its auxiliary files, in rustc and gccrs alike
+pub const AUXILIARY_DIR: &str = "auxiliary";
+
+impl Directive {
+    /// The DejaGnu line replacing the header
+    pub fn to_dejagnu(&self) -> String {
+        match self {
+            Directive::Edition(edition) => to_dejagnu_edition(edition),
+            Directive::CompileFlags(flags) => {
+                to_dejagnu_options(&gccrs_flags(flags).options.join(" "))
+            }
+            Directive::AuxBuild(files) => {
+                let sources: Vec<_> = files
+                    .iter()
+                    .map(|file| format!("{AUXILIARY_DIR}/{file}"))
+                    .collect();
+                format!(
+                    "// {{ dg-additional-sources \"{}\" }}",
+                    errors::tcl_string(&sources.join(" "))
+                )
+            }
+            Directive::Revision(revision) => to_dejagnu_options(&format!("-frust-cfg={revision}")),
+            Directive::Ignore(target) => format!(
+                "// {{ dg-skip-if \"\" {{ {} }} }}",
+                target_triplets(target).unwrap_or_default().join(" ")
+            ),
+            Directive::Only(target) => format!("// only-{target}"),
+            Directive::Mode(_) => String::new(),
+            Directive::ErrorPattern(pattern) => error_pattern_directive(pattern, Selector::ANY),
+            Directive::KnownBug(_) => String::new(),
+            // The test is skipped, gccrs lacks the capability
+            Directive::Needs(name) => format!("// {{ dg-skip-if \"{name}\" {{ {ANY_TARGET} }} }}"),
+            Directive::RustcEnv(key, value) => format!(
+                "// {{ dg-set-compiler-env-var {key} \"{}\" }}",
+                errors::tcl_string(value)
+            ),
+            Directive::DontRequireAnnotations(_)
+            | Directive::NoEquivalent(_)
+            | Directive::Ignored(_) => String::new(),
+            Directive::Unconverted(header) => format!("// UNCONVERTED: {header}"),
+            Directive::ExecEnv(variable) => format!("// UNCONVERTED: @ exec-env: {variable}"),
+        }
+    }
+}
+
+/// rustc flags taking a value, with the gccrs option the value is appended to
+const VALUE_FLAGS: &[(&str, &str)] = &[
+    ("--edition", "-frust-edition="),
+    ("--crate-type", "-frust-crate-type="),
+    ("--crate-name", "-frust-crate="),
+    ("--cfg", "-frust-cfg="),
+];
+
+/// The gccrs options of some rustc flags, with the flags which have none
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GccrsFlags {
+    pub options: Vec<String>,
+    /// The flags copied as they are, for lack of a gccrs spelling
+    pub unknown: Vec<String>,
+}
+
+/// Translates the rustc `flags` to their gccrs spellings, like `--edition 2021` into
+/// `-frust-edition=2021`. The other flags are kept verbatim, along with their value.
+pub fn gccrs_flags(flags: &[String]) -> GccrsFlags {
+    let mut translated = GccrsFlags::default();
+    let mut flags = flags.iter().map(String::as_str).peekable();
+    while let Some(flag) = flags.next() {
+        // `-C opt-level=2` and `-Copt-level=2` alike
+        let codegen = match flag {
+            "-C" => flags.next_if(|value| !value.starts_with('-')),
+            _ => flag.strip_prefix("-C").filter(|option| !option.is_empty()),
+        };
+        if let Some(option) = codegen {
+            match option.strip_prefix("opt-level=") {
+                Some(level) => translated.options.push(format!("-O{level}")),
+                None => translated.keep(format!("-C {option}")),
+            }
+            continue;
+        }
+        if flag == "-O" {
+            translated.options.push(flag.to_owned());
+            continue;
+        }
+        let (name, inline_value) = match flag.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value)),
+            _ => (flag, None),
+        };
+        let value = inline_value.or_else(|| flags.next_if(|value| !value.starts_with('-')));
+        let prefix = VALUE_FLAGS
+            .iter()
+            .find_map(|&(rustc, gccrs)| (rustc == name).then_some(gccrs));
+        match (prefix, value) {
+            (Some(prefix), Some(value)) => translated.options.push(format!("{prefix}{value}")),
+            (_, Some(value)) if inline_value.is_none() => {
+                translated.keep(format!("{flag} {value}"))
+            }
+            _ => translated.keep(flag.to_owned()),
+        }
+    }
+    translated
+}
+
+/// The rustc flags of the gccrs `options`, like `--crate-name foo` for `-frust-crate=foo`, if
+/// [`gccrs_flags`] translates all of them
+pub fn rustc_flags(options: &str) -> Option<String> {
+    let flags: Vec<String> = options
+        .split_whitespace()
+        .map(|option| {
+            VALUE_FLAGS.iter().find_map(|&(rustc, gccrs)| {
+                Some(format!("{rustc} {}", option.strip_prefix(gccrs)?))
+            })
+        })
+        .collect::<Option<_>>()?;
+    (!flags.is_empty()).then(|| flags.join(" "))
+}
+
+impl GccrsFlags {
+    fn keep(&mut self, flag: String) {
+        self.unknown.push(flag.clone());
+        self.options.push(flag);
+    }
+}
+
+/// The converted headers of `code` for `revision`, with the warnings about their conversion.
+///
+/// Every `compile-flags` header is merged into a single `dg-additional-options` line in place
+/// of the first one, and every `aux-build` header into a single `dg-additional-sources` line.
+/// The others are left empty so that the lines keep their numbers.
+///
+/// With a `revision`, the headers of the other revisions are left empty as well and the
+/// `revisions` header becomes the `--cfg` rustc passes for the revision. Without one, every
+/// header is converted.
+///
+/// `ignore-<target>` headers become a `dg-skip-if`, and the `only-<target>` ones are kept as
+/// comments. Those whose target has no [`TARGET_TRIPLETS`] are warned about and kept as well.
+/// Each `error-pattern` header becomes a `dg-error` of its own, on line 0.
+///
+/// The `needs-*` headers are removed or become a `dg-skip-if` according to `needs`, those it
+/// does not know are warned about and kept.
+///
+/// `rustc-env` headers become a `dg-set-compiler-env-var`, and when several set the same
+/// variable the last one wins, with a warning. The `exec-env` ones are warned about and kept
+/// as `// UNCONVERTED:` comments, the harness has no per-test environment for the run of a
+/// test.
+///
+/// The `//@` headers compiletest does not know become [`Directive::Unconverted`] comments.
+///
+/// An `edition` header fails unless it is one of [`EDITIONS`]. The default one is left empty
+/// without `explicit_edition`.
+pub fn parse_additional_options(
+    code: &str,
+    revision: Option<&str>,
+    needs: &NeedsPolicy,
+    explicit_edition: bool,
+) -> Result<(Vec<HeaderLine>, Vec<String>), InvalidEdition> {
     let mut headers = Vec::new();
+    let mut warnings = Vec::new();
+    let mut compile_flags = Vec::new();
+    let mut first_flags = None;
+    let mut aux_builds = Vec::new();
+    let mut first_aux_build = None;
+    // The header and line of each `rustc-env` variable
+    let mut rustc_envs = BTreeMap::new();
+    let targets = target_selector(code, revision);
+    let selector = Selector {
+        targets: &targets,
+        xfail: known_bug(code).is_some(),
+    };
 
     for (line_number, line) in code.lines().enumerate() {
         let line = line.trim();
         if line.is_empty() || line.starts_with("fn") || line.starts_with("mod") {
             continue;
         }
-        if is_header_line(line) {
-            if let Some(header_info) = add_additional_options(line, line_number) {
-                headers.push(header_info);
+        let line_number = line_number + 1; // 1 based-indexed instead of zero based
+        if let (Some(revision), Some(_)) = (revision, revisions_header(line)) {
+            headers.push(HeaderLine {
+                line_number,
+                dejagnu_header: Directive::Revision(revision.to_owned()).to_dejagnu(),
+                directive: Directive::Revision(revision.to_owned()),
+            });
+            continue;
+        }
+        if let Some(header) = unconverted_header(line) {
+            let directive = Directive::Unconverted(header.to_owned());
+            headers.push(HeaderLine {
+                line_number,
+                dejagnu_header: directive.to_dejagnu(),
+                directive,
+            });
+            continue;
+        }
+        if let Some((name, target)) = unknown_target(line) {
+            warnings.push(format!(
+                "line {line_number}: `{name}` has no known target triplet for `{target}`, kept as a comment"
+            ));
+            continue;
+        }
+        if let Some(variable) = exec_env(line) {
+            warnings.push(format!(
+                "line {line_number}: `exec-env: {variable}` sets the environment of the run, which DejaGnu does not convert, kept as a comment"
+            ));
+            let directive = Directive::ExecEnv(variable.to_owned());
+            headers.push(HeaderLine {
+                line_number,
+                dejagnu_header: directive.to_dejagnu(),
+                directive,
+            });
+            continue;
+        }
+        if let Some(Directive::Needs(name)) = parse_header_line(line) {
+            if needs.get(&name).is_none() {
+                warnings.push(format!(
+                    "line {line_number}: `{name}` is not in the needs policy, kept as a comment"
+                ));
+                // `parse_header_line` found the `//` at the start
+                let directive =
+                    Directive::Unconverted(line.trim_start()[2..].trim_start().to_owned());
+                headers.push(HeaderLine {
+                    line_number,
+                    dejagnu_header: directive.to_dejagnu(),
+                    directive,
+                });
+                continue;
+            }
+        }
+        if let Some((line_revisions, directive)) = revisioned_header_line(line) {
+            let other_revision = !applies_to_revision(line_revisions, revision);
+            if let Directive::Edition(edition) = &directive {
+                if !EDITIONS.contains(&edition.as_str()) {
+                    return Err(InvalidEdition {
+                        line: line_number,
+                        edition: edition.clone(),
+                    });
+                }
+            }
+            let dejagnu_header = match &directive {
+                _ if other_revision => String::new(),
+                Directive::Edition(edition) if !explicit_edition && edition == EDITIONS[0] => {
+                    String::new()
+                }
+                Directive::CompileFlags(flags) => {
+                    for flag in gccrs_flags(flags).unknown {
+                        warnings.push(format!(
+                            "line {line_number}: compile flag `{flag}` has no known gccrs spelling, kept as is"
+                        ));
+                    }
+                    compile_flags.extend(flags.iter().cloned());
+                    first_flags.get_or_insert(headers.len());
+                    String::new()
+                }
+                Directive::AuxBuild(files) => {
+                    aux_builds.extend(files.iter().cloned());
+                    first_aux_build.get_or_insert(headers.len());
+                    String::new()
+                }
+                Directive::ErrorPattern(pattern) => error_pattern_directive(pattern, selector),
+                Directive::Needs(name) if needs.get(name) == Some(Needs::Drop) => String::new(),
+                Directive::RustcEnv(key, _) => {
+                    if let Some((previous, previous_line)) =
+                        rustc_envs.insert(key.clone(), (headers.len(), line_number))
+                    {
+                        headers[previous].dejagnu_header = String::new();
+                        warnings.push(format!(
+                            "line {line_number}: `rustc-env` sets `{key}` again, the value of line {previous_line} is dropped"
+                        ));
+                    }
+                    directive.to_dejagnu()
+                }
+                directive => directive.to_dejagnu(),
+            };
+            headers.push(HeaderLine {
+                line_number,
+                dejagnu_header,
+                directive,
+            });
+        }
+    }
+    if let Some(first) = first_flags {
+        headers[first].dejagnu_header = Directive::CompileFlags(compile_flags).to_dejagnu();
+    }
+    if let Some(first) = first_aux_build {
+        headers[first].dejagnu_header = Directive::AuxBuild(aux_builds).to_dejagnu();
+    }
+    Ok((headers, warnings))
+}
+
+/// Whether a header for `line_revisions`, from `//@[a,b]`, is converted for `revision`
+fn applies_to_revision(line_revisions: Option<&str>, revision: Option<&str>) -> bool {
+    match (line_revisions, revision) {
+        (Some(revisions), Some(revision)) => revisions.split(',').any(|rev| rev.trim() == revision),
+        _ => true,
+    }
+}
+
+/// The `{ target ... }` selector contents of the directives of `code` for `revision`, like
+/// `x86_64-*-*` for `//@ only-x86_64`. Several `only-<target>` headers must all hold, and
+/// every target is selected without any.
+pub fn target_selector(code: &str, revision: Option<&str>) -> String {
+    let onlys: Vec<_> = code
+        .lines()
+        .filter_map(revisioned_header_line)
+        .filter(|(line_revisions, _)| applies_to_revision(*line_revisions, revision))
+        .filter_map(|(_, directive)| match directive {
+            Directive::Only(target) => target_triplets(&target),
+            _ => None,
+        })
+        .collect();
+    match onlys.as_slice() {
+        [] => ANY_TARGET.to_owned(),
+        [triplets] => triplets.join(" "),
+        onlys => {
+            let operands: Vec<_> = onlys
+                .iter()
+                .map(|triplets| match triplets {
+                    [triplet] => triplet.to_string(),
+                    triplets => format!("{{ {} }}", triplets.join(" ")),
+                })
+                .collect();
+            format!("{{ {} }}", operands.join(" && "))
+        }
+    }
+}
+
+/// The name and target of an `ignore-<target>` or `only-<target>` header on `line`, when the
+/// target has no [`TARGET_TRIPLETS`]
+fn unknown_target(line: &str) -> Option<(&str, &str)> {
+    let (_revisions, directive) = header_directive(line)?;
+    let (name, _reason) = split_directive(directive);
+    let (_only, target) = target_header(name)?;
+    target_triplets(target).is_none().then_some((name, target))
+}
+
+/// The header on `line` without its `//`, like `@ foo: bar`, when it is a `//@` one whose
+/// name compiletest does not know
+fn unconverted_header(line: &str) -> Option<&str> {
+    let (_revisions, directive) = at_directive(line)?;
+    let (name, _value) = split_directive(directive);
+    let known = name.is_empty()
+        || KNOWN_DIRECTIVE_NAMES.contains(&name)
+        || target_header(name).is_some()
+        || revisioned_header_line(line).is_some();
+    // `at_directive` found the `//` at the start
+    (!known).then(|| &line.trim_start()[2..])
+}
+
+/// The line numbers and [`Directive::Unconverted`] headers of `code`
+pub fn unconverted_headers(code: &str) -> Vec<(usize, &str)> {
+    code.lines()
+        .enumerate()
+        .filter_map(|(line_number, line)| Some((line_number + 1, unconverted_header(line)?)))
+        .collect()
+}
+
+/// The `KEY=VALUE` of an `exec-env` header on `line`
+fn exec_env(line: &str) -> Option<&str> {
+    let (_revisions, directive) = header_directive(line)?;
+    match split_directive(directive) {
+        ("exec-env", Some(variable)) if !variable.is_empty() => Some(variable),
+        _ => None,
+    }
+}
+
+/// Whether the header `name` is `only-<target>` rather than `ignore-<target>`, with the target
+fn target_header(name: &str) -> Option<(bool, &str)> {
+    if let Some(target) = name.strip_prefix("ignore-") {
+        Some((false, target))
+    } else {
+        name.strip_prefix("only-").map(|target| (true, target))
+    }
+}
+
+/// The files of the `aux-build` headers of `code`, relative to the [`AUXILIARY_DIR`] next to it
+pub fn aux_build_files(code: &str) -> Vec<String> {
+    code.lines()
+        .filter_map(parse_header_line)
+        .flat_map(|directive| match directive {
+            Directive::AuxBuild(files) => files,
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// The revisions of the `revisions` header of `code`, like `e2015` and `e2021` for
+/// `//@ revisions: e2015 e2021`. Empty for a test without revisions.
+pub fn revisions(code: &str) -> Vec<String> {
+    code.lines()
+        .filter_map(revisions_header)
+        .flatten()
+        .collect()
+}
+
+fn revisions_header(line: &str) -> Option<Vec<String>> {
+    let (_revision, directive) = header_directive(line)?;
+    match split_directive(directive) {
+        ("revisions", Some(revisions)) => Some(
+            revisions
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|revision| !revision.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// The directive converted from the header `line`, in either the `//@ edition: 2021` form
+/// or the older `// edition:2021` one, with any whitespace around the colon and the value
+pub fn parse_header_line(line: &str) -> Option<Directive> {
+    revisioned_header_line(line).map(|(_revisions, directive)| directive)
+}
+
+/// The issue of the `known-bug` header of `code`, like `#12345`
+pub fn known_bug(code: &str) -> Option<String> {
+    code.lines()
+        .filter_map(parse_header_line)
+        .find_map(|directive| match directive {
+            Directive::KnownBug(issue) => Some(issue),
+            _ => None,
+        })
+}
+
+/// The header making `code` a proc-macro test, like `force-host` or `proc-macro: derive.rs`.
+/// gccrs cannot build those yet.
+pub fn proc_macro_header(code: &str) -> Option<&str> {
+    code.lines().find_map(|line| {
+        let (_revisions, directive) = header_directive(line)?;
+        let is_proc_macro = match split_directive(directive) {
+            ("proc-macro" | "force-host", _) => true,
+            ("needs-crate-type", Some(types)) => types
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .any(|crate_type| crate_type == "proc-macro"),
+            _ => false,
+        };
+        is_proc_macro.then(|| directive.trim_end())
+    })
+}
+
+/// Whether `code` is a rustfix test, whose fixed code is in a `.fixed` file next to it
+pub fn runs_rustfix(code: &str) -> bool {
+    code.lines()
+        .any(|line| header_name(line) == Some("run-rustfix"))
+}
+
+/// Whether `code` is a test comparing the output of its run with the `.run.stdout` file
+/// next to it
+pub fn checks_run_results(code: &str) -> bool {
+    code.lines()
+        .any(|line| header_name(line) == Some("check-run-results"))
+}
+
+/// Whether `code` has any `error-pattern` header
+pub fn has_error_patterns(code: &str) -> bool {
+    code.lines()
+        .filter_map(parse_header_line)
+        .any(|directive| matches!(directive, Directive::ErrorPattern(_)))
+}
+
+/// The line (1-based) and value of the `edition` headers of `code` converted for `revision`
+pub fn edition_headers(code: &str, revision: Option<&str>) -> Vec<(usize, String)> {
+    code.lines()
+        .enumerate()
+        .filter_map(|(line_number, line)| match revisioned_header_line(line)? {
+            (line_revisions, Directive::Edition(edition))
+                if applies_to_revision(line_revisions, revision) =>
+            {
+                Some((line_number + 1, edition))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The last `edition` header of `code` converted for `revision` and the last `--edition` of
+/// its compile flags, when they disagree
+pub fn edition_conflict(code: &str, revision: Option<&str>) -> Option<ConflictingEdition> {
+    let (line, header) = edition_headers(code, revision).pop()?;
+    let (flags_line, flag) = code
+        .lines()
+        .enumerate()
+        .filter_map(|(line_number, line)| match revisioned_header_line(line)? {
+            (line_revisions, Directive::CompileFlags(flags))
+                if applies_to_revision(line_revisions, revision) =>
+            {
+                Some((line_number + 1, flag_edition(&flags)?.to_owned()))
             }
+            _ => None,
+        })
+        .last()?;
+    (flag != header).then_some(ConflictingEdition {
+        line,
+        header,
+        flags_line,
+        flag,
+    })
+}
+
+/// The value of the last `--edition 2021` or `--edition=2021` of `flags`
+fn flag_edition(flags: &[String]) -> Option<&str> {
+    let mut edition = None;
+    let mut flags = flags.iter().map(String::as_str);
+    while let Some(flag) = flags.next() {
+        match flag {
+            "--edition" => edition = flags.next().or(edition),
+            _ => edition = flag.strip_prefix("--edition=").or(edition),
         }
     }
-    headers
+    edition
+}
+
+/// [`parse_header_line`], with the revisions of `//@[rev1,rev2]` if any
+fn revisioned_header_line(line: &str) -> Option<(Option<&str>, Directive)> {
+    let (revisions, directive) = header_directive(line)?;
+    let (name, value) = split_directive(directive);
+    // The value of these is only the reason, as in `//@ ignore-windows: no symlinks`
+    if let Some((only, target)) = target_header(name) {
+        target_triplets(target)?;
+        let directive = if only {
+            Directive::Only(target.to_owned())
+        } else {
+            Directive::Ignore(target.to_owned())
+        };
+        return Some((revisions, directive));
+    }
+    if let Some(mode) = Mode::from_name(name) {
+        return Some((revisions, Directive::Mode(mode)));
+    }
+    if name.starts_with("needs-") {
+        return Some((revisions, Directive::Needs(name.to_owned())));
+    }
+    if NO_EQUIVALENT.contains(&name) {
+        return Some((revisions, Directive::NoEquivalent(name.to_owned())));
+    }
+    if let Some(&ignored) = IGNORED.iter().find(|&&ignored| ignored == name) {
+        return Some((revisions, Directive::Ignored(ignored)));
+    }
+    let directive = match (name, value?) {
+        // Checked by `parse_additional_options`, which knows the line
+        ("edition", edition) => Some(Directive::Edition(edition.to_owned())),
+        ("compile-flags", flags) if !flags.is_empty() => Some(Directive::CompileFlags(
+            flags.split_whitespace().map(str::to_owned).collect(),
+        )),
+        ("aux-build", file) if !file.is_empty() => Some(Directive::AuxBuild(vec![file.to_owned()])),
+        ("error-pattern", pattern) if !pattern.is_empty() => {
+            Some(Directive::ErrorPattern(pattern.to_owned()))
+        }
+        ("known-bug", issue) if !issue.is_empty() => Some(Directive::KnownBug(issue.to_owned())),
+        ("dont-require-annotations", kind) => {
+            kind.parse().ok().map(Directive::DontRequireAnnotations)
+        }
+        ("rustc-env", variable) => variable
+            .split_once('=')
+            .filter(|(key, _value)| !key.is_empty())
+            .map(|(key, value)| Directive::RustcEnv(key.to_owned(), value.to_owned())),
+        _ => None,
+    }?;
+    Some((revisions, directive))
 }
 
+/// The directive names compiletest knows, any other `//@` header is [`Directive::Unconverted`].
+/// Copied from <https://github.com/rust-lang/rust/blob/master/src/tools/compiletest/src/command-list.rs>
+pub const KNOWN_DIRECTIVE_NAMES: &[&str] = &[
+    "assembly-output",
+    "aux-bin",
+    "aux-build",
+    "aux-codegen-backend",
+    "aux-crate",
+    "build-aux-docs",
+    "build-fail",
+    "build-pass",
+    "check-fail",
+    "check-pass",
+    "check-run-results",
+    "check-stdout",
+    "check-test-line-numbers-match",
+    "compare-output-lines-by-subset",
+    "compile-flags",
+    "doc-flags",
+    "dont-check-compiler-stderr",
+    "dont-check-compiler-stdout",
+    "dont-check-failure-status",
+    "dont-require-annotations",
+    "edition",
+    "error-pattern",
+    "exec-env",
+    "failure-status",
+    "filecheck-flags",
+    "forbid-output",
+    "force-host",
+    "ignore-16bit",
+    "ignore-32bit",
+    "ignore-64bit",
+    "ignore-aarch64",
+    "ignore-aarch64-unknown-linux-gnu",
+    "ignore-android",
+    "ignore-apple",
+    "ignore-arm",
+    "ignore-avr",
+    "ignore-beta",
+    "ignore-cdb",
+    "ignore-compare-mode-next-solver",
+    "ignore-compare-mode-polonius",
+    "ignore-cross-compile",
+    "ignore-debug",
+    "ignore-eabi",
+    "ignore-emscripten",
+    "ignore-endian-big",
+    "ignore-freebsd",
+    "ignore-fuchsia",
+    "ignore-gdb",
+    "ignore-gdb-version",
+    "ignore-gnu",
+    "ignore-haiku",
+    "ignore-horizon",
+    "ignore-i686-pc-windows-gnu",
+    "ignore-i686-pc-windows-msvc",
+    "ignore-illumos",
+    "ignore-ios",
+    "ignore-linux",
+    "ignore-lldb",
+    "ignore-llvm-version",
+    "ignore-loongarch64",
+    "ignore-macabi",
+    "ignore-macos",
+    "ignore-mode-assembly",
+    "ignore-mode-codegen",
+    "ignore-mode-codegen-units",
+    "ignore-mode-coverage-map",
+    "ignore-mode-coverage-run",
+    "ignore-mode-crashes",
+    "ignore-mode-debuginfo",
+    "ignore-mode-incremental",
+    "ignore-mode-js-doc-test",
+    "ignore-mode-mir-opt",
+    "ignore-mode-pretty",
+    "ignore-mode-run-make",
+    "ignore-mode-run-pass-valgrind",
+    "ignore-mode-rustdoc",
+    "ignore-mode-rustdoc-json",
+    "ignore-mode-ui",
+    "ignore-mode-ui-fulldeps",
+    "ignore-msp430",
+    "ignore-msvc",
+    "ignore-musl",
+    "ignore-netbsd",
+    "ignore-nightly",
+    "ignore-none",
+    "ignore-nto",
+    "ignore-nvptx64",
+    "ignore-nvptx64-nvidia-cuda",
+    "ignore-openbsd",
+    "ignore-pass",
+    "ignore-remote",
+    "ignore-riscv64",
+    "ignore-s390x",
+    "ignore-sgx",
+    "ignore-spirv",
+    "ignore-stable",
+    "ignore-stage1",
+    "ignore-stage2",
+    "ignore-test",
+    "ignore-thumb",
+    "ignore-thumbv8m.base-none-eabi",
+    "ignore-thumbv8m.main-none-eabi",
+    "ignore-tvos",
+    "ignore-unix",
+    "ignore-unknown",
+    "ignore-uwp",
+    "ignore-visionos",
+    "ignore-vxworks",
+    "ignore-wasi",
+    "ignore-wasm",
+    "ignore-wasm32",
+    "ignore-wasm32-bare",
+    "ignore-wasm64",
+    "ignore-watchos",
+    "ignore-windows",
+    "ignore-windows-gnu",
+    "ignore-windows-msvc",
+    "ignore-x32",
+    "ignore-x86",
+    "ignore-x86_64",
+    "ignore-x86_64-apple-darwin",
+    "ignore-x86_64-unknown-linux-gnu",
+    "incremental",
+    "known-bug",
+    "llvm-cov-flags",
+    "min-cdb-version",
+    "min-gdb-version",
+    "min-lldb-version",
+    "min-llvm-version",
+    "min-system-llvm-version",
+    "needs-asm-support",
+    "needs-dlltool",
+    "needs-dynamic-linking",
+    "needs-force-clang-based-tests",
+    "needs-git-hash",
+    "needs-llvm-components",
+    "needs-profiler-support",
+    "needs-relocation-model-pic",
+    "needs-run-enabled",
+    "needs-rust-lld",
+    "needs-rust-lldb",
+    "needs-sanitizer-address",
+    "needs-sanitizer-cfi",
+    "needs-sanitizer-dataflow",
+    "needs-sanitizer-hwaddress",
+    "needs-sanitizer-kcfi",
+    "needs-sanitizer-leak",
+    "needs-sanitizer-memory",
+    "needs-sanitizer-memtag",
+    "needs-sanitizer-safestack",
+    "needs-sanitizer-shadow-call-stack",
+    "needs-sanitizer-support",
+    "needs-sanitizer-thread",
+    "needs-symlink",
+    "needs-threads",
+    "needs-unwind",
+    "needs-wasmtime",
+    "needs-xray",
+    "no-auto-check-cfg",
+    "no-prefer-dynamic",
+    "normalize-stderr",
+    "normalize-stderr-32bit",
+    "normalize-stderr-64bit",
+    "normalize-stderr-test",
+    "normalize-stdout",
+    "normalize-stdout-test",
+    "only-16bit",
+    "only-32bit",
+    "only-64bit",
+    "only-aarch64",
+    "only-aarch64-unknown-linux-gnu",
+    "only-apple",
+    "only-arm",
+    "only-avr",
+    "only-beta",
+    "only-bpf",
+    "only-cdb",
+    "only-gnu",
+    "only-i686-pc-windows-gnu",
+    "only-i686-pc-windows-msvc",
+    "only-ios",
+    "only-linux",
+    "only-loongarch64",
+    "only-loongarch64-unknown-linux-gnu",
+    "only-macos",
+    "only-mips",
+    "only-mips64",
+    "only-msp430",
+    "only-msvc",
+    "only-nightly",
+    "only-nvptx64",
+    "only-riscv64",
+    "only-sparc",
+    "only-sparc64",
+    "only-stable",
+    "only-thumb",
+    "only-tvos",
+    "only-unix",
+    "only-visionos",
+    "only-wasm32",
+    "only-wasm32-bare",
+    "only-wasm32-wasip1",
+    "only-watchos",
+    "only-windows",
+    "only-windows-gnu",
+    "only-windows-msvc",
+    "only-x86",
+    "only-x86_64",
+    "only-x86_64-fortanix-unknown-sgx",
+    "only-x86_64-pc-windows-gnu",
+    "only-x86_64-pc-windows-msvc",
+    "only-x86_64-unknown-linux-gnu",
+    "pp-exact",
+    "pretty-compare-only",
+    "pretty-expanded",
+    "pretty-mode",
+    "proc-macro",
+    "regex-error-pattern",
+    "remap-src-base",
+    "revisions",
+    "run-fail",
+    "run-flags",
+    "run-pass",
+    "run-rustfix",
+    "rustc-env",
+    "rustfix-only-machine-applicable",
+    "should-fail",
+    "should-ice",
+    "stderr-per-bitwidth",
+    "test-mir-pass",
+    "unique-doc-out-dir",
+    "unset-exec-env",
+    "unset-rustc-env",
+    "unused-revision-names",
+];
+
 pub fn is_header_line(line: &str) -> bool {
-    line.trim_start().starts_with("//@")
+    header_directive(line).is_some()
+}
+
+/// The name of the header directive on `line`, like `edition` for `//@ edition:2021`
+pub fn header_name(line: &str) -> Option<&str> {
+    let (_revision, directive) = header_directive(line)?;
+    Some(split_directive(directive).0).filter(|name| !name.is_empty())
+}
+
+/// A header directive of a test, as counted by the coverage report
+#[derive(Debug, PartialEq, Eq)]
+pub struct HeaderUse<'ln> {
+    pub line_number: usize,
+    pub name: &'ln str,
+    pub support: Support,
+}
+
+/// Every header directive of `code`, with what the conversion does with it
+pub fn header_uses(code: &str) -> Vec<HeaderUse<'_>> {
+    let mut uses = Vec::new();
+    for (line_number, line) in code.lines().enumerate() {
+        let Some(name) = header_name(line) else {
+            continue;
+        };
+        let converted = match parse_header_line(line) {
+            // Those of a `--needs-policy` file are not counted
+            Some(Directive::Needs(name)) => needs::builtin(&name).is_some(),
+            directive => directive.is_some(),
+        };
+        let support = if converted || revisions_header(line).is_some() {
+            Support::Handled
+        } else if KNOWN_DIRECTIVE_NAMES.contains(&name) {
+            // Copied to the output as a plain comment
+            Support::Dropped
+        } else {
+            Support::Unknown
+        };
+        uses.push(HeaderUse {
+            line_number: line_number + 1,
+            name,
+            support,
+        });
+    }
+    uses
 }
 
-fn add_additional_options(code: &str, line_number: usize) -> Option<HeaderLine> {
+/// The revision and the directive of a header `line`. Besides `//@`, the `//` comments
+/// rustc used before are headers when they start with a known directive name, followed by
+/// a colon or nothing, so that plain comments are left alone.
+fn header_directive(line: &str) -> Option<(Option<&str>, &str)> {
     //TODO: If we know the file extension, then update this to
     // let comment = if testfile.extension().is_some_and(|e| e == "rs") { "//@" } else { "#" };
-    let comment = "//@";
-
-    if let Some((_header_revision, non_revisioned_directive_line)) = line_directive(comment, code) {
-        // The non_revisioned_directive_line is the directive without the "//@" prefix
-        let edition = parse_edition(non_revisioned_directive_line)?;
-        Some(HeaderLine {
-            line_number: line_number + 1, // 1 based-indexed instead of zero based
-            _directive: "edition",
-            dejagnu_header: to_dejagnu_edition(edition.as_str()),
-        })
-    } else {
-        None
+    if let Some(found) = at_directive(line) {
+        return Some(found);
+    }
+    let (revision, directive) = line_directive("//", line)?;
+    let (name, value) = split_directive(directive);
+    let old_style = KNOWN_DIRECTIVE_NAMES.contains(&name)
+        && (value.is_some() || directive[name.len()..].trim().is_empty());
+    old_style.then_some((revision, directive))
+}
+
+/// The revisions and the directive of a `//@` header `line`, either `//@[rev] directive`
+/// or `//[rev]@ directive`
+fn at_directive(line: &str) -> Option<(Option<&str>, &str)> {
+    if let Some(found) = line_directive("//@", line) {
+        return Some(found);
+    }
+    match line_directive("//", line)? {
+        (Some(revisions), directive) => {
+            Some((Some(revisions), directive.strip_prefix('@')?.trim_start()))
+        }
+        (None, _) => None,
     }
 }
 
+/// The name of `directive` and its value after a colon if any, both trimmed
+fn split_directive(directive: &str) -> (&str, Option<&str>) {
+    let end = directive
+        .find(|c: char| c == ':' || c.is_whitespace())
+        .unwrap_or(directive.len());
+    let (name, rest) = directive.split_at(end);
+    let value = rest.trim_start().strip_prefix(':').map(str::trim);
+    (name, value)
+}
+
 fn line_directive<'line>(
     comment: &str,
     original_line: &'line str,
@@ -68,24 +1271,603 @@ fn line_directive<'line>(
     }
 }
 
-fn parse_edition(line: &str) -> Option<String> {
-    parse_name_value_directive(line, "edition")
+/// The `dg-error` of an `error-pattern` header, on line 0 to match anywhere in the file
+fn error_pattern_directive(pattern: &str, selector: Selector) -> String {
+    let pattern = errors::escape_dejagnu(pattern);
+    let selector = selector.for_directive("dg-error");
+    format!("// {{ dg-error \"{pattern}\" \"\" {{ {selector} }} 0 }}")
 }
 
-fn parse_name_value_directive(line: &str, directive: &str) -> Option<String> {
-    let colon = directive.len();
-
-    if line.starts_with(directive) && line.as_bytes().get(colon) == Some(&b':') {
-        let value = line[(colon + 1)..].to_owned();
-        Some(value)
-    } else {
-        None
-    }
+fn to_dejagnu_edition(edition: &str) -> String {
+    to_dejagnu_options(&format!("-frust-edition={edition}"))
 }
 
-fn to_dejagnu_edition(edition: &str) -> String {
+/// The options are a Tcl string, a `--cfg feature="foo"` keeps its quotes
+fn to_dejagnu_options(options: &str) -> String {
     format!(
-        "// {{ dg-additional-options \"-frust-edition={}\" }}",
-        edition
+        "// {{ dg-additional-options \"{}\" }}",
+        errors::tcl_string(options)
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header_line() {
+        let edition = |edition: &str| Some(Directive::Edition(edition.to_owned()));
+        for (line, expected) in [
+            // The older style
+            ("// edition:2018", edition("2018")),
+            ("  // edition: 2018", edition("2018")),
+            // The `//@` style
+            ("//@ edition: 2018", edition("2018")),
+            ("//@edition:2018", edition("2018")),
+            ("//@ edition:2021", edition("2021")),
+            ("//@[a] edition:2015", edition("2015")),
+            // Whitespace around the colon and the value
+            ("//@ edition : 2018 ", edition("2018")),
+            ("//@ edition:\t2024", edition("2024")),
+            ("// edition  :2018", edition("2018")),
+            (
+                "//@ compile-flags: -O  --edition 2021",
+                Some(Directive::CompileFlags(flags("-O --edition 2021"))),
+            ),
+            // Not a converted directive
+            ("//@ edition:", edition("")),
+            ("//@ edition", None),
+            ("//@ check-pass", Some(Directive::Mode(Mode::CheckPass))),
+            ("// run-fail", Some(Directive::Mode(Mode::RunFail))),
+            ("//@ incremental", Some(Directive::Ignored("incremental"))),
+            (
+                "//@ doc-flags: --document-private-items",
+                Some(Directive::Ignored("doc-flags")),
+            ),
+            ("//@ editions: 2018", None),
+            ("// This checks the 2018 edition", None),
+            ("// edition is only 2018 here", None),
+            ("/// edition:2018", None),
+            ("fn main() {} // edition:2018", None),
+            ("//~ ERROR edition:2018", None),
+            ("//@ compile-flags:", None),
+        ] {
+            assert_eq!(parse_header_line(line), expected, "{line:?}");
+        }
+    }
+
+    fn flags(flags: &str) -> Vec<String> {
+        flags.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn test_gccrs_flags() {
+        for (rustc, options) in [
+            ("--edition 2024", "-frust-edition=2024"),
+            ("--edition=2018", "-frust-edition=2018"),
+            ("--crate-type=lib", "-frust-crate-type=lib"),
+            (
+                "--crate-type lib --crate-name foo",
+                "-frust-crate-type=lib -frust-crate=foo",
+            ),
+            ("--cfg feature", "-frust-cfg=feature"),
+            ("-O -C opt-level=3 -Copt-level=s", "-O -O3 -Os"),
+        ] {
+            let translated = gccrs_flags(&flags(rustc));
+            assert_eq!(translated.options.join(" "), options, "{rustc}");
+            assert!(translated.unknown.is_empty(), "{rustc}");
+            if !options.contains("-O") {
+                let rustc_flags = rustc_flags(options).unwrap();
+                assert_eq!(gccrs_flags(&flags(&rustc_flags)).options.join(" "), options);
+            }
+        }
+
+        let translated = gccrs_flags(&flags(
+            "-Zunstable-options --edition 2024 -C panic=abort --test --extern dep",
+        ));
+        assert_eq!(
+            translated.options.join(" "),
+            "-Zunstable-options -frust-edition=2024 -C panic=abort --test --extern dep"
+        );
+        assert_eq!(
+            translated.unknown,
+            [
+                "-Zunstable-options",
+                "-C panic=abort",
+                "--test",
+                "--extern dep"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quoted_flags_are_escaped() {
+        let code =
+            "//@ compile-flags: --cfg feature=\"foo\"\n//@ aux-build: $dep.rs\nfn main() {}\n";
+        let (headers, _) =
+            parse_additional_options(code, None, &NeedsPolicy::default(), false).unwrap();
+        let lines: Vec<_> = headers
+            .iter()
+            .map(|header| header.dejagnu_header.as_str())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "// { dg-additional-options \"-frust-cfg=feature=\\\"foo\\\"\" }",
+                "// { dg-additional-sources \"auxiliary/\\$dep.rs\" }",
+            ]
+        );
+        for line in lines {
+            assert!(crate::dejagnu::validate(line).is_ok(), "{line}");
+        }
+    }
+
+    #[test]
+    fn test_compile_flags_are_merged() {
+        let code = "\
+//@ compile-flags: --edition 2021
+// compile-flags: -O -Zunstable-options
+//@ check-pass
+//@ compile-flags:--crate-type=lib
+fn f() {}
+";
+        let (headers, warnings) =
+            parse_additional_options(code, None, &NeedsPolicy::default(), false).unwrap();
+        let lines: Vec<_> = headers
+            .iter()
+            .map(|header| (header.line_number, header.dejagnu_header.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (
+                    1,
+                    "// { dg-additional-options \"-frust-edition=2021 -O -Zunstable-options -frust-crate-type=lib\" }"
+                ),
+                (2, ""),
+                (3, ""),
+                (4, ""),
+            ]
+        );
+        assert_eq!(
+            warnings,
+            ["line 2: compile flag `-Zunstable-options` has no known gccrs spelling, kept as is"]
+        );
+    }
+
+    #[test]
+    fn test_aux_builds_are_merged() {
+        let code = "//@ aux-build: helper.rs\n//@ edition:2021\n// aux-build:other.rs\n//@ aux-build:\nfn main() {}\n";
+        let (headers, warnings) =
+            parse_additional_options(code, None, &NeedsPolicy::default(), false).unwrap();
+        let lines: Vec<_> = headers
+            .iter()
+            .map(|header| (header.line_number, header.dejagnu_header.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (
+                    1,
+                    "// { dg-additional-sources \"auxiliary/helper.rs auxiliary/other.rs\" }"
+                ),
+                (2, "// { dg-additional-options \"-frust-edition=2021\" }"),
+                (3, ""),
+            ]
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(aux_build_files(code), ["helper.rs", "other.rs"]);
+    }
+
+    #[test]
+    fn test_revision_headers() {
+        let code = "//@ revisions: a b\n//@[a] edition:2021\n//@[a,b] compile-flags: -O\n//@[b]compile-flags: --cfg x\n";
+        assert_eq!(revisions(code), ["a", "b"]);
+        let lines = |revision| {
+            let (headers, _) =
+                parse_additional_options(code, revision, &NeedsPolicy::default(), false).unwrap();
+            headers
+                .into_iter()
+                .map(|header| header.dejagnu_header)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines(Some("a")),
+            [
+                "// { dg-additional-options \"-frust-cfg=a\" }",
+                "// { dg-additional-options \"-frust-edition=2021\" }",
+                "// { dg-additional-options \"-O\" }",
+                "",
+            ]
+        );
+        assert_eq!(
+            lines(Some("b")),
+            [
+                "// { dg-additional-options \"-frust-cfg=b\" }",
+                "",
+                "// { dg-additional-options \"-O -frust-cfg=x\" }",
+                "",
+            ]
+        );
+        // Every header applies without a revision, the `revisions` one is left as it is
+        assert_eq!(
+            lines(None),
+            [
+                "// { dg-additional-options \"-frust-edition=2021\" }",
+                "// { dg-additional-options \"-O -frust-cfg=x\" }",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_revision_after_the_slashes() {
+        assert_eq!(
+            header_directive("//[a]@ edition: 2021"),
+            Some((Some("a"), "edition: 2021"))
+        );
+        assert_eq!(
+            header_directive("//[a, b] compile-flags: -O"),
+            Some((Some("a, b"), "compile-flags: -O"))
+        );
+        assert_eq!(header_directive("//[a] just a comment"), None);
+        assert_eq!(
+            unconverted_header("//[a]@ frobnicate"),
+            Some("[a]@ frobnicate")
+        );
+
+        let code =
+            "//@ revisions: a b\n//[b]@ edition: 2021\n//[a,b] compile-flags: -O\n//[a]~ ERROR x\n";
+        assert_eq!(check_revisions(code), Ok(()));
+        assert_eq!(
+            check_revisions("//@ revisions: a b\n//@[a, c] edition: 2021\n"),
+            Err(UnknownRevision {
+                line: 2,
+                revision: "c".to_owned(),
+                revisions: vec!["a".to_owned(), "b".to_owned()],
+            })
+        );
+        assert_eq!(
+            check_revisions("//[a] compile-flags: -O\n")
+                .unwrap_err()
+                .revisions,
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_target_headers() {
+        let code = "\
+//@ ignore-windows
+// ignore-macos: no such syscall
+//@ ignore-wasm32
+//@ ignore-cross-compile
+//@ only-plan9
+//@ only-x86_64
+fn main() {}
+";
+        let (headers, warnings) =
+            parse_additional_options(code, None, &NeedsPolicy::default(), false).unwrap();
+        let lines: Vec<_> = headers
+            .iter()
+            .map(|header| (header.line_number, header.dejagnu_header.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (1, "// { dg-skip-if \"\" { *-*-mingw* } }"),
+                (2, "// { dg-skip-if \"\" { *-*-darwin* } }"),
+                (3, "// { dg-skip-if \"\" { wasm32-*-* } }"),
+                (6, "// only-x86_64"),
+            ]
+        );
+        assert_eq!(
+            warnings,
+            [
+                "line 4: `ignore-cross-compile` has no known target triplet for `cross-compile`, kept as a comment",
+                "line 5: `only-plan9` has no known target triplet for `plan9`, kept as a comment",
+            ]
+        );
+        assert_eq!(target_selector(code, None), "x86_64-*-*");
+    }
+
+    #[test]
+    fn test_target_selector() {
+        assert_eq!(target_selector("fn main() {}\n", None), "*-*-*");
+        let code = "//@ revisions: a b\n//@[a] only-linux\n//@ only-aarch64\n";
+        assert_eq!(
+            target_selector(code, None),
+            "{ *-*-linux* && aarch64*-*-* }"
+        );
+        assert_eq!(target_selector(code, Some("b")), "aarch64*-*-*");
+    }
+
+    #[test]
+    fn test_error_patterns() {
+        let lines = |code| {
+            let (headers, warnings) =
+                parse_additional_options(code, None, &NeedsPolicy::default(), false).unwrap();
+            assert!(warnings.is_empty(), "{warnings:?}");
+            headers
+                .into_iter()
+                .map(|header| header.dejagnu_header)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines("//@ error-pattern: cannot find value\nfn main() { x }\n"),
+            ["// { dg-error \"cannot find value\" \"\" { target *-*-* } 0 }"]
+        );
+        // Each pattern gets its own directive, within the `only-<target>` selector
+        assert_eq!(
+            lines("// error-pattern:first\n//@ only-linux\n//@ error-pattern: second one\n"),
+            [
+                "// { dg-error \"first\" \"\" { target *-*-linux* } 0 }",
+                "// only-linux",
+                "// { dg-error \"second one\" \"\" { target *-*-linux* } 0 }",
+            ]
+        );
+        assert_eq!(
+            lines(r#"//@ error-pattern: expected "a\b" [E0308]"#),
+            [r#"// { dg-error "expected .a\\\\b. \\\[E0308\\\]" "" { target *-*-* } 0 }"#]
+        );
+    }
+
+    #[test]
+    fn test_needs_headers() {
+        let code = "//@ needs-unwind\n// needs-asm-support\n//@ needs-git-hash\nfn main() {}\n";
+        let (headers, warnings) =
+            parse_additional_options(code, None, &NeedsPolicy::default(), false).unwrap();
+        let lines: Vec<_> = headers
+            .iter()
+            .map(|header| (header.line_number, header.dejagnu_header.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (1, ""),
+                (2, "// { dg-skip-if \"needs-asm-support\" { *-*-* } }"),
+                (3, "// UNCONVERTED: @ needs-git-hash"),
+            ]
+        );
+        assert_eq!(
+            warnings,
+            ["line 3: `needs-git-hash` is not in the needs policy, kept as a comment"]
+        );
+
+        let policy =
+            NeedsPolicy::parse("needs-unwind = \"skip\"\nneeds-git-hash = \"drop\"\n").unwrap();
+        let (headers, warnings) = parse_additional_options(code, None, &policy, false).unwrap();
+        let lines: Vec<_> = headers
+            .iter()
+            .map(|header| header.dejagnu_header.as_str())
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "// { dg-skip-if \"needs-unwind\" { *-*-* } }",
+                "// { dg-skip-if \"needs-asm-support\" { *-*-* } }",
+                "",
+            ]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_env_headers() {
+        let code = r#"//@ rustc-env: RUST_BACKTRACE=0
+//@ rustc-env:RUSTC_BOOTSTRAP=1
+//@ rustc-env: CARGO_PKG_DESCRIPTION=a "quoted" $name [with] {braces} \ and spaces
+//@ exec-env: RUST_MIN_STACK=16384
+//@ rustc-env: RUST_BACKTRACE=full
+//@ rustc-env: NO_VALUE
+fn main() {}
+"#;
+        let (headers, warnings) =
+            parse_additional_options(code, None, &NeedsPolicy::default(), false).unwrap();
+        let lines: Vec<_> = headers
+            .iter()
+            .map(|header| (header.line_number, header.dejagnu_header.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                // The first `RUST_BACKTRACE` is overridden by the one of line 5
+                (1, ""),
+                (2, "// { dg-set-compiler-env-var RUSTC_BOOTSTRAP \"1\" }"),
+                (
+                    3,
+                    r#"// { dg-set-compiler-env-var CARGO_PKG_DESCRIPTION "a \"quoted\" \$name \[with\] \{braces\} \\ and spaces" }"#
+                ),
+                (4, "// UNCONVERTED: @ exec-env: RUST_MIN_STACK=16384"),
+                (5, "// { dg-set-compiler-env-var RUST_BACKTRACE \"full\" }"),
+            ]
+        );
+        for (line_number, line) in &lines[1..] {
+            if *line_number != 4 {
+                crate::dejagnu::validate(line).unwrap();
+            }
+        }
+        assert_eq!(
+            warnings,
+            [
+                "line 4: `exec-env: RUST_MIN_STACK=16384` sets the environment of the run, which DejaGnu does not convert, kept as a comment",
+                "line 5: `rustc-env` sets `RUST_BACKTRACE` again, the value of line 1 is dropped",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unconverted_headers() {
+        let code = "//@ compile-flags: -O\n//@ gccrs-only\n//@[a] frobnicate: yes\n//@ run-rustfix\n// not-a-header: x\nfn main() {}\n";
+        let (headers, warnings) =
+            parse_additional_options(code, None, &NeedsPolicy::default(), false).unwrap();
+        let lines: Vec<_> = headers
+            .iter()
+            .map(|header| (header.line_number, header.dejagnu_header.as_str()))
+            .collect();
+        // The known `run-rustfix` is left alone, and `//` comments are not headers
+        assert_eq!(
+            lines,
+            [
+                (1, "// { dg-additional-options \"-O\" }"),
+                (2, "// UNCONVERTED: @ gccrs-only"),
+                (3, "// UNCONVERTED: @[a] frobnicate: yes"),
+            ]
+        );
+        assert!(warnings.is_empty());
+        assert_eq!(
+            unconverted_headers(code),
+            [(2, "@ gccrs-only"), (3, "@[a] frobnicate: yes")]
+        );
+    }
+
+    #[test]
+    fn test_annotation_policy() {
+        let code = "//@ dont-require-annotations: NOTE\n//@[b] dont-require-annotations: HELP\n//@ dont-require-annotations: MAYBE\nfn main() {}\n";
+        let policy = annotation_policy(code, None);
+        assert_eq!(
+            policy.optional,
+            [RustcErrorKind::Note, RustcErrorKind::Help]
+        );
+        assert_eq!(
+            annotation_policy(code, Some("a")).optional,
+            [RustcErrorKind::Note]
+        );
+        assert!(policy.is_optional(Some(RustcErrorKind::Note)));
+        assert!(!policy.is_optional(Some(RustcErrorKind::Error)));
+        assert!(!policy.is_optional(None));
+        assert!(annotation_policy("fn main() {}\n", None)
+            .optional
+            .is_empty());
+    }
+
+    #[test]
+    fn test_proc_macro_header() {
+        assert_eq!(
+            proc_macro_header("//@ edition: 2021\n// force-host\nfn main() {}\n"),
+            Some("force-host")
+        );
+        assert_eq!(
+            proc_macro_header("//@ needs-crate-type: dylib, proc-macro\n"),
+            Some("needs-crate-type: dylib, proc-macro")
+        );
+        assert_eq!(proc_macro_header("//@ needs-crate-type: dylib\n"), None);
+        assert_eq!(
+            proc_macro_header("// a proc-macro test\nfn main() {}\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_edition_conflicts() {
+        let conflict = |code: &str, revision| {
+            edition_conflict(code, revision).map(|conflict| {
+                (
+                    conflict.line,
+                    conflict.header,
+                    conflict.flags_line,
+                    conflict.flag,
+                )
+            })
+        };
+        assert_eq!(
+            conflict(
+                "//@ edition: 2021\n//@ compile-flags: -O --edition=2018\n",
+                None
+            ),
+            Some((1, "2021".to_owned(), 2, "2018".to_owned()))
+        );
+        // The same edition twice, or only one of them
+        assert_eq!(
+            conflict(
+                "//@ edition: 2021\n//@ compile-flags: --edition 2021\n",
+                None
+            ),
+            None
+        );
+        assert_eq!(conflict("//@ compile-flags: --edition 2018\n", None), None);
+        assert_eq!(
+            conflict("//@ edition: 2018\n//@ compile-flags: -O\n", None),
+            None
+        );
+        // Only the headers of the revision count
+        let code = "//@ revisions: a b\n//@ edition: 2021\n//@[a] compile-flags: --edition 2018\n";
+        assert!(conflict(code, Some("a")).is_some());
+        assert_eq!(conflict(code, Some("b")), None);
+    }
+
+    #[test]
+    fn test_edition_headers() {
+        let convert = |code: &str, explicit_edition| {
+            parse_additional_options(code, None, &NeedsPolicy::default(), explicit_edition)
+                .map(|(headers, _)| headers[0].dejagnu_header.clone())
+        };
+        for edition in ["2018", "2021", "2024"] {
+            for header in [
+                format!("//@ edition:{edition}"),
+                format!("//@ edition : {edition}\t"),
+                format!("// edition:  {edition}"),
+            ] {
+                assert_eq!(
+                    convert(&header, false),
+                    Ok(format!(
+                        "// {{ dg-additional-options \"-frust-edition={edition}\" }}"
+                    )),
+                    "{header}"
+                );
+            }
+        }
+        // The default edition goes without saying, unless asked to
+        assert_eq!(convert("//@ edition: 2015", false), Ok(String::new()));
+        assert_eq!(
+            convert("//@ edition: 2015", true),
+            Ok("// { dg-additional-options \"-frust-edition=2015\" }".to_owned())
+        );
+
+        let invalid = |line, edition: &str| {
+            Err(InvalidEdition {
+                line,
+                edition: edition.to_owned(),
+            })
+        };
+        assert_eq!(
+            convert("fn f() {}\n//@ edition:2019\n", false),
+            invalid(2, "2019")
+        );
+        assert_eq!(convert("//@ edition: ", false), invalid(1, ""));
+        assert_eq!(
+            invalid(2, "2019").unwrap_err().to_string(),
+            "line 2: `2019` is not an edition, expected one of 2015, 2018, 2021, 2024"
+        );
+    }
+
+    #[test]
+    fn test_test_mode() {
+        assert_eq!(test_mode("fn main() {}\n", None), Ok(None));
+        assert_eq!(
+            test_mode("// run-pass\n//@ run-pass\n", None),
+            Ok(Some(Mode::RunPass))
+        );
+        let code = "//@ revisions: a b\n//@[a] check-pass\n//@[b] run-fail\n";
+        assert_eq!(test_mode(code, Some("a")), Ok(Some(Mode::CheckPass)));
+        assert_eq!(test_mode(code, Some("b")), Ok(Some(Mode::RunFail)));
+        assert_eq!(
+            test_mode(code, None),
+            Err(ConflictingModes {
+                first: (2, Mode::CheckPass),
+                second: (3, Mode::RunFail),
+            })
+        );
+    }
+
+    #[test]
+    fn test_header_name() {
+        assert_eq!(header_name("//@ check-pass"), Some("check-pass"));
+        assert_eq!(header_name("// check-pass"), Some("check-pass"));
+        assert_eq!(header_name("// run-pass: really"), Some("run-pass"));
+        assert_eq!(header_name("// revisions are tricky"), None);
+        assert_eq!(header_name("// checks the pass"), None);
+        assert!(is_header_line("// aux-build:helper.rs"));
+        assert!(!is_header_line("// just a comment"));
+    }
+}
@@ -0,0 +1,310 @@
+//! This module contains the logic for reading the input files.
+//!
+//! Small files are read into a `String`. Files above a size threshold are memory-mapped
+//! when the `mmap` feature is enabled, so that a multi-megabyte generated test and its
+//! `stderr` are handed to the line-oriented parsers without being copied into memory.
+
+use std::{fmt, fs, io, ops::Deref, path::Path};
+
+/// Files larger than this many bytes are memory-mapped
+pub const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// Number of bytes looked at by [`is_binary`]
+pub const SNIFF_LENGTH: usize = 8 * 1024;
+
+/// The input file looks like a compiled artifact or an image rather than text
+#[derive(Debug)]
+pub struct BinaryInputError;
+
+impl fmt::Display for BinaryInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "input appears to be binary, not Rust source")
+    }
+}
+
+impl std::error::Error for BinaryInputError {}
+
+/// Whether `content` looks binary, judging from its first [`SNIFF_LENGTH`] bytes:
+/// any NUL byte, or more than one control character out of ten.
+///
+/// Non-ASCII bytes are never counted, they are how UTF-8 text looks.
+pub fn is_binary(content: &[u8]) -> bool {
+    let head = &content[..content.len().min(SNIFF_LENGTH)];
+    if head.contains(&0) {
+        return true;
+    }
+    let control = head
+        .iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c)) || b == 0x7f)
+        .count();
+    control * 10 > head.len()
+}
+
+/// Whether an error returned by [`read`] is a [`BinaryInputError`]
+pub fn is_binary_error(err: &io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|inner| inner.is::<BinaryInputError>())
+}
+
+/// Checks the raw content of a file before it is validated as UTF-8
+fn check_text(content: &[u8]) -> io::Result<()> {
+    if is_binary(content) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, BinaryInputError));
+    }
+    Ok(())
+}
+
+/// The content of an input file, usable as a `&str`
+#[derive(Debug)]
+pub enum Input {
+    /// The file was read into memory
+    Owned(String),
+    /// The file is memory-mapped, its content was checked to be valid UTF-8
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+/// The UTF-8 byte order mark some editors put at the start of files
+pub const BOM: &str = "\u{feff}";
+
+/// What to do with the byte order mark of the source file
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum BomMode {
+    /// Write it back at the start of the output
+    #[default]
+    Keep,
+    /// Leave it out of the output
+    Strip,
+}
+
+impl Input {
+    /// The whole content of the file, including its byte order mark if any
+    fn raw(&self) -> &str {
+        match self {
+            Input::Owned(content) => content,
+            // SAFETY: `read_mapped` only builds this variant after validating the content
+            #[cfg(feature = "mmap")]
+            Input::Mapped(map) => unsafe { std::str::from_utf8_unchecked(map) },
+        }
+    }
+
+    /// Whether the file starts with a byte order mark, which the content never includes
+    pub fn has_bom(&self) -> bool {
+        self.raw().starts_with(BOM)
+    }
+}
+
+impl Deref for Input {
+    type Target = str;
+
+    /// The content of the file, without its byte order mark
+    fn deref(&self) -> &str {
+        let raw = self.raw();
+        raw.strip_prefix(BOM).unwrap_or(raw)
+    }
+}
+
+/// The path naming standard input on the command line
+pub const STDIN: &str = "-";
+
+/// Whether `path` names standard input rather than a file
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN
+}
+
+/// How to name `path` in messages: quoted, or `standard input`
+pub fn display_name(path: &Path) -> String {
+    if is_stdin(path) {
+        "standard input".to_owned()
+    } else {
+        format!("`{}`", path.display())
+    }
+}
+
+/// Reads the whole content of `reader`, like standard input, checking it the same way as
+/// the content of a file
+pub fn read_from(mut reader: impl io::Read) -> io::Result<Input> {
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content)?;
+    check_text(&content)?;
+    let content = String::from_utf8(content)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(Input::Owned(content))
+}
+
+/// Reads the file at `path`, memory-mapping it if it is larger than [`MMAP_THRESHOLD`]
+pub fn read(path: &Path) -> io::Result<Input> {
+    read_with_threshold(path, MMAP_THRESHOLD)
+}
+
+/// Same as [`read`], with a custom threshold
+pub fn read_with_threshold(path: &Path, threshold: u64) -> io::Result<Input> {
+    let file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    // Empty files cannot be mapped on every platform
+    if len > threshold && len > 0 {
+        return read_mapped(&file);
+    }
+    read_owned(file)
+}
+
+fn read_owned(file: fs::File) -> io::Result<Input> {
+    read_from(file)
+}
+
+#[cfg(feature = "mmap")]
+fn read_mapped(file: &fs::File) -> io::Result<Input> {
+    // SAFETY: the map is read-only; like any other tool reading its inputs we assume they are
+    // not modified while being converted
+    let map = unsafe { memmap2::Mmap::map(file)? };
+    check_text(&map)?;
+    if let Err(err) = std::str::from_utf8(&map) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+    }
+    Ok(Input::Mapped(map))
+}
+
+#[cfg(not(feature = "mmap"))]
+fn read_mapped(file: &fs::File) -> io::Result<Input> {
+    read_owned(file.try_clone()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::transform::{self, Options},
+        std::io::Write,
+    };
+
+    fn fixture_paths() -> Vec<std::path::PathBuf> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext != "expected"))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn test_both_paths_read_the_fixture_corpus_identically() {
+        for path in fixture_paths() {
+            let small = read_with_threshold(&path, u64::MAX).unwrap();
+            let large = read_with_threshold(&path, 0).unwrap();
+            assert!(matches!(small, Input::Owned(_)));
+            #[cfg(feature = "mmap")]
+            assert!(matches!(large, Input::Mapped(_)));
+            assert_eq!(&*small, &*large, "{}", path.display());
+
+            if path.extension().is_some_and(|ext| ext == "rs") {
+                let stderr = path.with_extension("stderr");
+                let stderr = stderr
+                    .exists()
+                    .then(|| read_with_threshold(&stderr, 0).unwrap());
+                let options = Options::default();
+                assert_eq!(
+                    transform::transform_code(&small, stderr.as_deref(), &options).unwrap(),
+                    transform::transform_code(&large, stderr.as_deref(), &options).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_large_file_is_mapped() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for i in 0..100_000 {
+            if i % 1000 == 0 {
+                writeln!(file, "    let x{i} = y; //~ ERROR cannot find value `y`").unwrap();
+            } else {
+                writeln!(
+                    file,
+                    "    let x{i} = {i}; // generated to make the file large"
+                )
+                .unwrap();
+            }
+        }
+        file.flush().unwrap();
+        assert!(file.as_file().metadata().unwrap().len() > MMAP_THRESHOLD);
+
+        let input = read(file.path()).unwrap();
+        #[cfg(feature = "mmap")]
+        assert!(matches!(input, Input::Mapped(_)));
+        let (new_code, _) = transform::transform_code(&input, None, &Options::default()).unwrap();
+        assert_eq!(new_code.lines().count(), 100_000);
+        let directives = new_code
+            .lines()
+            .filter(|line| line.ends_with("{ target *-*-* } }"));
+        assert_eq!(directives.count(), 100);
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_rejected_on_both_paths() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"fn main() {}\n\xff\n").unwrap();
+        for threshold in [0, u64::MAX] {
+            let err = read_with_threshold(file.path(), threshold).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn test_binary_fixture_is_rejected_on_both_paths() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/binary/compiled.o");
+        for threshold in [0, u64::MAX] {
+            let err = read_with_threshold(&path, threshold).unwrap_err();
+            assert!(is_binary_error(&err));
+            assert_eq!(
+                err.to_string(),
+                "input appears to be binary, not Rust source"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unusual_text_is_not_binary() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/unicode.rs");
+        assert!(!is_binary(&fs::read(&path).unwrap()));
+        assert!(read(&path).is_ok());
+        // A few escape sequences or form feeds are fine, a NUL is not
+        assert!(!is_binary(
+            b"fn main() {}\x0c\n// \x1b[31mred\x1b[0m\n\tlet x = 1;\r\n"
+        ));
+        assert!(is_binary(b"fn main() {}\0"));
+        assert!(is_binary(&[0x01; 100]));
+        // Only the head of the file is looked at
+        let mut late_nul = vec![b'a'; SNIFF_LENGTH];
+        late_nul.push(0);
+        assert!(!is_binary(&late_nul));
+    }
+
+    #[test]
+    fn test_invalid_utf8_text_is_not_reported_as_binary() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"// caf\xe9\n").unwrap();
+        let err = read(file.path()).unwrap_err();
+        assert!(!is_binary_error(&err));
+    }
+
+    #[test]
+    fn test_bom_is_stripped_on_both_paths() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all("\u{feff}//@ edition:2021\n".as_bytes())
+            .unwrap();
+        for threshold in [0, u64::MAX] {
+            let input = read_with_threshold(file.path(), threshold).unwrap();
+            assert!(input.has_bom());
+            assert_eq!(&*input, "//@ edition:2021\n");
+        }
+        let input = read_with_threshold(file.path(), u64::MAX).unwrap();
+        assert!(!Input::Owned(input.to_string()).has_bom());
+    }
+
+    #[test]
+    fn test_empty_file_is_read() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert_eq!(&*read_with_threshold(file.path(), 0).unwrap(), "");
+    }
+}
@@ -0,0 +1,200 @@
+//! This module contains the routing of converted tests into the layout of the gccrs
+//! testsuite, which keeps compile-only tests and executable ones in separate directories.
+//!
+//! The kind of a test comes from its headers and annotations: `run-*` headers make an
+//! executable test, pass or fail headers and error annotations a compile-only one. Tests
+//! which could be either go to the compile directory and are reported as ambiguous.
+
+use {
+    crate::{errors, header},
+    serde::Serialize,
+    std::{
+        fmt,
+        path::{Path, PathBuf},
+        str::FromStr,
+    },
+};
+
+/// Headers of the tests which are run once compiled
+const RUN_HEADERS: &[&str] = &["run-crash", "run-fail", "run-pass"];
+
+/// Headers of the tests which are only compiled
+const COMPILE_HEADERS: &[&str] = &["build-fail", "build-pass", "check-fail", "check-pass"];
+
+/// Headers naming an auxiliary file of the test
+const AUX_HEADERS: &[&str] = &["aux-bin", "aux-build", "aux-crate", "proc-macro"];
+
+/// Where a converted test goes in the gccrs testsuite
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TestKind {
+    Compile,
+    Execute,
+}
+
+impl fmt::Display for TestKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TestKind::Compile => "compile",
+            TestKind::Execute => "execute",
+        })
+    }
+}
+
+/// The kind of a test, and why it is ambiguous if it is
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Detection {
+    pub kind: TestKind,
+    pub ambiguity: Option<&'static str>,
+}
+
+/// The directories of each kind of test, relative to the output directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub compile: PathBuf,
+    pub execute: PathBuf,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            compile: PathBuf::from("compile"),
+            execute: PathBuf::from("execute"),
+        }
+    }
+}
+
+impl Layout {
+    /// The directory of the tests of `kind`
+    pub fn dir(&self, kind: TestKind) -> &Path {
+        match kind {
+            TestKind::Compile => &self.compile,
+            TestKind::Execute => &self.execute,
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    /// Parses `COMPILE,EXECUTE`, like `compile,execute`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(',') {
+            Some((compile, execute))
+                if !compile.is_empty() && !execute.is_empty() && compile != execute =>
+            {
+                Ok(Layout {
+                    compile: PathBuf::from(compile),
+                    execute: PathBuf::from(execute),
+                })
+            }
+            _ => Err(format!(
+                "expected two different directories like `compile,execute`, found `{s}`"
+            )),
+        }
+    }
+}
+
+/// Detects whether the rustc test `code` is compile-only or executable
+pub fn detect(code: &str) -> Detection {
+    let headers: Vec<&str> = code.lines().filter_map(header::header_name).collect();
+    let has_header = |names: &[&str]| headers.iter().any(|name| names.contains(name));
+    let runs = has_header(RUN_HEADERS);
+    let compiles = has_header(COMPILE_HEADERS);
+    let has_errors = errors::load_error(code, None).is_ok_and(|errors| {
+        errors
+            .iter()
+            .any(|error| matches!(error.kind, Some(errors::RustcErrorKind::Error) | None))
+    });
+    let has_main = code.lines().any(|line| line.contains("fn main("));
+
+    let (kind, ambiguity) = match (runs, compiles || has_errors) {
+        (true, false) if has_main => (TestKind::Execute, None),
+        (true, false) => (
+            TestKind::Compile,
+            Some("run header without a `main` function"),
+        ),
+        (true, true) => (
+            TestKind::Compile,
+            Some("run header along with compile-only headers or error annotations"),
+        ),
+        (false, true) => (TestKind::Compile, None),
+        (false, false) if has_main => (
+            TestKind::Compile,
+            Some("no pass header nor error annotation, it may be meant to run"),
+        ),
+        (false, false) => (TestKind::Compile, None),
+    };
+    Detection { kind, ambiguity }
+}
+
+/// The auxiliary files named by the headers of `code`, relative to its `auxiliary` directory
+pub fn auxiliary_files(code: &str) -> Vec<&str> {
+    code.lines()
+        .filter(|line| header::header_name(line).is_some_and(|name| AUX_HEADERS.contains(&name)))
+        .filter_map(|line| {
+            let (_, value) = line.split_once(':')?;
+            // `aux-crate: name=file.rs` names the crate as well
+            let value = value.rsplit('=').next().unwrap_or(value).trim();
+            Some(value).filter(|value| !value.is_empty())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_kind(code: &str) -> (TestKind, bool) {
+        let detection = detect(code);
+        (detection.kind, detection.ambiguity.is_some())
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            detect_kind("//@ run-pass\nfn main() {}\n"),
+            (TestKind::Execute, false)
+        );
+        assert_eq!(
+            detect_kind("//@ check-pass\nfn main() {}\n"),
+            (TestKind::Compile, false)
+        );
+        assert_eq!(
+            detect_kind("fn main() {\n    x; //~ ERROR cannot find value\n}\n"),
+            (TestKind::Compile, false)
+        );
+        assert_eq!(detect_kind("pub fn f() {}\n"), (TestKind::Compile, false));
+        // Warnings do not stop a test from running
+        assert_eq!(
+            detect_kind("//@ run-pass\nfn main() {\n    let x = 1; //~ WARN unused\n}\n"),
+            (TestKind::Execute, false)
+        );
+
+        assert_eq!(detect_kind("fn main() {}\n"), (TestKind::Compile, true));
+        assert_eq!(detect_kind("//@ run-fail\n"), (TestKind::Compile, true));
+        assert_eq!(
+            detect_kind("//@ run-pass\nfn main() {} //~ ERROR x\n"),
+            (TestKind::Compile, true)
+        );
+    }
+
+    #[test]
+    fn test_auxiliary_files() {
+        let code = "//@ aux-build:helper.rs\n//@ aux-crate: dep=other.rs\n//@ edition:2021\n//@ aux-build:\n";
+        assert_eq!(auxiliary_files(code), ["helper.rs", "other.rs"]);
+    }
+
+    #[test]
+    fn test_parse_layout() {
+        assert_eq!("compile,execute".parse(), Ok(Layout::default()));
+        let layout: Layout = "rust/compile,rust/execute/torture".parse().unwrap();
+        assert_eq!(
+            layout.dir(TestKind::Execute),
+            Path::new("rust/execute/torture")
+        );
+        assert!("compile".parse::<Layout>().is_err());
+        assert!("a,a".parse::<Layout>().is_err());
+        assert!(",execute".parse::<Layout>().is_err());
+    }
+}
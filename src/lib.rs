@@ -0,0 +1,30 @@
+//! Converts `rustc` testcases into `DejaGnu` testcases for `gccrs`
+
+pub mod batch;
+pub mod cache;
+pub mod cli;
+pub mod corpus;
+pub mod coverage;
+pub mod dejagnu;
+pub mod diff;
+pub mod errors;
+pub mod explain;
+pub mod filter;
+#[cfg(any(test, fuzzing))]
+pub mod fuzz;
+pub mod header;
+pub mod input;
+pub mod layout;
+pub mod limits;
+pub mod lint;
+pub mod merge;
+pub mod mirror;
+pub mod modules;
+pub mod needs;
+pub mod regexes;
+pub mod reverse;
+pub mod roundtrip;
+pub mod selftest;
+pub mod stats;
+pub mod testlist;
+pub mod transform;
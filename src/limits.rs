@@ -0,0 +1,239 @@
+//! This module contains the resource caps applied to every converted file.
+//!
+//! Untrusted or accidentally binary inputs must fail with an error naming the exceeded
+//! limit instead of exhausting memory or hanging. The defaults are far above anything
+//! found in the rustc test suite.
+
+use std::fmt;
+
+/// Resource caps for a single conversion, `0` disables a limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum size of the source file, in bytes
+    pub max_file_size: u64,
+    /// Maximum size of the `stderr` file, in bytes
+    pub max_stderr_size: u64,
+    /// Maximum number of annotations in the source file
+    pub max_annotations: usize,
+    /// Maximum length of a source line, in bytes
+    pub max_line_length: usize,
+}
+
+impl Limits {
+    pub const DEFAULT_MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+    pub const DEFAULT_MAX_STDERR_SIZE: u64 = 64 * 1024 * 1024;
+    pub const DEFAULT_MAX_ANNOTATIONS: usize = 100_000;
+    pub const DEFAULT_MAX_LINE_LENGTH: usize = 4 * 1024 * 1024;
+
+    /// No limit at all
+    pub const UNLIMITED: Limits = Limits {
+        max_file_size: 0,
+        max_stderr_size: 0,
+        max_annotations: 0,
+        max_line_length: 0,
+    };
+
+    /// Checks the size of the source file, before or after reading it
+    pub fn check_file_size(&self, size: u64) -> Result<(), LimitError> {
+        if exceeds(size, self.max_file_size) {
+            return Err(LimitError::FileSize {
+                size,
+                limit: self.max_file_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks the size of the `stderr` file, before or after reading it
+    pub fn check_stderr_size(&self, size: u64) -> Result<(), LimitError> {
+        if exceeds(size, self.max_stderr_size) {
+            return Err(LimitError::StderrSize {
+                size,
+                limit: self.max_stderr_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks the number of annotations found in the source file
+    pub fn check_annotations(&self, count: usize) -> Result<(), LimitError> {
+        if exceeds(count as u64, self.max_annotations as u64) {
+            return Err(LimitError::Annotations {
+                count,
+                limit: self.max_annotations,
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks the size of the source file and the length of each of its lines
+    pub fn check_source(&self, code: &str) -> Result<(), LimitError> {
+        self.check_file_size(code.len() as u64)?;
+        if self.max_line_length == 0 {
+            return Ok(());
+        }
+        for (line_num, line) in code.lines().enumerate() {
+            if line.len() > self.max_line_length {
+                return Err(LimitError::LineLength {
+                    line_num: line_num + 1,
+                    length: line.len(),
+                    limit: self.max_line_length,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_file_size: Self::DEFAULT_MAX_FILE_SIZE,
+            max_stderr_size: Self::DEFAULT_MAX_STDERR_SIZE,
+            max_annotations: Self::DEFAULT_MAX_ANNOTATIONS,
+            max_line_length: Self::DEFAULT_MAX_LINE_LENGTH,
+        }
+    }
+}
+
+fn exceeds(value: u64, limit: u64) -> bool {
+    limit != 0 && value > limit
+}
+
+/// A limit exceeded by a file, with the measured value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LimitError {
+    /// The source file is larger than [`Limits::max_file_size`]
+    FileSize { size: u64, limit: u64 },
+    /// The `stderr` file is larger than [`Limits::max_stderr_size`]
+    StderrSize { size: u64, limit: u64 },
+    /// The source file has more annotations than [`Limits::max_annotations`].
+    /// Loading stops right after the limit, so `count` may be below the real number.
+    Annotations { count: usize, limit: usize },
+    /// A source line is longer than [`Limits::max_line_length`]
+    LineLength {
+        line_num: usize,
+        length: usize,
+        limit: usize,
+    },
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitError::FileSize { size, limit } => write!(
+                f,
+                "source file is {size} bytes, exceeding the `--max-file-size` limit of {limit}"
+            ),
+            LimitError::StderrSize { size, limit } => write!(
+                f,
+                "stderr file is {size} bytes, exceeding the `--max-stderr-size` limit of {limit}"
+            ),
+            LimitError::Annotations { count, limit } => write!(
+                f,
+                "source file has at least {count} annotations, exceeding the `--max-annotations` limit of {limit}"
+            ),
+            LimitError::LineLength {
+                line_num,
+                length,
+                limit,
+            } => write!(
+                f,
+                "line {line_num} is {length} bytes long, exceeding the `--max-input-line-length` limit of {limit}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitError {}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::transform::{transform_code, Options},
+    };
+
+    const CODE: &str = "fn main() {\n    f(); //~ ERROR a\n    g(); //~ ERROR b\n}\n";
+
+    fn transform_error(limits: Limits, code: &str, stderr: Option<&str>) -> LimitError {
+        let options = Options {
+            limits,
+            ..Options::default()
+        };
+        let err = transform_code(code, stderr, &options).unwrap_err();
+        err.downcast::<LimitError>().unwrap()
+    }
+
+    #[test]
+    fn test_defaults_do_not_trip_on_normal_tests() {
+        assert!(transform_code(CODE, Some("error: x\n"), &Options::default()).is_ok());
+        assert!(Limits::UNLIMITED.check_source(&"a".repeat(1 << 20)).is_ok());
+    }
+
+    #[test]
+    fn test_max_file_size() {
+        let limits = Limits {
+            max_file_size: 10,
+            ..Limits::default()
+        };
+        assert_eq!(
+            transform_error(limits, CODE, None),
+            LimitError::FileSize {
+                size: CODE.len() as u64,
+                limit: 10
+            }
+        );
+    }
+
+    #[test]
+    fn test_max_stderr_size() {
+        let limits = Limits {
+            max_stderr_size: 4,
+            ..Limits::default()
+        };
+        assert_eq!(
+            transform_error(limits, CODE, Some("error: x\n")),
+            LimitError::StderrSize { size: 9, limit: 4 }
+        );
+        assert!(limits.check_stderr_size(4).is_ok());
+    }
+
+    #[test]
+    fn test_max_annotations() {
+        let limits = Limits {
+            max_annotations: 1,
+            ..Limits::default()
+        };
+        assert_eq!(
+            transform_error(limits, CODE, None),
+            LimitError::Annotations { count: 2, limit: 1 }
+        );
+        assert!(limits.check_annotations(1).is_ok());
+    }
+
+    #[test]
+    fn test_max_line_length() {
+        let limits = Limits {
+            max_line_length: 19,
+            ..Limits::default()
+        };
+        assert_eq!(
+            transform_error(limits, CODE, None),
+            LimitError::LineLength {
+                line_num: 2,
+                length: 20,
+                limit: 19
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_names_the_limit() {
+        let err = LimitError::Annotations { count: 2, limit: 1 };
+        assert_eq!(
+            err.to_string(),
+            "source file has at least 2 annotations, exceeding the `--max-annotations` limit of 1"
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! This module contains the lints flagging conversions which are likely wrong.
+//!
+//! The lints look at the annotations as they are about to be rendered, after the error
+//! codes of the `stderr` file were merged in. None of them changes the output: each
+//! [`Finding`] is reported as a warning, unless its lint is denied.
+
+use {
+    crate::{
+        errors::{self, RustcErrorKind},
+        header::AnnotationPolicy,
+        transform::{self, Options, SecondaryMode},
+    },
+    std::{collections::BTreeMap, fmt},
+};
+
+/// More directives than this targeting a single line trigger [`Lint::CrowdedLine`]
+pub const CROWDED_LINE_LIMIT: usize = 3;
+
+/// The lints, named on the command line in kebab case, e.g. `--deny empty-pattern`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, clap::ValueEnum)]
+pub enum Lint {
+    /// A directive matching any message: no message in its pattern and no error code
+    EmptyPattern,
+    /// More than three directives target the same line
+    CrowdedLine,
+    /// An annotation without a known kind, converted into a `dg-error`
+    DefaultedKind,
+    /// A `HELP` or `SUGGESTION` annotation converted like an error
+    VerbatimSecondary,
+    /// A `stderr` file was given, yet fewer than half the errors got an error code
+    FewErrorCodes,
+}
+
+impl Lint {
+    /// The stable name of the lint
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::EmptyPattern => "empty-pattern",
+            Lint::CrowdedLine => "crowded-line",
+            Lint::DefaultedKind => "defaulted-kind",
+            Lint::VerbatimSecondary => "verbatim-secondary",
+            Lint::FewErrorCodes => "few-error-codes",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A suspicious conversion
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub lint: Lint,
+    /// The 1-based source line, `0` for findings about the whole file
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "[{}] {}", self.lint, self.message)
+        } else {
+            write!(f, "[{}] line {}: {}", self.lint, self.line, self.message)
+        }
+    }
+}
+
+/// Findings of denied lints, which fail the transformation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeniedLints(pub Vec<Finding>);
+
+impl fmt::Display for DeniedLints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} findings of denied lints", self.0.len())?;
+        for finding in &self.0 {
+            write!(f, "\n  {finding}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DeniedLints {}
+
+/// Runs every lint over the annotations of a file, sorted by line. Those `policy` keeps as
+/// comments are left out.
+pub fn check(
+    errors: &[errors::Error],
+    has_stderr: bool,
+    options: &Options,
+    policy: &AnnotationPolicy,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut targets: BTreeMap<usize, usize> = BTreeMap::new();
+    for error in errors {
+        if !transform::renders_directive(error, options, policy) {
+            continue;
+        }
+        // The line of the annotation itself, which is where the user would fix it
+        let line = usize::try_from(error.line_num as i64 - i64::from(error.relative_line_num))
+            .unwrap_or(error.line_num);
+        *targets.entry(error.line_num).or_default() += 1;
+
+        let has_code = error
+            .error_code
+            .as_deref()
+            .is_some_and(|code| !code.is_empty());
+        if !has_code
+            && errors::message_pattern_within(
+                &error.msg,
+                options.msg_style,
+                options.max_message_length,
+            )
+            .pattern
+            .is_empty()
+        {
+            findings.push(Finding {
+                lint: Lint::EmptyPattern,
+                line,
+                message: "the directive matches any message, it has no pattern nor error code"
+                    .to_owned(),
+            });
+        }
+        match error.kind {
+            None => findings.push(Finding {
+                lint: Lint::DefaultedKind,
+                line,
+                message: "the annotation has no known kind, it was converted into a `dg-error`"
+                    .to_owned(),
+            }),
+            Some(kind @ (RustcErrorKind::Help | RustcErrorKind::Suggestion))
+                if options.secondary == SecondaryMode::Convert =>
+            {
+                findings.push(Finding {
+                    lint: Lint::VerbatimSecondary,
+                    line,
+                    message: format!(
+                        "the {kind:?} annotation was converted into a `{}` directive verbatim",
+                        errors::dg_directive(error.kind)
+                    ),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    for (&line, &count) in &targets {
+        if count > CROWDED_LINE_LIMIT {
+            findings.push(Finding {
+                lint: Lint::CrowdedLine,
+                line,
+                message: format!(
+                    "{count} directives target this line, more than {CROWDED_LINE_LIMIT}"
+                ),
+            });
+        }
+    }
+
+    if has_stderr {
+        let errors: Vec<_> = errors
+            .iter()
+            .filter(|error| matches!(error.kind, Some(RustcErrorKind::Error) | None))
+            .collect();
+        let coded = errors
+            .iter()
+            .filter(|error| {
+                error
+                    .error_code
+                    .as_deref()
+                    .is_some_and(|code| !code.is_empty())
+            })
+            .count();
+        if !errors.is_empty() && coded * 2 < errors.len() {
+            findings.push(Finding {
+                lint: Lint::FewErrorCodes,
+                line: 0,
+                message: format!(
+                    "only {coded} of {} errors got an error code from the stderr file",
+                    errors.len()
+                ),
+            });
+        }
+    }
+
+    findings.sort_by_key(|finding| (finding.line, finding.lint));
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::transform::{transform_code, TransformReport},
+    };
+
+    fn lint(code: &str, stderr: Option<&str>, options: Options) -> TransformReport {
+        let options = Options {
+            lint: true,
+            ..options
+        };
+        transform_code(code, stderr, &options).unwrap().1
+    }
+
+    fn lints(report: &TransformReport) -> Vec<(Lint, usize)> {
+        report
+            .lints
+            .iter()
+            .map(|finding| (finding.lint, finding.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_each_lint_triggers() {
+        let code = "\
+fn main() {
+    let x = y;
+    //~^ ERROR cannot find value
+    //~| HELP a local variable with a similar name exists
+    //~| SUGGESTION x
+    //~| NOTE not found
+    foo(); //~ mismatched
+}
+";
+        let report = lint(code, None, Options::default());
+        assert_eq!(
+            lints(&report),
+            [
+                (Lint::CrowdedLine, 2),
+                (Lint::EmptyPattern, 3),
+                (Lint::EmptyPattern, 4),
+                (Lint::VerbatimSecondary, 4),
+                (Lint::EmptyPattern, 5),
+                (Lint::VerbatimSecondary, 5),
+                (Lint::EmptyPattern, 6),
+                (Lint::EmptyPattern, 7),
+                (Lint::DefaultedKind, 7),
+            ]
+        );
+        assert_eq!(
+            report.lints[0].to_string(),
+            "[crowded-line] line 2: 4 directives target this line, more than 3"
+        );
+
+        // Messages in the patterns and secondary annotations as comments leave the kinds
+        let options = Options {
+            msg_style: errors::MsgStyle::Escaped,
+            secondary: SecondaryMode::Comment,
+            ..Options::default()
+        };
+        let report = lint(code, None, options);
+        assert_eq!(lints(&report), [(Lint::DefaultedKind, 7)]);
+    }
+
+    #[test]
+    fn test_few_error_codes() {
+        let code = "\
+fn main() {
+    let x: i32 = \"\"; //~ ERROR mismatched types
+    let y = z; //~ ERROR cannot find value `z`
+    let w = v; //~ ERROR cannot find value `v`
+}
+";
+        let stderr = "error[E0308]: mismatched types\n  --> $DIR/test.rs:2:18\n";
+        let report = lint(code, Some(stderr), Options::default());
+        let finding = &report.lints[0];
+        assert_eq!(
+            finding.to_string(),
+            "[few-error-codes] only 1 of 3 errors got an error code from the stderr file"
+        );
+        // Without a stderr file there is nothing to expect
+        let report = lint(code, None, Options::default());
+        assert!(!lints(&report).contains(&(Lint::FewErrorCodes, 0)));
+    }
+
+    #[test]
+    fn test_denied_lints_fail() {
+        let code = "fn main() {} //~ mismatched\n";
+        let options = Options {
+            deny: vec![Lint::DefaultedKind],
+            ..Options::default()
+        };
+        let err = transform_code(code, None, &options).unwrap_err();
+        assert_eq!(
+            err.downcast::<DeniedLints>().unwrap().to_string(),
+            "1 findings of denied lints\n  [defaulted-kind] line 1: the annotation has no known kind, it was converted into a `dg-error`"
+        );
+
+        // Other lints are still only warnings, and nothing is linted by default
+        let options = Options {
+            deny: vec![Lint::CrowdedLine],
+            ..Options::default()
+        };
+        let (_, report) = transform_code(code, None, &options).unwrap();
+        assert_eq!(report.lints.len(), 2);
+        let (_, report) = transform_code(code, None, &Options::default()).unwrap();
+        assert!(report.lints.is_empty());
+    }
+}
@@ -1,30 +1,74 @@
 use {
-    anyhow::{Context, Result},
+    anyhow::Result,
     clap::Parser,
+    rusttest_to_dg::{batch, cli, corpus, regexes, selftest},
+    std::{io, process::ExitCode},
 };
 
-mod cli;
-mod errors;
-mod header;
-mod transform;
-
-fn main() -> Result<()> {
-    try_parse()
+fn main() -> ExitCode {
+    match try_parse() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            // The same report as returning the error from `main`, with a status telling why
+            eprintln!("Error: {err:?}");
+            ExitCode::from(cli::exit_code(&err))
+        }
+    }
 }
 
 fn try_parse() -> Result<()> {
-    let args = cli::Arguments::parse();
-
-    let (code, stderr_code) = cli::parse_arguments_and_read_file(&args)?;
+    regexes::validate()?;
 
-    let new_code = transform::transform_code(&code, stderr_code.as_deref()).with_context(|| {
-        format!(
-            "could not transform code from file `{}`",
-            args.source_file.display()
-        )
-    })?;
+    let cli = cli::Cli::parse();
 
-    cli::print_source_code(&new_code);
+    let mut output = io::BufWriter::new(io::stdout().lock());
+    match &cli.command {
+        Some(cli::Command::Verify { file }) => cli::run_verify(file, &mut output)?,
+        Some(cli::Command::Batch {
+            rust_src,
+            output_dir,
+            gccrs_src,
+            layout,
+            test_list,
+            list_format,
+            since,
+            jobs,
+        }) => {
+            let corpus = corpus::CorpusOptions {
+                output_dir: output_dir.clone(),
+                gccrs_src: gccrs_src.clone(),
+                layout: layout.clone(),
+                test_list: test_list.clone(),
+                list_format: *list_format,
+                since: since.clone(),
+                jobs: jobs.unwrap_or_else(batch::default_jobs),
+            };
+            cli::run_corpus(rust_src, &corpus, &mut output)?;
+        }
+        Some(cli::Command::Selftest) => selftest::run(&mut output)?,
+        Some(cli::Command::Explain {
+            annotation,
+            at_line,
+            render,
+        }) => cli::run_explain(annotation, *at_line, render, &mut output)?,
+        Some(cli::Command::Reverse { file }) => cli::run_reverse(file, &mut output)?,
+        Some(cli::Command::Completions { shell }) => cli::run_completions(*shell, &mut output)?,
+        None => {
+            // Without a subcommand clap requires the conversion arguments
+            let args = &cli.convert;
+            if args.dir.is_some() {
+                cli::run_dir(args, &mut io::stderr().lock())?;
+                return Ok(());
+            }
+            if args.source_file.len() > 1 || args.file_list.is_some() {
+                cli::run_files(args, &mut output, &mut io::stderr().lock())?;
+                return Ok(());
+            }
+            let report = cli::run(args, &mut output)?;
+            cli::print_report(args.reporter(), &report);
+            cli::write_stats(args, &report.stats, &mut io::stderr().lock())?;
+        }
+    }
 
     Ok(())
 }
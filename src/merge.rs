@@ -0,0 +1,234 @@
+//! This module contains the merge mode, which inserts directives into an
+//! already-ported file using only the rustc `stderr` file.
+
+use {
+    crate::{
+        dejagnu, errors,
+        stats::Stats,
+        transform::{self, TransformReport},
+    },
+    std::collections::BTreeMap,
+};
+
+/// Inserts a directive after every line reported in the `stderr` file.
+///
+/// Everything else in `code` is left untouched. Diagnostics pointing at a line which
+/// does not exist, is blank or already carries a directive are skipped with a warning.
+pub fn merge_stderr(
+    code: &str,
+    stderr_file: &str,
+    options: &transform::Options,
+) -> (String, TransformReport) {
+    let mut report = TransformReport {
+        stats: Stats {
+            files: 1,
+            ..Stats::default()
+        },
+        ..TransformReport::default()
+    };
+    let lines: Vec<&str> = code.lines().collect();
+
+    // Group the errors by their target line, keeping the `stderr` order within a line
+    let mut errors_by_line: BTreeMap<usize, Vec<errors::Error>> = BTreeMap::new();
+    for error in errors::load_stderr_errors(stderr_file, options.stderr_source_name()) {
+        let code_name = error.error_code.as_deref().unwrap_or("error");
+        let line = match error.line_num.checked_sub(1).and_then(|idx| lines.get(idx)) {
+            Some(line) => line,
+            None => {
+                report.warnings.push(format!(
+                    "skipping {code_name}: line {} does not exist in the target file",
+                    error.line_num
+                ));
+                continue;
+            }
+        };
+        if line.trim().is_empty() {
+            report.warnings.push(format!(
+                "skipping {code_name}: line {} is blank",
+                error.line_num
+            ));
+            continue;
+        }
+        if has_directive(line)
+            || lines
+                .get(error.line_num)
+                .is_some_and(|next| expects_line_above(next, error.line_num + 1))
+        {
+            report.warnings.push(format!(
+                "skipping {code_name}: line {} already has a directive",
+                error.line_num
+            ));
+            continue;
+        }
+        errors_by_line
+            .entry(error.line_num)
+            .or_default()
+            .push(error);
+    }
+
+    let mut new_code = String::new();
+    let mut new_line_num = 0;
+    for (line_num, line) in (1..).zip(lines) {
+        new_code.push_str(line);
+        new_code.push('\n');
+        new_line_num += 1;
+
+        for (offset, mut error) in (1..).zip(errors_by_line.remove(&line_num).unwrap_or_default()) {
+            // Each directive is inserted below the previous one, so the offset grows
+            error.relative_line_num = -offset;
+            let directive = error.to_string();
+            new_line_num += 1;
+            transform::check_line_length(
+                new_line_num,
+                directive.chars().count(),
+                options.max_line_length,
+                &mut report,
+            );
+            new_code.push_str(&directive);
+            new_code.push('\n');
+            report.merged += 1;
+            report.stats.add_error(&error);
+        }
+    }
+
+    (new_code, report)
+}
+
+/// Whether the line contains a `DejaGnu` directive
+fn has_directive(line: &str) -> bool {
+    line.contains("// { dg-")
+}
+
+/// Whether the line `line_num` holds only a directive targeting the line above it, like
+/// `// { dg-error "" "" { target *-*-* } .-1 }`. An inline one expects its own line.
+fn expects_line_above(line: &str, line_num: usize) -> bool {
+    dejagnu::code_before_directive(line) == Some("")
+        && dejagnu::parse_directive(line)
+            .and_then(|directive| directive.target_line(line_num))
+            .is_some_and(|target| target + 1 == line_num)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STDERR: &str = "\
+error[E0308]: mismatched types
+  --> $DIR/merge.rs:2:18
+   |
+LL |     let x: i32 = \"hello\";
+   |                  ^^^^^^^ expected `i32`, found `&str`
+
+error[E0425]: cannot find value `undefined` in this scope
+  --> $DIR/merge.rs:3:13
+   |
+LL |     let v = undefined;
+   |             ^^^^^^^^^ not found in this scope
+
+error[E0599]: no method named `foo` found for type `i32` in the current scope
+  --> $DIR/merge.rs:3:15
+   |
+LL |     let v = undefined.foo();
+   |                       ^^^ method not found in `i32`
+
+error: aborting due to 3 previous errors
+";
+
+    #[test]
+    fn test_merge_into_ported_file() {
+        let code = "\
+fn main() {
+    let x: i32 = \"hello\";
+    let v = undefined;
+}
+";
+        let expected = "\
+fn main() {
+    let x: i32 = \"hello\";
+// { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }
+    let v = undefined;
+// { dg-error \".E0425.\" \"\" { target *-*-* } .-1 }
+// { dg-error \".E0599.\" \"\" { target *-*-* } .-2 }
+}
+";
+        let (new_code, report) = merge_stderr(code, STDERR, &transform::Options::default());
+        assert_eq!(new_code, expected);
+        assert_eq!(report.merged, 3);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_merge_skips_conflicts_and_implausible_lines() {
+        let code = "\
+fn main() {
+    let x: i32 = \"hello\"; // { dg-error \"\" \"\" { target *-*-* } }
+
+}
+";
+        let (new_code, report) = merge_stderr(code, STDERR, &transform::Options::default());
+        assert_eq!(new_code, code);
+        assert_eq!(report.merged, 0);
+        assert_eq!(
+            report.warnings,
+            [
+                "skipping E0308: line 2 already has a directive",
+                "skipping E0425: line 3 is blank",
+                "skipping E0599: line 3 is blank",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline_directives_only_conflict_with_their_line() {
+        let code = "\
+fn main() {
+    let x: i32 = \"hello\";
+    let v = undefined; // { dg-warning \"unused\" }
+}
+";
+        let (new_code, report) = merge_stderr(code, STDERR, &transform::Options::default());
+        assert_eq!(
+            new_code,
+            "\
+fn main() {
+    let x: i32 = \"hello\";
+// { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }
+    let v = undefined; // { dg-warning \"unused\" }
+}
+"
+        );
+        assert_eq!(report.merged, 1);
+        assert_eq!(
+            report.warnings,
+            [
+                "skipping E0425: line 3 already has a directive",
+                "skipping E0599: line 3 already has a directive",
+            ]
+        );
+
+        // A directive alone below the line, expecting it, is a conflict
+        let code = "\
+fn main() {
+    let x: i32 = \"hello\";
+// { dg-error \"mismatched\" \"\" { target *-*-* } .-1 }
+}
+";
+        let (new_code, report) = merge_stderr(code, STDERR, &transform::Options::default());
+        assert_eq!(new_code, code);
+        assert_eq!(
+            report.warnings[0],
+            "skipping E0308: line 2 already has a directive"
+        );
+    }
+
+    #[test]
+    fn test_merge_skips_missing_lines() {
+        let (new_code, report) =
+            merge_stderr("fn main() {}\n", STDERR, &transform::Options::default());
+        assert_eq!(new_code, "fn main() {}\n");
+        assert_eq!(
+            report.warnings[0],
+            "skipping E0308: line 2 does not exist in the target file"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! This module contains the placement of converted files under an output directory, at the
+//! same place relative to it as the inputs are relative to a prefix.
+//!
+//! Paths are made absolute and normalized without touching the file system, so that relative
+//! and absolute inputs can be mixed and no `..` climbs out of the output directory.
+
+use {
+    crate::modules::normalize,
+    std::{
+        fmt, io,
+        path::{self, Path, PathBuf},
+    },
+};
+
+/// Places the inputs under `prefix` at the same place under `output_dir`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mirror {
+    output_dir: PathBuf,
+    prefix: PathBuf,
+}
+
+/// An input which is not under the prefix, so it has no place under the output directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutsidePrefix {
+    pub input: PathBuf,
+    pub prefix: PathBuf,
+}
+
+impl fmt::Display for OutsidePrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` is not under the prefix `{}`",
+            self.input.display(),
+            self.prefix.display()
+        )
+    }
+}
+
+impl std::error::Error for OutsidePrefix {}
+
+impl Mirror {
+    pub fn new(output_dir: &Path, prefix: &Path) -> io::Result<Self> {
+        Ok(Mirror {
+            output_dir: output_dir.to_owned(),
+            prefix: absolute(prefix)?,
+        })
+    }
+
+    /// The prefix the inputs are relative to, absolute
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// Where the converted `input` goes
+    pub fn output_path(&self, input: &Path) -> Result<PathBuf, OutsidePrefix> {
+        let outside = || OutsidePrefix {
+            input: input.to_owned(),
+            prefix: self.prefix.clone(),
+        };
+        let absolute = absolute(input).map_err(|_| outside())?;
+        match absolute.strip_prefix(&self.prefix) {
+            Ok(relative) if !relative.as_os_str().is_empty() => Ok(self.output_dir.join(relative)),
+            _ => Err(outside()),
+        }
+    }
+}
+
+/// The deepest directory holding every one of `files`, absolute
+pub fn common_parent(files: &[PathBuf]) -> io::Result<PathBuf> {
+    let mut common: Option<PathBuf> = None;
+    for file in files {
+        let file = absolute(file)?;
+        let parent = file.parent().unwrap_or(&file);
+        common = Some(match common {
+            None => parent.to_owned(),
+            Some(common) => common
+                .components()
+                .zip(parent.components())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect(),
+        });
+    }
+    Ok(common.unwrap_or_default())
+}
+
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    Ok(normalize(&path::absolute(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_path() {
+        let mirror = Mirror::new(Path::new("out"), Path::new("/src/tests/ui")).unwrap();
+        assert_eq!(
+            mirror.output_path(Path::new("/src/tests/ui/borrowck/foo.rs")),
+            Ok(PathBuf::from("out/borrowck/foo.rs"))
+        );
+        assert_eq!(
+            mirror.output_path(Path::new("/src/tests/ui/./lint/../typeck/bar.rs")),
+            Ok(PathBuf::from("out/typeck/bar.rs"))
+        );
+
+        // Relative inputs are relative to the working directory
+        let cwd = std::env::current_dir().unwrap();
+        let mirror = Mirror::new(Path::new("out"), &cwd.join("tests")).unwrap();
+        assert_eq!(
+            mirror.output_path(Path::new("tests/ui/foo.rs")),
+            Ok(PathBuf::from("out/ui/foo.rs"))
+        );
+    }
+
+    #[test]
+    fn test_outside_prefix() {
+        let mirror = Mirror::new(Path::new("out"), Path::new("/src/tests/ui")).unwrap();
+        for input in [
+            "/src/tests/uix/foo.rs",
+            "/src/tests/ui/../foo.rs",
+            "/src/tests/ui",
+        ] {
+            let err = mirror.output_path(Path::new(input)).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("`{input}` is not under the prefix `/src/tests/ui`")
+            );
+        }
+    }
+
+    #[test]
+    fn test_common_parent() {
+        let files = ["/src/ui/a/x.rs", "/src/ui/a/b/y.rs", "/src/ui/c/z.rs"].map(PathBuf::from);
+        assert_eq!(common_parent(&files).unwrap(), Path::new("/src/ui"));
+        assert_eq!(common_parent(&files[..1]).unwrap(), Path::new("/src/ui/a"));
+        assert_eq!(
+            common_parent(&["/a.rs", "/b/c.rs"].map(PathBuf::from)).unwrap(),
+            Path::new("/")
+        );
+    }
+}
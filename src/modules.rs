@@ -0,0 +1,477 @@
+//! This module contains the detection of the out-of-line modules of a test: the files
+//! loaded by its `mod name;` declarations, which have to be copied along with it.
+//!
+//! The declarations are found by a small lexer skipping comments, strings and character
+//! literals, so that a `mod name;` in a comment or a string is not mistaken for one. The
+//! files are then resolved the way rustc does: next to a crate root or a `mod.rs` file, in
+//! the directory named after any other module file, or wherever `#[path]` says.
+
+use {
+    crate::input,
+    std::{
+        collections::BTreeSet,
+        path::{Component, Path, PathBuf},
+    },
+};
+
+/// A `mod name;` declaration
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ModuleDecl {
+    /// The 1-based line of the declaration
+    pub line: usize,
+    pub name: String,
+    /// The value of its `#[path]` attribute, if any
+    pub path: Option<String>,
+    /// The inline modules it is declared in, outermost first
+    pub parents: Vec<String>,
+}
+
+/// The module files of a test
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModuleFiles {
+    /// The files found in declaration order, each followed by the modules it declares
+    pub files: Vec<PathBuf>,
+    /// The declarations without a file, with the reason
+    pub unresolved: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Punct(char),
+}
+
+/// Every out-of-line module declaration of `code`
+pub fn declarations(code: &str) -> Vec<ModuleDecl> {
+    let tokens = tokenize(code);
+    let mut decls = Vec::new();
+    // One entry per open brace, with the name of the module it opens if it does
+    let mut braces: Vec<Option<&str>> = Vec::new();
+    let mut index = 0;
+    while index < tokens.len() {
+        match &tokens[index] {
+            (line, Token::Ident(keyword)) if keyword == "mod" => {
+                if let Some((_, Token::Ident(name))) = tokens.get(index + 1) {
+                    match tokens.get(index + 2) {
+                        Some((_, Token::Punct(';'))) => decls.push(ModuleDecl {
+                            line: *line,
+                            name: name.clone(),
+                            path: path_attribute(&tokens[..index]),
+                            parents: braces
+                                .iter()
+                                .flatten()
+                                .map(|&name| name.to_owned())
+                                .collect(),
+                        }),
+                        Some((_, Token::Punct('{'))) => {
+                            braces.push(Some(name));
+                            index += 2;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            (_, Token::Punct('{')) => braces.push(None),
+            (_, Token::Punct('}')) => {
+                braces.pop();
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    decls
+}
+
+/// The value of the `#[path = "..."]` attribute ending `tokens`, before the visibility of
+/// the item if any
+fn path_attribute(tokens: &[(usize, Token)]) -> Option<String> {
+    let is_visibility = |token: &Token| match token {
+        Token::Ident(word) => matches!(word.as_str(), "pub" | "crate" | "super" | "self" | "in"),
+        Token::Punct(c) => matches!(c, '(' | ')' | ':'),
+        Token::Str(_) => false,
+    };
+    let mut end = tokens.len();
+    while end > 0 && is_visibility(&tokens[end - 1].1) {
+        end -= 1;
+    }
+    let mut path = None;
+    // Every outer attribute of the item, the last one first
+    while end > 0 && tokens[end - 1].1 == Token::Punct(']') {
+        let mut depth = 0;
+        let mut start = end;
+        while start > 0 {
+            start -= 1;
+            match tokens[start].1 {
+                Token::Punct(']') => depth += 1,
+                Token::Punct('[') => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+        }
+        if depth != 0 || start == 0 || tokens[start - 1].1 != Token::Punct('#') {
+            break;
+        }
+        if let [(_, Token::Ident(name)), (_, Token::Punct('=')), (_, Token::Str(value))] =
+            &tokens[start + 1..end - 1]
+        {
+            if name == "path" && path.is_none() {
+                path = Some(value.clone());
+            }
+        }
+        end = start - 1;
+    }
+    path
+}
+
+/// The identifiers, string literals and punctuation of `code`, with their line
+fn tokenize(code: &str) -> Vec<(usize, Token)> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            // Block comments nest
+            let mut depth = 0;
+            while i < chars.len() {
+                match (chars[i], chars.get(i + 1)) {
+                    ('/', Some('*')) => {
+                        depth += 1;
+                        i += 2;
+                    }
+                    ('*', Some('/')) => {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    (c, _) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        i += 1;
+                    }
+                }
+            }
+        } else if c == '"' {
+            let start = line;
+            let value = string(&chars, &mut i, &mut line);
+            tokens.push((start, Token::Str(value)));
+        } else if c == '\'' {
+            // A character literal, or a lifetime whose name is read as an identifier
+            if next == Some('\\') {
+                i += 3;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+                i += 1;
+            } else if chars.get(i + 2) == Some(&'\'') {
+                i += 3;
+            } else {
+                i += 1;
+            }
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            match (word.as_str(), chars.get(i)) {
+                // Byte and C strings
+                ("b" | "c", Some('"')) => {
+                    let start = line;
+                    let value = string(&chars, &mut i, &mut line);
+                    tokens.push((start, Token::Str(value)));
+                }
+                ("b", Some('\'')) => {}
+                ("r" | "br" | "cr", Some('"' | '#')) if raw_string_start(&chars, i) => {
+                    let start = line;
+                    let value = raw_string(&chars, &mut i, &mut line);
+                    tokens.push((start, Token::Str(value)));
+                }
+                _ => tokens.push((line, Token::Ident(word))),
+            }
+        } else {
+            tokens.push((line, Token::Punct(c)));
+            i += 1;
+        }
+    }
+    tokens
+}
+
+/// Reads the string literal starting with the quote at `i`, escapes resolved
+fn string(chars: &[char], i: &mut usize, line: &mut usize) -> String {
+    let mut value = String::new();
+    *i += 1;
+    while let Some(&c) = chars.get(*i) {
+        *i += 1;
+        match c {
+            '"' => break,
+            '\\' => {
+                if let Some(&escaped) = chars.get(*i) {
+                    *i += 1;
+                    match escaped {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        '\n' => *line += 1,
+                        escaped => value.push(escaped),
+                    }
+                }
+            }
+            c => {
+                if c == '\n' {
+                    *line += 1;
+                }
+                value.push(c);
+            }
+        }
+    }
+    value
+}
+
+/// Whether the hashes at `i` are followed by the quote of a raw string
+fn raw_string_start(chars: &[char], i: usize) -> bool {
+    chars[i..].iter().find(|&&c| c != '#') == Some(&'"')
+}
+
+/// Reads the raw string literal whose hashes start at `i`
+fn raw_string(chars: &[char], i: &mut usize, line: &mut usize) -> String {
+    let mut hashes = 0;
+    while chars.get(*i) == Some(&'#') {
+        hashes += 1;
+        *i += 1;
+    }
+    *i += 1;
+    let mut value = String::new();
+    while let Some(&c) = chars.get(*i) {
+        *i += 1;
+        if c == '"'
+            && chars[*i..]
+                .iter()
+                .take(hashes)
+                .filter(|&&c| c == '#')
+                .count()
+                == hashes
+        {
+            *i += hashes;
+            break;
+        }
+        if c == '\n' {
+            *line += 1;
+        }
+        value.push(c);
+    }
+    value
+}
+
+/// The file loaded by `decl`, declared in `file`. `mod_rs` tells whether `file` is a crate
+/// root, a `mod.rs` file or loaded through `#[path]`, whose modules are next to it rather
+/// than in the directory named after it.
+pub fn resolve(file: &Path, mod_rs: bool, decl: &ModuleDecl) -> Result<PathBuf, String> {
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let mut base = dir.to_path_buf();
+    if !mod_rs {
+        base.push(file.file_stem().unwrap_or_default());
+    }
+    base.extend(&decl.parents);
+
+    let candidates = match &decl.path {
+        // Outside of inline modules, the path is relative to the file itself
+        Some(path) if decl.parents.is_empty() => vec![dir.join(path)],
+        Some(path) => vec![base.join(path)],
+        None => vec![
+            base.join(format!("{}.rs", decl.name)),
+            base.join(&decl.name).join("mod.rs"),
+        ],
+    };
+    let candidates: Vec<PathBuf> = candidates.iter().map(|path| normalize(path)).collect();
+    if let Some(found) = candidates.iter().find(|path| path.is_file()) {
+        return Ok(found.clone());
+    }
+    let names: Vec<String> = candidates
+        .iter()
+        .map(|path| format!("`{}`", path.strip_prefix(dir).unwrap_or(path).display()))
+        .collect();
+    Err(format!(
+        "`mod {};` has no file at {}",
+        decl.name,
+        names.join(" or ")
+    ))
+}
+
+/// Every module file of the crate root `test`, modules of modules included
+pub fn module_files(test: &Path) -> ModuleFiles {
+    let mut found = ModuleFiles::default();
+    let dir = test.parent().unwrap_or(Path::new(""));
+    let mut seen = BTreeSet::from([normalize(test)]);
+    let mut pending = vec![(test.to_path_buf(), true)];
+    // Depth first, so that each file comes before the modules it declares
+    while let Some((file, mod_rs)) = pending.pop() {
+        if file != test {
+            found.files.push(file.clone());
+        }
+        let name = file
+            .strip_prefix(dir)
+            .unwrap_or(&file)
+            .display()
+            .to_string();
+        let code = match input::read(&file) {
+            Ok(code) => code,
+            Err(err) => {
+                found
+                    .unresolved
+                    .push(format!("{name}: could not read it: {err}"));
+                continue;
+            }
+        };
+        let mut modules = Vec::new();
+        for decl in declarations(&code) {
+            match resolve(&file, mod_rs, &decl) {
+                Ok(module) => {
+                    if seen.insert(module.clone()) {
+                        let mod_rs = decl.path.is_some() || module.ends_with("mod.rs");
+                        modules.push((module, mod_rs));
+                    }
+                }
+                Err(reason) => found
+                    .unresolved
+                    .push(format!("{name}:{}: {reason}", decl.line)),
+            }
+        }
+        pending.extend(modules.into_iter().rev());
+    }
+    found
+}
+
+/// `path` with its `.` and `..` components resolved without touching the file system
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::fs};
+
+    fn names(code: &str) -> Vec<(String, Option<String>, Vec<String>)> {
+        declarations(code)
+            .into_iter()
+            .map(|decl| (decl.name, decl.path, decl.parents))
+            .collect()
+    }
+
+    #[test]
+    fn test_declarations() {
+        let code = r##"
+//@ run-pass
+mod helper;
+#[path = "support/data.rs"]
+pub(crate) mod data;
+#[cfg(unix)]
+#[path = r#"raw.rs"#]
+mod raw;
+mod inline {
+    mod nested;
+    fn f() {}
+}
+// mod commented;
+/* mod /* nested */ block; */
+const S: &str = "mod in_string;";
+const R: &str = r#"mod in_raw_string;"#;
+const C: char = '"';
+fn g<'a>(x: &'a str) -> &'a str { x }
+mod after_lifetimes;
+"##;
+        assert_eq!(
+            names(code),
+            [
+                ("helper".to_owned(), None, vec![]),
+                (
+                    "data".to_owned(),
+                    Some("support/data.rs".to_owned()),
+                    vec![]
+                ),
+                ("raw".to_owned(), Some("raw.rs".to_owned()), vec![]),
+                ("nested".to_owned(), None, vec!["inline".to_owned()]),
+                ("after_lifetimes".to_owned(), None, vec![]),
+            ]
+        );
+        assert_eq!(declarations(code)[0].line, 3);
+        assert_eq!(declarations(code)[4].line, 19);
+    }
+
+    #[test]
+    fn test_module_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in [
+            (
+                "main.rs",
+                "mod helper;\nmod dir_module;\n#[path = \"../outside.rs\"]\nmod outside;\nmod missing;\n",
+            ),
+            ("helper.rs", "mod nested;\n"),
+            ("helper/nested.rs", "pub fn f() {}\n"),
+            ("dir_module/mod.rs", "mod child;\n"),
+            ("dir_module/child.rs", "mod helper_again {}\n"),
+        ] {
+            let path = dir.path().join("ui").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        fs::write(dir.path().join("outside.rs"), "").unwrap();
+
+        let ui = dir.path().join("ui");
+        let found = module_files(&ui.join("main.rs"));
+        assert_eq!(
+            found.files,
+            [
+                ui.join("helper.rs"),
+                ui.join("helper/nested.rs"),
+                ui.join("dir_module/mod.rs"),
+                ui.join("dir_module/child.rs"),
+                dir.path().join("outside.rs"),
+            ]
+        );
+        assert_eq!(
+            found.unresolved,
+            ["main.rs:5: `mod missing;` has no file at `missing.rs` or `missing/mod.rs`"]
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize(Path::new("a/./b/../c.rs")),
+            PathBuf::from("a/c.rs")
+        );
+        assert_eq!(normalize(Path::new("../a.rs")), PathBuf::from("../a.rs"));
+    }
+}
@@ -0,0 +1,126 @@
+//! This module contains the policy for the `needs-*` headers, which gate a test on a
+//! capability of the toolchain or the target, like `//@ needs-asm-support`.
+//!
+//! The header of a capability gccrs has is dropped, one it lacks skips the test with a
+//! `dg-skip-if`, and the headers the policy does not know are kept as comments with a
+//! warning. The built-in table can be overridden by a TOML file, see [`NeedsPolicy::parse`].
+
+use {
+    serde::Deserialize,
+    std::{collections::BTreeMap, fs, path::Path},
+};
+
+/// What the conversion does with a `needs-*` header
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Needs {
+    /// gccrs has the capability, the header is removed
+    Drop,
+    /// gccrs lacks the capability, the converted test is skipped
+    Skip,
+}
+
+/// The policy of the `needs-*` headers gccrs is known to support or not
+const BUILTIN: &[(&str, Needs)] = &[
+    ("needs-dynamic-linking", Needs::Drop),
+    ("needs-run-enabled", Needs::Drop),
+    ("needs-symlink", Needs::Drop),
+    ("needs-threads", Needs::Drop),
+    ("needs-unwind", Needs::Drop),
+    ("needs-asm-support", Needs::Skip),
+    ("needs-deterministic-layouts", Needs::Skip),
+    ("needs-llvm-components", Needs::Skip),
+    ("needs-profiler-runtime", Needs::Skip),
+    ("needs-rust-lld", Needs::Skip),
+    ("needs-sanitizer-address", Needs::Skip),
+    ("needs-sanitizer-cfi", Needs::Skip),
+    ("needs-sanitizer-hwaddress", Needs::Skip),
+    ("needs-sanitizer-kcfi", Needs::Skip),
+    ("needs-sanitizer-leak", Needs::Skip),
+    ("needs-sanitizer-memory", Needs::Skip),
+    ("needs-sanitizer-support", Needs::Skip),
+    ("needs-sanitizer-thread", Needs::Skip),
+    ("needs-wasmtime", Needs::Skip),
+    ("needs-xray", Needs::Skip),
+];
+
+/// The built-in policy of the header `name`, without any override
+pub fn builtin(name: &str) -> Option<Needs> {
+    BUILTIN
+        .iter()
+        .find_map(|&(header, needs)| (header == name).then_some(needs))
+}
+
+/// What is done with each `needs-*` header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NeedsPolicy {
+    headers: BTreeMap<String, Needs>,
+}
+
+impl Default for NeedsPolicy {
+    fn default() -> Self {
+        NeedsPolicy {
+            headers: BUILTIN
+                .iter()
+                .map(|&(header, needs)| (header.to_owned(), needs))
+                .collect(),
+        }
+    }
+}
+
+impl NeedsPolicy {
+    /// The policy of the header `name`, like `needs-unwind`
+    pub fn get(&self, name: &str) -> Option<Needs> {
+        self.headers.get(name).copied()
+    }
+
+    /// The built-in policy overridden by the `toml` entries, one per header:
+    ///
+    /// ```toml
+    /// needs-asm-support = "drop"
+    /// needs-threads = "skip"
+    /// ```
+    pub fn parse(toml: &str) -> Result<NeedsPolicy, String> {
+        let entries: BTreeMap<String, Needs> =
+            toml::from_str(toml).map_err(|err| err.message().to_owned())?;
+        let mut policy = NeedsPolicy::default();
+        for (header, needs) in entries {
+            if !header.starts_with("needs-") {
+                return Err(format!("`{header}` is not a `needs-*` header"));
+            }
+            policy.headers.insert(header, needs);
+        }
+        Ok(policy)
+    }
+
+    /// [`NeedsPolicy::parse`] of the file at `path`, as the parser of `--needs-policy`
+    pub fn load(path: &str) -> Result<NeedsPolicy, String> {
+        let toml = fs::read_to_string(Path::new(path))
+            .map_err(|err| format!("could not read `{path}`: {err}"))?;
+        NeedsPolicy::parse(&toml).map_err(|err| format!("invalid policy in `{path}`: {err}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides() {
+        let policy =
+            NeedsPolicy::parse("needs-asm-support = \"drop\"\n\"needs-gccrs-plugin\" = \"skip\"\n")
+                .unwrap();
+        assert_eq!(policy.get("needs-asm-support"), Some(Needs::Drop));
+        assert_eq!(policy.get("needs-gccrs-plugin"), Some(Needs::Skip));
+        // The others keep their built-in policy
+        assert_eq!(policy.get("needs-unwind"), Some(Needs::Drop));
+        assert_eq!(policy.get("needs-xray"), Some(Needs::Skip));
+        assert_eq!(policy.get("needs-git-hash"), None);
+
+        assert!(NeedsPolicy::parse("needs-unwind = \"maybe\"\n").is_err());
+        assert_eq!(
+            NeedsPolicy::parse("only-x86_64 = \"skip\"\n"),
+            Err("`only-x86_64` is not a `needs-*` header".to_owned())
+        );
+    }
+}
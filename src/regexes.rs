@@ -0,0 +1,195 @@
+//! This module contains every regex used by the crate.
+//!
+//! The patterns are compiled once and shared by all the modules. [`validate`] compiles
+//! them all at startup, so that a broken pattern fails fast with its name instead of
+//! panicking deep inside the parsing.
+
+use {
+    anyhow::{Context, Result},
+    regex::Regex,
+    std::sync::OnceLock,
+};
+
+/// Matches annotation sigils like `//~`, `//~|`, `//~^^^` or `//[rev1,rev2]~^`
+pub const SIGIL: &str = r"//(?:\[(?P<revs>[\w\-,]+)])?~(?P<adjust>\||\?|v+|\^*)";
+
+/// Matches what looks like a sigil [`SIGIL`] rejects, like `// ~ERROR` or `//[rev 1]~`
+pub const NEAR_SIGIL: &str = r"//\s+~|//\[[^\]]*\]\s*~";
+
+/// Matches the header line of a rustc diagnostic in a `stderr` file, with or without error
+/// code, which starts its block
+pub const STDERR_HEADER: &str =
+    r"(?m)^(?P<level>error|warning|note|help)(?:\[(?P<code>E\d{4})\])?: (?P<message>.+?)\r?$";
+
+/// Matches the location of the primary span in the block of a diagnostic, like
+/// `--> $DIR/a.rs:2:18`. The path is as short as possible, so that the `:` of a Windows path
+/// stay in it, and the `LL:COL` placeholders of a normalized file are accepted.
+pub const STDERR_LOCATION: &str =
+    r"(?m)^\s*-->\s*(?P<path>.+?):(?P<line_number>\d+|LL):(?P<column_number>\d+|CC|COL)?\s*$";
+
+/// Matches the name of the lint of a diagnostic, either in a note like
+/// ``= note: `#[warn(unused_variables)]` on by default`` or in the attribute quoted below
+/// `note: the lint level is defined here`
+pub const STDERR_LINT: &str = r"= note: `#\[\w+\((?P<lint>[\w:]+)\)\]`|the lint level is defined here\n\s+-->.*\n\s*\|\n[^|\n]*\| *#!?\[\w+\((?P<attribute>[\w:]+)";
+
+/// Matches the `[unused_variables]` naming the lint at the end of a warning message, in an
+/// annotation or in the header of a `stderr` entry
+pub const LINT_SUFFIX: &str = r"\s+\[(?P<lint>[a-z][a-z0-9_]*(?:::[a-z][a-z0-9_]*)*)\]$";
+
+/// Matches a whole rustc error code like `E0308`
+pub const ERROR_CODE: &str = r"^E\d{4}$";
+
+/// A named pattern, compiled on first use
+struct Pattern {
+    name: &'static str,
+    source: &'static str,
+    regex: OnceLock<Regex>,
+}
+
+impl Pattern {
+    const fn new(name: &'static str, source: &'static str) -> Self {
+        Pattern {
+            name,
+            source,
+            regex: OnceLock::new(),
+        }
+    }
+
+    fn compile(&self) -> Result<&Regex> {
+        if let Some(regex) = self.regex.get() {
+            return Ok(regex);
+        }
+        let regex = Regex::new(self.source)
+            .with_context(|| format!("built-in regex `{}` is invalid", self.name))?;
+        Ok(self.regex.get_or_init(|| regex))
+    }
+
+    fn get(&self) -> &Regex {
+        // Only reachable with a broken pattern, which `validate` reports at startup
+        self.compile().unwrap_or_else(|err| panic!("{err:#}"))
+    }
+}
+
+static SIGIL_PATTERN: Pattern = Pattern::new("sigil", SIGIL);
+static NEAR_SIGIL_PATTERN: Pattern = Pattern::new("near sigil", NEAR_SIGIL);
+static STDERR_HEADER_PATTERN: Pattern = Pattern::new("stderr header", STDERR_HEADER);
+static STDERR_LOCATION_PATTERN: Pattern = Pattern::new("stderr location", STDERR_LOCATION);
+static STDERR_LINT_PATTERN: Pattern = Pattern::new("stderr lint", STDERR_LINT);
+static LINT_SUFFIX_PATTERN: Pattern = Pattern::new("lint suffix", LINT_SUFFIX);
+static ERROR_CODE_PATTERN: Pattern = Pattern::new("error code", ERROR_CODE);
+
+/// Every pattern of the registry
+static REGISTRY: &[&Pattern] = &[
+    &SIGIL_PATTERN,
+    &NEAR_SIGIL_PATTERN,
+    &STDERR_HEADER_PATTERN,
+    &STDERR_LOCATION_PATTERN,
+    &STDERR_LINT_PATTERN,
+    &LINT_SUFFIX_PATTERN,
+    &ERROR_CODE_PATTERN,
+];
+
+/// Compiles every pattern of the registry, reporting the first invalid one by name
+pub fn validate() -> Result<()> {
+    for pattern in REGISTRY {
+        pattern.compile()?;
+    }
+    Ok(())
+}
+
+/// See [`SIGIL`]
+pub fn sigil() -> &'static Regex {
+    SIGIL_PATTERN.get()
+}
+
+/// See [`NEAR_SIGIL`]
+pub fn near_sigil() -> &'static Regex {
+    NEAR_SIGIL_PATTERN.get()
+}
+
+/// See [`STDERR_HEADER`]
+pub fn stderr_header() -> &'static Regex {
+    STDERR_HEADER_PATTERN.get()
+}
+
+/// See [`STDERR_LOCATION`]
+pub fn stderr_location() -> &'static Regex {
+    STDERR_LOCATION_PATTERN.get()
+}
+
+/// See [`STDERR_LINT`]
+pub fn stderr_lint() -> &'static Regex {
+    STDERR_LINT_PATTERN.get()
+}
+
+/// See [`LINT_SUFFIX`]
+pub fn lint_suffix() -> &'static Regex {
+    LINT_SUFFIX_PATTERN.get()
+}
+
+/// See [`ERROR_CODE`]
+pub fn error_code() -> &'static Regex {
+    ERROR_CODE_PATTERN.get()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_patterns_compile() {
+        assert!(validate().is_ok());
+        for pattern in REGISTRY {
+            assert!(
+                Regex::new(pattern.source).is_ok(),
+                "`{}` is invalid",
+                pattern.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_pattern_is_reported_by_name() {
+        let pattern = Pattern::new("broken", r"(unclosed");
+        let err = pattern.compile().unwrap_err();
+        assert!(err.to_string().contains("`broken`"));
+    }
+
+    #[test]
+    fn test_sigil_has_no_catastrophic_backtracking() {
+        // Near misses for every part of the pattern, repeated to make any
+        // super-linear behavior obvious
+        let inputs = [
+            "/".repeat(1 << 20),
+            "//[".repeat(1 << 18),
+            format!("//[{}", "a-,".repeat(1 << 18)),
+            format!("//[{}]", "a".repeat(1 << 20)),
+            format!("//~{}", "^".repeat(1 << 20)),
+            "//~|".repeat(1 << 18),
+        ];
+        let start = std::time::Instant::now();
+        for input in &inputs {
+            sigil().captures(input);
+            sigil().find_iter(input).count();
+        }
+        // Linear matching takes milliseconds even in debug builds,
+        // the bound only has to catch exponential blowups
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_patterns_are_shared() {
+        // The modules matching annotations and `stderr` files compile no pattern of their
+        // own, their tests aside
+        for (name, source) in [
+            ("errors.rs", include_str!("errors.rs")),
+            ("transform.rs", include_str!("transform.rs")),
+            ("explain.rs", include_str!("explain.rs")),
+            ("header.rs", include_str!("header.rs")),
+        ] {
+            let code = source.split("\n#[cfg(test)]\nmod tests").next().unwrap();
+            assert!(!code.contains("Regex::new"), "{name}");
+            assert!(!code.contains("(?P<adjust>"), "{name}");
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! This module contains the reverse conversion, turning `DejaGnu` directives back into
+//! rustc annotations.
+//!
+//! The directives are read with the parser of [`dejagnu`]. A directive alone on its line
+//! becomes a `//~^` with one caret per line of offset, or a `//~|` when it targets the same
+//! line as the annotation right above it. A directive after some code becomes a `//~`.
+//! Messages are only as complete as the patterns: the default `--msg-style none` leaves
+//! nothing but the error code, which compiletest matches like a message, as in
+//! `//~ ERROR E0308`. An annotation has either a message or a code, the code of a pattern
+//! with both is left out with a warning. A directive on line 0 becomes a `//~?`, in place of
+//! the first lines the whole-file directives are hoisted to.
+//!
+//! `dg-additional-options` become an `edition` header, or a `compile-flags` one when each
+//! option has a rustc flag.
+
+use crate::{
+    dejagnu::{self, LineSpec},
+    errors, header, regexes,
+};
+
+/// A test turned back into a rustc test
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reversed {
+    pub code: String,
+    /// The directives kept as they are, with the reason
+    pub warnings: Vec<String>,
+}
+
+/// Turns the directives of `code` back into rustc annotations and headers
+pub fn reverse(code: &str) -> Reversed {
+    let mut reversed = Reversed::default();
+    // The line and the target of the last annotation, which a `//~|` can follow
+    let mut previous: Option<(usize, usize)> = None;
+    for (index, line) in code.lines().enumerate() {
+        let line_num = index + 1;
+        let rebuilt = if dejagnu::has_directive(line) {
+            match reverse_line(line, line_num, previous) {
+                Ok(Rebuilt::Annotation {
+                    text,
+                    target,
+                    dropped_code,
+                }) => {
+                    previous = Some((line_num, target));
+                    if let Some(code) = dropped_code {
+                        reversed.warnings.push(format!(
+                            "line {line_num}: the error code {code} is left out, compiletest matches the message of an annotation or its code, not both"
+                        ));
+                    }
+                    Some(text)
+                }
+                Ok(Rebuilt::Header(text)) => Some(text),
+                Err(reason) => {
+                    reversed
+                        .warnings
+                        .push(format!("line {line_num}: {reason}, kept as is"));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        reversed.code.push_str(rebuilt.as_deref().unwrap_or(line));
+        reversed.code.push('\n');
+    }
+    reversed
+}
+
+/// What a directive line turns into
+enum Rebuilt {
+    /// An annotation, the 1-based line it targets, 0 for the whole file, and the error code of its pattern left
+    /// out for its message
+    Annotation {
+        text: String,
+        target: usize,
+        dropped_code: Option<String>,
+    },
+    Header(String),
+}
+
+fn reverse_line(
+    line: &str,
+    line_num: usize,
+    previous: Option<(usize, usize)>,
+) -> Result<Rebuilt, String> {
+    let directive = dejagnu::validate(line).map_err(|err| err.to_string())?;
+    let code = dejagnu::code_before_directive(line).unwrap_or("");
+
+    if directive.name == "dg-additional-options" {
+        let options = directive.args.first().map_or("", |word| word.text());
+        let editions: Vec<&str> = options
+            .split_whitespace()
+            .map(|option| option.strip_prefix("-frust-edition="))
+            .collect::<Option<_>>()
+            .unwrap_or_default();
+        return match (editions.as_slice(), header::rustc_flags(&unescape(options))) {
+            ([edition], _) if code.is_empty() => {
+                Ok(Rebuilt::Header(format!("//@ edition: {edition}")))
+            }
+            (_, Some(flags)) if code.is_empty() => {
+                Ok(Rebuilt::Header(format!("//@ compile-flags: {flags}")))
+            }
+            _ => Err(format!("options `{options}` have no rustc header")),
+        };
+    }
+
+    let kind = errors::directive_kind(&directive.name)
+        .ok_or_else(|| format!("`{}` has no rustc annotation", directive.name))?;
+    let offset = match directive.line {
+        None => 0,
+        Some(LineSpec::Relative(offset)) => offset,
+        Some(LineSpec::Absolute(0)) if code.is_empty() => {
+            let (message, dropped_code) = message(pattern(&directive, kind));
+            let mut text = format!("//~? {}", kind.keyword());
+            if !message.is_empty() {
+                text.push(' ');
+                text.push_str(&message);
+            }
+            return Ok(Rebuilt::Annotation {
+                text,
+                target: 0,
+                dropped_code: dropped_code.map(str::to_owned),
+            });
+        }
+        Some(LineSpec::Absolute(_)) => {
+            return Err("annotations cannot target an absolute line".to_owned())
+        }
+    };
+    let lines = offset.unsigned_abs() as usize;
+    let (target, sigil) = if offset > 0 {
+        // A `//~v` annotation, on a line of its own
+        if !code.is_empty() {
+            return Err("annotations after code cannot target a line below them".to_owned());
+        }
+        (line_num + lines, "v".repeat(lines))
+    } else {
+        let target = line_num
+            .checked_sub(lines)
+            .filter(|&target| target > 0)
+            .ok_or("the directive targets a line above the first one")?;
+        (target, "^".repeat(lines))
+    };
+    let sigil = if code.is_empty() && previous == Some((line_num - 1, target)) {
+        "|".to_owned()
+    } else {
+        sigil
+    };
+    let mut text = if code.is_empty() {
+        format!("//~{sigil} {}", kind.keyword())
+    } else {
+        format!("{code} //~{sigil} {}", kind.keyword())
+    };
+    let (message, dropped_code) = message(pattern(&directive, kind));
+    if !message.is_empty() {
+        text.push(' ');
+        text.push_str(&message);
+    }
+    Ok(Rebuilt::Annotation {
+        text,
+        target,
+        dropped_code: dropped_code.map(str::to_owned),
+    })
+}
+
+/// The pattern of `directive`, without the `help: ` the help messages were rendered with
+fn pattern(directive: &dejagnu::ParsedDirective, kind: errors::RustcErrorKind) -> &str {
+    let pattern = directive.args.first().map_or("", |word| word.text());
+    pattern
+        .strip_prefix(errors::message_prefix(Some(kind)))
+        .unwrap_or(pattern)
+}
+
+/// The annotation message matched by `pattern`, or its error code without a message. The
+/// code left out for the message is returned along with it.
+fn message(pattern: &str) -> (String, Option<&str>) {
+    let (pattern, code) = match split_error_code(pattern) {
+        Some((pattern, code)) => (pattern, Some(code)),
+        None => (pattern, None),
+    };
+    // The pattern is escaped for Tcl, then for the regex engine
+    let message = unescape(&unescape(pattern));
+    match code {
+        Some(code) if message.is_empty() => (code.to_owned(), None),
+        code => (message, code),
+    }
+}
+
+/// Splits the `.E0308.` at the end of a pattern, and the `.*` before it if any
+fn split_error_code(pattern: &str) -> Option<(&str, &str)> {
+    let rest = pattern.strip_suffix('.')?;
+    let dot = rest.rfind('.')?;
+    let code = &rest[dot + 1..];
+    if !regexes::error_code().is_match(code) {
+        return None;
+    }
+    let message = &rest[..dot];
+    Some((message.strip_suffix(".*").unwrap_or(message), code))
+}
+
+/// Removes one level of backslash escapes
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            errors::MsgStyle,
+            transform::{self, Options},
+        },
+        std::fs,
+    };
+
+    /// The line without any whitespace
+    fn squeeze(line: &str) -> String {
+        line.split_whitespace().collect()
+    }
+
+    #[test]
+    fn test_round_trip_over_the_fixtures() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let options = Options {
+            msg_style: MsgStyle::Escaped,
+            ..Options::default()
+        };
+        let mut paths: Vec<_> = ["", "whole_file"]
+            .into_iter()
+            .flat_map(|dir| fs::read_dir(format!("{fixtures}/{dir}")).unwrap())
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty());
+        for path in paths {
+            let code = fs::read_to_string(&path).unwrap();
+            let (new_code, _) = transform::transform_code(&code, None, &options).unwrap();
+            let reversed = reverse(&new_code);
+            assert_eq!(
+                reversed.warnings,
+                Vec::<String>::new(),
+                "{}",
+                path.display()
+            );
+            // The `//~?` annotations are hoisted to the top, and their lines left empty
+            let is_whole_file = |line: &String| line.starts_with("//~?");
+            let original: Vec<String> = code.lines().map(squeeze).collect();
+            let mut whole_file: Vec<String> = original
+                .iter()
+                .filter(|line| is_whole_file(line))
+                .cloned()
+                .collect();
+            let original: Vec<String> = original
+                .into_iter()
+                .map(|line| {
+                    if is_whole_file(&line) {
+                        String::new()
+                    } else {
+                        line
+                    }
+                })
+                .collect();
+            let (mut rebuilt_whole_file, rebuilt): (Vec<String>, Vec<String>) =
+                reversed.code.lines().map(squeeze).partition(is_whole_file);
+            assert_eq!(rebuilt, original, "{}", path.display());
+            whole_file.sort();
+            rebuilt_whole_file.sort();
+            assert_eq!(rebuilt_whole_file, whole_file, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn test_error_codes_and_messages() {
+        let code = "\
+fn main() {
+    let x: i32 = \"\";
+// { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }
+    f(); // { dg-warning \"unused .x. in \\\\\\[\\\\\\$y\\\\\\].*.E0001.\" \"\" { target *-*-* } }
+// { dg-note \"\" \"\" { target *-*-* } .-1 }
+// { dg-message \"help: a similar name exists\" \"\" { target *-*-* } .-2 }
+}
+";
+        let reversed = reverse(code);
+        assert_eq!(
+            reversed.warnings,
+            ["line 4: the error code E0001 is left out, compiletest matches the message of an annotation or its code, not both"]
+        );
+        assert_eq!(
+            reversed.code,
+            "\
+fn main() {
+    let x: i32 = \"\";
+//~^ ERROR E0308
+    f(); //~ WARN unused .x. in [$y]
+//~| NOTE
+//~| HELP a similar name exists
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_headers_and_unsupported_directives() {
+        let code = "\
+// { dg-additional-options \"-frust-edition=2018\" }
+// { dg-additional-options \"-O2\" }
+// { dg-error \"\" \"\" { target *-*-* } 0 }
+// { dg-additional-options \"-frust-crate=foo -frust-cfg=feature=\\\"a\\\"\" }
+// { dg-do compile }
+fn main() {} // { dg-error \"\" \"\" { target *-*-* } 1 }
+fn f() {} // { dg-error \"\" \"\" { target *-*-* } .+1 }
+// { dg-error \"
+";
+        let reversed = reverse(code);
+        assert_eq!(
+            reversed.code.lines().next(),
+            Some("//@ edition: 2018"),
+            "{}",
+            reversed.code
+        );
+        assert_eq!(
+            reversed.warnings,
+            [
+                "line 2: options `-O2` have no rustc header, kept as is",
+                "line 5: `dg-do` has no rustc annotation, kept as is",
+                "line 6: annotations cannot target an absolute line, kept as is",
+                "line 7: annotations after code cannot target a line below them, kept as is",
+                "line 8: unbalanced braces, kept as is",
+            ]
+        );
+        assert_eq!(
+            reversed.code.lines().skip(2).take(2).collect::<Vec<_>>(),
+            [
+                "//~? ERROR",
+                "//@ compile-flags: --crate-name foo --cfg feature=\"a\"",
+            ]
+        );
+        assert_eq!(
+            code.lines().skip(4).collect::<Vec<_>>(),
+            reversed.code.lines().skip(4).collect::<Vec<_>>()
+        );
+        assert_eq!(code.lines().nth(1), reversed.code.lines().nth(1));
+    }
+}
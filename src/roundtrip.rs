@@ -0,0 +1,221 @@
+//! This module contains the round-trip check of a transformation.
+//!
+//! Every directive emitted by the transformation is parsed back with the [`dejagnu`]
+//! validator and mapped to the line it targets. The resulting `(target line, directive)`
+//! pairs are compared, as a multiset, to the ones expected from the source annotations,
+//! so that an annotation silently dropped or duplicated by the renderer is reported.
+
+use {
+    crate::dejagnu::{self, DirectiveError},
+    std::{collections::BTreeMap, fmt},
+};
+
+/// A directive the output must contain: its name, and the line it targets
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Expectation {
+    /// The 1-based line the diagnostic is expected on
+    pub line: usize,
+    /// The directive name, e.g. `dg-error`
+    pub directive: &'static str,
+}
+
+/// A difference between the source annotations and the emitted directives
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    /// An annotation has no corresponding directive
+    Missing { line: usize, directive: String },
+    /// A directive matches no source annotation
+    Unexpected {
+        /// The line of the directive itself
+        directive_line: usize,
+        /// The line it targets
+        line: usize,
+        directive: String,
+    },
+    /// An emitted directive could not be parsed back
+    Invalid {
+        directive_line: usize,
+        error: DirectiveError,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Missing { line, directive } => {
+                write!(f, "no `{directive}` directive was emitted for the annotation of line {line}")
+            }
+            Mismatch::Unexpected {
+                directive_line,
+                line,
+                directive,
+            } => write!(
+                f,
+                "the `{directive}` directive of line {directive_line} targets line {line}, which has no such annotation"
+            ),
+            Mismatch::Invalid {
+                directive_line,
+                error,
+            } => write!(f, "the directive of line {directive_line} is invalid: {error}"),
+        }
+    }
+}
+
+/// The round-trip check failed, returned by the `--strict` mode
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundTripError(pub Vec<Mismatch>);
+
+impl fmt::Display for RoundTripError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the output does not match the source annotations ({} mismatches)",
+            self.0.len()
+        )?;
+        for mismatch in &self.0 {
+            write!(f, "\n  {mismatch}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RoundTripError {}
+
+/// The check, fed the emitted lines one at a time
+#[derive(Debug)]
+pub struct Checker {
+    expected: BTreeMap<(usize, String), usize>,
+    mismatches: Vec<Mismatch>,
+}
+
+impl Checker {
+    pub fn new(expected: impl IntoIterator<Item = Expectation>) -> Self {
+        let mut counts = BTreeMap::new();
+        for expectation in expected {
+            *counts
+                .entry((expectation.line, expectation.directive.to_owned()))
+                .or_default() += 1;
+        }
+        Checker {
+            expected: counts,
+            mismatches: Vec::new(),
+        }
+    }
+
+    /// Checks `line`, the 1-based line `line_num` of the output
+    pub fn line(&mut self, line_num: usize, line: &str) {
+        if !dejagnu::has_directive(line) {
+            return;
+        }
+        let directive = match dejagnu::validate(line) {
+            Ok(directive) => directive,
+            // Names which are not `DejaGnu` directives are never expected, `verify` reports them
+            Err(DirectiveError::UnknownDirective(_)) => return,
+            Err(error) => {
+                self.mismatches.push(Mismatch::Invalid {
+                    directive_line: line_num,
+                    error,
+                });
+                return;
+            }
+        };
+        // Excess and bogus directives are emitted on purpose, without an annotation
+        if !dejagnu::is_diagnostic(&directive.name) || directive.name == "dg-bogus" {
+            return;
+        }
+        let target = directive.target_line(line_num).unwrap_or(0);
+        match self.expected.get_mut(&(target, directive.name.clone())) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => self.mismatches.push(Mismatch::Unexpected {
+                directive_line: line_num,
+                line: target,
+                directive: directive.name,
+            }),
+        }
+    }
+
+    /// The mismatches found: the unexpected and invalid directives in output order,
+    /// then the missing ones by target line
+    pub fn finish(mut self) -> Vec<Mismatch> {
+        for ((line, directive), count) in self.expected {
+            for _ in 0..count {
+                self.mismatches.push(Mismatch::Missing {
+                    line,
+                    directive: directive.clone(),
+                });
+            }
+        }
+        self.mismatches
+    }
+}
+
+/// Checks a whole output against the expected directives
+pub fn check(new_code: &str, expected: impl IntoIterator<Item = Expectation>) -> Vec<Mismatch> {
+    let mut checker = Checker::new(expected);
+    for (line_num, line) in new_code.lines().enumerate() {
+        checker.line(line_num + 1, line);
+    }
+    checker.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expect(line: usize, directive: &'static str) -> Expectation {
+        Expectation { line, directive }
+    }
+
+    #[test]
+    fn test_matching_output() {
+        let new_code = "\
+fn main() {
+    let x: i32 = \"\"; // { dg-error \"\" \"\" { target *-*-* } }
+    // { dg-warning \"\" \"\" { target *-*-* } .-1 }
+}
+// { dg-excess-errors \"\" }
+// { dg-bogus \"\" \"\" { target *-*-* } 1 }
+";
+        let expected = [expect(2, "dg-warning"), expect(2, "dg-error")];
+        assert_eq!(check(new_code, expected), []);
+    }
+
+    #[test]
+    fn test_multiset_comparison() {
+        let new_code = "\
+fn main() {} // { dg-error \"\" \"\" { target *-*-* } }
+// { dg-error \"\" \"\" { target *-*-* } .-1 }
+// { dg-note \"\" \"\" { target *-*-* } .-1 }
+// { dg-error \"[\" \"\" { target *-*-* } }
+";
+        // Twice the error of line 1 is fine, a third time is not
+        let expected = [
+            expect(1, "dg-error"),
+            expect(1, "dg-error"),
+            expect(1, "dg-error"),
+            expect(3, "dg-warning"),
+        ];
+        assert_eq!(
+            check(new_code, expected),
+            [
+                Mismatch::Unexpected {
+                    directive_line: 3,
+                    line: 2,
+                    directive: "dg-note".to_owned()
+                },
+                Mismatch::Invalid {
+                    directive_line: 4,
+                    error: DirectiveError::CommandSubstitution
+                },
+                Mismatch::Missing {
+                    line: 1,
+                    directive: "dg-error".to_owned()
+                },
+                Mismatch::Missing {
+                    line: 3,
+                    directive: "dg-warning".to_owned()
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! This module contains the self test of the tool.
+//!
+//! A few representative tests of `tests/fixtures` are embedded in the binary along with
+//! their expected conversion, so that an installed tool can check itself without any
+//! file around. They also show what each kind of annotation is converted into.
+
+use {
+    crate::transform::{self, Options},
+    anyhow::Result,
+    std::io,
+};
+
+/// An embedded test and its expected conversion
+#[derive(Clone, Copy, Debug)]
+pub struct Case {
+    pub name: &'static str,
+    pub code: &'static str,
+    pub stderr: Option<&'static str>,
+    pub expected: &'static str,
+}
+
+macro_rules! case {
+    ($name:literal) => {
+        case!($name, None)
+    };
+    ($name:literal, $stderr:expr) => {
+        Case {
+            name: $name,
+            code: include_str!(concat!("../tests/fixtures/", $name, ".rs")),
+            stderr: $stderr,
+            expected: include_str!(concat!("../tests/fixtures/", $name, ".expected")),
+        }
+    };
+}
+
+/// The embedded cases: carets, follows with warnings and notes, same-line annotations,
+/// error codes from a `stderr` file and edition headers
+pub const CASES: &[Case] = &[
+    case!("caret"),
+    case!("follow"),
+    case!("inline"),
+    case!(
+        "stderr_codes",
+        Some(include_str!("../tests/fixtures/stderr_codes.stderr"))
+    ),
+    case!("edition"),
+];
+
+impl Case {
+    /// Converts the case, returning a description of the differences if it fails
+    pub fn check(&self) -> Result<(), String> {
+        let new_code = match transform::transform_code(self.code, self.stderr, &Options::default())
+        {
+            Ok((new_code, _)) => new_code,
+            Err(err) => return Err(format!("conversion failed: {err:#}")),
+        };
+        if new_code == self.expected {
+            return Ok(());
+        }
+        Err(diff(self.expected, &new_code))
+    }
+}
+
+/// A line by line diff, enough as the conversion never adds or removes lines
+fn diff(expected: &str, actual: &str) -> String {
+    let mut expected = expected.lines();
+    let mut actual = actual.lines();
+    let mut diff = String::new();
+    for line_num in 1.. {
+        let (left, right) = (expected.next(), actual.next());
+        if left.is_none() && right.is_none() {
+            break;
+        }
+        if left == right {
+            continue;
+        }
+        diff.push_str(&format!("line {line_num}:\n"));
+        if let Some(left) = left {
+            diff.push_str(&format!("-{left}\n"));
+        }
+        if let Some(right) = right {
+            diff.push_str(&format!("+{right}\n"));
+        }
+    }
+    if diff.is_empty() {
+        // Only the final newline differs
+        diff.push_str("the outputs differ in their final newline\n");
+    }
+    diff
+}
+
+/// Runs every embedded case, writing one line per case and the diff of failed ones.
+///
+/// Fails if any case failed.
+pub fn run(output: &mut impl io::Write) -> Result<()> {
+    let mut failed = 0;
+    for case in CASES {
+        match case.check() {
+            Ok(()) => writeln!(output, "{} ... ok", case.name)?,
+            Err(diff) => {
+                failed += 1;
+                writeln!(output, "{} ... FAILED", case.name)?;
+                write!(output, "{diff}")?;
+            }
+        }
+    }
+    writeln!(
+        output,
+        "{} cases: {} passed, {failed} failed",
+        CASES.len(),
+        CASES.len() - failed
+    )?;
+    output.flush()?;
+    if failed != 0 {
+        anyhow::bail!("{failed} selftest cases failed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selftest_passes() {
+        let mut output = Vec::new();
+        run(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output,
+            "caret ... ok\nfollow ... ok\ninline ... ok\nstderr_codes ... ok\nedition ... ok\n5 cases: 5 passed, 0 failed\n"
+        );
+    }
+
+    #[test]
+    fn test_failed_case_shows_a_diff() {
+        let case = Case {
+            expected: "fn main() {\n    let x: i32 = \"hello\";\n}\n",
+            ..CASES[3]
+        };
+        let diff = case.check().unwrap_err();
+        assert!(diff.starts_with(
+            "line 3:\n-}\n+// { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }\nline 4:\n+    let v = undefined;\n"
+        ), "{diff}");
+
+        let case = Case {
+            code: "//~| NOTE orphan\n",
+            ..CASES[0]
+        };
+        assert!(case.check().unwrap_err().starts_with("conversion failed: "));
+    }
+}
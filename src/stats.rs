@@ -0,0 +1,192 @@
+//! This module contains the counts behind `--stats`: how many files and annotations a
+//! conversion handled, by kind, and how many `//~` comments it could not read.
+//!
+//! Every transformation fills the [`Stats`] of its report, runs over many files sum them.
+
+use {
+    crate::errors::{Error, LoadedErrors, RustcErrorKind},
+    serde::Serialize,
+    std::{collections::BTreeMap, io},
+};
+
+/// The counts of one or many transformations
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    /// Files converted
+    pub files: usize,
+    /// Annotations converted, whatever they became
+    pub annotations: usize,
+    /// The annotations by [`kind_name`]
+    pub kinds: BTreeMap<&'static str, usize>,
+    /// Annotations given an error code from the `stderr` file
+    pub error_codes: usize,
+    /// Lines which looked like annotations but could not be read as any
+    pub skipped: usize,
+    /// `//@` headers compiletest does not know, kept as `// UNCONVERTED:` comments
+    pub unconverted_headers: usize,
+    /// Diagnostics of the `stderr` file which matched no annotation
+    #[serde(skip_serializing_if = "is_zero")]
+    pub unmatched_stderr: usize,
+    /// Annotations removed because they repeat an earlier one of the same line
+    #[serde(skip_serializing_if = "is_zero")]
+    pub duplicates: usize,
+    /// The [`header::IGNORED`] headers dropped from the output, by name
+    ///
+    /// [`header::IGNORED`]: crate::header::IGNORED
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub ignored_headers: BTreeMap<&'static str, usize>,
+    /// Tests left out of the conversion, by [`SkipCategory::name`]
+    ///
+    /// [`SkipCategory::name`]: crate::transform::SkipCategory::name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub skipped_tests: BTreeMap<&'static str, usize>,
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+/// The name of an annotation kind in the stats, `none` without one
+pub fn kind_name(kind: Option<RustcErrorKind>) -> &'static str {
+    match kind {
+        None => "none",
+        Some(RustcErrorKind::Error) => "error",
+        Some(RustcErrorKind::Help) => "help",
+        Some(RustcErrorKind::Note) => "note",
+        Some(RustcErrorKind::Suggestion) => "suggestion",
+        Some(RustcErrorKind::Warning) => "warning",
+    }
+}
+
+impl Stats {
+    /// The counts of a single file, whose annotations are `loaded`
+    pub fn of_file(loaded: &LoadedErrors) -> Self {
+        let mut stats = Stats {
+            files: 1,
+            skipped: loaded.skipped.len(),
+            duplicates: loaded.duplicates.len(),
+            ..Stats::default()
+        };
+        for error in &loaded.errors {
+            stats.add_error(error);
+        }
+        stats
+    }
+
+    /// Counts an annotation, or a directive of the merge mode
+    pub fn add_error(&mut self, error: &Error) {
+        self.annotations += 1;
+        *self.kinds.entry(kind_name(error.kind)).or_default() += 1;
+        if error.error_code.is_some() {
+            self.error_codes += 1;
+        }
+    }
+
+    /// Adds the counts of `other` to these ones
+    pub fn merge(&mut self, other: &Stats) {
+        self.files += other.files;
+        self.annotations += other.annotations;
+        for (kind, count) in &other.kinds {
+            *self.kinds.entry(kind).or_default() += count;
+        }
+        self.error_codes += other.error_codes;
+        self.skipped += other.skipped;
+        self.unconverted_headers += other.unconverted_headers;
+        self.unmatched_stderr += other.unmatched_stderr;
+        self.duplicates += other.duplicates;
+        for (name, count) in &other.ignored_headers {
+            *self.ignored_headers.entry(name).or_default() += count;
+        }
+        for (category, count) in &other.skipped_tests {
+            *self.skipped_tests.entry(category).or_default() += count;
+        }
+    }
+
+    /// Writes the counts, one per line
+    pub fn write_table(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        writeln!(writer, "files:             {:>6}", self.files)?;
+        writeln!(writer, "annotations:       {:>6}", self.annotations)?;
+        for (kind, count) in &self.kinds {
+            writeln!(writer, "  {kind:16} {count:>6}")?;
+        }
+        writeln!(writer, "with an error code: {:>5}", self.error_codes)?;
+        writeln!(writer, "not understood:    {:>6}", self.skipped)?;
+        writeln!(
+            writer,
+            "unconverted headers: {:>4}",
+            self.unconverted_headers
+        )?;
+        if self.unmatched_stderr > 0 {
+            writeln!(writer, "unmatched stderr:  {:>6}", self.unmatched_stderr)?;
+        }
+        if self.duplicates > 0 {
+            writeln!(writer, "duplicates removed: {:>5}", self.duplicates)?;
+        }
+        if !self.ignored_headers.is_empty() {
+            let total: usize = self.ignored_headers.values().sum();
+            writeln!(writer, "ignored headers:   {total:>6}")?;
+            for (name, count) in &self.ignored_headers {
+                writeln!(writer, "  {name:16} {count:>6}")?;
+            }
+        }
+        for (category, count) in &self.skipped_tests {
+            writeln!(writer, "{:19}{count:>6}", format!("skipped {category}:"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::errors::{self, LoadOptions},
+    };
+
+    #[test]
+    fn test_fixture_counts() {
+        let code = include_str!("../tests/fixtures/stats.rs");
+        let stderr = include_str!("../tests/fixtures/stats.stderr");
+        let loaded = errors::load_errors(code, Some(stderr), &LoadOptions::default()).unwrap();
+        let stats = Stats::of_file(&loaded);
+        // The note of the `//~|` is not the message of the entry, it has no error code
+        assert_eq!(
+            stats,
+            Stats {
+                files: 1,
+                annotations: 5,
+                kinds: BTreeMap::from([("error", 2), ("note", 1), ("warning", 2)]),
+                error_codes: 1,
+                skipped: 1,
+                unconverted_headers: 0,
+                unmatched_stderr: 0,
+                duplicates: 0,
+                ignored_headers: BTreeMap::new(),
+                skipped_tests: BTreeMap::new(),
+            }
+        );
+
+        let mut total = stats.clone();
+        total.merge(&stats);
+        assert_eq!(
+            (total.files, total.kinds["warning"], total.skipped),
+            (2, 4, 2)
+        );
+
+        let mut table = Vec::new();
+        stats.write_table(&mut table).unwrap();
+        assert_eq!(
+            String::from_utf8(table).unwrap(),
+            "\
+files:                  1
+annotations:            5
+  error                 2
+  note                  1
+  warning               2
+with an error code:     1
+not understood:         1
+unconverted headers:    0
+"
+        );
+    }
+}
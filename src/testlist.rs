@@ -0,0 +1,139 @@
+//! This module contains the list of the tests written by a batch conversion, to wire them
+//! into the gccrs harness.
+//!
+//! The list is either a Tcl snippet to `source` from an `.exp` file, or a plain list of
+//! paths. Either way the paths are relative to the output directory and sorted, so that
+//! the file only changes when the converted tests do and can be committed. The tests which
+//! were not written are listed in commented-out form, with the reason.
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The variable of the Tcl snippet holding the tests
+pub const TCL_VARIABLE: &str = "rusttest_to_dg_tests";
+
+/// The format of the test list
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    /// A Tcl snippet appending each test to a list, for `dg-runtest`
+    #[default]
+    Tcl,
+    /// One path per line
+    List,
+}
+
+/// The tests of a batch conversion, by path relative to the output directory
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TestList {
+    /// Each test, with the reason it was not written if it was not
+    pub tests: BTreeMap<PathBuf, Option<String>>,
+}
+
+impl TestList {
+    /// Adds a test written at `path`
+    pub fn add(&mut self, path: PathBuf) {
+        self.tests.insert(path, None);
+    }
+
+    /// Adds a test which would have been written at `path`
+    pub fn add_skipped(&mut self, path: PathBuf, reason: String) {
+        self.tests.insert(path, Some(reason));
+    }
+
+    /// Writes the list in `format`
+    pub fn write(&self, format: ListFormat, writer: &mut impl io::Write) -> io::Result<()> {
+        match format {
+            ListFormat::Tcl => {
+                writeln!(
+                    writer,
+                    "# Generated by rusttest-to-dg, regenerate it instead of editing it.\n\
+                     # The paths are relative to the testsuite directory, for example:\n\
+                     #   foreach test ${TCL_VARIABLE} {{\n\
+                     #       dg-runtest $srcdir/$subdir/$test \"\" $DEFAULT_RUSTFLAGS\n\
+                     #   }}\n\
+                     set {TCL_VARIABLE} {{}}"
+                )?;
+                for (path, skipped) in &self.tests {
+                    let path = tcl_path(path);
+                    match skipped {
+                        None => writeln!(writer, "lappend {TCL_VARIABLE} {path}")?,
+                        Some(reason) => {
+                            writeln!(writer, "# lappend {TCL_VARIABLE} {path} ;# {reason}")?
+                        }
+                    }
+                }
+            }
+            ListFormat::List => {
+                for (path, skipped) in &self.tests {
+                    let path = slash_path(path);
+                    match skipped {
+                        None => writeln!(writer, "{path}")?,
+                        Some(reason) => writeln!(writer, "# {path}: {reason}")?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `path` with forward slashes
+fn slash_path(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    components.join("/")
+}
+
+/// [`slash_path`], braced if Tcl would otherwise split or substitute it
+fn tcl_path(path: &Path) -> String {
+    let path = slash_path(path);
+    if path.contains(|c: char| c.is_whitespace() || "\"$[]{};\\#".contains(c)) {
+        format!("{{{path}}}")
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats() {
+        let mut list = TestList::default();
+        list.add(PathBuf::from("execute/run/add.rs"));
+        list.add_skipped(
+            PathBuf::from("compile/broken.rs"),
+            "annotation pointing above the file".to_owned(),
+        );
+        list.add(PathBuf::from("compile/a b.rs"));
+
+        let mut output = Vec::new();
+        list.write(ListFormat::List, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\
+compile/a b.rs
+# compile/broken.rs: annotation pointing above the file
+execute/run/add.rs
+"
+        );
+
+        let mut output = Vec::new();
+        list.write(ListFormat::Tcl, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.ends_with(
+            "\
+set rusttest_to_dg_tests {}
+lappend rusttest_to_dg_tests {compile/a b.rs}
+# lappend rusttest_to_dg_tests compile/broken.rs ;# annotation pointing above the file
+lappend rusttest_to_dg_tests execute/run/add.rs
+"
+        ));
+    }
+}
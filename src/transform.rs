@@ -2,88 +2,1627 @@
 /// The known directives are copied from https://github.com/rust-lang/rust/blob/master/src/tools/compiletest/src/command-list.rs
 use {
     crate::{
-        errors,
-        header::{is_header_line, parse_additional_options},
-        regex,
+        coverage::{Coverage, Support},
+        dejagnu, errors,
+        header::{self, is_header_line, parse_additional_options},
+        limits::Limits,
+        lint::{self, DeniedLints},
+        needs::NeedsPolicy,
+        roundtrip::{self, Expectation, RoundTripError},
+        stats::Stats,
     },
     anyhow::Result,
+    serde::Serialize,
+    std::{collections::BTreeMap, io},
 };
 
+/// How secondary (`NOTE` and `HELP`) annotations are rendered
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum SecondaryMode {
+    /// Convert them into `DejaGnu` directives like every other annotation
+    #[default]
+    Convert,
+    /// Render them as plain `// rustc-note: <message>` comments
+    Comment,
+    /// Remove them from the output
+    Drop,
+}
+
+/// How the directives refer to the lines they expect a diagnostic on
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LineNumbers {
+    /// By their offset from the directive, like `.-1`, which survives edits of the lines
+    /// above
+    #[default]
+    Relative,
+    /// By their number in the emitted file, like `7`
+    Absolute,
+}
+
+/// Expected run outputs longer than this many lines are left for a manual conversion
+pub const DEFAULT_MAX_STDOUT_LINES: usize = 64;
+
+/// Options controlling how the code is transformed
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub secondary: SecondaryMode,
+    pub msg_style: errors::MsgStyle,
+    /// The length [`errors::MsgStyle::Portable`] messages are cut at, `0` keeps them whole
+    pub max_message_length: usize,
+    /// Start the patterns with the column of the `stderr` file when known, like
+    /// `"18: mismatched types"`, which the harness matches against the column of the compiler
+    pub columns: bool,
+    /// Generate the directives from the `stderr` file, below the lines it reports, instead
+    /// of the `//~` annotations. It is always done for a source without any.
+    pub from_stderr: bool,
+    /// Warn about generated lines longer than this, `0` disables the check
+    pub max_line_length: usize,
+    /// Source lines longer than this many bytes are not scanned for annotations, `0` disables the guard
+    pub scan_line_limit: usize,
+    /// Resource caps, exceeding one fails the transformation
+    pub limits: Limits,
+    /// Report progress every this many annotations, `0` disables the reports
+    pub progress_interval: usize,
+    /// Receives the progress of annotation loading, if any
+    pub progress: Option<errors::ProgressCallback>,
+    /// Fail when the emitted directives do not match the source annotations,
+    /// instead of only warning. The output is already written by then.
+    pub strict: bool,
+    /// Run the lints, reporting their findings
+    pub lint: bool,
+    /// Lints whose findings fail the transformation, they are run even without `lint`
+    pub deny: Vec<lint::Lint>,
+    /// The revision whose annotations and headers are converted, the others are removed.
+    /// Every annotation and header is converted without one.
+    pub revision: Option<String>,
+    /// Start compile-fail tests with a `dg-do compile` too, which is the default of DejaGnu
+    pub explicit_dg_do: bool,
+    /// Leave the tests of a `known-bug` out, see [`skipped`]
+    pub skip_known_bugs: bool,
+    /// Convert the proc-macro tests [`skipped`] otherwise, under a warning comment
+    pub convert_anyway: bool,
+    /// What is done with the `needs-*` headers
+    pub needs: NeedsPolicy,
+    /// Keep the `edition` headers of the default edition, 2015
+    pub explicit_edition: bool,
+    /// The edition of every test, one of [`header::EDITIONS`], over their `edition` headers
+    pub edition: Option<String>,
+    /// Expected run outputs of more lines than this are left for a manual conversion, see
+    /// [`write_run_output`]. `0` disables the check.
+    pub max_stdout_lines: usize,
+    /// Keep the [`header::IGNORED`] headers as they are, instead of dropping them
+    pub keep_ignored_headers: bool,
+    /// Fail on the `//@` headers compiletest does not know, instead of keeping them as
+    /// `// UNCONVERTED:` comments, and on a [`header::ConflictingEdition`]
+    pub strict_headers: bool,
+    /// Fail on the errors of the `stderr` file which match no annotation, instead of only
+    /// warning about them
+    pub strict_stderr: bool,
+    /// The file name of the source, like `mismatch.rs`. The entries of the `stderr` file
+    /// located in another file, like an auxiliary crate, are left out when set.
+    pub source_name: Option<String>,
+    /// Keep the entries of the `stderr` file located in any file, even with a `source_name`
+    pub match_any_file: bool,
+    /// Add the notes and help messages of the `stderr` file no annotation expects below the
+    /// lines they report, the labels of the secondary spans included
+    pub with_notes: bool,
+    /// Remove the directives repeating an earlier one of the same line, which would expect
+    /// the diagnostic twice
+    pub dedup: bool,
+    /// Whether the directives target their line by offset or by number
+    pub line_numbers: LineNumbers,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            secondary: SecondaryMode::default(),
+            msg_style: errors::MsgStyle::default(),
+            max_message_length: errors::DEFAULT_MAX_MESSAGE_LENGTH,
+            columns: false,
+            from_stderr: false,
+            max_line_length: 0,
+            scan_line_limit: errors::DEFAULT_SCAN_LINE_LIMIT,
+            limits: Limits::default(),
+            progress_interval: 0,
+            progress: None,
+            strict: false,
+            lint: false,
+            deny: Vec::new(),
+            revision: None,
+            explicit_dg_do: false,
+            skip_known_bugs: false,
+            convert_anyway: false,
+            needs: NeedsPolicy::default(),
+            explicit_edition: false,
+            edition: None,
+            max_stdout_lines: DEFAULT_MAX_STDOUT_LINES,
+            keep_ignored_headers: false,
+            strict_headers: false,
+            strict_stderr: false,
+            source_name: None,
+            match_any_file: false,
+            with_notes: false,
+            dedup: true,
+            line_numbers: LineNumbers::default(),
+        }
+    }
+}
+
+impl Options {
+    /// The file name the entries of the `stderr` file must be located in, see
+    /// [`Options::source_name`]
+    pub fn stderr_source_name(&self) -> Option<&str> {
+        self.source_name.as_deref().filter(|_| !self.match_any_file)
+    }
+}
+
+/// Summary of what happened during a transformation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TransformReport {
+    /// Secondary annotations converted into directives
+    pub secondary_converted: usize,
+    /// Secondary annotations rendered as plain comments
+    pub secondary_commented: usize,
+    /// Secondary annotations removed from the output
+    pub secondary_dropped: usize,
+    /// Directives inserted by the merge mode
+    pub merged: usize,
+    /// Messages shortened by [`errors::MsgStyle::Prefix`]
+    pub messages_truncated: usize,
+    /// Total number of characters cut off from the truncated messages
+    pub truncated_chars: usize,
+    /// Generated lines (1-based) longer than the `--max-line-length` limit
+    pub long_lines: Vec<usize>,
+    /// Problems worth telling the user about, which did not stop the transformation
+    pub warnings: Vec<String>,
+    /// Findings of the lints, when enabled
+    pub lints: Vec<lint::Finding>,
+    /// The headers and annotation forms met, with what was done with them
+    pub coverage: Coverage,
+    /// The counts of `--stats`
+    pub stats: Stats,
+    /// The [`header::NO_EQUIVALENT`] headers of the test, which were dropped
+    pub dropped_headers: Vec<String>,
+    /// Why the test was left out, see [`skipped`]. Nothing was converted.
+    pub skipped: Option<Skip>,
+}
+
+/// The kinds of tests left out of the conversion
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipCategory {
+    /// A test of a rustc bug, with [`Options::skip_known_bugs`]
+    KnownBug,
+    /// A proc-macro test, which gccrs cannot build yet, without [`Options::convert_anyway`]
+    ProcMacro,
+}
+
+impl SkipCategory {
+    /// The name of the category in the stats and the `--skipped-report`, like `known-bug`
+    pub fn name(self) -> &'static str {
+        match self {
+            SkipCategory::KnownBug => "known-bug",
+            SkipCategory::ProcMacro => "proc-macro",
+        }
+    }
+
+    /// What the tests of the category are called in the summary of a directory run
+    pub fn plural(self) -> &'static str {
+        match self {
+            SkipCategory::KnownBug => "known bugs",
+            SkipCategory::ProcMacro => "proc-macro tests",
+        }
+    }
+}
+
+/// A test left out of the conversion
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Skip {
+    pub category: SkipCategory,
+    /// What made it skipped: the issue of a `known-bug`, or the header of a proc-macro test
+    pub reason: String,
+}
+
+impl TransformReport {
+    /// Adds the report of another revision of the same test, whose counts are the same
+    pub fn add_revision(&mut self, other: TransformReport) {
+        for warning in other.warnings {
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
+        }
+    }
+}
+
+/// The report of `code` when it is left out of the conversion, with the warning saying so:
+/// a `known-bug` test with [`Options::skip_known_bugs`], or a proc-macro test without
+/// [`Options::convert_anyway`]. `name` is the file of the test.
+pub fn skipped(code: &str, name: &str, options: &Options) -> Option<TransformReport> {
+    let known_bug = options
+        .skip_known_bugs
+        .then(|| header::known_bug(code))
+        .flatten();
+    let (skip, warning) = if let Some(issue) = known_bug {
+        let warning =
+            format!("{name} is a test of the rustc bug {issue}, skipped by --skip-known-bugs");
+        let skip = Skip {
+            category: SkipCategory::KnownBug,
+            reason: issue,
+        };
+        (skip, warning)
+    } else {
+        let header = header::proc_macro_header(code).filter(|_| !options.convert_anyway)?;
+        let warning = format!(
+            "{name} is a proc-macro test (`{header}`), skipped, --convert-anyway converts it"
+        );
+        let skip = Skip {
+            category: SkipCategory::ProcMacro,
+            reason: header.to_owned(),
+        };
+        (skip, warning)
+    };
+    let mut stats = Stats::default();
+    stats.skipped_tests.insert(skip.category.name(), 1);
+    Some(TransformReport {
+        warnings: vec![warning],
+        stats,
+        skipped: Some(skip),
+        ..TransformReport::default()
+    })
+}
+
+/// The `dg-output` directives expecting `stdout` from the run of a test, one per line. Each
+/// newline is matched with any carriage return before it.
+pub fn output_directives(stdout: &str) -> Vec<String> {
+    stdout
+        .split_inclusive('\n')
+        .map(|line| {
+            let (text, newline) = match line.strip_suffix('\n') {
+                Some(text) => (text.strip_suffix('\r').unwrap_or(text), "\\r*\\n"),
+                None => (line, ""),
+            };
+            let pattern = errors::escape_dejagnu(text);
+            format!("// {{ dg-output \"{pattern}{newline}\" }}")
+        })
+        .collect()
+}
+
+/// Writes the [`output_directives`] of `stdout`, the expected output of the run of `code`
+/// read from `stdout_name`, after the converted `code`. An output of more lines than
+/// [`Options::max_stdout_lines`] is only warned about in `report`.
+pub fn write_run_output(
+    writer: &mut impl io::Write,
+    code: &str,
+    stdout: &str,
+    stdout_name: &str,
+    options: &Options,
+    report: &mut TransformReport,
+) -> io::Result<()> {
+    let lines = stdout.lines().count();
+    if options.max_stdout_lines != 0 && lines > options.max_stdout_lines {
+        report.warnings.push(format!(
+            "the expected output {stdout_name} has {lines} lines, more than the {} of --max-stdout-lines, it needs manual conversion",
+            options.max_stdout_lines
+        ));
+        return Ok(());
+    }
+    if !code.is_empty() && !code.ends_with('\n') {
+        writeln!(writer)?;
+    }
+    for directive in output_directives(stdout) {
+        writeln!(writer, "{directive}")?;
+    }
+    Ok(())
+}
+
 /// Transform code to `DejaGnu` format
-pub fn transform_code(code: &str, stderr_file: Option<&str>) -> Result<String> {
-    // Load the rustc error messages, codes, lines and relative line numbers
-    let errors = errors::load_error(code, stderr_file);
-    // For storing the transformed code
-    let mut new_code = String::new();
-    let additional_options = parse_additional_options(code);
+///
+/// The output only depends on the inputs: errors are kept in source order and
+/// no hash-based grouping is involved, so repeated or parallel runs are byte-identical.
+pub fn transform_code(
+    code: &str,
+    stderr_file: Option<&str>,
+    options: &Options,
+) -> Result<(String, TransformReport)> {
+    let mut new_code = Vec::with_capacity(code.len());
+    let report = transform_to(&mut new_code, code, stderr_file, options)?;
+    // Only ever built from `&str` pieces, but a panic here would abort a whole batch
+    let new_code = String::from_utf8(new_code)?;
+    Ok((new_code, report))
+}
 
-    // regular expression to capture the error with revision directive
-    let captures_regex = regex!(r"//(?:\[(?P<revs>[\w\-,]+)])?~(?P<adjust>\||\^*)");
+/// Transform code to `DejaGnu` format, writing each line to `writer` as soon as it is produced
+pub fn transform_to(
+    writer: &mut impl io::Write,
+    code: &str,
+    stderr_file: Option<&str>,
+    options: &Options,
+) -> Result<TransformReport> {
+    let stderr = match stderr_file {
+        Some(stderr_file) => {
+            options.limits.check_stderr_size(stderr_file.len() as u64)?;
+            Some(errors::parse_stderr(stderr_file))
+        }
+        None => None,
+    };
+    transform_parsed_to(writer, code, stderr.as_deref(), options)
+}
 
-    let mut line_num = 1;
-    // finding the respective line number and adding the error code
-    for line in code.lines() {
-        let mut new_line = line.to_string();
+/// Loads the annotations of `code` within the limits of `options`, without converting them
+pub fn load_annotations(
+    code: &str,
+    stderr: Option<&[errors::StderrDiagnostic]>,
+    options: &Options,
+) -> Result<errors::LoadedErrors> {
+    options.limits.check_source(code)?;
+    let load_options = errors::LoadOptions {
+        scan_line_limit: options.scan_line_limit,
+        max_annotations: options.limits.max_annotations,
+        progress_interval: options.progress_interval,
+        progress: options.progress.clone(),
+        source_name: options.stderr_source_name().map(str::to_owned),
+        dedup: options.dedup,
+        revision: options.revision.clone(),
+    };
+    let loaded = errors::load_errors_parsed(code, stderr, &load_options)?;
+    options.limits.check_annotations(loaded.errors.len())?;
+    Ok(loaded)
+}
 
-        if is_header_line(line) {
-            for header in additional_options.iter() {
-                if header.line_number != line_num {
-                    continue;
+/// Same as [`transform_to`], with a `stderr` file already parsed by [`errors::parse_stderr`]
+pub fn transform_parsed_to(
+    writer: &mut impl io::Write,
+    code: &str,
+    stderr: Option<&[errors::StderrDiagnostic]>,
+    options: &Options,
+) -> Result<TransformReport> {
+    // Load the rustc error messages, codes, lines and relative line numbers
+    let mut loaded = load_annotations(code, stderr, options)?;
+    // Without annotations, the directives come from the `stderr` file
+    let from_stderr = match stderr {
+        Some(stderr) if options.from_stderr || loaded.errors.is_empty() => {
+            loaded.errors = errors::stderr_errors(stderr, options.stderr_source_name());
+            loaded.forms.clear();
+            loaded.duplicates.clear();
+            !loaded.errors.is_empty()
+        }
+        _ => false,
+    };
+    let stats = Stats::of_file(&loaded);
+    let errors::LoadedErrors {
+        mut errors,
+        warnings,
+        forms,
+        unmatched,
+        duplicates,
+        ..
+    } = loaded;
+    let mut report = TransformReport {
+        warnings,
+        stats,
+        ..TransformReport::default()
+    };
+    // The notes and help messages the test does not expect, which go below their lines
+    let notes = match stderr {
+        Some(stderr) if options.with_notes && from_stderr => errors::stderr_notes(
+            stderr
+                .iter()
+                .filter(|result| result.is_in(options.stderr_source_name())),
+        ),
+        Some(stderr) if options.with_notes => {
+            errors::stderr_notes(unmatched.iter().map(|&index| &stderr[index]))
+        }
+        _ => Vec::new(),
+    };
+    // The diagnostics the test does not expect, which gccrs is then free to leave out. The
+    // labels of the secondary spans are not diagnostics of their own.
+    if let (false, Some(stderr)) = (from_stderr, stderr) {
+        let unmatched: Vec<_> = unmatched
+            .iter()
+            .map(|&index| &stderr[index])
+            .filter(|result| !result.label)
+            .filter(|result| {
+                !(options.with_notes
+                    && result.line_number.is_some()
+                    && matches!(
+                        result.level,
+                        errors::RustcErrorKind::Note | errors::RustcErrorKind::Help
+                    ))
+            })
+            .collect();
+        let errors: Vec<_> = unmatched
+            .iter()
+            .filter(|result| result.level == errors::RustcErrorKind::Error)
+            .map(|result| result.to_string())
+            .collect();
+        if options.strict_stderr && !errors.is_empty() {
+            return Err(errors::UnmatchedStderr(errors).into());
+        }
+        report.stats.unmatched_stderr = unmatched.len();
+        report.warnings.extend(
+            unmatched
+                .iter()
+                .map(|result| format!("{result}, in the stderr file, matches no annotation")),
+        );
+    }
+    let (mut additional_options, header_warnings) = parse_additional_options(
+        code,
+        options.revision.as_deref(),
+        &options.needs,
+        options.explicit_edition,
+    )?;
+    report.warnings.extend(header_warnings);
+    // The `--edition` of the command line replaces the header, not the flags
+    if let Some(conflict) = header::edition_conflict(code, options.revision.as_deref())
+        .filter(|_| options.edition.is_none())
+    {
+        if options.strict_headers {
+            return Err(conflict.into());
+        }
+        report
+            .warnings
+            .push(format!("{conflict}, gccrs gets both of them"));
+    }
+    if let Some(edition) = &options.edition {
+        for (line, source) in header::edition_headers(code, options.revision.as_deref()) {
+            report.warnings.push(format!(
+                "line {line}: `edition: {source}` is overridden by --edition {edition}"
+            ));
+            for header in &mut additional_options {
+                if header.line_number == line {
+                    header.dejagnu_header = String::new();
                 }
-                new_line = header.dejagnu_header.to_string();
-                break;
+            }
+        }
+    }
+    let unconverted: Vec<_> = additional_options
+        .iter()
+        .filter_map(|header| match &header.directive {
+            header::Directive::Unconverted(directive) => Some((header.line_number, directive)),
+            _ => None,
+        })
+        .collect();
+    if let (true, Some(&(line, directive))) = (options.strict_headers, unconverted.first()) {
+        return Err(header::UnknownHeader {
+            line,
+            header: directive.clone(),
+        }
+        .into());
+    }
+    report.stats.unconverted_headers = unconverted.len();
+    for header in &additional_options {
+        if let header::Directive::Ignored(name) = header.directive {
+            *report.stats.ignored_headers.entry(name).or_default() += 1;
+        }
+    }
+    // Without a header to replace them, the lines are copied as they are
+    if options.keep_ignored_headers {
+        additional_options
+            .retain(|header| !matches!(header.directive, header::Directive::Ignored(_)));
+    }
+    let policy = header::annotation_policy(code, options.revision.as_deref());
+    let mut dropped_headers: Vec<String> = Vec::new();
+    for header in &additional_options {
+        if let header::Directive::NoEquivalent(name) = &header.directive {
+            if !dropped_headers.contains(name) {
+                dropped_headers.push(name.clone());
+            }
+        }
+    }
+    report.dropped_headers = dropped_headers;
+    let targets = header::target_selector(code, options.revision.as_deref());
+    header::check_revisions(code)?;
+    let mode = header::test_mode(code, options.revision.as_deref())?;
+    let mut first_lines: Vec<String> = match mode {
+        Some(mode) => mode
+            .dejagnu_lines()
+            .iter()
+            .map(|&line| line.to_owned())
+            .collect(),
+        None if options.explicit_dg_do => vec![header::DG_DO_COMPILE.to_owned()],
+        None => Vec::new(),
+    };
+    if let Some(edition) = &options.edition {
+        first_lines.push(header::Directive::Edition(edition.clone()).to_dejagnu());
+    }
+    // The `dg-error` directives of a rustc bug are expected to fail, unless the bug is a
+    // crash: a test expecting nothing at all, like those of `tests/crashes`
+    let known_bug = header::known_bug(code);
+    if let Some(issue) = &known_bug {
+        first_lines.push(format!("// rustc known-bug: {issue}"));
+        if errors.is_empty() && mode.is_none() && !header::has_error_patterns(code) {
+            first_lines.push("// { dg-ice \"\" }".to_owned());
+        }
+    }
+    let selector = errors::Selector {
+        targets: &targets,
+        xfail: known_bug.is_some(),
+    };
+    for header in header::header_uses(code) {
+        report.coverage.add_header(header.name, header.support);
+    }
+    for (error, form) in errors.iter().zip(&forms) {
+        report
+            .coverage
+            .add_annotation(form, annotation_support(error, options, &policy));
+    }
+
+    // Every directive is rendered once up front along with the line it replaces,
+    // the loop below only borrows them
+    let mut rendered = Vec::with_capacity(errors.len());
+    let mut expected = Vec::new();
+    // The directives of the `//~?` annotations, which go at the top of the file
+    let mut hoisted = Vec::new();
+    // The directives generated from the `stderr` file, by the line they go below. No line
+    // of the source is replaced, so the lines it reports are the ones of the source.
+    let mut inserted: BTreeMap<usize, Vec<(String, errors::Error)>> = BTreeMap::new();
+    let code_lines = code.lines().count();
+    for (index, error) in errors.iter_mut().enumerate() {
+        if from_stderr {
+            if error.line_num > code_lines {
+                report.warnings.push(format!(
+                    "the {} of line {} in the stderr file is past the end of the source, it is left out",
+                    errors::dg_directive(error.kind),
+                    error.line_num
+                ));
+                continue;
+            }
+            // Each directive goes below the previous ones of the line
+            let below = inserted.entry(error.line_num).or_default();
+            error.relative_line_num = -i32::try_from(below.len() + 1)?;
+            let directive = render_error(error, options, &policy, selector, &mut report);
+            if !directive.is_empty() {
+                below.push((directive, error.clone()));
             }
         } else {
-            // TODO: This is not the efficient way to find respective line number
-            for error in errors.iter() {
-                // Checking the original line number
-                if (i32::try_from(error.line_num)? - error.relative_line_num)
-                    != i32::try_from(line_num)?
-                {
-                    continue;
-                }
-                // In rustc test suites, the error directive is
-                // on the same line or on the next line, but not on the previous line
-                // See this: https://rustc-dev-guide.rust-lang.org/tests/ui.html#error-annotations
-                // For the error on the next line
-                if error.relative_line_num != 0 {
-                    // We simply add the error message, not to worry about the code
-                    // The error was printed by our overloaded `Display` trait
-                    new_line = format!("{}", error);
-                } else {
-                    // For the error on the same line, we need to add error message at the end of the line
-                    let captures = captures_regex
-                        .captures(line)
-                        .expect("Could not find the error directive");
-
-                    // Get the part of comment before the sigil (e.g. `~^` or ~|)
-                    let whole_match = captures.get(0).unwrap();
-                    // Get the existing source code before the error directive //~ ERROR or similar to this
-                    let before_match = &line[..whole_match.start()];
-
-                    // The error was printed by our overloaded `Display` trait
-                    new_line = format!("{}{}", before_match, error);
+            // Checking the original line number
+            let annotation_line = i32::try_from(error.line_num)? - error.relative_line_num;
+            // A repeated annotation is removed like a dropped one, the first one expects
+            // the diagnostic
+            if duplicates.contains(&index) {
+                rendered.push((annotation_line, String::new()));
+                continue;
+            }
+            let directive = render_error(error, options, &policy, selector, &mut report);
+            if error.is_whole_file() {
+                if !directive.is_empty() {
+                    hoisted.push(directive);
                 }
-                break;
+                rendered.push((annotation_line, String::new()));
+            } else {
+                rendered.push((annotation_line, directive));
+            }
+        }
+        let directive = errors::dg_directive(error.kind);
+        if renders_directive(error, options, &policy) && dejagnu::is_diagnostic(directive) {
+            expected.push(Expectation {
+                line: error.line_num,
+                directive,
+            });
+        }
+    }
+    // The lines from each annotation to its target, no directive can go in between
+    let spans: Vec<(usize, usize)> = if from_stderr {
+        Vec::new()
+    } else {
+        errors
+            .iter()
+            .filter(|error| !error.is_whole_file())
+            .filter_map(|error| {
+                let annotation_line =
+                    i64::try_from(error.line_num).ok()? - i64::from(error.relative_line_num);
+                let annotation_line = usize::try_from(annotation_line).ok()?;
+                Some((
+                    error.line_num.min(annotation_line),
+                    error.line_num.max(annotation_line),
+                ))
+            })
+            .collect()
+    };
+    for mut note in notes {
+        if note.line_num > code_lines {
+            report.warnings.push(format!(
+                "the {} of line {} in the stderr file is past the end of the source, it is left out",
+                errors::dg_directive(note.kind),
+                note.line_num
+            ));
+            continue;
+        }
+        // Below the annotations which target its line or a line across it
+        let mut below_line = note.line_num;
+        while let Some(end) = spans
+            .iter()
+            .filter(|&&(start, end)| start <= below_line && below_line < end)
+            .map(|&(_, end)| end)
+            .max()
+        {
+            below_line = end;
+        }
+        let below = inserted.entry(below_line).or_default();
+        note.relative_line_num = -i32::try_from(below_line - note.line_num + below.len() + 1)?;
+        let directive = render_error(&note, options, &policy, selector, &mut report);
+        let name = errors::dg_directive(note.kind);
+        if directive.is_empty() {
+            continue;
+        }
+        if renders_directive(&note, options, &policy) && dejagnu::is_diagnostic(name) {
+            expected.push(Expectation {
+                line: note.line_num,
+                directive: name,
+            });
+        }
+        below.push((directive, note));
+    }
+    // The `error-pattern` headers are expected anywhere in the file
+    for header in &additional_options {
+        if matches!(header.directive, header::Directive::ErrorPattern(_))
+            && !header.dejagnu_header.is_empty()
+        {
+            expected.push(Expectation {
+                line: 0,
+                directive: "dg-error",
+            });
+        }
+    }
+    let mut checker = roundtrip::Checker::new(expected);
+
+    // Everything the lints look at is known before the first line is written
+    if options.lint || !options.deny.is_empty() {
+        let findings = lint::check(&errors, stderr.is_some(), options, &policy);
+        let denied: Vec<_> = findings
+            .iter()
+            .filter(|finding| options.deny.contains(&finding.lint))
+            .cloned()
+            .collect();
+        if !denied.is_empty() {
+            return Err(DeniedLints(denied).into());
+        }
+        report.lints = findings;
+    }
+
+    // A forced proc-macro test says so before anything else
+    if let Some(header) = header::proc_macro_header(code).filter(|_| options.convert_anyway) {
+        first_lines.insert(
+            0,
+            format!("// WARNING: proc-macro test (`{header}`) converted with --convert-anyway, gccrs cannot build it yet"),
+        );
+    }
+
+    for directive in hoisted {
+        checker.line(0, &directive);
+        first_lines.push(directive);
+    }
+
+    // `dg-do` comes before every other directive
+    for line in &first_lines {
+        writer.write_all(line.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    // The line of the emitted file being written, which the absolute line numbers count
+    let mut output_line = first_lines.len();
+
+    // The error of each annotation line. Each `//~|` of a chain has its own line, which its
+    // directive replaces with the offset of the target from there, so that the directives
+    // keep the order of the annotations.
+    let mut on_line: BTreeMap<i32, usize> = BTreeMap::new();
+    for (index, (annotation_line, _)) in rendered.iter().enumerate() {
+        on_line.entry(*annotation_line).or_insert(index);
+    }
+    // The directive of each header line, the first one when a line has several
+    let mut header_on_line: BTreeMap<usize, &str> = BTreeMap::new();
+    for header in &additional_options {
+        header_on_line
+            .entry(header.line_number)
+            .or_insert(&header.dejagnu_header);
+    }
+
+    // finding the respective line number and adding the error code
+    for (line_num, line) in (1..).zip(code.lines()) {
+        // The output line is `prefix` followed by `suffix`, both borrowed,
+        // so that unchanged lines are written without any allocation
+        let (mut prefix, mut suffix) = (line, "");
+        // The error whose directive the line holds
+        let mut directive_of = None;
+        output_line += 1;
+
+        if is_header_line(line) {
+            if let Some(header) = header_on_line.get(&line_num) {
+                prefix = header;
             }
+        } else if let Some(&index) = on_line.get(&i32::try_from(line_num)?) {
+            let (error, (_, rendered)) = (&errors[index], &rendered[index]);
+            // In rustc test suites, the error directive is
+            // on the same line or on the next line, but not on the previous line
+            // See this: https://rustc-dev-guide.rust-lang.org/tests/ui.html#error-annotations
+            // The code before the annotation is where the parser found its sigil, a
+            // `//` in a string or in the message is no sigil
+            let before_match = error
+                .annotation_span
+                .and_then(|(start, _)| line.get(..start))
+                .ok_or(errors::AnnotationError::MissingAnnotation { line: line_num })?;
+            // The directive of a `//~?` is at the top, only the code before it is left
+            if error.relative_line_num != 0
+                && !error.is_whole_file()
+                && before_match.trim().is_empty()
+            {
+                // A line holding only the annotation becomes the directive
+                prefix = rendered;
+            } else if rendered.is_empty() {
+                prefix = before_match.trim_end();
+            } else {
+                // The code before the annotation stays, whatever line it targets
+                prefix = before_match;
+                suffix = rendered;
+            }
+            if !rendered.is_empty() {
+                directive_of = Some(error);
+            }
+        }
+        // Lines copied verbatim from the input are not our responsibility
+        if prefix != line || !suffix.is_empty() {
+            let length = prefix.chars().count() + suffix.chars().count();
+            check_line_length(line_num, length, options.max_line_length, &mut report);
+            if suffix.is_empty() {
+                checker.line(line_num, prefix);
+            } else {
+                checker.line(line_num, &[prefix, suffix].concat());
+            }
+        }
+        // The round trip reads the offsets, only the written line has the number
+        let absolute = directive_of
+            .and_then(|error| absolute_directive(error, output_line, options, &policy, selector));
+        match &absolute {
+            Some(directive) if suffix.is_empty() => prefix = directive,
+            Some(directive) => suffix = directive,
+            None => {}
+        }
+        writer.write_all(prefix.as_bytes())?;
+        writer.write_all(suffix.as_bytes())?;
+        writer.write_all(b"\n")?;
+        for (offset, (directive, error)) in
+            (1..).zip(inserted.remove(&line_num).unwrap_or_default())
+        {
+            output_line += 1;
+            check_line_length(
+                line_num,
+                directive.chars().count(),
+                options.max_line_length,
+                &mut report,
+            );
+            // Numbered past the line they go below, so that their `.-N` targets it
+            checker.line(line_num + offset, &directive);
+            let absolute = absolute_directive(&error, output_line, options, &policy, selector);
+            writer.write_all(absolute.as_deref().unwrap_or(&directive).as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+    }
+
+    let mismatches = checker.finish();
+    if options.strict && !mismatches.is_empty() {
+        return Err(RoundTripError(mismatches).into());
+    }
+    report.warnings.extend(
+        mismatches
+            .iter()
+            .map(|mismatch| format!("round trip: {mismatch}")),
+    );
+
+    Ok(report)
+}
+
+/// The directive of `error` written on the line `output_line` of the emitted file, targeting
+/// its line by number. `None` keeps the rendered one, with [`LineNumbers::Relative`] or for a
+/// `//~?`, which has no line.
+fn absolute_directive(
+    error: &errors::Error,
+    output_line: usize,
+    options: &Options,
+    policy: &header::AnnotationPolicy,
+    selector: errors::Selector,
+) -> Option<String> {
+    if options.line_numbers != LineNumbers::Absolute || error.is_whole_file() {
+        return None;
+    }
+    let target = output_line.checked_add_signed(isize::try_from(error.relative_line_num).ok()?)?;
+    let error = errors::Error {
+        target_line: Some(target),
+        ..error.clone()
+    };
+    // Rendered a second time, the report already counts the first one
+    Some(render_error(
+        &error,
+        options,
+        policy,
+        selector,
+        &mut TransformReport::default(),
+    ))
+}
+
+/// Renders the `DejaGnu` directive of an error, embedding its message as configured
+fn render_directive(
+    error: &errors::Error,
+    options: &Options,
+    selector: errors::Selector,
+    report: &mut TransformReport,
+) -> String {
+    let message =
+        errors::message_pattern_within(&error.msg, options.msg_style, options.max_message_length);
+    if message.truncated > 0 {
+        report.messages_truncated += 1;
+        report.truncated_chars += message.truncated;
+    }
+    let mut pattern = errors::prefixed_pattern(error.kind, &message.pattern);
+    // gcc ends its warnings with the option enabling them, rustc with the lint
+    if let (errors::MsgStyle::Portable, Some(errors::RustcErrorKind::Warning), Some(lint)) =
+        (options.msg_style, error.kind, &error.lint)
+    {
+        if !pattern.is_empty() {
+            pattern.push_str(".*");
+        }
+        pattern.push_str(&errors::escape_dejagnu(&format!("[{lint}]")));
+    }
+    let directive = match error.column.filter(|_| options.columns) {
+        Some(column) => error.to_directive_for(&format!("{column}: {pattern}"), selector),
+        None => error.to_directive_for(&pattern, selector),
+    };
+    #[cfg(test)]
+    if let Some(hook) = tests::RENDER_HOOK.get() {
+        return hook(error, directive);
+    }
+    directive
+}
+
+/// Reports a generated line of `length` characters longer than `max_line_length`.
+///
+/// The check never modifies anything. A limit of `0` disables it.
+pub fn check_line_length(
+    line_num: usize,
+    length: usize,
+    max_line_length: usize,
+    report: &mut TransformReport,
+) {
+    if max_line_length != 0 && length > max_line_length {
+        report.long_lines.push(line_num);
+        report.warnings.push(format!(
+            "line {line_num} is {length} characters long, exceeding the limit of {max_line_length}"
+        ));
+    }
+}
+
+/// Whether `error` is rendered as a directive rather than a comment or nothing
+pub(crate) fn renders_directive(
+    error: &errors::Error,
+    options: &Options,
+    policy: &header::AnnotationPolicy,
+) -> bool {
+    error.applies_to(options.revision.as_deref())
+        && !policy.is_optional(error.kind)
+        && (!error.kind.is_some_and(|kind| kind.is_secondary())
+            || options.secondary == SecondaryMode::Convert)
+}
+
+/// What the conversion does with the annotation of `error`
+fn annotation_support(
+    error: &errors::Error,
+    options: &Options,
+    policy: &header::AnnotationPolicy,
+) -> Support {
+    if error.kind.is_none() {
+        // Converted into a `dg-error`, which may not be what the test meant
+        Support::Unknown
+    } else if !renders_directive(error, options, policy) {
+        Support::Dropped
+    } else if dejagnu::is_diagnostic(errors::dg_directive(error.kind)) {
+        Support::Handled
+    } else {
+        // Rendered into something which is not a `DejaGnu` directive
+        Support::Unknown
+    }
+}
+
+/// Renders a single error according to the secondary annotation mode and message style.
+/// An empty string means the annotation was dropped.
+///
+/// `selector` is the one of the directive, [`errors::Selector::ANY`] or the one of the
+/// `only-<target>` and `known-bug` headers of the test. The annotations `policy` makes
+/// optional are plain comments.
+pub(crate) fn render_error(
+    error: &errors::Error,
+    options: &Options,
+    policy: &header::AnnotationPolicy,
+    selector: errors::Selector,
+    report: &mut TransformReport,
+) -> String {
+    // The annotations of the other revisions are removed like dropped ones
+    if !error.applies_to(options.revision.as_deref()) {
+        return String::new();
+    }
+    if let (true, Some(kind)) = (policy.is_optional(error.kind), error.kind) {
+        return comment_annotation(kind, &error.msg);
+    }
+    let kind = match error.kind {
+        Some(kind) if kind.is_secondary() => kind,
+        _ => return render_directive(error, options, selector, report),
+    };
+    match options.secondary {
+        SecondaryMode::Convert => {
+            report.secondary_converted += 1;
+            render_directive(error, options, selector, report)
+        }
+        SecondaryMode::Comment => {
+            report.secondary_commented += 1;
+            comment_annotation(kind, &error.msg)
+        }
+        SecondaryMode::Drop => {
+            // The annotation line is kept (empty) so that the relative
+            // offsets of the neighbouring directives stay valid
+            report.secondary_dropped += 1;
+            String::new()
         }
-        new_code.push_str(&new_line);
-        new_code.push('\n');
-        line_num += 1;
     }
+}
 
-    Ok(new_code)
+/// The plain comment keeping an annotation of `kind` which is not converted into a directive
+fn comment_annotation(kind: errors::RustcErrorKind, msg: &str) -> String {
+    let label = match kind {
+        errors::RustcErrorKind::Error => "error",
+        errors::RustcErrorKind::Warning => "warning",
+        errors::RustcErrorKind::Help => "help",
+        errors::RustcErrorKind::Note | errors::RustcErrorKind::Suggestion => "note",
+    };
+    format!("// rustc-{label}: {msg}")
 }
 
 #[cfg(test)]
 mod tests {
-    use super::*;
+    use {
+        super::*,
+        std::{fs, path, thread},
+    };
+
+    /// Rewrites a rendered directive
+    type RenderHook = fn(&errors::Error, String) -> String;
+
+    thread_local! {
+        /// Replaces the rendered directives, to break the renderer on purpose
+        pub(super) static RENDER_HOOK: std::cell::Cell<Option<RenderHook>> =
+            const { std::cell::Cell::new(None) };
+    }
+
+    /// A fixture from `tests/fixtures`: file name, source code and optional `.stderr` sibling
+    type Fixture = (String, String, Option<String>);
+
+    fn fixture_dir() -> path::PathBuf {
+        path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+    }
+
+    /// Loads the fixture corpus in a stable (sorted) order
+    fn fixture_corpus() -> Vec<Fixture> {
+        let mut paths: Vec<_> = fs::read_dir(fixture_dir())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rs"))
+            .collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .map(|path| {
+                let code = fs::read_to_string(&path).unwrap();
+                let stderr = fs::read_to_string(path.with_extension("stderr")).ok();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, code, stderr)
+            })
+            .collect()
+    }
+
+    const SECONDARY_FIXTURE: &str = "\
+fn main() {
+    foo(); //~ NOTE not found here
+    //~^ ERROR cannot find function
+    bar(); //~ HELP a function with a similar name exists
+}
+";
+
+    fn transform_secondary(secondary: SecondaryMode) -> (String, TransformReport) {
+        let options = Options {
+            secondary,
+            ..Options::default()
+        };
+        transform_code(SECONDARY_FIXTURE, None, &options).unwrap()
+    }
+
+    #[test]
+    fn test_output_directives() {
+        assert_eq!(
+            output_directives("one\r\n\ntwo {x}"),
+            [
+                r#"// { dg-output "one\r*\n" }"#,
+                r#"// { dg-output "\r*\n" }"#,
+                r#"// { dg-output "two \\\{x\\\}" }"#,
+            ]
+        );
+        assert!(output_directives("").is_empty());
+    }
+
+    #[test]
+    fn test_dropped_headers_are_reported_once() {
+        let code = "//@ normalize-stderr-test: \"a\" -> \"b\"\n//@ normalize-stderr-test: \"c\" -> \"d\"\n//@ dont-check-compiler-stdout\nfn main() {}\n";
+        let (new_code, report) = transform_code(code, None, &Options::default()).unwrap();
+        assert_eq!(new_code, "\n\n\nfn main() {}\n");
+        assert_eq!(
+            report.dropped_headers,
+            ["normalize-stderr-test", "dont-check-compiler-stdout"]
+        );
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_secondary_convert() {
+        let (new_code, report) = transform_secondary(SecondaryMode::Convert);
+        assert_eq!(
+            new_code,
+            "\
+fn main() {
+    foo(); // { dg-note \"\" \"\" { target *-*-* } }
+// { dg-error \"\" \"\" { target *-*-* } .-1 }
+    bar(); // { dg-message \"\" \"\" { target *-*-* } }
+}
+"
+        );
+        assert_eq!(report.secondary_converted, 2);
+        assert_eq!(report.secondary_commented, 0);
+        assert_eq!(report.secondary_dropped, 0);
+    }
+
+    /// The directive of the single annotation of kind `keyword`, with its message
+    fn render_kind(keyword: &str) -> String {
+        let code = format!("fn main() {{}} //~ {keyword} the message\n");
+        let options = Options {
+            msg_style: errors::MsgStyle::Escaped,
+            ..Options::default()
+        };
+        let (new_code, report) = transform_code(&code, None, &options).unwrap();
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        new_code
+    }
+
+    #[test]
+    fn test_help_is_a_message_directive() {
+        assert_eq!(
+            render_kind("HELP"),
+            "fn main() {} // { dg-message \"help: the message\" \"\" { target *-*-* } }\n"
+        );
+    }
+
+    #[test]
+    fn test_suggestion_is_a_message_directive() {
+        assert_eq!(
+            render_kind("SUGGESTION"),
+            "fn main() {} // { dg-message \"help: the message\" \"\" { target *-*-* } }\n"
+        );
+    }
+
+    #[test]
+    fn test_note_is_a_note_directive() {
+        assert_eq!(
+            render_kind("NOTE"),
+            "fn main() {} // { dg-note \"the message\" \"\" { target *-*-* } }\n"
+        );
+    }
+
+    #[test]
+    fn test_secondary_comment() {
+        let (new_code, report) = transform_secondary(SecondaryMode::Comment);
+        assert_eq!(
+            new_code,
+            "\
+fn main() {
+    foo(); // rustc-note: not found here
+// { dg-error \"\" \"\" { target *-*-* } .-1 }
+    bar(); // rustc-help: a function with a similar name exists
+}
+"
+        );
+        assert_eq!(report.secondary_converted, 0);
+        assert_eq!(report.secondary_commented, 2);
+        assert_eq!(report.secondary_dropped, 0);
+    }
+
+    #[test]
+    fn test_secondary_drop() {
+        let (new_code, report) = transform_secondary(SecondaryMode::Drop);
+        assert_eq!(
+            new_code,
+            "\
+fn main() {
+    foo();
+// { dg-error \"\" \"\" { target *-*-* } .-1 }
+    bar();
+}
+"
+        );
+        assert_eq!(report.secondary_converted, 0);
+        assert_eq!(report.secondary_commented, 0);
+        assert_eq!(report.secondary_dropped, 2);
+    }
+
+    #[test]
+    fn test_output_is_deterministic_across_runs_and_threads() {
+        let corpus = fixture_corpus();
+        assert!(!corpus.is_empty());
+        let convert = |(_, code, stderr): &Fixture| {
+            transform_code(code, stderr.as_deref(), &Options::default()).unwrap()
+        };
+
+        let first: Vec<_> = corpus.iter().map(convert).collect();
+        let second: Vec<_> = corpus.iter().map(convert).collect();
+        assert_eq!(first, second);
+
+        // Spread the corpus over 8 threads and put the results back in corpus order
+        let jobs = 8;
+        let mut parallel: Vec<_> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..jobs)
+                .map(|worker| {
+                    let corpus = &corpus;
+                    scope.spawn(move || {
+                        corpus
+                            .iter()
+                            .enumerate()
+                            .filter(|(index, _)| index % jobs == worker)
+                            .map(|(index, fixture)| (index, convert(fixture)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+        parallel.sort_by_key(|(index, _)| *index);
+        let parallel: Vec<_> = parallel.into_iter().map(|(_, result)| result).collect();
+
+        for ((name, _, _), (sequential, parallel)) in corpus.iter().zip(first.iter().zip(&parallel))
+        {
+            assert_eq!(
+                sequential, parallel,
+                "fixture `{name}` differs between runs"
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_generated_lines_are_reported() {
+        let long_code = format!("let {} = 1;", "a".repeat(40));
+        let code = format!(
+            "fn main() {{\n    {long_code}\n    undefined_fn(); //~ ERROR cannot find function\n}}\n"
+        );
+        let options = Options {
+            max_line_length: 40,
+            ..Options::default()
+        };
+        let (new_code, report) = transform_code(&code, None, &options).unwrap();
+        assert_eq!(report.long_lines, [3]);
+        assert_eq!(
+            report.warnings,
+            ["line 3 is 58 characters long, exceeding the limit of 40"]
+        );
+        // The output is never modified by the check
+        assert_eq!(
+            transform_code(&code, None, &Options::default()).unwrap().0,
+            new_code
+        );
+    }
+
+    #[test]
+    fn test_minified_lines_are_copied_without_scanning() {
+        // A generated line of a few hundred kilobytes, ending with an annotation
+        let minified = format!("{} //~ ERROR never converted", "x+".repeat(150_000));
+        let code = format!("fn main() {{\n{minified}\n    f(); //~ ERROR converted\n}}\n");
+        let (new_code, report) = transform_code(&code, None, &Options::default()).unwrap();
+        let lines: Vec<_> = new_code.lines().collect();
+        assert_eq!(lines[1], minified);
+        assert_eq!(
+            lines[2],
+            "    f(); // { dg-error \"\" \"\" { target *-*-* } }"
+        );
+        assert_eq!(
+            report.warnings,
+            ["line 2 is 300026 bytes long, not scanning it for annotations (limit is 65536)"]
+        );
+    }
+
+    #[test]
+    fn test_line_length_check_is_disabled_by_default() {
+        let code = format!("//~ ERROR {}\n", "a".repeat(200));
+        let (_, report) = transform_code(&code, None, &Options::default()).unwrap();
+        assert!(report.long_lines.is_empty());
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_repeated_annotations_are_removed() {
+        let code = "\
+fn main() {
+    let x: i32 = \"\"; //~ ERROR mismatched types
+    //~^ ERROR mismatched types
+    //~| ERROR mismatched types
+    //~| WARN mismatched types
+}
+";
+        let transform_with = |dedup| {
+            let options = Options {
+                msg_style: errors::MsgStyle::Escaped,
+                dedup,
+                ..Options::default()
+            };
+            transform_code(code, None, &options).unwrap()
+        };
+
+        let (new_code, report) = transform_with(true);
+        assert_eq!(
+            new_code,
+            "\
+fn main() {
+    let x: i32 = \"\"; // { dg-error \"mismatched types\" \"\" { target *-*-* } }
+
+
+// { dg-warning \"mismatched types\" \"\" { target *-*-* } .-3 }
+}
+"
+        );
+        assert_eq!(report.stats.duplicates, 2);
+        assert_eq!(report.stats.annotations, 4);
+
+        // rustc may emit the same error twice as well
+        let (new_code, report) = transform_with(false);
+        assert_eq!(new_code.matches("dg-error").count(), 3);
+        assert_eq!(report.stats.duplicates, 0);
+    }
+
+    #[test]
+    fn test_msg_style() {
+        let code = "\
+fn main() {
+    undefined_fn(); //~ ERROR cannot find function
+    let _ = (; //~ ERROR expected expression, found `;`
+    let _ = [; //~ ERROR (unclosed delimiter
+}
+";
+        let transform_with = |msg_style| {
+            let options = Options {
+                msg_style,
+                ..Options::default()
+            };
+            transform_code(code, None, &options).unwrap()
+        };
+
+        let (new_code, report) = transform_with(errors::MsgStyle::None);
+        assert_eq!(
+            transform_code(code, None, &Options::default()).unwrap().0,
+            new_code
+        );
+        assert_eq!(report.messages_truncated, 0);
+
+        let (new_code, report) = transform_with(errors::MsgStyle::Prefix);
+        assert_eq!(
+            new_code,
+            "\
+fn main() {
+    undefined_fn(); // { dg-error \"cannot find function\" \"\" { target *-*-* } }
+    let _ = (; // { dg-error \"expected expression, found `;`\" \"\" { target *-*-* } }
+    let _ = [; // { dg-error \"\" \"\" { target *-*-* } }
+}
+"
+        );
+        assert_eq!(report.messages_truncated, 1);
+        assert_eq!(report.truncated_chars, "(unclosed delimiter".len());
+
+        let (new_code, report) = transform_with(errors::MsgStyle::Escaped);
+        assert!(new_code.contains("{ dg-error \"\\\\(unclosed delimiter\" \"\""));
+        assert_eq!(report.messages_truncated, 0);
+    }
+
+    #[test]
+    fn test_edition_headers_of_every_style() {
+        for header in ["// edition:2018", "//@ edition: 2018", "//@edition:2018"] {
+            let code = format!("{header}\n// This test needs the 2018 edition\nfn main() {{}}\n");
+            let (new_code, report) = transform_code(&code, None, &Options::default()).unwrap();
+            assert_eq!(
+                new_code,
+                "// { dg-additional-options \"-frust-edition=2018\" }\n// This test needs the 2018 edition\nfn main() {}\n",
+                "{header}"
+            );
+            assert_eq!(report.coverage.headers["edition"].support, Support::Handled);
+        }
+    }
+
+    #[test]
+    fn test_only_headers_restrict_the_selectors() {
+        let code =
+            "//@ ignore-windows\n//@ only-x86_64\nfn main() {\n    let x = 1; //~ WARN unused\n}\n";
+        let (new_code, report) = transform_code(code, None, &Options::default()).unwrap();
+        assert_eq!(
+            new_code,
+            "// { dg-skip-if \"\" { *-*-mingw* } }\n// only-x86_64\nfn main() {\n    let x = 1; // { dg-warning \"\" \"\" { target x86_64-*-* } }\n}\n"
+        );
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert_eq!(
+            report.coverage.headers["only-x86_64"].support,
+            Support::Handled
+        );
+    }
+
+    #[test]
+    fn test_error_patterns_are_expected_anywhere() {
+        let code = "//@ error-pattern: cannot find value `x`\n//@ error-pattern: aborting\nfn main() { x }\n";
+        let options = Options {
+            strict: true,
+            ..Options::default()
+        };
+        let (new_code, report) = transform_code(code, None, &options).unwrap();
+        assert_eq!(
+            new_code,
+            "// { dg-error \"cannot find value `x`\" \"\" { target *-*-* } 0 }\n// { dg-error \"aborting\" \"\" { target *-*-* } 0 }\nfn main() { x }\n"
+        );
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    }
+
+    #[test]
+    fn test_known_bugs_expect_their_errors_to_fail() {
+        let code = "//@ known-bug: #12345\n//@ error-pattern: aborting\nfn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n    let y = 1; //~ WARN unused\n}\n";
+        let options = Options {
+            strict: true,
+            ..Options::default()
+        };
+        let (new_code, report) = transform_code(code, None, &options).unwrap();
+        assert_eq!(
+            new_code,
+            "// rustc known-bug: #12345
+
+// { dg-error \"aborting\" \"\" { xfail *-*-* } 0 }
+fn main() {
+    let x: i32 = \"\"; // { dg-error \"\" \"\" { xfail *-*-* } }
+    let y = 1; // { dg-warning \"\" \"\" { target *-*-* } }
+}
+"
+        );
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert_eq!(report.skipped, None);
+
+        // A crash expects nothing else
+        let (new_code, _) =
+            transform_code("//@ known-bug: #98765\nfn main() {}\n", None, &options).unwrap();
+        assert_eq!(
+            new_code,
+            "// rustc known-bug: #98765\n// { dg-ice \"\" }\n\nfn main() {}\n"
+        );
+    }
+
+    #[test]
+    fn test_fixture_corpus_matches_expected_output() {
+        for (name, code, stderr) in fixture_corpus() {
+            let expected_path = fixture_dir().join(&name).with_extension("expected");
+            let expected = fs::read_to_string(expected_path).unwrap();
+            let (new_code, _) =
+                transform_code(&code, stderr.as_deref(), &Options::default()).unwrap();
+            assert_eq!(new_code, expected, "fixture `{name}` differs");
+        }
+    }
+
+    #[test]
+    fn test_follow_chains_target_the_line_they_follow() {
+        let code = fs::read_to_string(fixture_dir().join("follow_chain.rs")).unwrap();
+        for edition in [None, Some("2021".to_owned())] {
+            let options = Options {
+                edition,
+                ..Options::default()
+            };
+            let (new_code, _) = transform_code(&code, None, &options).unwrap();
+            // The `--edition` directive comes first, moving the source down a line
+            let shift = new_code
+                .lines()
+                .position(|line| line == "fn main() {")
+                .unwrap();
+            let targets: Vec<_> = dejagnu::scan(&new_code)
+                .iter()
+                .filter_map(|(line_num, directive)| directive.target_line(*line_num))
+                .filter(|&line| line > shift)
+                .map(|line| line - shift)
+                .collect();
+            assert_eq!(targets, [2, 2, 2, 2, 7, 7, 7], "{new_code}");
+        }
+    }
+
+    #[test]
+    fn test_offsets_target_the_annotated_lines() {
+        let code = fs::read_to_string(fixture_dir().join("offsets.rs")).unwrap();
+        let (new_code, _) = transform_code(&code, None, &Options::default()).unwrap();
+        // Each annotation line is replaced in place, the lines stay where they are
+        assert_eq!(new_code.lines().count(), code.lines().count());
+        let targets: Vec<_> = dejagnu::scan(&new_code)
+            .iter()
+            .filter(|(_, directive)| dejagnu::is_diagnostic(&directive.name))
+            .filter_map(|(line_num, directive)| directive.target_line(*line_num))
+            .collect();
+        let lines: Vec<_> = errors::load_error(&code, None)
+            .unwrap()
+            .iter()
+            .map(|error| error.line_num)
+            .collect();
+        assert_eq!(targets, lines);
+        assert_eq!(targets, [3, 3, 7, 7, 12, 12, 3]);
+    }
 
     #[test]
-    fn test_transform() {
-        // as suggested by @CohenArthur, we only need to add error code in msg
-        let dg_msg = "// { dg-error \"\" \"\" { target *-*-* } .-1 }\n";
-        let rust_msg = "//~^ ERROR expected one of `:`, `@`, or `|`, found `)`";
-        assert_eq!(transform_code(rust_msg, None).unwrap(), dg_msg);
+    fn test_every_annotation_of_a_line_is_converted() {
+        let code = "\
+fn main() {
+    //~vv ERROR first
+    //~| ERROR second
+    let x: i32 = \"\"; //~ ERROR third
+    //~^ ERROR fourth
+    //~| NOTE fifth
+}
+";
+        let (new_code, _) = transform_code(code, None, &Options::default()).unwrap();
+        let targets: Vec<_> = dejagnu::scan(&new_code)
+            .iter()
+            .filter_map(|(line_num, directive)| directive.target_line(*line_num))
+            .collect();
+        assert_eq!(targets, [4, 4, 4, 4, 4], "{new_code}");
+    }
+
+    #[test]
+    fn test_large_files_are_converted_in_linear_time() {
+        // About 5,000 lines, one in six an annotation of the line above it
+        let mut code = String::from("fn main() {\n");
+        for index in 0..800 {
+            code.push_str(&format!(
+                "    let x{index}: i32 = \"\";\n    //~^ ERROR mismatched types\n"
+            ));
+            code.push_str(&"    let _ = 1;\n".repeat(4));
+        }
+        code.push_str("}\n");
+        let start = std::time::Instant::now();
+        let (new_code, report) = transform_code(&code, None, &Options::default()).unwrap();
+        // The conversion takes milliseconds, the bound only has to catch a blowup
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert_eq!(new_code.lines().count(), code.lines().count());
+        let targets: Vec<_> = dejagnu::scan(&new_code)
+            .iter()
+            .filter_map(|(line_num, directive)| directive.target_line(*line_num))
+            .collect();
+        let lines: Vec<_> = (0..800).map(|index| 2 + index * 6).collect();
+        assert_eq!(targets, lines);
+    }
+
+    #[test]
+    fn test_streamed_output_matches_buffered_output() {
+        for (name, code, stderr) in fixture_corpus() {
+            let (buffered, buffered_report) =
+                transform_code(&code, stderr.as_deref(), &Options::default()).unwrap();
+            let mut streamed = io::BufWriter::new(Vec::new());
+            let streamed_report =
+                transform_to(&mut streamed, &code, stderr.as_deref(), &Options::default()).unwrap();
+            let streamed = streamed.into_inner().unwrap();
+            assert_eq!(streamed, buffered.as_bytes(), "fixture `{name}` differs");
+            assert_eq!(streamed_report, buffered_report);
+        }
+    }
+
+    /// A writer accepting a limited number of bytes before failing
+    struct FailingWriter {
+        remaining: usize,
+    }
+
+    impl io::Write for FailingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.remaining == 0 {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "writer is full"));
+            }
+            let written = buf.len().min(self.remaining);
+            self.remaining -= written;
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_write_errors_are_propagated() {
+        let code = "fn main() {\n    let x: i32 = \"a\";\n    //~^ ERROR mismatched types\n}\n";
+        let mut writer = FailingWriter { remaining: 20 };
+        let error = transform_to(&mut writer, code, None, &Options::default()).unwrap_err();
+        let error = error.downcast::<io::Error>().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_round_trip_is_clean() {
+        for (name, code, stderr) in fixture_corpus() {
+            for secondary in [
+                SecondaryMode::Convert,
+                SecondaryMode::Comment,
+                SecondaryMode::Drop,
+            ] {
+                let options = Options {
+                    secondary,
+                    strict: true,
+                    ..Options::default()
+                };
+                let (_, report) = transform_code(&code, stderr.as_deref(), &options)
+                    .unwrap_or_else(|err| panic!("fixture `{name}`: {err:#}"));
+                assert!(
+                    !report.warnings.iter().any(|w| w.starts_with("round trip")),
+                    "fixture `{name}`: {:?}",
+                    report.warnings
+                );
+            }
+        }
+    }
+
+    /// Transforms `code` with a renderer broken by `hook`
+    fn transform_broken(
+        code: &str,
+        hook: RenderHook,
+        strict: bool,
+    ) -> Result<(String, TransformReport)> {
+        RENDER_HOOK.set(Some(hook));
+        let options = Options {
+            strict,
+            ..Options::default()
+        };
+        let result = transform_code(code, None, &options);
+        RENDER_HOOK.set(None);
+        result
+    }
+
+    const ROUND_TRIP_FIXTURE: &str = "\
+fn main() {
+    let x: i32 = \"\"; //~ ERROR mismatched types
+    let y = 1;
+    //~^ WARN unused variable
+}
+";
+
+    #[test]
+    fn test_round_trip_catches_a_broken_renderer() {
+        // Dropping the warning and pointing the error one line too far
+        let (_, report) = transform_broken(
+            ROUND_TRIP_FIXTURE,
+            |error, directive| match error.kind {
+                Some(errors::RustcErrorKind::Warning) => "//".to_owned(),
+                _ => directive.replace("} }", "} .+1 }"),
+            },
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            report.warnings,
+            [
+                "round trip: the `dg-error` directive of line 2 targets line 3, which has no such annotation",
+                "round trip: no `dg-error` directive was emitted for the annotation of line 2",
+                "round trip: no `dg-warning` directive was emitted for the annotation of line 3",
+            ]
+        );
+
+        let err = transform_broken(ROUND_TRIP_FIXTURE, |_, _| String::new(), true).unwrap_err();
+        let err = err.downcast::<RoundTripError>().unwrap();
+        assert_eq!(err.0.len(), 2);
+        assert_eq!(
+            err.to_string(),
+            "the output does not match the source annotations (2 mismatches)
+  no `dg-error` directive was emitted for the annotation of line 2
+  no `dg-warning` directive was emitted for the annotation of line 3"
+        );
+
+        // The hook is only installed for the broken transformations
+        let (_, report) = transform_code(ROUND_TRIP_FIXTURE, None, &Options::default()).unwrap();
+        assert_eq!(report.warnings, Vec::<String>::new());
     }
 }
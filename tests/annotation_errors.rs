@@ -0,0 +1,62 @@
+//! A malformed annotation fails the conversion of its file, showing the line it is on.
+
+use std::{fs, process::Command};
+
+#[test]
+fn malformed_annotations_report_their_line() {
+    let long = format!("ERROR {}", "x".repeat(100));
+    for (code, context, error) in [
+        (
+            "fn main() {\n    let x = 1; //~\n}\n".to_owned(),
+            "in `let x = 1; //~`".to_owned(),
+            "line 2: empty annotation after `//~`",
+        ),
+        (
+            "fn main() {}\n//~| NOTE nothing to follow\n".to_owned(),
+            "in `//~| NOTE nothing to follow`".to_owned(),
+            "line 2: `//~|` without a preceding annotation to follow",
+        ),
+        (
+            "fn main() {}\n//~^^^ ERROR too far up\n".to_owned(),
+            "in `//~^^^ ERROR too far up`".to_owned(),
+            "line 2: `//~^^^` points above the first line of the file",
+        ),
+        (
+            "//~^ ERROR above the file\nfn main() {}\n".to_owned(),
+            "in `//~^ ERROR above the file`".to_owned(),
+            "line 1: `//~^` points above the first line of the file",
+        ),
+        (
+            "fn main() {}\n//~vvvv ERROR below the file\n".to_owned(),
+            "in `//~vvvv ERROR below the file`".to_owned(),
+            "line 2: `//~vvvv` points below the last line of the file",
+        ),
+        (
+            format!("//~| {long}\n"),
+            format!("in `{}...`", &format!("//~| {long}")[..80]),
+            "line 1: `//~|` without a preceding annotation to follow",
+        ),
+    ] {
+        let dir = tempfile::tempdir().unwrap();
+        let test = dir.path().join("test.rs");
+        fs::write(&test, &code).unwrap();
+        let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+            .arg(&test)
+            .output()
+            .unwrap();
+        assert_eq!(result.status.code(), Some(3), "{code}");
+        let stderr = String::from_utf8(result.stderr).unwrap();
+        // A backtrace may follow, depending on the environment
+        assert!(
+            stderr.contains(&format!(
+                "could not transform code from file `{}`\n",
+                test.display()
+            )),
+            "{stderr}"
+        );
+        assert!(
+            stderr.contains(&format!("0: {context}\n    1: {error}\n")),
+            "{stderr}"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+//! Leaves out the diagnostics of the `stderr` file located in an auxiliary file, unless
+//! `--match-any-file` is given.
+
+use std::{fs, path::Path, process::Command};
+
+const TEST: &str = "fn main() {\n    let x: i32 = \"\"; //~ ERROR\n}\n";
+
+/// The error of the helper crate is on line 2 as well, and comes first
+const STDERR: &str = "\
+error[E0425]: cannot find value `y` in this scope
+  --> $DIR/auxiliary/helper.rs:2:5
+   |
+LL |     y
+   |     ^ not found in this scope
+
+error[E0308]: mismatched types
+  --> $DIR/test.rs:2:18
+   |
+LL |     let x: i32 = \"\";
+   |            ---   ^^ expected `i32`, found `&str`
+
+error: aborting due to 2 previous errors
+";
+
+fn convert(dir: &Path, args: &[&str]) -> (String, String) {
+    let test = dir.join("test.rs");
+    fs::write(&test, TEST).unwrap();
+    fs::write(dir.join("test.stderr"), STDERR).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .arg(&test)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{result:?}");
+    (
+        String::from_utf8(result.stdout).unwrap(),
+        String::from_utf8(result.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn auxiliary_diagnostics_are_left_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let (stdout, stderr) = convert(dir.path(), &["-v"]);
+    assert_eq!(
+        stdout,
+        "fn main() {\n    let x: i32 = \"\"; // { dg-error \".E0308.\" \"\" { target *-*-* } }\n}\n\n"
+    );
+    assert!(
+        stderr.contains(
+            "line 2: dg-error, code E0308, msg 'mismatched types'\n1 stderr entries located in another file than the source, left out\n"
+        ),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("matches no annotation"), "{stderr}");
+}
+
+#[test]
+fn match_any_file_keeps_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let (stdout, stderr) = convert(dir.path(), &["--match-any-file"]);
+    assert_eq!(
+        stdout,
+        "fn main() {\n    let x: i32 = \"\"; // { dg-error \".E0425.\" \"\" { target *-*-* } }\n}\n\n"
+    );
+    assert!(
+        stderr.contains(
+            "warning: line 2: error[E0308]: mismatched types, in the stderr file, matches no annotation\n"
+        ),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,152 @@
+//! Runs the conversion cases of `tests/cases.toml`.
+//!
+//! Each `[[case]]` has a `name`, an `input` and either the `expected` output or the
+//! `error` the conversion fails with. It may also have a `stderr` file content, an
+//! `options` table (`secondary`, `msg_style`, `max_line_length`, `strict`) and a
+//! `skip` reason. Failures are reported per case, with a diff.
+//!
+//! Run `UPDATE_CASES=1 cargo test --test cases` to write the current results back as the
+//! expectations of every case which is not skipped, then review the changes.
+
+use {
+    clap::ValueEnum,
+    rusttest_to_dg::{diff, transform},
+    std::{env, fs, path::Path},
+    toml_edit::{DocumentMut, Item, Table},
+};
+
+/// The result of converting a case: the output, or the error message
+type Outcome = Result<String, String>;
+
+fn string<'a>(case: &'a Table, key: &str) -> Result<Option<&'a str>, String> {
+    match case.get(key) {
+        None => Ok(None),
+        Some(item) => item
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| format!("`{key}` is not a string")),
+    }
+}
+
+fn options(case: &Table) -> Result<transform::Options, String> {
+    let mut options = transform::Options::default();
+    let Some(table) = case.get("options") else {
+        return Ok(options);
+    };
+    let table = table.as_table_like().ok_or("`options` is not a table")?;
+    for (key, value) in table.iter() {
+        let text = value.as_str();
+        match key {
+            "secondary" => {
+                options.secondary =
+                    ValueEnum::from_str(text.ok_or("`secondary` is not a string")?, false)?
+            }
+            "msg_style" => {
+                options.msg_style =
+                    ValueEnum::from_str(text.ok_or("`msg_style` is not a string")?, false)?
+            }
+            "max_line_length" => {
+                let length = value
+                    .as_integer()
+                    .ok_or("`max_line_length` is not an integer")?;
+                options.max_line_length = usize::try_from(length).map_err(|e| e.to_string())?
+            }
+            "strict" => options.strict = value.as_bool().ok_or("`strict` is not a boolean")?,
+            _ => return Err(format!("unknown option `{key}`")),
+        }
+    }
+    Ok(options)
+}
+
+/// Converts a case, failing if the case itself is malformed
+fn convert(case: &Table) -> Result<Outcome, String> {
+    let input = string(case, "input")?.ok_or("missing `input`")?;
+    let stderr = string(case, "stderr")?;
+    let options = options(case)?;
+    Ok(transform::transform_code(input, stderr, &options)
+        .map(|(new_code, _)| new_code)
+        .map_err(|err| format!("{err:#}")))
+}
+
+/// What the case expects
+fn expectation(case: &Table) -> Result<Outcome, String> {
+    match (string(case, "expected")?, string(case, "error")?) {
+        (Some(expected), None) => Ok(Ok(expected.to_owned())),
+        (None, Some(error)) => Ok(Err(error.to_owned())),
+        _ => Err("expected exactly one of `expected` and `error`".to_owned()),
+    }
+}
+
+/// Describes how `actual` differs from `expected`
+fn describe(expected: &Outcome, actual: &Outcome) -> String {
+    match (expected, actual) {
+        (Ok(expected), Ok(actual)) if expected.lines().eq(actual.lines()) => {
+            "the outputs only differ in their final newline\n".to_owned()
+        }
+        (Ok(expected), Ok(actual)) => diff::unified("expected", "actual", expected, actual),
+        (Ok(_), Err(error)) => format!("conversion failed: {error}\n"),
+        (Err(expected), Ok(_)) => format!("conversion succeeded, expected error: {expected}\n"),
+        (Err(expected), Err(error)) => format!("-error: {expected}\n+error: {error}\n"),
+    }
+}
+
+#[test]
+fn cases() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases.toml");
+    let text = fs::read_to_string(&path).unwrap();
+    let mut document: DocumentMut = text.parse().unwrap();
+    let update = env::var_os("UPDATE_CASES").is_some();
+
+    let cases = document["case"]
+        .as_array_of_tables_mut()
+        .expect("`tests/cases.toml` has no `[[case]]`");
+    let mut failures = Vec::new();
+    let mut report = String::new();
+    for (index, case) in cases.iter_mut().enumerate() {
+        let name = string(case, "name")
+            .ok()
+            .flatten()
+            .map_or_else(|| format!("#{index}"), str::to_owned);
+        if let Ok(Some(reason)) = string(case, "skip") {
+            report.push_str(&format!("case `{name}` ... skipped: {reason}\n"));
+            continue;
+        }
+        let actual = match convert(case) {
+            Ok(actual) => actual,
+            Err(problem) => {
+                failures.push(format!("case `{name}` is malformed: {problem}\n"));
+                continue;
+            }
+        };
+        if update {
+            case.remove("expected");
+            case.remove("error");
+            match &actual {
+                Ok(new_code) => case["expected"] = Item::from(new_code.as_str()),
+                Err(error) => case["error"] = Item::from(error.as_str()),
+            }
+            report.push_str(&format!("case `{name}` ... updated\n"));
+            continue;
+        }
+        match expectation(case) {
+            Ok(expected) if expected == actual => {
+                report.push_str(&format!("case `{name}` ... ok\n"));
+            }
+            Ok(expected) => failures.push(format!(
+                "case `{name}` failed:\n{}",
+                describe(&expected, &actual)
+            )),
+            Err(problem) => failures.push(format!("case `{name}` is malformed: {problem}\n")),
+        }
+    }
+    if update {
+        fs::write(&path, document.to_string()).unwrap();
+    }
+    print!("{report}");
+    assert!(
+        failures.is_empty(),
+        "{} cases failed, run with UPDATE_CASES=1 to accept the new results\n\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,39 @@
+//! `--check` accepts a conversion redirected from `stdout` as well as one written with `-o`.
+
+use std::{fs, process::Command};
+
+#[test]
+fn redirected_output_is_up_to_date() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(
+        &test,
+        "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n}\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+            .arg(&test)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let redirected = dir.path().join("redirected.rs");
+    let result = run(&[]);
+    assert!(result.status.success(), "{result:?}");
+    fs::write(&redirected, &result.stdout).unwrap();
+    let written = dir.path().join("written.rs");
+    assert!(run(&["-o", written.to_str().unwrap()]).status.success());
+
+    for converted in [&redirected, &written] {
+        let result = run(&["--check", converted.to_str().unwrap()]);
+        assert!(result.status.success(), "{result:?}");
+        assert!(result.stdout.is_empty(), "{result:?}");
+    }
+
+    fs::write(&redirected, [&result.stdout[..], b"\n"].concat()).unwrap();
+    assert!(!run(&["--check", redirected.to_str().unwrap()])
+        .status
+        .success());
+}
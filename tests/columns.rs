@@ -0,0 +1,34 @@
+//! Matches the errors of a line to the `stderr` entries by column, and checks the columns
+//! with `--columns`.
+
+mod common;
+
+use {
+    common::{convert, fixture},
+    std::fs,
+};
+
+#[test]
+fn two_errors_on_a_line_get_the_entry_of_their_column() {
+    // rustc reports the name resolution error first, although it is further on the line
+    let stderr = fs::read_to_string(fixture("columns", "same_line.stderr")).unwrap();
+    assert!(stderr.find("E0425").unwrap() < stderr.find("E0308").unwrap());
+
+    let test = fixture("columns", "same_line.rs");
+    let test = test.to_str().unwrap();
+    let expected = fs::read_to_string(fixture("columns", "same_line.expected")).unwrap();
+    assert_eq!(convert(&[test, "--columns"]), format!("{expected}\n"));
+    assert_eq!(
+        convert(&[test, "--columns", "--msg-style", "escaped"]),
+        "fn main() {\n    let x: i32 = \"\"; let y = missing;\n// { dg-error \"18: mismatched types.*.E0308.\" \"\" { target *-*-* } .-1 }\n// { dg-error \"30: cannot find value `missing` in this scope.*.E0425.\" \"\" { target *-*-* } .-2 }\n}\n\n"
+    );
+}
+
+#[test]
+fn the_columns_are_only_checked_with_columns() {
+    let test = fixture("columns", "same_line.rs");
+    assert_eq!(
+        convert(&[test.to_str().unwrap()]),
+        "fn main() {\n    let x: i32 = \"\"; let y = missing;\n// { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }\n// { dg-error \".E0425.\" \"\" { target *-*-* } .-2 }\n}\n\n"
+    );
+}
@@ -0,0 +1,47 @@
+//! The test and the helpers shared by the integration tests.
+
+// Each test crate uses its own part of them
+#![allow(dead_code)]
+
+use {
+    clap::Parser,
+    rusttest_to_dg::cli::{self, Arguments},
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+/// A test converting without any warning
+pub const TEST: &str = "fn main() {\n    let x = 1; //~ WARN unused variable\n}\n";
+
+/// [`TEST`] converted with the default options
+pub const CONVERTED: &str =
+    "fn main() {\n    let x = 1; // { dg-warning \"\" \"\" { target *-*-* } }\n}\n";
+
+/// Writes `content` to `path`, creating its parent directories
+pub fn write(path: &Path, content: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+/// The `dir` directory of `tests/fixtures`
+pub fn fixtures(dir: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(dir)
+}
+
+/// The `name` file of the `dir` directory of `tests/fixtures`, or of `tests/fixtures` itself
+/// for an empty `dir`
+pub fn fixture(dir: &str, name: &str) -> PathBuf {
+    fixtures(dir).join(name)
+}
+
+/// What the conversion with the command line `args` writes to stdout
+pub fn convert(args: &[&str]) -> String {
+    let args = Arguments::try_parse_from(["rusttest-to-dg"].iter().chain(args)).unwrap();
+    let mut stdout = Vec::new();
+    cli::run(&args, &mut stdout).unwrap();
+    String::from_utf8(stdout).unwrap()
+}
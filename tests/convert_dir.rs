@@ -0,0 +1,329 @@
+//! Converts a small tree of tests with `--dir`, through the command line entry point.
+
+use {
+    clap::Parser,
+    rusttest_to_dg::{
+        batch,
+        cli::{self, Arguments},
+    },
+    std::{fs, path::Path},
+};
+
+/// Nested tests: one with a `stderr` file next to it, one which cannot be converted
+const TREE: &[(&str, &str)] = &[
+    (
+        "typeck/mismatch.rs",
+        "fn main() {\n    let x: i32 = \"\";\n    //~^ ERROR mismatched types\n}\n",
+    ),
+    (
+        "typeck/mismatch.stderr",
+        "error[E0308]: mismatched types\n  --> $DIR/mismatch.rs:2:18\n",
+    ),
+    (
+        "lint/nested/unused.rs",
+        "fn main() {\n    let x = 1; //~ WARN unused variable\n}\n",
+    ),
+    ("broken/orphan.rs", "//~| NOTE nothing to follow\n"),
+    ("notes.txt", "not a test\n"),
+];
+
+fn tree(dir: &Path) {
+    for (path, content) in TREE {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+#[test]
+fn every_file_is_converted_in_the_same_layout() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("ui");
+    let output = dir.path().join("out");
+    tree(&input);
+
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        input.to_str().unwrap(),
+        "--output-dir",
+        output.to_str().unwrap(),
+    ])
+    .unwrap();
+    let mut log = Vec::new();
+    let err = cli::run_dir(&args, &mut log).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("1 of 3 files under `{}` failed to convert", input.display())
+    );
+
+    // The `stderr` file was picked up on its own
+    assert_eq!(
+        fs::read_to_string(output.join("typeck/mismatch.rs")).unwrap(),
+        "fn main() {\n    let x: i32 = \"\";\n// { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }\n}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(output.join("lint/nested/unused.rs")).unwrap(),
+        "fn main() {\n    let x = 1; // { dg-warning \"\" \"\" { target *-*-* } }\n}\n"
+    );
+    assert!(!output.join("broken").exists());
+    assert!(!output.join("notes.txt").exists());
+
+    let log = String::from_utf8(log).unwrap();
+    let orphan = input.join("broken/orphan.rs");
+    assert!(
+        log.contains(&format!(
+            "=== {} ===\nerror: could not transform code from file `{}`: in `//~| NOTE nothing to follow`: line 1: `//~|` without a preceding annotation to follow\n",
+            orphan.display(),
+            orphan.display()
+        )),
+        "{log}"
+    );
+    assert!(log.ends_with("3 files: 2 converted, 1 failed, 0 warnings\n"));
+}
+
+#[test]
+fn a_clean_tree_succeeds() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("ui");
+    tree(&input);
+    fs::remove_file(input.join("broken/orphan.rs")).unwrap();
+
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        input.to_str().unwrap(),
+        "--output-dir",
+        dir.path().join("out").to_str().unwrap(),
+    ])
+    .unwrap();
+    let outcome = cli::run_dir(&args, &mut Vec::new()).unwrap();
+    assert_eq!((outcome.converted(), outcome.failed()), (2, 0));
+}
+
+#[test]
+fn parallel_conversion_matches_sequential_conversion() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("ui");
+    for copy in 0..12 {
+        tree(&input.join(format!("copy{copy:02}")));
+    }
+
+    let convert = |jobs: &str| {
+        let output = dir.path().join(format!("out{jobs}"));
+        let args = Arguments::try_parse_from([
+            "rusttest-to-dg",
+            "--dir",
+            input.to_str().unwrap(),
+            "--output-dir",
+            output.to_str().unwrap(),
+            "--jobs",
+            jobs,
+        ])
+        .unwrap();
+        let mut log = Vec::new();
+        let err = cli::run_dir(&args, &mut log).unwrap_err().to_string();
+        let files: Vec<_> = batch::rust_files(&output)
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                let content = fs::read_to_string(&path).unwrap();
+                (path.strip_prefix(&output).unwrap().to_owned(), content)
+            })
+            .collect();
+        (err, String::from_utf8(log).unwrap(), files)
+    };
+    let sequential = convert("1");
+    assert_eq!(sequential.2.len(), 24);
+    assert_eq!(sequential, convert("4"));
+}
+
+#[test]
+fn dir_conflicts_with_a_single_file() {
+    for args in [
+        &["rusttest-to-dg", "--dir", "ui"][..],
+        &[
+            "rusttest-to-dg",
+            "--dir",
+            "ui",
+            "--output-dir",
+            "out",
+            "test.rs",
+        ],
+        &[
+            "rusttest-to-dg",
+            "--dir",
+            "ui",
+            "--output-dir",
+            "out",
+            "-o",
+            "test.rs",
+        ],
+    ] {
+        assert!(Arguments::try_parse_from(args).is_err(), "{args:?}");
+    }
+}
+
+#[test]
+fn include_and_exclude_select_the_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("ui");
+    let output = dir.path().join("out");
+    tree(&input);
+    let test = TREE[2].1;
+    for path in [
+        "proc-macro/derive.rs",
+        "typeck/proc-macro/attr.rs",
+        "typeck/dyn-2015.rs",
+        "typeck/nested/deep.rs",
+    ] {
+        let path = input.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, test).unwrap();
+    }
+
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        input.to_str().unwrap(),
+        "--output-dir",
+        output.to_str().unwrap(),
+        "--include",
+        "typeck/**",
+        "--include",
+        "proc-macro/*",
+        "--exclude",
+        "**/proc-macro/**",
+        "--exclude",
+        "*-2015.rs",
+        "-v",
+    ])
+    .unwrap();
+    let mut log = Vec::new();
+    let outcome = cli::run_dir(&args, &mut log).unwrap();
+    let converted: Vec<_> = outcome
+        .files
+        .iter()
+        .map(|file| file.job.source_file.strip_prefix(&input).unwrap())
+        .collect();
+    assert_eq!(
+        converted,
+        [
+            Path::new("typeck/mismatch.rs"),
+            Path::new("typeck/nested/deep.rs")
+        ]
+    );
+    assert!(!output.join("proc-macro").exists());
+    assert!(!output.join("typeck/dyn-2015.rs").exists());
+
+    // Files left out by the includes are listed along with the excluded ones
+    let log = String::from_utf8(log).unwrap();
+    let excluded: Vec<_> = [
+        "broken/orphan.rs",
+        "lint/nested/unused.rs",
+        "proc-macro/derive.rs",
+        "typeck/dyn-2015.rs",
+        "typeck/proc-macro/attr.rs",
+    ]
+    .iter()
+    .map(|path| format!("  {}\n", input.join(path).display()))
+    .collect();
+    assert!(
+        log.starts_with(&format!("5 files excluded:\n{}", excluded.concat())),
+        "{log}"
+    );
+
+    // They are only listed with `--verbose`
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        input.to_str().unwrap(),
+        "--output-dir",
+        dir.path().join("quiet").to_str().unwrap(),
+        "--exclude",
+        "**/proc-macro/**",
+    ])
+    .unwrap();
+    let mut log = Vec::new();
+    cli::run_dir(&args, &mut log).unwrap_err();
+    assert!(!String::from_utf8(log).unwrap().contains("excluded"));
+}
+
+#[test]
+fn fail_fast_stops_at_the_first_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("ui");
+    let output = dir.path().join("out");
+    for (path, content) in [
+        ("a/first.rs", TREE[2].1),
+        ("b/broken.rs", TREE[3].1),
+        ("c/after.rs", TREE[2].1),
+        ("d/broken.rs", TREE[3].1),
+    ] {
+        let path = input.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        input.to_str().unwrap(),
+        "--output-dir",
+        output.to_str().unwrap(),
+        "--jobs",
+        "1",
+        "--fail-fast",
+    ])
+    .unwrap();
+    let mut log = Vec::new();
+    let err = cli::run_dir(&args, &mut log).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "--fail-fast stopped at `{}`, the 1 files converted so far are kept in `{}`",
+            input.join("b/broken.rs").display(),
+            output.display()
+        )
+    );
+    assert!(
+        format!("{err:#}").ends_with("line 1: `//~|` without a preceding annotation to follow"),
+        "{err:#}"
+    );
+    assert_eq!(cli::exit_code(&err), cli::EXIT_ANNOTATION);
+
+    // Nothing after the failure was converted, what came before it is written
+    assert!(output.join("a/first.rs").exists());
+    assert!(!output.join("c").exists());
+    let log = String::from_utf8(log).unwrap();
+    assert!(!log.contains("after.rs"), "{log}");
+    assert!(log.ends_with("2 files: 1 converted, 1 failed, 0 warnings\n"));
+}
+
+#[test]
+fn binary_inputs_are_counted_on_their_own() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("ui");
+    tree(&input);
+    fs::write(input.join("compiled.rs"), b"\x7fELF\x02\x01\x01\0\0\0").unwrap();
+
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        input.to_str().unwrap(),
+        "--output-dir",
+        dir.path().join("out").to_str().unwrap(),
+    ])
+    .unwrap();
+    let mut log = Vec::new();
+    cli::run_dir(&args, &mut log).unwrap_err();
+    let log = String::from_utf8(log).unwrap();
+    assert!(
+        log.contains("input appears to be binary, not Rust source"),
+        "{log}"
+    );
+    assert!(
+        log.ends_with("4 files: 2 converted, 2 failed, 0 warnings, 1 binary input\n"),
+        "{log}"
+    );
+}
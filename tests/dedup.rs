@@ -0,0 +1,37 @@
+//! Removes the annotations repeating an earlier one of the same line, unless `--no-dedup`.
+
+use std::{fs, process::Command};
+
+#[test]
+fn no_dedup_keeps_the_repeated_annotations() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(
+        &test,
+        "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n    //~^ ERROR mismatched types\n}\n",
+    )
+    .unwrap();
+    let convert = |args: &[&str]| {
+        let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+            .arg(&test)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(result.status.success(), "{result:?}");
+        (
+            String::from_utf8(result.stdout).unwrap(),
+            String::from_utf8(result.stderr).unwrap(),
+        )
+    };
+
+    let (stdout, stderr) = convert(&["--stats"]);
+    assert_eq!(
+        stdout,
+        "fn main() {\n    let x: i32 = \"\"; // { dg-error \"\" \"\" { target *-*-* } }\n\n}\n\n"
+    );
+    assert!(stderr.contains("duplicates removed:     1\n"), "{stderr}");
+
+    let (stdout, stderr) = convert(&["--stats", "--no-dedup"]);
+    assert_eq!(stdout.matches("dg-error").count(), 2, "{stdout}");
+    assert!(!stderr.contains("duplicates"), "{stderr}");
+}
@@ -0,0 +1,107 @@
+//! Converts tests for the edition of `--edition`, whatever their `edition` headers say.
+
+use {
+    clap::Parser,
+    rusttest_to_dg::cli::Arguments,
+    std::{fs, path::Path, process::Command},
+};
+
+fn convert(test: &Path, edition: &str) -> (String, String) {
+    let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .arg(test)
+        .args(["--edition", edition])
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{result:?}");
+    (
+        String::from_utf8(result.stdout).unwrap(),
+        String::from_utf8(result.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn the_edition_is_injected_into_a_test_without_header() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(
+        &test,
+        "fn main() {\n    let x = 1; //~ WARN unused variable\n}\n",
+    )
+    .unwrap();
+    let (stdout, stderr) = convert(&test, "2021");
+    assert_eq!(
+        stdout,
+        "// { dg-additional-options \"-frust-edition=2021\" }\nfn main() {\n    let x = 1; // { dg-warning \"\" \"\" { target *-*-* } }\n}\n\n"
+    );
+    assert!(!stderr.contains("warning:"), "{stderr}");
+
+    // Even the default edition, which an `edition` header would not convert
+    let (stdout, _) = convert(&test, "2015");
+    assert!(
+        stdout.starts_with("// { dg-additional-options \"-frust-edition=2015\" }\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn the_edition_wins_over_the_header_with_a_warning() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(&test, "//@ check-pass\n//@ edition: 2018\nfn main() {}\n").unwrap();
+    let (stdout, stderr) = convert(&test, "2024");
+    assert_eq!(
+        stdout,
+        "// { dg-do compile }\n// { dg-additional-options \"-frust-edition=2024\" }\n\n\nfn main() {}\n\n"
+    );
+    assert!(
+        stderr.contains("warning: line 2: `edition: 2018` is overridden by --edition 2024\n"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn an_invalid_edition_is_rejected() {
+    for edition in ["2020", "21", "latest"] {
+        let err = Arguments::try_parse_from(["rusttest-to-dg", "test.rs", "--edition", edition])
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("[possible values: 2015, 2018, 2021, 2024]"),
+            "{err}"
+        );
+    }
+    assert!(Arguments::try_parse_from(["rusttest-to-dg", "test.rs", "--edition", "2018"]).is_ok());
+}
+
+#[test]
+fn an_edition_header_disagreeing_with_the_flags_is_warned_about() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(
+        &test,
+        "//@ edition: 2021\n//@ compile-flags: --edition 2018\nfn main() {}\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+            .arg(&test)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let conflict =
+        "line 1: `edition: 2021` disagrees with `--edition 2018` in the compile flags of line 2";
+
+    let result = run(&[]);
+    assert!(result.status.success(), "{result:?}");
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!("{conflict}, gccrs gets both of them\n")),
+        "{stderr}"
+    );
+
+    let result = run(&["--strict-headers"]);
+    assert!(!result.status.success(), "{result:?}");
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(stderr.contains(conflict), "{stderr}");
+}
@@ -0,0 +1,62 @@
+//! Checks the exit status of the binary for each kind of failure.
+
+mod common;
+
+use {
+    common::write,
+    std::{path::Path, process::Command},
+};
+
+/// Converts `args` in `dir` and returns the exit status
+fn status(dir: &Path, args: &[&str]) -> Option<i32> {
+    Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .code()
+}
+
+#[test]
+fn success() {
+    let dir = tempfile::tempdir().unwrap();
+    write(&dir.path().join("test.rs"), "fn main() {} //~ ERROR oops\n");
+    write(
+        &dir.path().join("test.stderr"),
+        "error[E0308]: oops\n  --> $DIR/test.rs:1:13\n",
+    );
+    assert_eq!(status(dir.path(), &["test.rs"]), Some(0));
+}
+
+#[test]
+fn unreadable_input() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(status(dir.path(), &["missing.rs"]), Some(2));
+
+    write(&dir.path().join("test.rs"), "fn main() {}\n");
+    assert_eq!(
+        status(dir.path(), &["test.rs", "-e", "missing.stderr"]),
+        Some(2)
+    );
+}
+
+#[test]
+fn malformed_annotation() {
+    let dir = tempfile::tempdir().unwrap();
+    write(&dir.path().join("test.rs"), "//~| NOTE nothing to follow\n");
+    assert_eq!(status(dir.path(), &["test.rs"]), Some(3));
+}
+
+#[test]
+fn unmatched_error_codes() {
+    let dir = tempfile::tempdir().unwrap();
+    write(&dir.path().join("test.rs"), "fn main() {} //~ ERROR oops\n");
+    write(
+        &dir.path().join("test.stderr"),
+        "error[E0425]: something else\n  --> $DIR/test.rs:7:5\n",
+    );
+    assert_eq!(status(dir.path(), &["test.rs"]), Some(4));
+    // Without the `stderr` file there is nothing to match
+    assert_eq!(status(dir.path(), &["test.rs", "--no-stderr"]), Some(0));
+}
@@ -0,0 +1,4 @@
+//@ edition:2021
+pub fn add(x: i32, y: i32) -> i32 {
+    x + y
+}
@@ -0,0 +1,7 @@
+//@ aux-build: helper.rs
+//@ aux-build:macros.rs
+extern crate helper;
+
+fn main() {
+    helper::add(1, "2"); //~ ERROR mismatched types
+}
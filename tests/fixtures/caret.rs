@@ -0,0 +1,7 @@
+// Annotations pointing at the line above the comment
+fn main() {
+    let x: i32 = "a";
+    //~^ ERROR mismatched types
+    let y = ;
+    //~^ ERROR expected expression, found `;`
+}
@@ -0,0 +1,5 @@
+fn main() {
+    let x: i32 = ""; let y = missing;
+    //~^ ERROR mismatched types
+    //~| ERROR cannot find value `missing` in this scope
+}
@@ -0,0 +1,9 @@
+// Annotations pointing at a line below the comment
+fn main() {
+    //~v ERROR mismatched types
+    let x: i32 = "a";
+    //~vvv ERROR expected expression, found `;`
+    let y = 1;
+    let z = 2;
+    let w = ;
+}
@@ -0,0 +1,7 @@
+//@ edition:2021
+
+async fn foo() {}
+
+fn main() {
+    foo().await; //~ ERROR `await` is only allowed inside `async` functions and blocks
+}
@@ -0,0 +1,8 @@
+fn foo(x: &u8) -> &u8 { x }
+
+fn main() {
+    let z = foo;
+    //~^ WARN unused variable: `z`
+    //~| NOTE `#[warn(unused_variables)]` on by default
+    //~| HELP if this is intentional, prefix it with an underscore
+}
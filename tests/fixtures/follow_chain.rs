@@ -0,0 +1,10 @@
+fn main() {
+    let x: u32 = "a";
+    //~^ ERROR mismatched types
+    //~| NOTE expected `u32`, found `&str`
+    //~| NOTE expected due to this
+    //~| NOTE the type is declared here
+    let y = x + 1; //~ WARN unused variable: `y`
+    //~| NOTE `#[warn(unused_variables)]` on by default
+    //~| HELP if this is intentional, prefix it with an underscore
+}
@@ -0,0 +1,9 @@
+// An older test, whose diagnostics are only in its blessed stderr file
+#![deny(non_snake_case)]
+
+fn F() {}
+
+fn main() {
+    let x: i32 = ""; let y: u8 = 256;
+    let unused = undefined;
+}
@@ -0,0 +1,5 @@
+// Annotations on the same line as the code
+fn main() {
+    undefined_fn(); //~ ERROR cannot find function `undefined_fn` in this scope
+    let _ = 1 + "a"; //~ ERROR cannot add `&str` to `{integer}`
+}
@@ -0,0 +1,8 @@
+fn main() {
+    let x: i32 = "";
+    //~^ ERROR mismatched types
+    let y = missing; //~ ERROR cannot find value `missing` in this scope
+    //~vv WARN unused variable: `z`
+    // The annotation above targets the line below this one
+    let z = 1;
+}
@@ -0,0 +1,5 @@
+fn main() {
+    let x: i32 = ""; //~ FOO bar
+    //~^ ERROR mismatched types
+    undefined; // ~ERROR cannot find value
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let x: i32 = "";
+    let y = x; //~^ ERROR mismatched types
+    let z = y;
+    drop(z); //~^^^ NOTE expected due to this
+}
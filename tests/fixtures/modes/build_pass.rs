@@ -0,0 +1,11 @@
+//@ build-pass
+//@ compile-flags: -O
+
+#[inline(never)]
+fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+fn main() {
+    add(1, 2);
+}
@@ -0,0 +1,5 @@
+//@ check-pass
+// Unused variables are only warned about
+fn main() {
+    let x = 1; //~ WARN unused variable
+}
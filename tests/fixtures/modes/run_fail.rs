@@ -0,0 +1,7 @@
+//@ run-fail
+// The test passes when the program panics
+
+fn main() {
+    let v: Vec<u32> = Vec::new();
+    let _ = v[0];
+}
@@ -0,0 +1,7 @@
+//@ run-pass
+//@ edition:2021
+
+fn main() {
+    let v: Vec<u32> = (1..=3).collect();
+    assert_eq!(v.iter().sum::<u32>(), 6);
+}
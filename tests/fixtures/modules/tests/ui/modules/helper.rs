@@ -0,0 +1,8 @@
+// Module of `main.rs`
+pub fn answer() -> u32 {
+    data()
+}
+
+fn data() -> u32 {
+    super::data::ANSWER
+}
@@ -0,0 +1,9 @@
+//@ run-pass
+// A sibling module and one loaded through `#[path]`, unlike this one: mod commented;
+mod helper;
+#[path = "support/data.rs"]
+mod data;
+
+fn main() {
+    assert_eq!(helper::answer(), data::ANSWER);
+}
@@ -0,0 +1,3 @@
+mod gone; //~ ERROR file not found for module `gone`
+
+fn main() {}
@@ -0,0 +1,2 @@
+// Module of `main.rs`, loaded through `#[path]`
+pub const ANSWER: u32 = 42;
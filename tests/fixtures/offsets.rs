@@ -0,0 +1,13 @@
+//@ edition: 2021
+fn main() {
+    let a: i32 = "";
+
+    //~^^ ERROR mismatched types
+    //~| NOTE expected due to this
+    let b = 1; //~ WARN unused variable: `b`
+    //~| HELP if this is intentional, prefix it with an underscore
+    //~vvv ERROR cannot find value `c` in this scope
+    //~| NOTE not found
+    //~^^^^^^^^ WARN unused variable: `a`
+    c;
+}
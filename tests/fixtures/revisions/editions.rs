@@ -0,0 +1,10 @@
+//@ revisions: e2015 e2021
+//@[e2021] edition:2021
+//@[e2015] compile-flags: --edition 2015
+
+fn main() {
+    let dyn = 1; //[e2021]~ ERROR expected identifier, found keyword `dyn`
+    let x: i32 = ""; //~ ERROR mismatched types
+    async fn f() {}
+    //[e2015]~^ ERROR `async fn` is not permitted in Rust 2015
+}
@@ -0,0 +1,11 @@
+//@ revisions: base strict
+//@ compile-flags: -O
+//[strict]@ edition: 2021
+//[base] compile-flags: --cfg legacy
+
+#[cfg(legacy)]
+fn old() {}
+
+fn main() {
+    let dyn = 1; //[strict]~ ERROR expected identifier, found keyword `dyn`
+}
@@ -0,0 +1,8 @@
+//@ run-pass
+//@ check-run-results
+
+fn main() {
+    for i in 0..100 {
+        println!("{i}");
+    }
+}
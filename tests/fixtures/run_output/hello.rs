@@ -0,0 +1,8 @@
+//@ run-pass
+//@ check-run-results
+
+fn main() {
+    println!("Hello, \"world\"!");
+    println!();
+    println!("[1, 2, 3] costs $5 (or more?)");
+}
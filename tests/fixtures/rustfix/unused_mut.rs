@@ -0,0 +1,7 @@
+//@ run-rustfix
+#![deny(unused_mut)]
+
+fn main() {
+    let mut x = 1; //~ ERROR variable does not need to be mutable
+    println!("{x}");
+}
@@ -0,0 +1,5 @@
+fn foo_with_multiple_qualified_paths(x: &str) -> &i32 { x }
+//~^ ERROR mismatched types
+//~| ERROR missing lifetime specifier
+
+fn main() {}
@@ -0,0 +1,9 @@
+fn main() {
+    let x: i32 = "";
+    //~^ ERROR mismatched types
+    //~| NOTE expected `i32`
+    let unused = 1; //~ WARN unused variable
+    let other = 2; //~ WARN unused variable
+    undefined; //~ ERROR cannot find value `undefined`
+    x; //~ EROR misspelt kind
+}
@@ -0,0 +1,6 @@
+fn main() {
+    let x: i32 = "hello";
+    //~^ ERROR mismatched types
+    let v = undefined;
+    //~^ ERROR cannot find value `undefined` in this scope
+}
@@ -0,0 +1,10 @@
+// Tests mixing scripts, emoji and combining characters: 漢字, Ελληνικά, עברית, 🦀
+//@ edition:2021
+
+fn main() {
+    let crab = "🦀 ferris — «quoted» ‘single’";
+    let café = 1; //~ ERROR non-ASCII identifiers are not stable: `café`
+    let ñ = "a̐éö̲"; //~ WARN unused variable: `ñ`
+    println!("{crab}\u{200b}{café}");
+    //~^ ERROR cannot find value `café` in this scope
+}
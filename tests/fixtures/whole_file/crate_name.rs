@@ -0,0 +1,10 @@
+//@ compile-flags: --crate-name 1bad
+// The crate name comes from the command line, rustc reports it without a span
+
+fn main() {
+    let x: i32 = "";
+    //~^ ERROR mismatched types
+    let y = 1; //~ WARN unused variable: `y`
+}
+
+//~? ERROR invalid character `1` in crate name: `1bad`
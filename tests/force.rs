@@ -0,0 +1,138 @@
+//! Writes over existing output files, which are only replaced with `--force`.
+
+mod common;
+
+use {
+    clap::Parser,
+    common::{write, CONVERTED, TEST},
+    rusttest_to_dg::cli::{self, Arguments},
+    std::{fs, process::Command},
+};
+
+const HAND_TWEAKED: &str = "// { dg-options \"-w\" }\nfn main() {}\n";
+
+#[test]
+fn an_existing_output_file_is_only_replaced_with_force() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    let output = dir.path().join("converted.rs");
+    write(&test, TEST);
+    let convert = |extra: &[&str]| {
+        let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+            .arg(&test)
+            .arg("-o")
+            .arg(&output)
+            .args(extra)
+            .output()
+            .unwrap();
+        assert!(result.status.success(), "{result:?}");
+        String::from_utf8(result.stderr).unwrap()
+    };
+
+    // A fresh file is written, with or without `--force`
+    let stderr = convert(&[]);
+    assert!(!stderr.contains("warning"), "{stderr}");
+    assert_eq!(fs::read_to_string(&output).unwrap(), CONVERTED);
+
+    fs::write(&output, HAND_TWEAKED).unwrap();
+    let stderr = convert(&[]);
+    assert!(
+        stderr.contains(&format!(
+            "warning: `{}` already exists, not overwriting it without --force",
+            output.display()
+        )),
+        "{stderr}"
+    );
+    assert_eq!(fs::read_to_string(&output).unwrap(), HAND_TWEAKED);
+
+    let stderr = convert(&["--force"]);
+    assert!(!stderr.contains("warning"), "{stderr}");
+    assert_eq!(fs::read_to_string(&output).unwrap(), CONVERTED);
+}
+
+#[test]
+fn an_existing_file_does_not_stop_a_directory_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    let out = dir.path().join("out");
+    write(&ui.join("fresh.rs"), TEST);
+    write(&ui.join("tweaked.rs"), TEST);
+    write(&out.join("tweaked.rs"), HAND_TWEAKED);
+    let convert = |extra: &[&str]| {
+        let args = Arguments::try_parse_from(
+            [
+                "rusttest-to-dg",
+                "--dir",
+                ui.to_str().unwrap(),
+                "--output-dir",
+                out.to_str().unwrap(),
+            ]
+            .iter()
+            .chain(extra),
+        )
+        .unwrap();
+        let mut log = Vec::new();
+        cli::run_dir(&args, &mut log).unwrap();
+        String::from_utf8(log).unwrap()
+    };
+
+    let log = convert(&[]);
+    assert_eq!(fs::read_to_string(out.join("fresh.rs")).unwrap(), CONVERTED);
+    assert_eq!(
+        fs::read_to_string(out.join("tweaked.rs")).unwrap(),
+        HAND_TWEAKED
+    );
+    assert!(
+        log.contains(&format!(
+            "warning: `{}` already exists, not overwriting it without --force\n",
+            out.join("tweaked.rs").display()
+        )),
+        "{log}"
+    );
+    assert!(
+        log.ends_with("2 files: 2 converted, 0 failed, 0 warnings, 1 existing kept\n"),
+        "{log}"
+    );
+
+    let log = convert(&["--force"]);
+    assert_eq!(
+        fs::read_to_string(out.join("tweaked.rs")).unwrap(),
+        CONVERTED
+    );
+    assert!(!log.contains("already exists"), "{log}");
+    assert!(log.ends_with("2 files: 2 converted, 0 failed, 0 warnings\n"));
+}
+
+#[test]
+fn an_existing_file_is_kept_among_several_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    let out = dir.path().join("out");
+    let (fresh, tweaked) = (ui.join("fresh.rs"), ui.join("tweaked.rs"));
+    write(&fresh, TEST);
+    write(&tweaked, TEST);
+    write(&out.join("tweaked.rs"), HAND_TWEAKED);
+
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        fresh.to_str().unwrap(),
+        tweaked.to_str().unwrap(),
+        "--output-dir",
+        out.to_str().unwrap(),
+    ])
+    .unwrap();
+    let (mut stdout, mut log) = (Vec::new(), Vec::new());
+    cli::run_files(&args, &mut stdout, &mut log).unwrap();
+    assert!(stdout.is_empty());
+    assert_eq!(fs::read_to_string(out.join("fresh.rs")).unwrap(), CONVERTED);
+    assert_eq!(
+        fs::read_to_string(out.join("tweaked.rs")).unwrap(),
+        HAND_TWEAKED
+    );
+    let log = String::from_utf8(log).unwrap();
+    assert!(log.contains("already exists, not overwriting it"), "{log}");
+    assert!(
+        log.ends_with("1 existing files kept, --force overwrites them\n"),
+        "{log}"
+    );
+}
@@ -0,0 +1,105 @@
+//! Generates the directives of a test without `//~` annotations from its `stderr` file.
+
+mod common;
+
+use {
+    common::fixture,
+    rusttest_to_dg::{
+        dejagnu,
+        transform::{self, Options},
+    },
+    std::{fs, process::Command},
+};
+
+#[test]
+fn the_directives_go_below_the_lines_of_the_stderr_file() {
+    let code = fs::read_to_string(fixture("", "from_stderr.rs")).unwrap();
+    let stderr = fs::read_to_string(fixture("", "from_stderr.stderr")).unwrap();
+    let options = Options {
+        strict: true,
+        ..Options::default()
+    };
+    let (converted, report) = transform::transform_code(&code, Some(&stderr), &options).unwrap();
+    assert_eq!(
+        converted,
+        fs::read_to_string(fixture("", "from_stderr.expected")).unwrap()
+    );
+    // One per error and warning, the note setting the lint level is left out
+    assert_eq!(report.stats.annotations, 5);
+    assert_eq!(report.stats.error_codes, 2);
+
+    // Every line of the source is still there, each directive targets the reported line
+    let lines: Vec<_> = converted.lines().collect();
+    let mut targets = Vec::new();
+    let mut copied = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        match dejagnu::validate(line) {
+            Ok(directive) => targets.push(lines[directive.target_line(index + 1).unwrap() - 1]),
+            Err(_) => copied.push(*line),
+        }
+    }
+    assert_eq!(copied, code.lines().collect::<Vec<_>>());
+    assert_eq!(
+        targets,
+        [
+            "fn F() {}",
+            "    let x: i32 = \"\"; let y: u8 = 256;",
+            "    let x: i32 = \"\"; let y: u8 = 256;",
+            "    let unused = undefined;",
+            "    let unused = undefined;",
+        ]
+    );
+}
+
+#[test]
+fn the_stderr_messages_are_used() {
+    let code = "fn main() {\n    let x = 1;\n}\n";
+    let stderr = "warning: unused variable: `x`\n  --> $DIR/a.rs:2:9\n\nerror: unknown\n  --> $DIR/a.rs:9:1\n";
+    let options = Options {
+        msg_style: rusttest_to_dg::errors::MsgStyle::Escaped,
+        ..Options::default()
+    };
+    let (converted, report) = transform::transform_code(code, Some(stderr), &options).unwrap();
+    assert_eq!(
+        converted,
+        "fn main() {\n    let x = 1;\n// { dg-warning \"unused variable: `x`\" \"\" { target *-*-* } .-1 }\n}\n"
+    );
+    assert_eq!(
+        report.warnings,
+        ["the dg-error of line 9 in the stderr file is past the end of the source, it is left out"]
+    );
+}
+
+#[test]
+fn from_stderr_wins_over_the_annotations() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(
+        &test,
+        "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("test.stderr"),
+        "error[E0308]: mismatched types\n  --> $DIR/test.rs:2:18\n",
+    )
+    .unwrap();
+    let convert = |args: &[&str]| {
+        let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+            .arg(&test)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(result.status.success(), "{result:?}");
+        String::from_utf8(result.stdout).unwrap()
+    };
+    assert_eq!(
+        convert(&[]),
+        "fn main() {\n    let x: i32 = \"\"; // { dg-error \".E0308.\" \"\" { target *-*-* } }\n}\n\n"
+    );
+    // The annotation is left as it is
+    assert_eq!(
+        convert(&["--from-stderr"]),
+        "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n// { dg-error \".E0308.\" \"\" { target *-*-* } .-1 }\n}\n\n"
+    );
+}
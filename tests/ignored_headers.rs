@@ -0,0 +1,82 @@
+//! Drops the headers which do not matter to gccrs, like `//@ incremental`, and counts them.
+
+use {
+    clap::Parser,
+    rusttest_to_dg::cli::{self, Arguments},
+    std::{fs, process::Command},
+};
+
+const TEST: &str = "//@ check-pass\n//@ incremental\n//@ pretty-expanded\n//@ doc-flags: --document-private-items\n//@ incremental\nfn main() {}\n";
+
+#[test]
+fn ignored_headers_are_dropped_and_counted() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(&test, TEST).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .arg(&test)
+        .arg("--stats")
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{result:?}");
+    assert_eq!(
+        String::from_utf8(result.stdout).unwrap(),
+        "// { dg-do compile }\n\n\n\n\n\nfn main() {}\n\n"
+    );
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains(
+            "ignored headers:        4\n  doc-flags             1\n  incremental           2\n  pretty-expanded       1\n"
+        ),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn keep_ignored_headers_copies_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(&test, TEST).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .arg(&test)
+        .arg("--keep-ignored-headers")
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{result:?}");
+    assert_eq!(
+        String::from_utf8(result.stdout).unwrap(),
+        format!(
+            "// {{ dg-do compile }}\n{}\n",
+            &TEST["//@ check-pass".len()..]
+        )
+    );
+}
+
+#[test]
+fn ignored_headers_are_summed_over_a_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    fs::create_dir(&ui).unwrap();
+    fs::write(ui.join("first.rs"), TEST).unwrap();
+    fs::write(ui.join("second.rs"), "//@ pp-exact\nfn main() {}\n").unwrap();
+    fs::write(ui.join("plain.rs"), "fn main() {}\n").unwrap();
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        ui.to_str().unwrap(),
+        "--output-dir",
+        dir.path().join("out").to_str().unwrap(),
+        "--stats-json",
+    ])
+    .unwrap();
+    let mut log = Vec::new();
+    cli::run_dir(&args, &mut log).unwrap();
+    let log = String::from_utf8(log).unwrap();
+    let json = log.lines().find(|line| line.starts_with('{')).unwrap();
+    let stats: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(stats["files"], 3);
+    assert_eq!(
+        stats["ignored_headers"],
+        serde_json::json!({"doc-flags": 1, "incremental": 2, "pp-exact": 1, "pretty-expanded": 1})
+    );
+}
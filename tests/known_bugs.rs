@@ -0,0 +1,98 @@
+//! Leaves the tests of a rustc bug out with `--skip-known-bugs`, instead of converting them.
+
+mod common;
+
+use {
+    clap::Parser,
+    common::{convert, write, TEST},
+    rusttest_to_dg::{
+        cli::{self, Arguments},
+        transform::{Skip, SkipCategory},
+    },
+    std::process::Command,
+};
+
+const KNOWN_BUG: &str =
+    "//@ known-bug: #12345\nfn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n}\n";
+
+#[test]
+fn a_known_bug_is_xfail_without_the_flag() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("bug.rs");
+    write(&test, KNOWN_BUG);
+    assert_eq!(
+        convert(&[test.to_str().unwrap()]),
+        "// rustc known-bug: #12345\n\nfn main() {\n    let x: i32 = \"\"; // { dg-error \"\" \"\" { xfail *-*-* } }\n}\n\n"
+    );
+}
+
+#[test]
+fn a_skipped_known_bug_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("bug.rs");
+    let output = dir.path().join("out.rs");
+    write(&test, KNOWN_BUG);
+    let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .arg(&test)
+        .arg("-o")
+        .arg(&output)
+        .arg("--skip-known-bugs")
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{result:?}");
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!(
+            "warning: `{}` is a test of the rustc bug #12345, skipped by --skip-known-bugs",
+            test.display()
+        )),
+        "{stderr}"
+    );
+    assert!(!output.exists());
+
+    // Nor anything on the standard output
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        test.to_str().unwrap(),
+        "--skip-known-bugs",
+    ])
+    .unwrap();
+    let mut stdout = Vec::new();
+    let report = cli::run(&args, &mut stdout).unwrap();
+    assert!(stdout.is_empty());
+    assert_eq!(
+        report.skipped,
+        Some(Skip {
+            category: SkipCategory::KnownBug,
+            reason: "#12345".to_owned()
+        })
+    );
+}
+
+#[test]
+fn skipped_known_bugs_are_counted_in_a_directory_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    let out = dir.path().join("out");
+    write(&ui.join("bug.rs"), KNOWN_BUG);
+    write(&ui.join("test.rs"), TEST);
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        ui.to_str().unwrap(),
+        "--output-dir",
+        out.to_str().unwrap(),
+        "--skip-known-bugs",
+    ])
+    .unwrap();
+    let mut log = Vec::new();
+    let outcome = cli::run_dir(&args, &mut log).unwrap();
+    assert_eq!((outcome.converted(), outcome.skipped()), (1, 1));
+    assert!(out.join("test.rs").exists());
+    assert!(!out.join("bug.rs").exists());
+    let log = String::from_utf8(log).unwrap();
+    assert!(
+        log.ends_with("2 files: 1 converted, 0 failed, 1 warnings, 1 known bugs skipped\n"),
+        "{log}"
+    );
+}
@@ -0,0 +1,61 @@
+//! Writes the lines the directives target by their number in the emitted file with
+//! `--line-numbers absolute`, instead of their offset from the directive.
+
+mod common;
+
+use {
+    common::{convert, fixture},
+    rusttest_to_dg::{
+        dejagnu,
+        transform::{self, LineNumbers, Options},
+    },
+    std::fs,
+};
+
+/// The line each diagnostic directive of `code` targets
+fn targets(code: &str) -> Vec<usize> {
+    dejagnu::scan(code)
+        .iter()
+        .filter(|(_, directive)| dejagnu::is_diagnostic(&directive.name))
+        .filter_map(|(line_num, directive)| directive.target_line(*line_num))
+        .collect()
+}
+
+#[test]
+fn both_modes_target_the_same_lines() {
+    let test = fixture("line_numbers", "notes.rs");
+    let mut converted = Vec::new();
+    for mode in ["relative", "absolute"] {
+        let expected =
+            fs::read_to_string(fixture("line_numbers", &format!("notes.{mode}.expected"))).unwrap();
+        let new_code = convert(&[
+            test.to_str().unwrap(),
+            "--with-notes",
+            "--edition",
+            "2021",
+            "--line-numbers",
+            mode,
+        ]);
+        assert_eq!(new_code, format!("{expected}\n"), "{mode}");
+        converted.push(new_code);
+    }
+    // The edition directive and the note inserted below the first error move the rest down
+    assert_eq!(targets(&converted[0]), [3, 3, 6, 9]);
+    assert_eq!(targets(&converted[1]), targets(&converted[0]));
+    assert!(!converted[1].contains(" .-"), "{}", converted[1]);
+}
+
+#[test]
+fn whole_file_directives_keep_their_zero() {
+    let options = Options {
+        line_numbers: LineNumbers::Absolute,
+        ..Options::default()
+    };
+    let (new_code, _) =
+        transform::transform_code("fn main() {}\n//~? ERROR crate not found\n", None, &options)
+            .unwrap();
+    assert_eq!(
+        new_code,
+        "// { dg-error \"\" \"\" { target *-*-* } 0 }\nfn main() {}\n\n"
+    );
+}
@@ -0,0 +1,88 @@
+//! Feeds inputs which used to panic through the command line entry point,
+//! each of them must come back as an error instead.
+
+use {
+    clap::Parser,
+    rusttest_to_dg::cli::{self, Arguments},
+    std::{fs, path::Path},
+};
+
+/// Inputs which used to abort the whole program, with what must be reported
+const PANICKING_INPUTS: &[(&str, &[u8], &str)] = &[
+    ("empty_annotation", b"fn main() {} //~\n", "line 1"),
+    (
+        "orphan_follow",
+        b"fn main() {}\n//~| NOTE nothing to follow\n",
+        "line 2",
+    ),
+    ("carets_above_file", b"//~^^ ERROR too far up\n", "line 1"),
+    (
+        "invalid_utf8",
+        b"fn main() {}\n\xff\xfe //~ ERROR\n",
+        "invalid utf-8",
+    ),
+];
+
+fn run(dir: &Path, name: &str, contents: &[u8], extra_args: &[&str]) -> anyhow::Result<String> {
+    let path = dir.join(format!("{name}.rs"));
+    fs::write(&path, contents).unwrap();
+    let args = Arguments::try_parse_from(
+        ["rusttest-to-dg", path.to_str().unwrap()]
+            .into_iter()
+            .chain(extra_args.iter().copied()),
+    )
+    .unwrap();
+    let mut output = Vec::new();
+    cli::run(&args, &mut output)?;
+    Ok(String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn known_panicking_inputs_return_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    for (name, contents, expected) in PANICKING_INPUTS {
+        for extra_args in [
+            &[][..],
+            &["--msg-style", "escaped"],
+            &["--secondary", "drop"],
+        ] {
+            let err = run(dir.path(), name, contents, extra_args)
+                .expect_err(&format!("`{name}` must be rejected"));
+            let message = format!("{err:#}");
+            assert!(message.contains(expected), "`{name}`: {message}");
+        }
+    }
+}
+
+#[test]
+fn missing_files_return_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.rs");
+    let args = Arguments::try_parse_from(["rusttest-to-dg", missing.to_str().unwrap()]).unwrap();
+    assert!(cli::run(&args, &mut Vec::new()).is_err());
+
+    let err = run(
+        dir.path(),
+        "ok",
+        b"fn main() {}\n",
+        &["-e", "missing.stderr"],
+    )
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("could not read stderr file"));
+}
+
+#[test]
+fn well_formed_input_still_converts() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(
+        dir.path(),
+        "ok",
+        b"fn main() {\n    f(); //~ ERROR x\n}\n",
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        output,
+        "fn main() {\n    f(); // { dg-error \"\" \"\" { target *-*-* } }\n}\n\n"
+    );
+}
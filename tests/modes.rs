@@ -0,0 +1,72 @@
+//! Converts a test of each mode, which the `dg-do` directive starting the output states.
+
+mod common;
+
+use {
+    clap::Parser,
+    common::{convert, fixture},
+    rusttest_to_dg::cli::{self, Arguments},
+    std::fs,
+};
+
+/// The path of the `name` test
+fn test(name: &str) -> String {
+    let test = fixture("modes", &format!("{name}.rs"));
+    test.to_str().unwrap().to_owned()
+}
+
+fn expected(name: &str) -> String {
+    fs::read_to_string(fixture("modes", &format!("{name}.expected"))).unwrap()
+}
+
+#[test]
+fn each_mode_starts_with_its_dg_do() {
+    for (name, first_lines) in [
+        ("check_pass", "// { dg-do compile }\n\n"),
+        ("build_pass", "// { dg-do compile }\n\n"),
+        ("run_pass", "// { dg-do run }\n\n"),
+        (
+            "run_fail",
+            "// { dg-do run }\n// { dg-shouldfail \"run-fail\" }\n\n",
+        ),
+    ] {
+        let converted = convert(&[&test(name)]);
+        assert_eq!(converted, format!("{}\n", expected(name)), "{name}");
+        assert!(converted.starts_with(first_lines), "{name}: {converted}");
+        // `--explicit-dg-do` only changes compile-fail tests
+        assert_eq!(
+            convert(&[&test(name), "--explicit-dg-do"]),
+            converted,
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn compile_fail_tests_only_state_it_when_asked_to() {
+    let converted = convert(&[&test("compile_fail")]);
+    assert_eq!(converted, format!("{}\n", expected("compile_fail")));
+    assert!(!converted.contains("dg-do"));
+
+    let explicit = convert(&[&test("compile_fail"), "--explicit-dg-do"]);
+    assert_eq!(explicit, format!("{}\n", expected("compile_fail.explicit")));
+    assert_eq!(explicit, format!("// {{ dg-do compile }}\n{converted}"));
+}
+
+#[test]
+fn conflicting_modes_are_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(
+        &test,
+        "//@ check-pass\n//@ check-pass\n//@ run-pass\nfn main() {}\n",
+    )
+    .unwrap();
+    let args = Arguments::try_parse_from(["rusttest-to-dg", test.to_str().unwrap()]).unwrap();
+    let err = cli::run(&args, &mut Vec::new()).unwrap_err();
+    assert!(
+        format!("{err:#}")
+            .ends_with("line 3: `run-pass` conflicts with the `check-pass` header of line 1"),
+        "{err:#}"
+    );
+}
@@ -0,0 +1,109 @@
+//! Converts several tests in a single invocation of the binary.
+
+use std::{fs, process::Command};
+
+#[test]
+fn each_file_is_converted_after_its_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let mismatch = dir.path().join("mismatch.rs");
+    let unused = dir.path().join("unused.rs");
+    let orphan = dir.path().join("orphan.rs");
+    fs::write(
+        &mismatch,
+        "fn main() {\n    let x: i32 = \"\";\n    //~^ ERROR mismatched types\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("mismatch.stderr"),
+        "error[E0308]: mismatched types\n  --> $DIR/mismatch.rs:2:18\n",
+    )
+    .unwrap();
+    fs::write(
+        &unused,
+        "fn main() {\n    let x = 1; //~ WARN unused variable\n}\n",
+    )
+    .unwrap();
+    fs::write(&orphan, "//~| NOTE nothing to follow\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .args([&mismatch, &orphan, &unused])
+        .output()
+        .unwrap();
+
+    // The broken file does not stop the one after it, but fails the run
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "// ===== {} =====\n\
+             fn main() {{\n    let x: i32 = \"\";\n// {{ dg-error \".E0308.\" \"\" {{ target *-*-* }} .-1 }}\n}}\n\n\
+             // ===== {} =====\n\
+             fn main() {{\n    let x = 1; // {{ dg-warning \"\" \"\" {{ target *-*-* }} }}\n}}\n\n",
+            mismatch.display(),
+            unused.display()
+        )
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!(
+            "=== {} ===\nerror: could not transform code from file `{}`",
+            orphan.display(),
+            orphan.display()
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("1 of 3 files failed to convert"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn listed_files_are_relative_to_the_list() {
+    let dir = tempfile::tempdir().unwrap();
+    let manifests = dir.path().join("manifests");
+    let tests = manifests.join("tests");
+    fs::create_dir_all(&tests).unwrap();
+    fs::write(
+        tests.join("unused.rs"),
+        "fn main() {\n    let x = 1; //~ WARN unused variable\n}\n",
+    )
+    .unwrap();
+    let list = manifests.join("gccrs.list");
+    fs::write(
+        &list,
+        "# what gccrs can take\n\ntests/unused.rs\ntests/missing.rs\n",
+    )
+    .unwrap();
+
+    // Run from elsewhere, the paths of the list do not depend on it
+    let output = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .current_dir(dir.path())
+        .arg("--file-list")
+        .arg(&list)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "// ===== {} =====\n\
+             fn main() {{\n    let x = 1; // {{ dg-warning \"\" \"\" {{ target *-*-* }} }}\n}}\n\n",
+            tests.join("unused.rs").display()
+        )
+    );
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains(&format!(
+            "error: {}:4: `{}` does not exist\n",
+            list.display(),
+            tests.join("missing.rs").display()
+        )),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("1 of 2 files failed to convert"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,60 @@
+//! Converts the `needs-*` headers of a test, with the built-in policy and a `--needs-policy`
+//! file overriding it.
+
+use {
+    clap::Parser,
+    rusttest_to_dg::cli::{self, Arguments},
+    std::fs,
+};
+
+const TEST: &str =
+    "//@ needs-threads\n//@ needs-asm-support\n//@ needs-gccrs-plugin\nfn main() {}\n";
+
+fn convert(args: &[&str]) -> Result<(String, Vec<String>), String> {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(&test, TEST).unwrap();
+    let args = Arguments::try_parse_from(
+        ["rusttest-to-dg", test.to_str().unwrap()]
+            .into_iter()
+            .chain(args.iter().copied()),
+    )
+    .map_err(|err| err.to_string())?;
+    let mut stdout = Vec::new();
+    let report = cli::run(&args, &mut stdout).unwrap();
+    Ok((String::from_utf8(stdout).unwrap(), report.warnings))
+}
+
+#[test]
+fn the_builtin_policy_drops_skips_and_warns() {
+    let (converted, warnings) = convert(&[]).unwrap();
+    assert_eq!(
+        converted,
+        "\n// { dg-skip-if \"needs-asm-support\" { *-*-* } }\n// UNCONVERTED: @ needs-gccrs-plugin\nfn main() {}\n\n"
+    );
+    assert_eq!(
+        warnings,
+        ["line 3: `needs-gccrs-plugin` is not in the needs policy, kept as a comment"]
+    );
+}
+
+#[test]
+fn a_policy_file_overrides_the_builtin_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let policy = dir.path().join("needs.toml");
+    fs::write(
+        &policy,
+        "needs-asm-support = \"drop\"\nneeds-gccrs-plugin = \"skip\"\n",
+    )
+    .unwrap();
+    let (converted, warnings) = convert(&["--needs-policy", policy.to_str().unwrap()]).unwrap();
+    assert_eq!(
+        converted,
+        "\n\n// { dg-skip-if \"needs-gccrs-plugin\" { *-*-* } }\nfn main() {}\n\n"
+    );
+    assert!(warnings.is_empty(), "{warnings:?}");
+
+    fs::write(&policy, "needs-threads = \"sometimes\"\n").unwrap();
+    let err = convert(&["--needs-policy", policy.to_str().unwrap()]).unwrap_err();
+    assert!(err.contains("invalid policy in"), "{err}");
+}
@@ -0,0 +1,221 @@
+//! Converts tests into `--output-dir`, at the same place as under their prefix.
+
+mod common;
+
+use {
+    clap::Parser,
+    common::{fixtures, write, CONVERTED, TEST},
+    rusttest_to_dg::cli::{self, Arguments},
+    std::{fs, path::Path},
+};
+
+fn arguments(args: &[&Path]) -> Arguments {
+    Arguments::try_parse_from(
+        ["rusttest-to-dg"]
+            .into_iter()
+            .chain(args.iter().map(|arg| arg.to_str().unwrap())),
+    )
+    .unwrap()
+}
+
+#[test]
+fn a_file_keeps_its_place_under_the_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("tests/ui");
+    let out = dir.path().join("out");
+    let test = ui.join("borrowck/foo.rs");
+    write(&test, TEST);
+
+    let args = arguments(&[
+        &test,
+        Path::new("--output-dir"),
+        &out,
+        Path::new("--strip-prefix"),
+        &ui,
+    ]);
+    let mut stdout = Vec::new();
+    cli::run(&args, &mut stdout).unwrap();
+    assert!(stdout.is_empty());
+    assert_eq!(
+        fs::read_to_string(out.join("borrowck/foo.rs")).unwrap(),
+        CONVERTED
+    );
+
+    // Without a prefix, the file goes right under the output directory
+    let args = arguments(&[&test, Path::new("--output-dir"), &out]);
+    cli::run(&args, &mut Vec::new()).unwrap();
+    assert_eq!(fs::read_to_string(out.join("foo.rs")).unwrap(), CONVERTED);
+}
+
+#[test]
+fn files_keep_their_places_under_their_common_parent() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    let out = dir.path().join("out");
+    let (first, second) = (ui.join("lint/unused.rs"), ui.join("typeck/deep/unused.rs"));
+    write(&first, TEST);
+    write(&second, TEST);
+
+    let args = arguments(&[&first, &second, Path::new("--output-dir"), &out]);
+    let mut stdout = Vec::new();
+    cli::run_files(&args, &mut stdout, &mut Vec::new()).unwrap();
+    assert!(stdout.is_empty());
+    for relative in ["lint/unused.rs", "typeck/deep/unused.rs"] {
+        assert_eq!(
+            fs::read_to_string(out.join(relative)).unwrap(),
+            CONVERTED,
+            "{relative}"
+        );
+    }
+}
+
+#[test]
+fn a_directory_keeps_its_place_under_the_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    let out = dir.path().join("out");
+    write(&ui.join("lint/unused.rs"), TEST);
+
+    let args = arguments(&[
+        Path::new("--dir"),
+        &ui.join("lint"),
+        Path::new("--output-dir"),
+        &out,
+        Path::new("--strip-prefix"),
+        &ui,
+    ]);
+    cli::run_dir(&args, &mut Vec::new()).unwrap();
+    assert_eq!(
+        fs::read_to_string(out.join("lint/unused.rs")).unwrap(),
+        CONVERTED
+    );
+}
+
+#[test]
+fn inputs_outside_the_prefix_are_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    let out = dir.path().join("out");
+    let test = dir.path().join("elsewhere/foo.rs");
+    write(&test, TEST);
+
+    let args = arguments(&[
+        &test,
+        Path::new("--output-dir"),
+        &out,
+        Path::new("--strip-prefix"),
+        &ui,
+    ]);
+    let err = cli::run(&args, &mut Vec::new()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "`{}` is not under the prefix `{}`",
+            test.display(),
+            ui.display()
+        )
+    );
+    assert!(!out.exists());
+
+    let args = arguments(&[
+        Path::new("--dir"),
+        test.parent().unwrap(),
+        Path::new("--output-dir"),
+        &out,
+        Path::new("--strip-prefix"),
+        &ui,
+    ]);
+    assert!(cli::run_dir(&args, &mut Vec::new()).is_err());
+    assert!(!out.exists());
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn auxiliary_files_are_converted_next_to_the_test() {
+    let fixtures = fixtures("aux_build");
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("out");
+
+    let args = arguments(&[
+        &fixtures.join("uses_helper.rs"),
+        Path::new("--output-dir"),
+        &out,
+    ]);
+    cli::run(&args, &mut Vec::new()).unwrap();
+    assert_eq!(
+        read(&out.join("uses_helper.rs")),
+        "\
+// { dg-additional-sources \"auxiliary/helper.rs auxiliary/macros.rs\" }
+
+extern crate helper;
+
+fn main() {
+    helper::add(1, \"2\"); // { dg-error \"\" \"\" { target *-*-* } }
+}
+"
+    );
+    // The headers of the auxiliary file are converted, the file without any is copied
+    assert_eq!(
+        read(&out.join("auxiliary/helper.rs")),
+        read(&fixtures.join("auxiliary/helper.rs")).replace(
+            "//@ edition:2021",
+            "// { dg-additional-options \"-frust-edition=2021\" }"
+        )
+    );
+    assert_eq!(
+        read(&out.join("auxiliary/macros.rs")),
+        read(&fixtures.join("auxiliary/macros.rs"))
+    );
+
+    // Converting the whole directory writes each auxiliary file once, without a warning
+    let out = dir.path().join("dir");
+    let args = arguments(&[
+        Path::new("--dir"),
+        &fixtures,
+        Path::new("--output-dir"),
+        &out,
+    ]);
+    let mut log = Vec::new();
+    cli::run_dir(&args, &mut log).unwrap();
+    let log = String::from_utf8(log).unwrap();
+    assert!(!log.contains("already exists"), "{log}");
+    assert!(
+        log.ends_with("3 files: 3 converted, 0 failed, 0 warnings\n"),
+        "{log}"
+    );
+    assert!(out.join("auxiliary/helper.rs").is_file());
+}
+
+#[test]
+fn a_missing_auxiliary_file_fails_the_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    let out = dir.path().join("out");
+    write(&ui.join("fine.rs"), TEST);
+    let test = ui.join("needs_helper.rs");
+    fs::write(&test, format!("//@ aux-build: missing.rs\n{TEST}")).unwrap();
+
+    let args = arguments(&[Path::new("--dir"), &ui, Path::new("--output-dir"), &out]);
+    let mut log = Vec::new();
+    let err = cli::run_dir(&args, &mut log).unwrap_err();
+    assert!(err.to_string().starts_with("1 of 2 files"), "{err}");
+    let log = String::from_utf8(log).unwrap();
+    assert!(
+        log.contains(&format!(
+            "error: the auxiliary file `{}` of `aux-build: missing.rs` in `{}` does not exist\n",
+            ui.join("auxiliary/missing.rs").display(),
+            test.display()
+        )),
+        "{log}"
+    );
+    assert_eq!(read(&out.join("fine.rs")), CONVERTED);
+    assert!(!out.join("needs_helper.rs").exists());
+
+    let args = arguments(&[&test, Path::new("--output-dir"), &out]);
+    let err = cli::run(&args, &mut Vec::new()).unwrap_err();
+    assert_eq!(cli::exit_code(&err), cli::EXIT_INPUT);
+    assert!(!out.join("needs_helper.rs").exists());
+}
@@ -0,0 +1,118 @@
+//! Leaves the proc-macro tests out, which gccrs cannot build yet, unless `--convert-anyway`.
+
+mod common;
+
+use {
+    clap::Parser,
+    common::{write, TEST},
+    rusttest_to_dg::{
+        cli::{self, Arguments},
+        transform::SkipCategory,
+    },
+    std::fs,
+};
+
+const DERIVE: &str = "//@ proc-macro: derive.rs\n#[derive(Hello)]\nstruct S;\nfn main() {}\n";
+const HOST: &str = "//@ force-host\n//@ no-prefer-dynamic\nfn main() {}\n";
+const CRATE_TYPE: &str = "//@ needs-crate-type: proc-macro\nfn main() {}\n";
+
+#[test]
+fn a_proc_macro_test_is_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("derive.rs");
+    write(&test, DERIVE);
+    let args = Arguments::try_parse_from(["rusttest-to-dg", test.to_str().unwrap()]).unwrap();
+    let mut stdout = Vec::new();
+    let report = cli::run(&args, &mut stdout).unwrap();
+    assert!(stdout.is_empty());
+    let skip = report.skipped.unwrap();
+    assert_eq!(
+        (skip.category, skip.reason.as_str()),
+        (SkipCategory::ProcMacro, "proc-macro: derive.rs")
+    );
+    assert_eq!(
+        report.warnings,
+        [format!(
+            "`{}` is a proc-macro test (`proc-macro: derive.rs`), skipped, --convert-anyway converts it",
+            test.display()
+        )]
+    );
+    assert_eq!(report.stats.skipped_tests["proc-macro"], 1);
+}
+
+#[test]
+fn skipped_tests_are_listed_in_the_skipped_report() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    let out = dir.path().join("out");
+    write(&ui.join("derive.rs"), DERIVE);
+    write(&ui.join("host.rs"), HOST);
+    write(&ui.join("nested/crate_type.rs"), CRATE_TYPE);
+    write(&ui.join("test.rs"), TEST);
+    let report = dir.path().join("skipped.json");
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        ui.to_str().unwrap(),
+        "--output-dir",
+        out.to_str().unwrap(),
+        "--skipped-report",
+        report.to_str().unwrap(),
+        "--stats",
+    ])
+    .unwrap();
+    let mut log = Vec::new();
+    let outcome = cli::run_dir(&args, &mut log).unwrap();
+    assert_eq!((outcome.converted(), outcome.skipped()), (1, 3));
+    assert!(out.join("test.rs").exists());
+    assert!(!out.join("derive.rs").exists());
+    let log = String::from_utf8(log).unwrap();
+    assert!(
+        log.contains("4 files: 1 converted, 0 failed, 3 warnings, 3 proc-macro tests skipped\n"),
+        "{log}"
+    );
+    assert!(log.ends_with("skipped proc-macro:     3\n"), "{log}");
+
+    let skipped: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&report).unwrap()).unwrap();
+    let record = |path: &str, reason: &str| {
+        serde_json::json!({
+            "path": ui.join(path),
+            "category": "proc-macro",
+            "reason": reason,
+        })
+    };
+    assert_eq!(
+        skipped,
+        serde_json::json!([
+            record("derive.rs", "proc-macro: derive.rs"),
+            record("host.rs", "force-host"),
+            record("nested/crate_type.rs", "needs-crate-type: proc-macro"),
+        ])
+    );
+
+    // The report only goes with a directory
+    assert!(Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "derive.rs",
+        "--skipped-report",
+        "skipped.json",
+    ])
+    .is_err());
+}
+
+#[test]
+fn convert_anyway_converts_under_a_warning() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("host.rs");
+    write(&test, HOST);
+    let args =
+        Arguments::try_parse_from(["rusttest-to-dg", test.to_str().unwrap(), "--convert-anyway"])
+            .unwrap();
+    let mut stdout = Vec::new();
+    let report = cli::run(&args, &mut stdout).unwrap();
+    assert_eq!(report.skipped, None);
+    assert!(String::from_utf8(stdout).unwrap().starts_with(
+        "// WARNING: proc-macro test (`force-host`) converted with --convert-anyway, gccrs cannot build it yet\n//@ force-host\n"
+    ));
+}
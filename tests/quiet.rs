@@ -0,0 +1,79 @@
+//! Checks what the binary prints on stderr at each level, from `--quiet` to `-vv`.
+
+use std::{fs, path::Path, process::Command};
+
+/// Converts `args` in `dir` and returns what was printed on stderr
+fn stderr(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    String::from_utf8(output.stderr).unwrap()
+}
+
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("test.rs"),
+        "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n    let y = 1; //~ FOO bar\n}\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("test.stderr"),
+        "error[E0308]: mismatched types\n  --> $DIR/test.rs:2:18\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("orphan.rs"),
+        "//~| NOTE nothing to follow\n",
+    )
+    .unwrap();
+    dir
+}
+
+const NOTE: &str = "note: using the stderr file `test.stderr` found next to FILE";
+const ANNOTATION: &str = "line 2: dg-error, code E0308, msg 'mismatched types'";
+const STDERR_ERROR: &str = "stderr line 2: E0308, msg 'mismatched types'";
+
+#[test]
+fn notes_are_printed_by_default() {
+    let dir = tree();
+    let stderr = stderr(dir.path(), &["test.rs"]);
+    assert!(stderr.contains(NOTE), "{stderr}");
+    assert!(!stderr.contains(ANNOTATION), "{stderr}");
+}
+
+#[test]
+fn verbose_raises_the_level() {
+    let dir = tree();
+    let stderr = stderr(dir.path(), &["test.rs", "-v"]);
+    assert!(stderr.contains(NOTE), "{stderr}");
+    assert!(stderr.contains(ANNOTATION), "{stderr}");
+    assert!(
+        stderr.contains("line 3: skipped, unknown annotation kind `FOO`"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains(STDERR_ERROR), "{stderr}");
+
+    let stderr = self::stderr(dir.path(), &["test.rs", "-vv"]);
+    assert!(stderr.contains(STDERR_ERROR), "{stderr}");
+}
+
+#[test]
+fn quiet_only_prints_errors() {
+    let dir = tree();
+    assert_eq!(stderr(dir.path(), &["test.rs", "-q"]), "");
+
+    let stderr = self::stderr(dir.path(), &["test.rs", "orphan.rs", "--quiet"]);
+    // A backtrace may follow, depending on the environment
+    assert!(
+        stderr.starts_with(
+            "error: could not transform code from file `orphan.rs`: in `//~| NOTE nothing to follow`: line 1: `//~|` without a preceding annotation to follow\n\
+             Error: 1 of 2 files failed to convert\n"
+        ),
+        "{stderr}"
+    );
+
+    assert!(self::stderr(dir.path(), &["test.rs", "-q", "-v"]).contains("cannot be used with"));
+}
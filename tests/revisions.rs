@@ -0,0 +1,165 @@
+//! Converts a test with revisions, each revision seeing its own annotations and headers.
+
+mod common;
+
+use {
+    clap::Parser,
+    common::{convert, fixture, fixtures},
+    rusttest_to_dg::cli::{self, Arguments},
+    std::fs,
+};
+
+/// The path of the `name` test
+fn test(name: &str) -> String {
+    let test = fixture("revisions", name);
+    test.to_str().unwrap().to_owned()
+}
+
+fn expected(revision: &str) -> String {
+    fs::read_to_string(fixture(
+        "revisions",
+        &format!("editions.{revision}.expected"),
+    ))
+    .unwrap()
+}
+
+#[test]
+fn each_revision_sees_its_own_errors() {
+    let (e2015, e2021) = (expected("e2015"), expected("e2021"));
+    assert!(e2015.contains("async fn f() {}\n// { dg-error"));
+    assert!(e2015.contains("let dyn = 1;\n"));
+    assert!(e2021.contains("let dyn = 1; // { dg-error"));
+    assert!(e2021.contains("async fn f() {}\n\n"));
+
+    let editions = test("editions.rs");
+    assert_eq!(
+        convert(&[&editions]),
+        format!("// ===== revision e2015 =====\n{e2015}// ===== revision e2021 =====\n{e2021}\n")
+    );
+    assert_eq!(
+        convert(&[&editions, "--revision", "e2021"]),
+        format!("{e2021}\n")
+    );
+
+    let args = Arguments::try_parse_from(["rusttest-to-dg", &editions, "--revision", "e2018"]);
+    let err = cli::run(&args.unwrap(), &mut Vec::new()).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("`e2018` is not one of the revisions of"),
+        "{err}"
+    );
+    assert!(err.to_string().ends_with(": e2015, e2021"), "{err}");
+}
+
+#[test]
+fn each_revision_gets_its_own_file_under_the_output_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("out");
+    convert(&[&test("editions.rs"), "--output-dir", out.to_str().unwrap()]);
+
+    let directory = dir.path().join("dir");
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        fixtures("revisions").to_str().unwrap(),
+        "--output-dir",
+        directory.to_str().unwrap(),
+    ])
+    .unwrap();
+    cli::run_dir(&args, &mut Vec::new()).unwrap();
+
+    for out in [out, directory] {
+        assert!(!out.join("editions.rs").exists());
+        for revision in ["e2015", "e2021"] {
+            assert_eq!(
+                fs::read_to_string(out.join(format!("editions.{revision}.rs"))).unwrap(),
+                expected(revision),
+                "{revision}"
+            );
+        }
+    }
+}
+
+#[test]
+fn a_test_with_revisions_is_rewritten_for_one_of_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("editions.rs");
+    fs::copy(fixture("revisions", "editions.rs"), &test).unwrap();
+    let in_place = |args: &[&str]| {
+        let args = Arguments::try_parse_from(
+            ["rusttest-to-dg", test.to_str().unwrap(), "--in-place"]
+                .into_iter()
+                .chain(args.iter().copied()),
+        )
+        .unwrap();
+        cli::run(&args, &mut Vec::new())
+    };
+
+    let err = in_place(&[]).unwrap_err();
+    assert!(err.to_string().contains("pick the one"), "{err}");
+    in_place(&["--revision", "e2015"]).unwrap();
+    assert_eq!(fs::read_to_string(&test).unwrap(), expected("e2015"));
+}
+
+#[test]
+fn revision_headers_only_apply_to_their_revision() {
+    for revision in ["base", "strict"] {
+        let expected =
+            fs::read_to_string(fixture("revisions", &format!("flags.{revision}.expected")))
+                .unwrap();
+        assert_eq!(
+            convert(&[&test("flags.rs"), "--revision", revision]),
+            format!("{expected}\n")
+        );
+    }
+    let base = fs::read_to_string(fixture("revisions", "flags.base.expected")).unwrap();
+    let strict = fs::read_to_string(fixture("revisions", "flags.strict.expected")).unwrap();
+    // The shared flag is in both, the `//[rev]@` and the `//[rev] compile-flags:` only in theirs
+    assert!(base.contains("\"-O -frust-cfg=legacy\""), "{base}");
+    assert!(!base.contains("-frust-edition"), "{base}");
+    assert!(strict.contains("\"-O\""), "{strict}");
+    assert!(strict.contains("-frust-edition=2021"), "{strict}");
+    assert!(!strict.contains("legacy\""), "{strict}");
+}
+
+#[test]
+fn nothing_is_written_when_a_revision_fails() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("broken.rs");
+    fs::write(
+        &test,
+        "//@ revisions: a b\nfn main() {} //[a]~ ERROR x\n//[b]~^^^^^^^^^ ERROR y\n",
+    )
+    .unwrap();
+    let args = Arguments::try_parse_from(["rusttest-to-dg", test.to_str().unwrap()]).unwrap();
+    let mut stdout = Vec::new();
+    cli::run(&args, &mut stdout).unwrap_err();
+    assert_eq!(String::from_utf8(stdout).unwrap(), "");
+}
+
+#[test]
+fn a_header_for_an_unknown_revision_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("typo.rs");
+    fs::write(
+        &test,
+        "//@ revisions: base strict\n//[strcit]@ edition: 2021\nfn main() {}\n",
+    )
+    .unwrap();
+    let args = Arguments::try_parse_from(["rusttest-to-dg", test.to_str().unwrap()]).unwrap();
+    let err = cli::run(&args, &mut Vec::new()).unwrap_err();
+    assert!(
+        format!("{err:#}")
+            .ends_with("line 2: `strcit` is not one of the revisions of the test: base, strict"),
+        "{err:#}"
+    );
+
+    fs::write(&test, "//[base] compile-flags: -O\nfn main() {}\n").unwrap();
+    let err = cli::run(&args, &mut Vec::new()).unwrap_err();
+    assert!(
+        format!("{err:#}").ends_with(
+            "line 1: the header is for the revision `base`, but the test has no `revisions` header"
+        ),
+        "{err:#}"
+    );
+}
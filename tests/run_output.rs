@@ -0,0 +1,111 @@
+//! Converts the `.run.stdout` file of a `check-run-results` test into `dg-output` directives.
+
+mod common;
+
+use {
+    clap::Parser,
+    common::{convert, fixture, fixtures},
+    rusttest_to_dg::{
+        cli::{self, Arguments},
+        dejagnu,
+    },
+    std::{fs, path::Path},
+};
+
+fn expected(name: &str) -> String {
+    fs::read_to_string(fixture("run_output", name)).unwrap()
+}
+
+fn run_dir(dir: &Path, out: &Path, extra: &[&str]) -> String {
+    let args = Arguments::try_parse_from(
+        [
+            "rusttest-to-dg",
+            "--dir",
+            dir.to_str().unwrap(),
+            "--output-dir",
+            out.to_str().unwrap(),
+        ]
+        .into_iter()
+        .chain(extra.iter().copied()),
+    )
+    .unwrap();
+    let mut log = Vec::new();
+    cli::run_dir(&args, &mut log).unwrap();
+    String::from_utf8(log).unwrap()
+}
+
+#[test]
+fn the_expected_output_follows_the_converted_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("out");
+    let log = run_dir(&fixtures("run_output"), &out, &[]);
+    let hello = fs::read_to_string(out.join("hello.rs")).unwrap();
+    assert_eq!(hello, expected("hello.expected"));
+    assert!(hello.ends_with("// { dg-output \"\\r*\\n\" }\n// { dg-output \"\\\\\\[1, 2, 3\\\\\\] costs \\\\\\$5 \\\\(or more\\\\?\\\\)\\r*\\n\" }\n"));
+    assert_eq!(dejagnu::verify(&hello), []);
+
+    // The output of 100 lines is over the threshold
+    assert_eq!(
+        fs::read_to_string(out.join("counting.rs")).unwrap(),
+        expected("counting.expected")
+    );
+    assert!(
+        log.contains(&format!(
+            "warning: the expected output `{}` has 100 lines, more than the 64 of --max-stdout-lines, it needs manual conversion\n",
+            fixture("run_output", "counting.run.stdout").display()
+        )),
+        "{log}"
+    );
+
+    let out = dir.path().join("unlimited");
+    let log = run_dir(&fixtures("run_output"), &out, &["--max-stdout-lines", "0"]);
+    assert!(!log.contains("warning:"), "{log}");
+    let counting = fs::read_to_string(out.join("counting.rs")).unwrap();
+    assert_eq!(counting.matches("dg-output").count(), 100);
+    assert!(counting.ends_with("// { dg-output \"99\\r*\\n\" }\n"));
+}
+
+#[test]
+fn a_missing_stdout_file_is_a_warning() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    fs::create_dir(&ui).unwrap();
+    fs::copy(fixture("run_output", "hello.rs"), ui.join("hello.rs")).unwrap();
+    let out = dir.path().join("out");
+    let log = run_dir(&ui, &out, &[]);
+    assert!(!fs::read_to_string(out.join("hello.rs"))
+        .unwrap()
+        .contains("dg-output"));
+    assert!(
+        log.contains(&format!(
+            "warning: `{}` checks the results of its run but has no `{}` file, its output is not checked\n",
+            ui.join("hello.rs").display(),
+            ui.join("hello.run.stdout").display()
+        )),
+        "{log}"
+    );
+}
+
+#[test]
+fn a_single_file_takes_its_expected_output_from_stdout_file() {
+    let test = fixture("run_output", "hello.rs");
+    let test = test.to_str().unwrap();
+    // Nothing is looked for next to the file without `--stdout-file`
+    assert!(!convert(&[test]).contains("dg-output"));
+    let stdout = fixture("run_output", "hello.run.stdout");
+    assert_eq!(
+        convert(&[test, "--stdout-file", stdout.to_str().unwrap()]),
+        expected("hello.expected") + "\n"
+    );
+
+    assert!(Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        "ui",
+        "--output-dir",
+        "out",
+        "--stdout-file",
+        stdout.to_str().unwrap(),
+    ])
+    .is_err());
+}
@@ -0,0 +1,96 @@
+//! Converts a `run-rustfix` test along with its `.fixed` file, into a second test.
+
+mod common;
+
+use {
+    clap::Parser,
+    common::{fixture, fixtures},
+    rusttest_to_dg::cli::{self, Arguments},
+    std::{fs, path::Path},
+};
+
+fn run_dir(dir: &Path, out: &Path, extra: &[&str]) -> String {
+    let args = Arguments::try_parse_from(
+        [
+            "rusttest-to-dg",
+            "--dir",
+            dir.to_str().unwrap(),
+            "--output-dir",
+            out.to_str().unwrap(),
+        ]
+        .into_iter()
+        .chain(extra.iter().copied()),
+    )
+    .unwrap();
+    let mut log = Vec::new();
+    cli::run_dir(&args, &mut log).unwrap();
+    String::from_utf8(log).unwrap()
+}
+
+#[test]
+fn the_fixed_file_becomes_a_compile_test() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("out");
+    run_dir(&fixtures("rustfix"), &out, &[]);
+    let expected = |name| fs::read_to_string(fixture("rustfix", name)).unwrap();
+    assert_eq!(
+        fs::read_to_string(out.join("unused_mut.rs")).unwrap(),
+        expected("unused_mut.expected")
+    );
+    let fixed = fs::read_to_string(out.join("unused_mut_fixed.rs")).unwrap();
+    assert_eq!(fixed, expected("unused_mut_fixed.expected"));
+    assert!(fixed.starts_with(
+        "// `unused_mut.rs` once fixed by rustfix, converted from `unused_mut.fixed`\n// { dg-do compile }\n"
+    ));
+
+    let out = dir.path().join("no-rustfix");
+    run_dir(&fixtures("rustfix"), &out, &["--no-rustfix"]);
+    assert!(out.join("unused_mut.rs").exists());
+    assert!(!out.join("unused_mut_fixed.rs").exists());
+}
+
+#[test]
+fn a_missing_fixed_file_is_a_warning() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    fs::create_dir(&ui).unwrap();
+    fs::copy(
+        fixture("rustfix", "unused_mut.rs"),
+        ui.join("unused_mut.rs"),
+    )
+    .unwrap();
+    let out = dir.path().join("out");
+    let log = run_dir(&ui, &out, &[]);
+    assert!(out.join("unused_mut.rs").exists());
+    assert!(!out.join("unused_mut_fixed.rs").exists());
+    assert!(
+        log.contains(&format!(
+            "warning: `{}` runs rustfix but has no `{}` file, only the test is converted\n",
+            ui.join("unused_mut.rs").display(),
+            ui.join("unused_mut.fixed").display()
+        )),
+        "{log}"
+    );
+    assert!(
+        log.ends_with("1 files: 1 converted, 0 failed, 1 warnings\n"),
+        "{log}"
+    );
+}
+
+#[test]
+fn a_single_test_under_the_output_dir_gets_its_fixed_file_too() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = fixture("rustfix", "unused_mut.rs");
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        test.to_str().unwrap(),
+        "--output-dir",
+        dir.path().to_str().unwrap(),
+        "--strip-prefix",
+        fixtures("rustfix").to_str().unwrap(),
+    ])
+    .unwrap();
+    let report = cli::run(&args, &mut Vec::new()).unwrap();
+    assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+    assert!(dir.path().join("unused_mut_fixed.rs").exists());
+}
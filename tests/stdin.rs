@@ -0,0 +1,49 @@
+//! Pipes tests into the binary, with `-` as the source file.
+
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn convert_stdin(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn snippet_from_stdin() {
+    let output = convert_stdin(
+        &["-"],
+        b"fn main() {\n    let x: i32 = \"\";\n    //~^ ERROR mismatched types\n}\n",
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "fn main() {\n    let x: i32 = \"\";\n// { dg-error \"\" \"\" { target *-*-* } .-1 }\n}\n\n"
+    );
+}
+
+#[test]
+fn errors_name_standard_input() {
+    let output = convert_stdin(&["-"], b"fn main() {}\n\xff\n");
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("could not read sourcefile standard input"),
+        "{stderr}"
+    );
+
+    let output = convert_stdin(&["-"], b"//~| NOTE nothing to follow\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("could not transform code from standard input"),
+        "{stderr}"
+    );
+}
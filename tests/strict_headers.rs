@@ -0,0 +1,72 @@
+//! Keeps the `//@` headers compiletest does not know as comments, or fails on them with
+//! `--strict-headers`.
+
+use {
+    clap::Parser,
+    rusttest_to_dg::cli::{self, Arguments},
+    std::{fs, process::Command},
+};
+
+const TEST: &str = "//@ check-pass\n//@ gccrs-only: for now\nfn main() {}\n";
+
+#[test]
+fn an_unknown_header_is_kept_and_listed() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(&test, TEST).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .arg(&test)
+        .args(["--verbose", "--stats"])
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{result:?}");
+    assert_eq!(
+        String::from_utf8(result.stdout).unwrap(),
+        "// { dg-do compile }\n\n// UNCONVERTED: @ gccrs-only: for now\nfn main() {}\n\n"
+    );
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains("line 2: unconverted header `//@ gccrs-only: for now`\n"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("unconverted headers:    1\n"), "{stderr}");
+}
+
+#[test]
+fn strict_headers_fail_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let ui = dir.path().join("ui");
+    fs::create_dir(&ui).unwrap();
+    fs::write(ui.join("unknown.rs"), TEST).unwrap();
+    fs::write(ui.join("known.rs"), "//@ check-pass\nfn main() {}\n").unwrap();
+    let out = dir.path().join("out");
+    let args = Arguments::try_parse_from([
+        "rusttest-to-dg",
+        "--dir",
+        ui.to_str().unwrap(),
+        "--output-dir",
+        out.to_str().unwrap(),
+        "--strict-headers",
+    ])
+    .unwrap();
+    let mut log = Vec::new();
+    cli::run_dir(&args, &mut log).unwrap_err();
+    let log = String::from_utf8(log).unwrap();
+    assert!(
+        log.contains(&format!(
+            "error: could not transform code from file `{}`: line 2: `//@ gccrs-only: for now` is not a known directive, rejected by --strict-headers\n",
+            ui.join("unknown.rs").display()
+        )),
+        "{log}"
+    );
+    assert!(
+        log.ends_with("2 files: 1 converted, 1 failed, 0 warnings\n"),
+        "{log}"
+    );
+    // A file with only known headers is converted as usual
+    assert_eq!(
+        fs::read_to_string(out.join("known.rs")).unwrap(),
+        "// { dg-do compile }\n\nfn main() {}\n"
+    );
+    assert!(!out.join("unknown.rs").exists());
+}
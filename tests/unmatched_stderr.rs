@@ -0,0 +1,84 @@
+//! Reports the diagnostics of the `stderr` file no annotation expects, and fails on the
+//! errors among them with `--strict-stderr`.
+
+use std::{fs, path::Path, process::Command};
+
+const TEST: &str =
+    "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n    let y = undefined;\n}\n";
+
+const STDERR: &str = "\
+error[E0308]: mismatched types
+  --> $DIR/test.rs:2:18
+
+error[E0425]: cannot find value `undefined` in this scope, its name is long enough to be cut
+  --> $DIR/test.rs:3:13
+
+warning: unused variable: `y`
+  --> $DIR/test.rs:3:9
+";
+
+fn convert(dir: &Path, args: &[&str]) -> std::process::Output {
+    let test = dir.join("test.rs");
+    fs::write(&test, TEST).unwrap();
+    fs::write(dir.join("test.stderr"), STDERR).unwrap();
+    Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .arg(&test)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn unmatched_diagnostics_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = convert(dir.path(), &["--stats-json"]);
+    assert!(result.status.success(), "{result:?}");
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains("warning: line 3: error[E0425]: cannot find value `undefined` in this scope, its name is lon..., in the stderr file, matches no annotation\n"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(
+            "warning: line 3: warning: unused variable: `y`, in the stderr file, matches no annotation\n"
+        ),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("E0308"), "{stderr}");
+    let json = stderr.lines().find(|line| line.starts_with('{')).unwrap();
+    let stats: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(stats["unmatched_stderr"], 2);
+}
+
+#[test]
+fn strict_stderr_fails_on_unmatched_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let result = convert(dir.path(), &["--strict-stderr"]);
+    assert_eq!(result.status.code(), Some(1), "{result:?}");
+    let stderr = String::from_utf8(result.stderr).unwrap();
+    assert!(
+        stderr.contains("1 error of the stderr file matches no annotation\n"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("line 3: error[E0425]: cannot find value `undefined`"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("unused variable"), "{stderr}");
+
+    // Unmatched warnings alone are not a failure
+    let dir = tempfile::tempdir().unwrap();
+    let test = dir.path().join("test.rs");
+    fs::write(
+        &test,
+        TEST.replace("undefined;", "undefined; //~ ERROR cannot find value"),
+    )
+    .unwrap();
+    fs::write(dir.path().join("test.stderr"), STDERR).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .arg(&test)
+        .arg("--strict-stderr")
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{result:?}");
+}
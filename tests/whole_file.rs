@@ -0,0 +1,63 @@
+//! Hoists the directives of the `//~?` annotations, for diagnostics without a span, to the
+//! top of the converted test.
+
+mod common;
+
+use {
+    common::{convert, fixture},
+    rusttest_to_dg::dejagnu::{self, LineSpec},
+    std::fs,
+};
+
+/// The conversion of the `crate_name` test
+fn converted() -> String {
+    let test = fixture("whole_file", "crate_name.rs");
+    convert(&[test.to_str().unwrap(), "--msg-style", "escaped", "--strict"])
+}
+
+#[test]
+fn whole_file_directives_are_hoisted() {
+    let expected = fs::read_to_string(fixture("whole_file", "crate_name.expected")).unwrap();
+    let converted = converted();
+    assert_eq!(converted, format!("{expected}\n"));
+
+    let first = dejagnu::validate(converted.lines().next().unwrap()).unwrap();
+    assert_eq!(first.name, "dg-error");
+    assert_eq!(first.line, Some(LineSpec::Absolute(0)));
+    // The annotation line is left empty, nothing remains of it
+    assert!(!converted.contains("//~"), "{converted}");
+    let source = fs::read_to_string(fixture("whole_file", "crate_name.rs")).unwrap();
+    let lines: Vec<_> = converted.lines().collect();
+    // One more line for the hoisted directive and the empty one the output ends with, the
+    // annotation line itself is kept empty
+    assert_eq!(lines.len(), source.lines().count() + 2);
+    assert_eq!(lines[source.lines().count()], "");
+}
+
+#[test]
+fn the_other_directives_still_target_their_line() {
+    let converted = converted();
+    let lines: Vec<_> = converted.lines().collect();
+    let targets: Vec<_> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let directive = dejagnu::validate(line).ok()?;
+            let line_num = index + 1;
+            let target = directive.target_line(line_num)?;
+            Some((directive.name, target))
+        })
+        .collect();
+    // The hoisted directive is one more line above them, which shifts them all alike
+    assert_eq!(
+        targets,
+        [
+            ("dg-error".to_owned(), 0),
+            ("dg-additional-options".to_owned(), 2),
+            ("dg-error".to_owned(), 6),
+            ("dg-warning".to_owned(), 8),
+        ]
+    );
+    assert_eq!(lines[6 - 1], "    let x: i32 = \"\";");
+    assert!(lines[8 - 1].starts_with("    let y = 1;"));
+}
@@ -0,0 +1,82 @@
+//! Adds the notes, help messages and secondary labels of the `stderr` file no annotation
+//! expects with `--with-notes`.
+
+use std::{fs, path::Path, process::Command};
+
+/// The `stderr` file rustc blesses for `let x: i32 = "";`, whose `expected due to this`
+/// label points at the type
+const STDERR: &str = "\
+error[E0308]: mismatched types
+  --> $DIR/test.rs:2:18
+   |
+LL |     let x: i32 = \"\";
+   |            ---   ^^ expected `i32`, found `&str`
+   |            |
+   |            expected due to this
+
+help: consider removing the quotes
+  --> $DIR/test.rs:2:18
+   |
+LL |     let x: i32 = 1;
+   |                  ~
+
+error: aborting due to 1 previous error
+
+For more information about this error, try `rustc --explain E0308`.
+";
+
+fn convert(dir: &Path, test: &str, args: &[&str]) -> (String, String) {
+    let path = dir.join("test.rs");
+    fs::write(&path, test).unwrap();
+    fs::write(dir.join("test.stderr"), STDERR).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_rusttest-to-dg"))
+        .arg(&path)
+        .args(["--msg-style", "escaped", "--strict"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(result.status.success(), "{result:?}");
+    (
+        String::from_utf8(result.stdout).unwrap(),
+        String::from_utf8(result.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn notes_go_below_the_annotations_of_their_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = "fn main() {\n    let x: i32 = \"\";\n    //~^ ERROR mismatched types\n}\n";
+    let (stdout, stderr) = convert(dir.path(), test, &["--with-notes"]);
+    assert_eq!(
+        stdout,
+        "fn main() {\n    let x: i32 = \"\";\n// { dg-error \"mismatched types.*.E0308.\" \"\" { target *-*-* } .-1 }\n// { dg-note \"expected due to this\" \"\" { target *-*-* } .-2 }\n// { dg-message \"help: consider removing the quotes\" \"\" { target *-*-* } .-3 }\n}\n\n"
+    );
+    // They are expected now
+    assert!(!stderr.contains("matches no annotation"), "{stderr}");
+}
+
+#[test]
+fn annotated_notes_are_not_repeated() {
+    let dir = tempfile::tempdir().unwrap();
+    let test = "fn main() {\n    let x: i32 = \"\"; //~ ERROR mismatched types\n    //~^ NOTE expected due to this\n}\n";
+    let (stdout, _) = convert(dir.path(), test, &["--with-notes"]);
+    assert_eq!(
+        stdout,
+        "fn main() {\n    let x: i32 = \"\"; // { dg-error \"mismatched types.*.E0308.\" \"\" { target *-*-* } }\n// { dg-note \"expected due to this\" \"\" { target *-*-* } .-1 }\n// { dg-message \"help: consider removing the quotes\" \"\" { target *-*-* } .-2 }\n}\n\n"
+    );
+
+    // Without the flag, only the help message is told about, the label is no diagnostic
+    let (stdout, stderr) = convert(dir.path(), test, &[]);
+    assert!(!stdout.contains("dg-message"), "{stdout}");
+    assert!(
+        stderr.contains(
+            "warning: line 2: help: consider removing the quotes, in the stderr file, matches no annotation\n"
+        ),
+        "{stderr}"
+    );
+    assert_eq!(
+        stderr.matches("matches no annotation").count(),
+        1,
+        "{stderr}"
+    );
+}